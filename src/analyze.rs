@@ -0,0 +1,87 @@
+// Structure of EXR files: their parts and the layers of channels within each, to pick what to convert
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use clap::Args;
+use exr::meta::{attribute::SampleType, header::Header, BlockDescription, MetaData};
+
+use crate::decode::part_name;
+
+/// Print the parts of EXR files, with their size, compression and channels grouped by layer. Only headers are read
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    /// EXR files to analyze
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
+
+pub fn run(args: &AnalyzeArgs) -> Result<(), String> {
+    for path in &args.files {
+        let file =
+            File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        let headers = MetaData::read_from_buffered(BufReader::new(file), false)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?
+            .headers;
+
+        println!("{}", path.display());
+        // Deep parts are the only ones the first valid layer skips
+        let default = headers.iter().position(|h| !h.deep);
+        for (index, header) in headers.iter().enumerate() {
+            print_part(index, header, default == Some(index));
+        }
+    }
+    Ok(())
+}
+
+fn print_part(index: usize, header: &Header, default: bool) {
+    let name = part_name(header).map_or(String::new(), |n| format!(" \"{}\"", n));
+    let blocks = match header.blocks {
+        BlockDescription::ScanLines => "scan lines",
+        BlockDescription::Tiles(_) => "tiles",
+    };
+    let mut notes = String::new();
+    if default {
+        notes.push_str(", converted by default");
+    }
+    if header.deep {
+        notes.push_str(", deep data, cannot be converted");
+    }
+    println!(
+        "  part {}{}: {}x{} at {},{}, {}, {}{}",
+        index,
+        name,
+        header.layer_size.0,
+        header.layer_size.1,
+        header.own_attributes.layer_position.0,
+        header.own_attributes.layer_position.1,
+        header.compression,
+        blocks,
+        notes
+    );
+
+    // Channels named layer.channel, in file order of their first channel
+    let mut layers: Vec<(&str, Vec<String>)> = Vec::new();
+    let names: Vec<String> = header
+        .channels
+        .list
+        .iter()
+        .map(|c| c.name.to_string())
+        .collect();
+    for (name, channel) in names.iter().zip(&header.channels.list) {
+        let (layer, channel_name) = name.rsplit_once('.').unwrap_or(("", name));
+        let sample_type = match channel.sample_type {
+            SampleType::U32 => "u32",
+            SampleType::F16 => "f16",
+            SampleType::F32 => "f32",
+        };
+        let channel_name = format!("{} ({})", channel_name, sample_type);
+        match layers.iter_mut().find(|(l, _)| *l == layer) {
+            Some((_, channels)) => channels.push(channel_name),
+            None => layers.push((layer, vec![channel_name])),
+        }
+    }
+    for (layer, channels) in layers {
+        let layer = if layer.is_empty() { "(none)" } else { layer };
+        println!("    layer {}: {}", layer, channels.join(", "));
+    }
+}
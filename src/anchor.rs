@@ -0,0 +1,61 @@
+// Exposure set by pegging a linear value of the scene to a named display level, as lighting artists meter
+
+use clap::ValueEnum;
+
+/// Display levels a scene value can be pegged to, in linear display light relative to SDR white
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq)]
+pub enum AnchorLevel {
+    /// 18% middle gray
+    Gray18,
+    /// SDR white
+    White,
+}
+
+impl AnchorLevel {
+    fn display_level(&self) -> f32 {
+        match self {
+            AnchorLevel::Gray18 => 0.18,
+            AnchorLevel::White => 1.0,
+        }
+    }
+}
+
+/// A linear scene value and the display level it is shown at
+#[derive(Debug, Copy, Clone)]
+pub struct Anchor {
+    pub level: AnchorLevel,
+    pub value: f32,
+}
+
+/// Parse `level=value`, such as gray18=0.18
+pub fn parse_anchor(text: &str) -> Result<Anchor, String> {
+    let (level, value) = text
+        .split_once('=')
+        .ok_or_else(|| "expected LEVEL=VALUE, such as gray18=0.18".to_string())?;
+    let level = AnchorLevel::from_str(level, true).map_err(|_| {
+        let names: Vec<_> = AnchorLevel::value_variants()
+            .iter()
+            .filter_map(|l| l.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect();
+        format!(
+            "unknown level {:?}, expected one of {}",
+            level,
+            names.join(", ")
+        )
+    })?;
+    let value: f32 = value
+        .parse()
+        .map_err(|e| format!("invalid value {:?}: {}", value, e))?;
+    if !value.is_finite() || value <= 0.0 {
+        return Err(format!("anchored value must be above 0, got {}", value));
+    }
+    Ok(Anchor { level, value })
+}
+
+impl Anchor {
+    /// Exposure value (eV) bringing the scene value to its display level
+    pub fn exposure(&self) -> f32 {
+        (self.level.display_level() / self.value).log2()
+    }
+}
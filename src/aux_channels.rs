@@ -0,0 +1,188 @@
+// Other EXR channels (depth, normals, IDs) written as normalized PNGs alongside the conversion
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use exr::prelude::FlatSamples;
+use png::Encoder as PNGEncoder;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::color_stuff::Pixel;
+
+/// EXR channels written to a PNG, one for grayscale or three for RGB
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AuxChannel {
+    pub name: String,
+    pub channels: Vec<String>,
+    pub path: PathBuf,
+}
+
+/// Parse `NAME=CHANNEL:PATH`, or `NAME=CHANNEL,CHANNEL,CHANNEL:PATH` for RGB
+pub fn parse_aux_channel(text: &str) -> Result<AuxChannel, String> {
+    let (name, rest) = text
+        .split_once('=')
+        .ok_or_else(|| "expected NAME=CHANNEL:PATH".to_string())?;
+    let (channels, path) = rest
+        .split_once(':')
+        .ok_or_else(|| "expected NAME=CHANNEL:PATH".to_string())?;
+    let channels: Vec<String> = channels.split(',').map(|c| c.trim().to_string()).collect();
+    if channels.len() != 1 && channels.len() != 3 {
+        return Err(format!(
+            "expected 1 channel for grayscale or 3 for RGB, got {}",
+            channels.len()
+        ));
+    }
+    Ok(AuxChannel {
+        name: name.to_string(),
+        channels,
+        path: PathBuf::from(path),
+    })
+}
+
+impl AuxChannel {
+    /// Same channels written to another file
+    pub fn with_path(&self, path: PathBuf) -> AuxChannel {
+        AuxChannel {
+            path,
+            ..self.clone()
+        }
+    }
+}
+
+/// Whether a channel is used by any request, so its samples are kept while decoding
+pub fn is_requested(requests: &[AuxChannel], channel: &str) -> bool {
+    requests
+        .iter()
+        .any(|r| r.channels.iter().any(|c| c == channel))
+}
+
+/// Samples of any type as f32, integers (such as IDs) taken as numbers
+pub fn samples(samples: &FlatSamples) -> Vec<f32> {
+    match samples {
+        FlatSamples::F16(values) => values.iter().map(|v| v.to_f32()).collect(),
+        FlatSamples::F32(values) => values.clone(),
+        FlatSamples::U32(values) => values.iter().map(|v| *v as f32).collect(),
+    }
+}
+
+/// One image per request from the kept channel samples, grayscale ones repeating their channel in R, G and B
+pub fn assemble(
+    requests: &[AuxChannel],
+    kept: &[(String, Vec<f32>)],
+    pixel_count: usize,
+) -> Result<Vec<Vec<Pixel>>, String> {
+    let find = |channel: &str| {
+        let (_, samples) = kept
+            .iter()
+            .find(|(name, _)| name == channel)
+            .ok_or_else(|| format!("No channel {} in input EXR", channel))?;
+        if samples.len() != pixel_count {
+            return Err(format!(
+                "Channel {} is subsampled, it cannot be written as an auxiliary output",
+                channel
+            ));
+        }
+        Ok(samples)
+    };
+
+    requests
+        .iter()
+        .map(|request| {
+            let planes = request
+                .channels
+                .iter()
+                .map(|c| find(c))
+                .collect::<Result<Vec<_>, _>>()?;
+            let (r, g, b) = match planes[..] {
+                [y] => (y, y, y),
+                [r, g, b] => (r, g, b),
+                _ => unreachable!(),
+            };
+            Ok(r.iter()
+                .zip(g)
+                .zip(b)
+                .map(|((&r, &g), &b)| Pixel { r, g, b })
+                .collect())
+        })
+        .collect()
+}
+
+/// Write every auxiliary image as a 16-bit PNG, stretching the range of its finite values to the full range of the PNG
+pub fn write(
+    requests: &[AuxChannel],
+    images: &[Vec<Pixel>],
+    width: usize,
+    height: usize,
+) -> Result<(), String> {
+    for (request, pixels) in requests.iter().zip(images) {
+        let values = || {
+            pixels
+                .iter()
+                .flat_map(|p| [p.r, p.g, p.b])
+                .filter(|v| v.is_finite())
+        };
+        let min = values().fold(f32::INFINITY, f32::min);
+        let max = values().fold(f32::NEG_INFINITY, f32::max);
+        let scale = if max > min { (max - min).recip() } else { 0.0 };
+        info!(
+            name = request.name,
+            path = %request.path.display(),
+            min,
+            max,
+            "Writing auxiliary channels, normalized from this range"
+        );
+
+        let normalize = |v: f32| {
+            let normalized = if v.is_finite() {
+                (v - min) * scale
+            } else {
+                0.0
+            };
+            ((normalized.clamp(0.0, 1.0) * u16::MAX as f32).round() as u16).to_be_bytes()
+        };
+        let grayscale = request.channels.len() == 1;
+        let data: Vec<u8> = pixels
+            .iter()
+            .flat_map(|p| {
+                if grayscale {
+                    vec![p.r]
+                } else {
+                    vec![p.r, p.g, p.b]
+                }
+            })
+            .flat_map(normalize)
+            .collect();
+        write_png(&request.path, &data, width, height, grayscale)?;
+    }
+    Ok(())
+}
+
+fn write_png(
+    path: &Path,
+    data: &[u8],
+    width: usize,
+    height: usize,
+    grayscale: bool,
+) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+    let mut encoder = PNGEncoder::new(
+        BufWriter::new(file),
+        width.try_into().unwrap(),
+        height.try_into().unwrap(),
+    );
+    encoder.set_color(if grayscale {
+        png::ColorType::Grayscale
+    } else {
+        png::ColorType::Rgb
+    });
+    encoder.set_depth(png::BitDepth::Sixteen);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(data))
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+}
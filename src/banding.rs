@@ -0,0 +1,112 @@
+// https://en.wikipedia.org/wiki/Colour_banding
+// Banding of HDR reconstructed from the 8-bit Gain Map: in smooth gradients, one code of the Gain Map shows as a visible step when boosts span many stops
+
+use tracing::{info, warn};
+
+use crate::{
+    color_stuff::Pixel, planar::PlanarView, recovery::RecoveryEncoding, resize::gain_map_size,
+    PixelParameters,
+};
+
+/// Step in nits above which the Gain Map gets dithered
+pub const DEFAULT_THRESHOLD_NITS: f32 = 5.0;
+/// Relative difference of SDR luminance under which neighbours belong to a smooth area
+const SMOOTH_SDR: f32 = 0.02;
+/// Largest difference of neighbouring Gain Map samples still part of a smooth gradient, rather than an edge
+const SMOOTH_CODES: u8 = 4;
+
+/// Largest HDR luminance step found between neighbouring Gain Map samples of smooth areas
+pub struct Banding {
+    /// Step in nits
+    pub max_step_nits: f32,
+    /// HDR luminance in nits on the bright side of that step
+    pub at_nits: f32,
+    /// Pairs of neighbouring samples measured, a few codes apart in smooth areas
+    pub steps: usize,
+}
+
+/// Reconstruct HDR luminance one code apart wherever neighbouring samples of the 8-bit Gain Map `recoveries` form a gradient and the gamma-encoded RGB SDR image `image` is smooth. Both codes use the same SDR luminance, so only the Gain Map quantization is measured
+pub fn analyze(
+    recoveries: &[u8],
+    image: PlanarView,
+    encoding: &RecoveryEncoding,
+    parameters: &PixelParameters,
+    sdr_white_nits: f32,
+) -> Banding {
+    let (width, height) = (image.width, image.height);
+    let (map_width, map_height) = gain_map_size(width, height, encoding.scale);
+    let stops = encoding.max_log2 - encoding.min_log2;
+    let boost = |code: u8| {
+        let recovery = encoding.curve.decode(code as f32 / 255.0, stops);
+        (encoding.min_log2 + stops * recovery).exp2()
+    };
+    // SDR luminance at the top left pixel of a Gain Map sample
+    let sdr = |x: usize, y: usize| {
+        let (x, y) = (
+            (x * encoding.scale).min(width - 1),
+            (y * encoding.scale).min(height - 1),
+        );
+        let [r, g, b] = image
+            .pixel(y * width + x)
+            .map(|v| parameters.transfer.decode(v as f32 / 255.0));
+        parameters.coefficients.luminance(&Pixel { r, g, b })
+    };
+    let hdr = |sdr: f32, code: u8| {
+        ((sdr + parameters.offset_sdr) * boost(code) - parameters.offset_hdr) * sdr_white_nits
+    };
+
+    let mut banding = Banding {
+        max_step_nits: 0.0,
+        at_nits: 0.0,
+        steps: 0,
+    };
+    for y in 0..map_height {
+        for x in 0..map_width {
+            let here = recoveries[y * map_width + x];
+            let neighbours = [(x + 1, y), (x, y + 1)]
+                .into_iter()
+                .filter(|&(nx, ny)| nx < map_width && ny < map_height);
+            for (nx, ny) in neighbours {
+                let there = recoveries[ny * map_width + nx];
+                if !(1..=SMOOTH_CODES).contains(&here.abs_diff(there)) {
+                    continue;
+                }
+                let (a, b) = (sdr(x, y), sdr(nx, ny));
+                if (a - b).abs() > SMOOTH_SDR * a.max(b) {
+                    continue;
+                }
+                let mean = (a + b) / 2.0;
+                let code = here.max(there);
+                let (low, high) = (hdr(mean, code - 1), hdr(mean, code));
+                banding.steps += 1;
+                if high - low > banding.max_step_nits {
+                    banding.max_step_nits = high - low;
+                    banding.at_nits = high;
+                }
+            }
+        }
+    }
+    banding
+}
+
+impl Banding {
+    /// Log the step, warning when it is above `threshold_nits`
+    pub fn report(&self, threshold_nits: f32) {
+        if self.max_step_nits > threshold_nits {
+            warn!(
+                max_step_nits = self.max_step_nits,
+                at_nits = self.at_nits,
+                steps = self.steps,
+                threshold_nits,
+                "Gain Map quantization steps may show as banding"
+            );
+        } else {
+            info!(
+                max_step_nits = self.max_step_nits,
+                at_nits = self.at_nits,
+                steps = self.steps,
+                "Gain Map banding"
+            );
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{mpf, ultra_hdr_stuff::XMP_NAMESPACE};
+
+const APP0_MARKER: u8 = 0xE0;
+const APP1_MARKER: u8 = 0xE1;
+const APP2_MARKER: u8 = 0xE2;
+const SOS_MARKER: u8 = 0xDA;
+/// Start Of Frame markers, holding image dimensions. C4, C8 and CC are other markers
+const SOF_MARKERS: [u8; 13] = [
+    0xC0, 0xC1, 0xC2, 0xC3, 0xC5, 0xC6, 0xC7, 0xC9, 0xCA, 0xCB, 0xCD, 0xCE, 0xCF,
+];
+
+/// An already encoded JPEG, reused as the primary image without re-encoding
+pub struct BaseJpeg {
+    data: Vec<u8>,
+    /// Marker and range (marker included) of every segment before image data
+    segments: Vec<(u8, usize, usize)>,
+    /// Where image data starts, from the SOS marker on
+    scan_start: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl BaseJpeg {
+    pub fn read(path: &Path) -> Result<BaseJpeg, String> {
+        let data =
+            fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        BaseJpeg::parse(data, &path.display().to_string())
+    }
+
+    /// Parse a JPEG held in memory, `name` identifying it in errors
+    pub fn parse(mut data: Vec<u8>, name: &str) -> Result<BaseJpeg, String> {
+        let invalid = || format!("{} is not a valid JPEG file", name);
+        if !data.starts_with(&[0xFF, 0xD8]) {
+            return Err(invalid());
+        }
+        // Only keep the primary image of multi-picture files, such as existing Ultra HDR images
+        if let Some(primary) = mpf::images(&data)
+            .map_err(|e| format!("{}: {}", name, e))?
+            .and_then(|images| images.into_iter().next())
+        {
+            data.truncate(primary.end);
+        }
+
+        let mut segments = Vec::new();
+        let mut dimensions = None;
+        let mut position = 2;
+        let scan_start = loop {
+            // Skip fill bytes
+            while data.get(position + 1) == Some(&0xFF) {
+                position += 1;
+            }
+            let marker = *data.get(position + 1).ok_or_else(invalid)?;
+            if marker == SOS_MARKER {
+                break position;
+            }
+            let length = u16::from_be_bytes(
+                data.get(position + 2..position + 4)
+                    .ok_or_else(invalid)?
+                    .try_into()
+                    .unwrap(),
+            ) as usize;
+            let end = position + 2 + length;
+            let payload = data.get(position + 4..end).ok_or_else(invalid)?;
+            if SOF_MARKERS.contains(&marker) && payload.len() >= 5 {
+                dimensions = Some((
+                    u16::from_be_bytes([payload[3], payload[4]]) as usize,
+                    u16::from_be_bytes([payload[1], payload[2]]) as usize,
+                ));
+            }
+            segments.push((marker, position, end));
+            position = end;
+        };
+        let (width, height) = dimensions.ok_or_else(invalid)?;
+
+        Ok(BaseJpeg {
+            data,
+            segments,
+            scan_start,
+            width,
+            height,
+        })
+    }
+
+    /// Every APPn segment before image data, as (n, payload)
+    pub fn app_segments(&self) -> impl Iterator<Item = (u8, &[u8])> {
+        self.segments
+            .iter()
+            .filter(|(marker, _, _)| (APP0_MARKER..=APP0_MARKER + 15).contains(marker))
+            .map(|(marker, start, end)| (marker - APP0_MARKER, &self.data[start + 4..*end]))
+    }
+
+    /// Change APPn segment payloads in place, given as (n, payload). Their length cannot change
+    pub fn edit_app_segments(&mut self, mut edit: impl FnMut(u8, &mut [u8])) {
+        for (marker, start, end) in &self.segments {
+            if (APP0_MARKER..=APP0_MARKER + 15).contains(marker) {
+                edit(marker - APP0_MARKER, &mut self.data[start + 4..*end]);
+            }
+        }
+    }
+
+    /// Drop the APPn segments for which `keep` returns false, given (n, payload)
+    pub fn retain_app_segments(&mut self, mut keep: impl FnMut(u8, &[u8]) -> bool) {
+        let data = &self.data;
+        self.segments.retain(|(marker, start, end)| {
+            !(APP0_MARKER..=APP0_MARKER + 15).contains(marker)
+                || keep(marker - APP0_MARKER, &data[start + 4..*end])
+        });
+    }
+
+    /// Write the JPEG with extra APPn segments (n, payload) placed after its leading APP0 / APP1 segments. Existing XMP and MPF segments are dropped as they would conflict, everything else is copied byte for byte
+    pub fn write_with_segments(
+        &self,
+        writer: &mut dyn Write,
+        extra: &[(u8, &[u8])],
+    ) -> io::Result<()> {
+        let kept: Vec<&(u8, usize, usize)> = self
+            .segments
+            .iter()
+            .filter(|(marker, start, end)| {
+                let payload = &self.data[start + 4..*end];
+                !(*marker == APP1_MARKER && payload.starts_with(XMP_NAMESPACE)
+                    || *marker == APP2_MARKER && mpf::is_index(payload))
+            })
+            .collect();
+        let leading = kept
+            .iter()
+            .take_while(|(marker, _, _)| *marker == APP0_MARKER || *marker == APP1_MARKER)
+            .count();
+
+        writer.write_all(&[0xFF, 0xD8])?;
+        for (_, start, end) in &kept[..leading] {
+            writer.write_all(&self.data[*start..*end])?;
+        }
+        for (n, payload) in extra {
+            writer.write_all(&[0xFF, APP0_MARKER + n])?;
+            writer.write_all(&(payload.len() as u16 + 2).to_be_bytes())?;
+            writer.write_all(payload)?;
+        }
+        for (_, start, end) in &kept[leading..] {
+            writer.write_all(&self.data[*start..*end])?;
+        }
+        writer.write_all(&self.data[self.scan_start..])
+    }
+}
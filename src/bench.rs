@@ -0,0 +1,210 @@
+// Throughput of pipeline stages on this machine, to pick thread and encoder settings
+
+use std::{
+    f32::consts::TAU,
+    io::Cursor,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use exr::prelude::{f16, Encoding, Image, Layer, LayerAttributes, SpecificChannels, WritableImage};
+use tracing::info;
+
+use crate::{
+    color_spaces::{REC_2020, REC_709},
+    color_stuff::Pixel,
+    decode::decode_exr,
+    gain::GainBasis,
+    jpeg_bands::{self, EncoderMode, JpegSettings},
+    lut::{parse_lut_size, BakedLut},
+    process_cpu,
+    projection::parse_size,
+    transfer_functions::Transfer,
+    trims::SdrTrims,
+    PixelParameters, JPEG_QUALITY, OFFSET_HDR, OFFSET_SDR,
+};
+
+/// Time pipeline stages on a synthetic image at several resolutions and thread counts, and print their throughput. Does not read or write any file
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Image sizes to measure, as WIDTHxHEIGHT
+    #[arg(long, value_delimiter = ',', value_parser = parse_size, default_value = "1920x1080,3840x2160")]
+    sizes: Vec<(usize, usize)>,
+    /// Thread counts to measure. Defaults to powers of two up to the number of cores, and the number of cores
+    #[arg(long, value_delimiter = ',')]
+    threads: Vec<usize>,
+    /// Runs of every measurement, the fastest one is kept
+    #[arg(long, default_value_t = 3)]
+    iterations: usize,
+    /// Process with the SDR rendition baked into a 3D LUT of this many grid points per axis, as --bake-lut does
+    #[arg(long, value_parser = parse_lut_size)]
+    bake_lut: Option<usize>,
+}
+
+/// Part of a conversion that is timed, and the setting it helps picking
+#[derive(Debug, Copy, Clone)]
+enum Stage {
+    /// Half float ZIP EXR decompression, --decode-threads
+    Decode,
+    /// Color conversion, SDR rendition and gains, on CPU
+    Process,
+    /// SDR JPEG encoding, --encoder fast (a single thread is --encoder quality)
+    Jpeg,
+}
+
+impl Stage {
+    fn name(&self) -> &'static str {
+        match self {
+            Stage::Decode => "decode",
+            Stage::Process => "process",
+            Stage::Jpeg => "jpeg",
+        }
+    }
+}
+
+pub fn run(args: &BenchArgs) -> Result<(), String> {
+    let cores = thread::available_parallelism().map_or(1, |n| n.get());
+    let thread_counts = if args.threads.is_empty() {
+        let mut counts: Vec<usize> = (0..).map(|p| 1 << p).take_while(|&n| n < cores).collect();
+        counts.push(cores);
+        counts
+    } else {
+        args.threads.clone()
+    };
+    if thread_counts.contains(&0) {
+        return Err("Thread counts must be positive".to_string());
+    }
+    let iterations = args.iterations.max(1);
+
+    println!(
+        "{:<8} {:>11} {:>7} {:>9}",
+        "stage", "size", "threads", "MPix/s"
+    );
+    for &(width, height) in &args.sizes {
+        info!(width, height, "Preparing synthetic image");
+        let pixels = synthetic(width, height);
+        let exr = encode_exr(&pixels, width, height)?;
+        let mut parameters = PixelParameters {
+            conversion_matrix: REC_709.rgb_space_conversion_matrix(&REC_2020),
+            conversion_matrix_f64: REC_709.rgb_space_conversion_matrix_f64(&REC_2020),
+            factor: 1.0,
+            transfer: Transfer::Gamma24,
+            coefficients: REC_2020.luminance_values().unwrap(),
+            coefficients_f64: REC_2020.luminance_values_f64().unwrap(),
+            offset_hdr: OFFSET_HDR,
+            offset_sdr: OFFSET_SDR,
+            gain_basis: GainBasis::Luminance,
+            trims: SdrTrims {
+                down_conversion: None,
+                saturation: 1.0,
+                contrast: 1.0,
+                pivot: 0.18,
+                knee_start: 1.0,
+                knee_strength: 0.0,
+            },
+            lut: None,
+        };
+        parameters.lut = args.bake_lut.map(|size| BakedLut::bake(size, &parameters));
+        let (image, _, _) = process_cpu(&mut pixels.clone(), &parameters, 0);
+        let jpeg_settings = JpegSettings {
+            mode: EncoderMode::Fast,
+            quality: JPEG_QUALITY,
+            progressive: false,
+        };
+
+        for stage in [Stage::Decode, Stage::Process, Stage::Jpeg] {
+            for &threads in &thread_counts {
+                let mut fastest = Duration::MAX;
+                for _ in 0..iterations {
+                    // Processing converts pixels in place
+                    let mut linear_light = pixels.clone();
+                    let start = Instant::now();
+                    match stage {
+                        Stage::Decode => {
+                            decode_exr(
+                                Cursor::new(&exr),
+                                Path::new("synthetic.exr"),
+                                threads,
+                                None,
+                            )?;
+                        }
+                        Stage::Process => {
+                            process_cpu(&mut linear_light, &parameters, threads);
+                        }
+                        Stage::Jpeg => {
+                            jpeg_bands::encode_bands(
+                                &mut Vec::new(),
+                                jpeg_settings,
+                                threads,
+                                image.view(width, height),
+                                |_| Ok(()),
+                            )
+                            .map_err(|e| format!("Could not encode JPEG: {}", e))?;
+                        }
+                    }
+                    fastest = fastest.min(start.elapsed());
+                }
+                println!(
+                    "{:<8} {:>11} {:>7} {:>9.1}",
+                    stage.name(),
+                    format!("{}x{}", width, height),
+                    threads,
+                    (width * height) as f64 / 1e6 / fastest.as_secs_f64()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Exposure sweep from -8 to +4 stops left to right, hue changing top to bottom, with grain so compression has work to do
+fn synthetic(width: usize, height: usize) -> Vec<Pixel> {
+    (0..height)
+        .flat_map(|y| {
+            (0..width).map(move |x| {
+                let grain = hash(x as u32, y as u32) as f32 / u32::MAX as f32 - 0.5;
+                let level = (12.0 * x as f32 / width as f32 - 8.0 + 0.1 * grain).exp2();
+                let hue = TAU * y as f32 / height as f32;
+                let channel = |phase: f32| level * (1.0 + 0.5 * (hue + phase).cos());
+                Pixel {
+                    r: channel(0.0),
+                    g: channel(TAU / 3.0),
+                    b: channel(2.0 * TAU / 3.0),
+                }
+            })
+        })
+        .collect()
+}
+
+fn hash(x: u32, y: u32) -> u32 {
+    let mut h = x.wrapping_mul(0x9E3779B1) ^ y.wrapping_mul(0x85EBCA77);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B3C6D);
+    h ^ (h >> 12)
+}
+
+/// Half float RGB with ZIP compression, as renderers usually write
+fn encode_exr(pixels: &[Pixel], width: usize, height: usize) -> Result<Vec<u8>, String> {
+    let channels = SpecificChannels::rgb(|position: exr::math::Vec2<usize>| {
+        let pixel = pixels[position.y() * width + position.x()];
+        (
+            f16::from_f32(pixel.r),
+            f16::from_f32(pixel.g),
+            f16::from_f32(pixel.b),
+        )
+    });
+    let image = Image::from_layer(Layer::new(
+        (width, height),
+        LayerAttributes::default(),
+        Encoding::SMALL_LOSSLESS,
+        channels,
+    ));
+    let mut bytes = Cursor::new(Vec::new());
+    image
+        .write()
+        .to_buffered(&mut bytes)
+        .map_err(|e| format!("Could not encode synthetic EXR: {}", e))?;
+    Ok(bytes.into_inner())
+}
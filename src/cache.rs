@@ -0,0 +1,192 @@
+// Conversions cached by content, so re-running a batch only converts inputs or settings that changed
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{ArgMatches, Args, CommandFactory};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    sequence::SequenceStats,
+    sha256::{hash_file, Sha256},
+    App, Outputs,
+};
+
+/// Options left out of the settings hash, as they do not change outputs
+const IGNORED: [&str; 9] = [
+    "exr",
+    "manifest",
+    "cache_dir",
+    "force",
+    "log_format",
+    "log_level",
+    "log_file",
+    "continue_on_error",
+    "jobs",
+];
+
+/// Directory of cached conversions: `entries` holds what each conversion wrote, `objects` the files themselves, named after their SHA-256 so identical outputs are stored once
+pub struct Cache {
+    directory: PathBuf,
+    /// SHA-256 of tool version, effective settings and files they refer to
+    settings: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    stats: SequenceStats,
+    outputs: Outputs,
+    files: Vec<CachedFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedFile {
+    path: PathBuf,
+    sha256: String,
+}
+
+/// Options naming files the conversion writes or watches rather than reads as references
+const NOT_REFERENCES: [&str; 4] = ["exr", "manifest", "range_to", "log_file"];
+
+/// Whether values of option `id` that name existing files are read by conversions, such as --base-jpeg or --sdr-exr
+fn is_reference(id: &str) -> bool {
+    let outputs = Outputs::augment_args(clap::Command::new(""));
+    !NOT_REFERENCES.contains(&id) && !outputs.get_arguments().any(|a| a.get_id() == id)
+}
+
+/// Every existing file given as the value of a non-output option, in the order of options
+pub fn references(matches: &ArgMatches) -> Vec<PathBuf> {
+    App::command()
+        .get_arguments()
+        .map(|a| a.get_id().as_str())
+        .filter(|id| is_reference(id))
+        .filter_map(|id| matches.get_raw(id))
+        .flatten()
+        .map(PathBuf::from)
+        .filter(|path| path.is_file())
+        .collect()
+}
+
+impl Cache {
+    /// Hash every effective setting, including contents of files given as values such as --base-jpeg. Output paths are settings too, but not their contents
+    pub fn new(directory: &Path, matches: &ArgMatches) -> Result<Cache, String> {
+        let mut hasher = Sha256::default();
+        hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+        for argument in App::command().get_arguments() {
+            let id = argument.get_id().as_str();
+            if IGNORED.contains(&id) {
+                continue;
+            }
+            let Some(values) = matches.get_raw(id) else {
+                continue;
+            };
+            hasher.update(format!("\0{}", id).as_bytes());
+            for value in values {
+                hasher.update(format!("\0{}", value.to_string_lossy()).as_bytes());
+                let path = Path::new(value);
+                if is_reference(id) && path.is_file() {
+                    let sha256 = hash_file(path)
+                        .map_err(|e| format!("Could not hash {}: {}", path.display(), e))?;
+                    hasher.update(sha256.as_bytes());
+                }
+            }
+        }
+        Ok(Cache {
+            directory: directory.to_path_buf(),
+            settings: hasher.finish_hex(),
+        })
+    }
+
+    /// Key of the conversion of `input` with these settings and locked range
+    pub fn key(&self, input: &Path, locked: Option<&SequenceStats>) -> Result<String, String> {
+        let mut hasher = Sha256::default();
+        hasher.update(self.settings.as_bytes());
+        hasher.update(
+            hash_file(input)
+                .map_err(|e| format!("Could not hash {}: {}", input.display(), e))?
+                .as_bytes(),
+        );
+        if let Some(locked) = locked {
+            hasher.update(serde_json::to_string(locked).unwrap().as_bytes());
+        }
+        Ok(hasher.finish_hex())
+    }
+
+    /// Outputs of a cached conversion, copied back where missing or changed since. None if it was never cached or the cache lost some of its files
+    pub fn restore(&self, key: &str) -> Option<(SequenceStats, Outputs)> {
+        let text = fs::read_to_string(self.entry_path(key)).ok()?;
+        let entry: Entry = serde_json::from_str(&text).ok()?;
+        for file in &entry.files {
+            if hash_file(&file.path).is_ok_and(|sha256| sha256 == file.sha256) {
+                continue;
+            }
+            let object = self.object_path(&file.sha256);
+            if !object.is_file() {
+                warn!(path = %file.path.display(), "Cached output is missing, converting again");
+                return None;
+            }
+            let copied = file
+                .path
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::copy(&object, &file.path));
+            if let Err(e) = copied {
+                warn!(path = %file.path.display(), error = %e, "Could not restore cached output, converting again");
+                return None;
+            }
+        }
+        Some((entry.stats, entry.outputs))
+    }
+
+    /// Keep the files `outputs` were written to under `key`
+    pub fn store(
+        &self,
+        key: &str,
+        stats: SequenceStats,
+        outputs: &Outputs,
+        args: &App,
+    ) -> Result<(), String> {
+        let error =
+            |path: &Path, e: std::io::Error| format!("Could not cache {}: {}", path.display(), e);
+        let objects = self.directory.join("objects");
+        fs::create_dir_all(&objects).map_err(|e| error(&objects, e))?;
+        let mut files = Vec::new();
+        for path in outputs.paths(args) {
+            // Listed outputs some settings leave unwritten
+            if !path.is_file() {
+                continue;
+            }
+            let sha256 = hash_file(&path).map_err(|e| error(&path, e))?;
+            let object = self.object_path(&sha256);
+            if !object.is_file() {
+                fs::copy(&path, &object).map_err(|e| error(&path, e))?;
+            }
+            files.push(CachedFile { path, sha256 });
+        }
+
+        let entry_path = self.entry_path(key);
+        if let Some(parent) = entry_path.parent() {
+            fs::create_dir_all(parent).map_err(|e| error(parent, e))?;
+        }
+        let entry = Entry {
+            stats,
+            outputs: outputs.clone(),
+            files,
+        };
+        fs::write(&entry_path, serde_json::to_string_pretty(&entry).unwrap())
+            .map_err(|e| error(&entry_path, e))?;
+        info!(key, "Cached outputs");
+        Ok(())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.directory.join("entries").join(format!("{}.json", key))
+    }
+
+    fn object_path(&self, sha256: &str) -> PathBuf {
+        self.directory.join("objects").join(sha256)
+    }
+}
@@ -0,0 +1,90 @@
+use clap::ValueEnum;
+
+use crate::color_spaces::{ARRI_WIDE_GAMUT_4, CINEMA_GAMUT, S_GAMUT3_CINE, V_GAMUT};
+use crate::color_stuff::Chromaticities;
+
+/// Log curves found in camera footage, decoded back to scene-referred linear light
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum CameraLog {
+    /// Sony S-Log3, native gamut S-Gamut3.Cine
+    SLog3,
+    /// Panasonic V-Log, native gamut V-Gamut
+    VLog,
+    /// Canon Log 3, native gamut Cinema Gamut
+    CanonLog3,
+    /// ARRI LogC4, native gamut ARRI Wide Gamut 4
+    ArriLogC4,
+}
+
+impl CameraLog {
+    /// Gamut this curve is normally paired with in-camera
+    pub fn native_gamut(&self) -> Chromaticities {
+        match self {
+            CameraLog::SLog3 => S_GAMUT3_CINE,
+            CameraLog::VLog => V_GAMUT,
+            CameraLog::CanonLog3 => CINEMA_GAMUT,
+            CameraLog::ArriLogC4 => ARRI_WIDE_GAMUT_4,
+        }
+    }
+
+    /// Go from a full-range normalized code value to scene-referred linear light
+    pub fn decode(&self, value: f32) -> f32 {
+        match self {
+            CameraLog::SLog3 => s_log3_decode(value),
+            CameraLog::VLog => v_log_decode(value),
+            CameraLog::CanonLog3 => canon_log3_decode(value),
+            CameraLog::ArriLogC4 => arri_log_c4_decode(value),
+        }
+    }
+}
+
+// https://pro.sony/s3/cms-static-content/uploadfile/06/1237494271406.pdf
+pub fn s_log3_decode(value: f32) -> f32 {
+    let code = value * 1023.0;
+    if code >= 171.210_3 {
+        10.0f32.powf((code - 420.0) / 261.5) * (0.18 + 0.01) - 0.01
+    } else {
+        (code - 95.0) * 0.01125 / (171.210_3 - 95.0)
+    }
+}
+
+// https://pro-av.panasonic.net/en/cinema_camera_varicam_eva/support/pdf/VARICAM_V-Log_V-Gamut.pdf
+pub fn v_log_decode(value: f32) -> f32 {
+    const B: f32 = 0.00873;
+    const C: f32 = 0.241514;
+    const D: f32 = 0.598206;
+
+    if value < 0.181 {
+        (value - 0.125) / 5.6
+    } else {
+        10.0f32.powf((value - D) / C) - B
+    }
+}
+
+// Canon Log 3 v1.2, as found in Canon's ACES IDTs. Result is scaled by 0.9 to go from reflectance to scene linear
+pub fn canon_log3_decode(value: f32) -> f32 {
+    let reflectance = if value < 0.097_465_47 {
+        -(10.0f32.powf((0.127_839_01 - value) / 0.367_268_45) - 1.0) / 14.98325
+    } else if value <= 0.152_778_91 {
+        (value - 0.125_122_19) / 1.975_479_8
+    } else {
+        (10.0f32.powf((value - 0.122_405_37) / 0.367_268_45) - 1.0) / 14.98325
+    };
+
+    reflectance * 0.9
+}
+
+// https://www.arri.com/resource/blob/278790/bea879ac0d041a925bed27a096ab3ec2/2022-05-arri-logc4-specification-data.pdf
+pub fn arri_log_c4_decode(value: f32) -> f32 {
+    let a = (2.0f32.powi(18) - 16.0) / 117.45;
+    let b = (1023.0 - 95.0) / 1023.0;
+    let c = 95.0 / 1023.0;
+    let s = (7.0 * 2.0f32.ln() * 2.0f32.powf(7.0 - 14.0 * c / b)) / (a * b);
+    let t = (2.0f32.powf(14.0 * (-c / b) + 6.0) - 64.0) / a;
+
+    if value >= 0.0 {
+        (2.0f32.powf(14.0 * (value - c) / b + 6.0) - 64.0) / a
+    } else {
+        value * s + t
+    }
+}
@@ -0,0 +1,159 @@
+use clap::ValueEnum;
+use exr::{image::FlatSamples, math::Vec2};
+
+use crate::{
+    color_stuff::{LuminanceCoefficients, Pixel},
+    precision::StoredPixel,
+};
+
+/// How to interpret integer samples found in color channels
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum ChannelType {
+    /// Integer value as-is, 1 is SDR white
+    Numeric,
+    /// Full u32 range maps to 0.0 - 1.0
+    Normalized,
+    /// Samples are the bits of 32-bit floats
+    FloatBits,
+}
+
+/// Read samples of a color channel as f32 without copying the channel. f16 and f32 are read natively, integer samples are refused unless an interpretation is forced
+pub fn color_samples<'a>(
+    name: &str,
+    samples: &'a FlatSamples,
+    force: Option<ChannelType>,
+) -> Result<Box<dyn Iterator<Item = f32> + 'a>, String> {
+    Ok(match samples {
+        FlatSamples::F16(values) => Box::new(values.iter().map(|v| v.to_f32())),
+        FlatSamples::F32(values) => Box::new(values.iter().copied()),
+        FlatSamples::U32(values) => match force {
+            None => {
+                return Err(format!(
+                    "Channel {} holds u32 integer samples, which are usually IDs and not color. Use --force-channel-type to interpret them anyway",
+                    name
+                ))
+            }
+            Some(ChannelType::Numeric) => Box::new(values.iter().map(|v| *v as f32)),
+            Some(ChannelType::Normalized) => {
+                Box::new(values.iter().map(|v| (*v as f64 / u32::MAX as f64) as f32))
+            }
+            Some(ChannelType::FloatBits) => Box::new(values.iter().map(|v| f32::from_bits(*v))),
+        },
+    })
+}
+
+/// Samples of a luminance / chroma image: Y, and optionally RY and BY which are usually subsampled
+#[derive(Default)]
+pub struct LuminanceChroma {
+    y: Option<Vec<f32>>,
+    ry: Option<(Vec<f32>, Vec2<usize>)>,
+    by: Option<(Vec<f32>, Vec2<usize>)>,
+}
+
+impl LuminanceChroma {
+    /// Keep samples of a Y, RY or BY channel. Returns false for any other channel
+    pub fn insert(&mut self, name: &str, samples: Vec<f32>, sampling: Vec2<usize>) -> bool {
+        match name {
+            "Y" => self.y = Some(samples),
+            "RY" => self.ry = Some((samples, sampling)),
+            "BY" => self.by = Some((samples, sampling)),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Reconstruct RGB as described by the OpenEXR spec. Without chroma, the image is gray. Chroma is upsampled by repeating samples
+    pub fn into_rgb<P: StoredPixel>(
+        self,
+        width: usize,
+        height: usize,
+        coefficients: &LuminanceCoefficients,
+    ) -> Result<Option<Vec<P>>, String> {
+        let Some(y) = self.y else {
+            return Ok(None);
+        };
+        let (Some(ry), Some(by)) = (self.ry, self.by) else {
+            return Ok(Some(
+                y.into_iter()
+                    .map(|v| P::store(Pixel { r: v, g: v, b: v }))
+                    .collect(),
+            ));
+        };
+
+        let ry = upsample(ry, width, height).ok_or("RY channel does not match image size")?;
+        let by = upsample(by, width, height).ok_or("BY channel does not match image size")?;
+        Ok(Some(
+            y.into_iter()
+                .zip(ry.into_iter().zip(by))
+                .map(|(y, (ry, by))| {
+                    let r = (ry + 1.0) * y;
+                    let b = (by + 1.0) * y;
+                    let g = (y - r * coefficients.red - b * coefficients.blue) / coefficients.green;
+                    P::store(Pixel { r, g, b })
+                })
+                .collect(),
+        ))
+    }
+}
+
+/// Half-band filter of the OpenEXR reference implementation for chroma subsampled by 2. Weights of the samples 1, 3, 5... pixels away
+const HALF_BAND: [f32; 7] = [
+    0.627123, -0.186077, 0.087929, -0.043159, 0.019597, -0.007540, 0.002128,
+];
+
+/// Samples contributing to a pixel of a channel sampled every `sampling` pixels, with their weights. Linear interpolation for unusual sampling rates
+fn taps(position: usize, sampling: usize, count: usize) -> Vec<(usize, f32)> {
+    let clamp = |k: isize| k.clamp(0, count as isize - 1) as usize;
+    let (i, phase) = ((position / sampling) as isize, position % sampling);
+    if phase == 0 {
+        vec![(clamp(i), 1.0)]
+    } else if sampling == 2 {
+        (0..HALF_BAND.len() as isize)
+            .flat_map(|t| {
+                let weight = HALF_BAND[t as usize];
+                [(clamp(i - t), weight), (clamp(i + 1 + t), weight)]
+            })
+            .collect()
+    } else {
+        let f = phase as f32 / sampling as f32;
+        vec![(clamp(i), 1.0 - f), (clamp(i + 1), f)]
+    }
+}
+
+/// Full resolution copy of a possibly subsampled channel, filtering horizontally then vertically
+fn upsample(
+    (samples, sampling): (Vec<f32>, Vec2<usize>),
+    width: usize,
+    height: usize,
+) -> Option<Vec<f32>> {
+    // Some readers already give full resolution samples
+    if samples.len() == width * height {
+        return Some(samples);
+    }
+    let row_length = width.div_ceil(sampling.x());
+    let rows = height.div_ceil(sampling.y());
+    if row_length == 0 || rows == 0 || samples.len() < row_length * rows {
+        return None;
+    }
+
+    let filter = |taps: &[(usize, f32)], sample: &dyn Fn(usize) -> f32| -> f32 {
+        taps.iter().map(|(k, weight)| sample(*k) * weight).sum()
+    };
+    let columns: Vec<_> = (0..width)
+        .map(|x| taps(x, sampling.x(), row_length))
+        .collect();
+    let horizontal: Vec<f32> = samples
+        .chunks_exact(row_length)
+        .take(rows)
+        .flat_map(|row| columns.iter().map(|taps| filter(taps, &|k| row[k])))
+        .collect();
+    Some(
+        (0..height)
+            .flat_map(|y| {
+                let taps = taps(y, sampling.y(), rows);
+                let horizontal = &horizontal;
+                (0..width).map(move |x| filter(&taps, &|k| horizontal[k * width + x]))
+            })
+            .collect(),
+    )
+}
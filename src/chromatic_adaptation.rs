@@ -0,0 +1,33 @@
+use clap::ValueEnum;
+use ultra_hdr_core::{
+    adaptation::{self, BRADFORD, CAT02, VON_KRIES, XYZ_SCALING},
+    Matrix3x3d,
+};
+
+use crate::{color_stuff::CIExyCoords, Matrix3x3f};
+
+/// Chromatic adaptation transform, models how colors look the same under a different white
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum Cat {
+    Bradford,
+    Cat02,
+    VonKries,
+    XyzScaling,
+}
+
+impl Cat {
+    /// XYZ to cone response domain
+    fn cone_matrix(&self) -> &'static Matrix3x3d {
+        match self {
+            Cat::Bradford => &BRADFORD,
+            Cat::Cat02 => &CAT02,
+            Cat::VonKries => &VON_KRIES,
+            Cat::XyzScaling => &XYZ_SCALING,
+        }
+    }
+
+    /// Matrix adapting XYZ colors seen under source white to destination white
+    pub fn adaptation_matrix(&self, source: CIExyCoords, destination: CIExyCoords) -> Matrix3x3f {
+        adaptation::adaptation_matrix(self.cone_matrix(), source, destination).unwrap()
+    }
+}
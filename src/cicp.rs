@@ -0,0 +1,73 @@
+// https://www.itu.int/rec/T-REC-H.273
+
+use clap::ValueEnum;
+
+use crate::{color_spaces::ColorSpace, color_stuff::Chromaticities, transfer_functions::Transfer};
+
+/// Which color description outputs carry
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq)]
+pub enum ColorMetadata {
+    /// ICC profile in JPEGs, gAMA and cHRM chunks in PNGs
+    Icc,
+    /// CICP code points: cICP chunk in PNGs, ICC cicp tag in JPEGs as they have no standalone segment for it
+    Cicp,
+    /// Both of the above
+    Both,
+}
+
+impl ColorMetadata {
+    pub fn icc(&self) -> bool {
+        matches!(self, ColorMetadata::Icc | ColorMetadata::Both)
+    }
+
+    pub fn cicp(&self) -> bool {
+        matches!(self, ColorMetadata::Cicp | ColorMetadata::Both)
+    }
+}
+
+/// Coding-independent code points, describing a color encoding with a few numbers
+#[derive(Debug, Copy, Clone)]
+pub struct Cicp {
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub full_range: bool,
+}
+
+impl Cicp {
+    /// Code points for RGB output, None if primaries or transfer have no code point
+    pub fn for_output(chromaticities: &Chromaticities, transfer: Transfer) -> Option<Cicp> {
+        let colour_primaries = match ColorSpace::identify(chromaticities)? {
+            ColorSpace::Rec709 => 1,
+            ColorSpace::Rec2020 | ColorSpace::Rec2100 => 9,
+            ColorSpace::DisplayP3 => 12,
+            _ => return None,
+        };
+        let transfer_characteristics = match transfer {
+            // No code point for a pure 2.4 power curve
+            Transfer::Gamma24 => return None,
+            Transfer::Srgb => 13,
+            // BT.470 System M
+            Transfer::Gamma22 => 4,
+            // BT.709, which displays decode with BT.1886
+            Transfer::Bt1886 => 1,
+        };
+        Some(Cicp {
+            colour_primaries,
+            transfer_characteristics,
+            // Identity, pixels are RGB
+            matrix_coefficients: 0,
+            full_range: true,
+        })
+    }
+
+    /// Layout shared by PNG cICP chunk and ICC cicp tag contents
+    pub fn bytes(&self) -> [u8; 4] {
+        [
+            self.colour_primaries,
+            self.transfer_characteristics,
+            self.matrix_coefficients,
+            self.full_range as u8,
+        ]
+    }
+}
@@ -48,6 +48,10 @@ pub enum ColorSpace {
     AcesAp0,
     AcesAp1,
     DisplayP3,
+    SGamut3Cine,
+    VGamut,
+    CinemaGamut,
+    ArriWideGamut4,
 }
 
 impl ColorSpace {
@@ -59,8 +63,40 @@ impl ColorSpace {
             ColorSpace::AcesAp0 => ACES_AP0,
             ColorSpace::AcesAp1 => ACES_AP1,
             ColorSpace::DisplayP3 => DISPLAY_P3,
+            ColorSpace::SGamut3Cine => S_GAMUT3_CINE,
+            ColorSpace::VGamut => V_GAMUT,
+            ColorSpace::CinemaGamut => CINEMA_GAMUT,
+            ColorSpace::ArriWideGamut4 => ARRI_WIDE_GAMUT_4,
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSpace::Rec709 => "Rec. 709",
+            ColorSpace::Rec2020 => "Rec. 2020",
+            ColorSpace::Rec2100 => "Rec. 2100",
+            ColorSpace::AcesAp0 => "ACES AP0",
+            ColorSpace::AcesAp1 => "ACES AP1",
+            ColorSpace::DisplayP3 => "Display P3",
+            ColorSpace::SGamut3Cine => "S-Gamut3.Cine",
+            ColorSpace::VGamut => "V-Gamut",
+            ColorSpace::CinemaGamut => "Cinema Gamut",
+            ColorSpace::ArriWideGamut4 => "ARRI Wide Gamut 4",
+        }
+    }
+
+    /// Known color space with these chromaticities, if any
+    pub fn identify(chromaticities: &Chromaticities) -> Option<ColorSpace> {
+        let close =
+            |a: CIExyCoords, b: CIExyCoords| (a.x - b.x).abs() < 1e-4 && (a.y - b.y).abs() < 1e-4;
+        ColorSpace::value_variants().iter().copied().find(|space| {
+            let known = space.chromaticities();
+            close(known.red, chromaticities.red)
+                && close(known.green, chromaticities.green)
+                && close(known.blue, chromaticities.blue)
+                && close(known.white, chromaticities.white)
+        })
+    }
 }
 
 // https://www.itu.int/dms_pubrec/itu-r/rec/bt/R-REC-BT.709-6-201506-I!!PDF-E.pdf
@@ -111,3 +147,53 @@ pub const DISPLAY_P3: Chromaticities = Chromaticities {
     blue: CIExyCoords { x: 0.150, y: 0.060 },
     white: D65_ILLUMINANT,
 };
+
+// https://pro.sony/s3/cms-static-content/uploadfile/06/1237494271406.pdf
+pub const S_GAMUT3_CINE: Chromaticities = Chromaticities {
+    red: CIExyCoords { x: 0.766, y: 0.275 },
+    green: CIExyCoords { x: 0.225, y: 0.800 },
+    blue: CIExyCoords {
+        x: 0.089,
+        y: -0.087,
+    },
+    white: D65_ILLUMINANT,
+};
+
+// https://pro-av.panasonic.net/en/cinema_camera_varicam_eva/support/pdf/VARICAM_V-Log_V-Gamut.pdf
+pub const V_GAMUT: Chromaticities = Chromaticities {
+    red: CIExyCoords { x: 0.730, y: 0.280 },
+    green: CIExyCoords { x: 0.165, y: 0.840 },
+    blue: CIExyCoords {
+        x: 0.100,
+        y: -0.030,
+    },
+    white: D65_ILLUMINANT,
+};
+
+// Canon "Cinema Gamut" as used by Canon Log 2 / 3 ACES IDTs
+pub const CINEMA_GAMUT: Chromaticities = Chromaticities {
+    red: CIExyCoords { x: 0.740, y: 0.270 },
+    green: CIExyCoords { x: 0.170, y: 1.140 },
+    blue: CIExyCoords {
+        x: 0.080,
+        y: -0.100,
+    },
+    white: D65_ILLUMINANT,
+};
+
+// https://www.arri.com/resource/blob/278790/bea879ac0d041a925bed27a096ab3ec2/2022-05-arri-logc4-specification-data.pdf
+pub const ARRI_WIDE_GAMUT_4: Chromaticities = Chromaticities {
+    red: CIExyCoords {
+        x: 0.7347,
+        y: 0.2653,
+    },
+    green: CIExyCoords {
+        x: 0.1424,
+        y: 0.8576,
+    },
+    blue: CIExyCoords {
+        x: 0.0991,
+        y: -0.0308,
+    },
+    white: D65_ILLUMINANT,
+};
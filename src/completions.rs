@@ -0,0 +1,31 @@
+// Shell completions and man page, generated from the command line definition
+
+use std::io;
+
+use clap::Args;
+use clap_complete::Shell;
+
+use crate::environment;
+
+/// Print a completion script for a shell to stdout, such as `exr2ultra-hdr completions bash > /etc/bash_completion.d/exr2ultra-hdr`
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell the script is for
+    shell: Shell,
+}
+
+pub fn print_completions(args: &CompletionsArgs) {
+    clap_complete::generate(
+        args.shell,
+        &mut environment::command(),
+        env!("CARGO_PKG_NAME"),
+        &mut io::stdout(),
+    );
+}
+
+/// Print a roff man page of every flag and subcommand to stdout
+pub fn print_man_page() -> Result<(), String> {
+    clap_mangen::Man::new(environment::command())
+        .render(&mut io::stdout())
+        .map_err(|e| format!("Could not write man page: {}", e))
+}
@@ -0,0 +1,323 @@
+// Grid of SDR renditions of several frames, labeled with frame numbers, for shot reviews
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use clap::Args;
+use png::{Encoder as PNGEncoder, ScaledFloat};
+use tracing::{error, info, warn};
+
+use crate::{
+    color_stuff::{to_png_chromaticities, Chromaticities},
+    convert_image,
+    decode::prefetch,
+    frames::{expand, parse_frame_range, FrameRange},
+    jpeg_bands::{self, JpegSettings},
+    planar::PlanarRgb,
+    process_pixel,
+    resize::fit_within,
+    sdr_pixel,
+    sinks::{write_file, OutputMetadata, OutputSink, Planes},
+    transfer_functions::Transfer,
+    App, Outputs, JPEG_QUALITY,
+};
+
+/// Pixels between cells, and around the sheet
+const GAP: usize = 4;
+const BACKGROUND: u8 = 16;
+const LABEL_BACKGROUND: u8 = 40;
+const LABEL_FOREGROUND: u8 = 230;
+/// Size of a font pixel, in sheet pixels
+const FONT_SCALE: usize = 2;
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+/// Strip below every frame holding its number
+const LABEL_HEIGHT: usize = GLYPH_HEIGHT * FONT_SCALE + 2 * GAP;
+/// Digits 0 to 9, one row of 3 bits per line, most significant bit on the left
+const DIGITS: [[u8; GLYPH_HEIGHT]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b111],
+    [0b111, 0b001, 0b111, 0b100, 0b111],
+    [0b111, 0b001, 0b111, 0b001, 0b111],
+    [0b101, 0b101, 0b111, 0b001, 0b001],
+    [0b111, 0b100, 0b111, 0b001, 0b111],
+    [0b111, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b001, 0b001, 0b001],
+    [0b111, 0b101, 0b111, 0b101, 0b111],
+    [0b111, 0b101, 0b111, 0b001, 0b111],
+];
+
+/// Lay out the SDR renditions of several frames in a single grid image. Conversion flags go before the subcommand
+#[derive(Args)]
+pub struct ContactSheetArgs {
+    /// Where to write the sheet, JPEG or PNG depending on extension
+    sheet: PathBuf,
+    /// Frames to lay out, in order. With --frames, a single printf-style path such as render.%04d.exr
+    #[arg(required = true)]
+    exr: Vec<PathBuf>,
+    /// Lay out this range of frames, missing ones being skipped
+    #[arg(long, value_parser = parse_frame_range)]
+    frames: Option<FrameRange>,
+    /// Frames per row
+    #[arg(long, default_value_t = 4)]
+    columns: usize,
+    /// Longest side of every frame on the sheet, in pixels
+    #[arg(long, default_value_t = 320)]
+    cell_size: usize,
+}
+
+/// Downscaled SDR rendition of one frame, and how to interpret it
+pub struct Cell {
+    width: usize,
+    height: usize,
+    image: PlanarRgb,
+    transfer: Transfer,
+    chromaticities: Chromaticities,
+    icc_profile: Vec<u8>,
+}
+
+/// Where a conversion leaves its cell
+pub struct CellSlot {
+    /// Longest side of the cell
+    size: usize,
+    cell: Mutex<Option<Cell>>,
+}
+
+/// Fills a cell slot instead of writing a file
+pub struct ContactCellSink(pub Arc<CellSlot>);
+
+impl OutputSink for ContactCellSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        // Downscale from linear light
+        let coefficients = metadata.chromaticities.luminance_values().unwrap();
+        let (width, height) = fit_within(planes.width, planes.height, self.0.size);
+        let image = planes
+            .linear_light
+            .downscale_box(planes.width, planes.height, width, height)
+            .iter()
+            .map(|p| sdr_pixel(p, metadata.factor, &metadata.trims, &coefficients))
+            .map(|p| [p.r, p.g, p.b].map(|v| process_pixel(v, metadata.transfer)))
+            .collect();
+        *self.0.cell.lock().unwrap() = Some(Cell {
+            width,
+            height,
+            image,
+            transfer: metadata.transfer,
+            chromaticities: metadata.chromaticities,
+            icc_profile: metadata.icc_profile.to_vec(),
+        });
+        Ok(())
+    }
+}
+
+/// Convert every frame and write the sheet. Frames that fail are left out
+pub fn run(args: &App, sheet: &ContactSheetArgs) -> Result<(), String> {
+    let frames = sheet.frames()?;
+    if frames.is_empty() {
+        return Err("No frames to lay out".to_string());
+    }
+    if sheet.columns == 0 || sheet.cell_size == 0 {
+        return Err("Contact sheet columns and cell size must be positive".to_string());
+    }
+
+    let mut cells = Vec::with_capacity(frames.len());
+    let mut failed = 0;
+    thread::scope(|scope| {
+        let paths = frames.iter().map(|(path, _)| path.clone()).collect();
+        let images = prefetch(scope, args, paths);
+        for ((path, number), image) in frames.iter().zip(images) {
+            let slot = Arc::new(CellSlot {
+                size: sheet.cell_size,
+                cell: Mutex::new(None),
+            });
+            let outputs = Outputs {
+                contact_sheet_cell: Some(slot.clone()),
+                ..Default::default()
+            };
+            let converted =
+                image.and_then(|image| convert_image(args, path, image, &outputs, None));
+            let cell = slot.cell.lock().unwrap().take();
+            match (converted, cell) {
+                (Ok(_), Some(cell)) => cells.push((*number, cell)),
+                (Err(e), _) => {
+                    error!(file = %path.display(), error = e, "Failed to convert");
+                    failed += 1;
+                }
+                (Ok(_), None) => unreachable!("contact sheet sink did not run"),
+            }
+        }
+    });
+    if cells.is_empty() {
+        return Err("No frame could be converted".to_string());
+    }
+
+    write_sheet(&sheet.sheet, &cells, sheet.columns, args)?;
+    info!(frames = cells.len(), path = %sheet.sheet.display(), "Wrote contact sheet");
+    if failed > 0 {
+        Err(format!("{} of {} frames failed", failed, frames.len()))
+    } else {
+        Ok(())
+    }
+}
+
+impl ContactSheetArgs {
+    /// Input paths and their frame numbers
+    fn frames(&self) -> Result<Vec<(PathBuf, u32)>, String> {
+        let Some(range) = self.frames else {
+            return Ok(self
+                .exr
+                .iter()
+                .enumerate()
+                .map(|(index, path)| (path.clone(), frame_number(path).unwrap_or(index as u32 + 1)))
+                .collect());
+        };
+        let [pattern] = self.exr.as_slice() else {
+            return Err("Only one input path pattern can be used with --frames".to_string());
+        };
+        if expand(pattern, range.first).is_none() {
+            return Err(format!(
+                "{} has no frame number pattern such as %04d",
+                pattern.display()
+            ));
+        }
+        Ok((range.first..=range.last)
+            .filter_map(|frame| {
+                let path = expand(pattern, frame).unwrap();
+                if path.exists() {
+                    Some((path, frame))
+                } else {
+                    warn!(frame, file = %path.display(), "Missing frame, skipping");
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// Trailing digits of a file stem, as in render.1001.exr
+fn frame_number(path: &Path) -> Option<u32> {
+    let stem = path.file_stem()?.to_str()?;
+    let digits = stem.len() - stem.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    stem[stem.len() - digits..].parse().ok()
+}
+
+/// Assemble cells in a grid and encode it
+fn write_sheet(
+    path: &Path,
+    cells: &[(u32, Cell)],
+    columns: usize,
+    args: &App,
+) -> Result<(), String> {
+    let columns = columns.min(cells.len());
+    let rows = cells.len().div_ceil(columns);
+    let cell_width = cells.iter().map(|(_, c)| c.width).max().unwrap();
+    let cell_height = cells.iter().map(|(_, c)| c.height).max().unwrap();
+    let width = GAP + columns * (cell_width + GAP);
+    let height = GAP + rows * (cell_height + LABEL_HEIGHT + GAP);
+    let mut sheet = PlanarRgb {
+        planes: [(); 3].map(|_| vec![BACKGROUND; width * height]),
+    };
+    let mut fill = |x: usize, y: usize, value: [u8; 3]| {
+        for (plane, value) in sheet.planes.iter_mut().zip(value) {
+            plane[y * width + x] = value;
+        }
+    };
+
+    for (index, (number, cell)) in cells.iter().enumerate() {
+        let left = GAP + index % columns * (cell_width + GAP);
+        let top = GAP + index / columns * (cell_height + LABEL_HEIGHT + GAP);
+        // Centered in its cell
+        let (offset_x, offset_y) = (
+            (cell_width - cell.width) / 2,
+            (cell_height - cell.height) / 2,
+        );
+        for (index, pixel) in cell
+            .image
+            .view(cell.width, cell.height)
+            .pixels()
+            .enumerate()
+        {
+            fill(
+                left + offset_x + index % cell.width,
+                top + offset_y + index / cell.width,
+                pixel,
+            )
+        }
+
+        let label_top = top + cell_height;
+        for y in label_top..label_top + LABEL_HEIGHT {
+            for x in left..left + cell_width {
+                fill(x, y, [LABEL_BACKGROUND; 3])
+            }
+        }
+        let text = number.to_string();
+        let advance = (GLYPH_WIDTH + 1) * FONT_SCALE;
+        if text.len() * advance > cell_width {
+            continue;
+        }
+        let text_left = left + (cell_width - text.len() * advance) / 2;
+        for (i, digit) in text.bytes().enumerate() {
+            for (glyph_y, bits) in DIGITS[(digit - b'0') as usize].iter().enumerate() {
+                for glyph_x in 0..GLYPH_WIDTH {
+                    if bits >> (GLYPH_WIDTH - 1 - glyph_x) & 1 == 0 {
+                        continue;
+                    }
+                    for (dx, dy) in
+                        (0..FONT_SCALE).flat_map(|dx| (0..FONT_SCALE).map(move |dy| (dx, dy)))
+                    {
+                        fill(
+                            text_left + i * advance + glyph_x * FONT_SCALE + dx,
+                            label_top + GAP + glyph_y * FONT_SCALE + dy,
+                            [LABEL_FOREGROUND; 3],
+                        )
+                    }
+                }
+            }
+        }
+    }
+
+    // Every frame was converted with the same settings
+    let first = &cells[0].1;
+    let is_jpeg = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("jpg") || e.eq_ignore_ascii_case("jpeg"));
+    if is_jpeg {
+        write_file(path, |writer| {
+            jpeg_bands::encode(
+                writer,
+                JpegSettings {
+                    mode: args.encoder,
+                    quality: JPEG_QUALITY,
+                    progressive: args.progressive,
+                },
+                sheet.view(width, height),
+                |encoder| encoder.add_icc_profile(&first.icc_profile),
+            )
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+        })
+    } else {
+        let file =
+            File::create(path).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        let mut encoder = PNGEncoder::new(
+            BufWriter::new(file),
+            width.try_into().unwrap(),
+            height.try_into().unwrap(),
+        );
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_gamma(ScaledFloat::new(first.transfer.approximate_gamma().recip()));
+        encoder.set_source_chromaticities(to_png_chromaticities(first.chromaticities));
+        let mut writer = encoder.write_header().map_err(|e| e.to_string())?;
+        let mut stream = writer.stream_writer().map_err(|e| e.to_string())?;
+        for row in sheet.view(width, height).interleaved_rows() {
+            stream.write_all(&row).map_err(|e| e.to_string())?;
+        }
+        stream.finish().map_err(|e| e.to_string())
+    }
+}
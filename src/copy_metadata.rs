@@ -0,0 +1,168 @@
+// Metadata copied from a reference JPEG, like the camera photo an EXR was developed from
+
+use std::{fs, path::Path};
+
+use tracing::{info, warn};
+
+use crate::{
+    base_jpeg::BaseJpeg,
+    exif::{retarget, tiff_from_exif, NORMAL_ORIENTATION},
+    mpf,
+    ultra_hdr_stuff::XMP_NAMESPACE,
+    xmp::{self, GainMapXmp, CONTAINER_NAMESPACE, GAIN_MAP_NAMESPACE},
+};
+/// Largest APP1 payload
+const MAX_SEGMENT_PAYLOAD: usize = 65533;
+
+pub struct ReferenceMetadata {
+    /// TIFF-structured EXIF data, including GPS
+    pub exif: Option<Vec<u8>>,
+    /// XMP packet, without namespace header
+    pub xmp: Option<String>,
+}
+
+impl ReferenceMetadata {
+    /// Read EXIF and XMP of a JPEG. EXIF orientation is replaced by this one, as pixels come from the EXR
+    pub fn read(path: &Path, orientation: u16) -> Result<ReferenceMetadata, String> {
+        let name = path.display().to_string();
+        let data = fs::read(path).map_err(|e| format!("Could not read {}: {}", name, e))?;
+        let images = mpf::images(&data)
+            .map_err(|e| format!("{}: {}", name, e))?
+            .unwrap_or_default();
+        let jpeg = BaseJpeg::parse(data.clone(), &name)?;
+        let mut metadata = ReferenceMetadata {
+            exif: None,
+            xmp: None,
+        };
+        for (n, payload) in jpeg.app_segments().filter(|(n, _)| *n == 1) {
+            if let Some(tiff) = tiff_from_exif(payload) {
+                let mut tiff = tiff.to_vec();
+                match retarget(&mut tiff, orientation) {
+                    None => warn!(path = %name, "Ignoring malformed EXIF data"),
+                    Some(found) => {
+                        if !found && orientation != NORMAL_ORIENTATION {
+                            warn!("Reference EXIF has no orientation entry, output orientation is lost")
+                        }
+                        metadata.exif = Some(tiff)
+                    }
+                }
+            } else if let Some(packet) = payload.strip_prefix(XMP_NAMESPACE) {
+                let packet = String::from_utf8_lossy(packet);
+                let properties = match xmp::parse(&packet) {
+                    Ok(properties) => properties,
+                    Err(e) => {
+                        warn!(path = %name, error = e, "Ignoring malformed XMP data");
+                        continue;
+                    }
+                };
+                // Gain map descriptions would conflict with ours
+                if properties.iter().any(|p| {
+                    p.namespace == GAIN_MAP_NAMESPACE || p.namespace == CONTAINER_NAMESPACE
+                }) {
+                    info!(segment = n, "Not copying gain map XMP");
+                    log_container(&properties);
+                } else {
+                    metadata.xmp = Some(packet.into_owned())
+                }
+            }
+        }
+
+        // Gain map of an existing Ultra HDR image, which gets replaced
+        for image in images.iter().skip(1) {
+            let Ok(image) = BaseJpeg::parse(data[image.clone()].to_vec(), &name) else {
+                continue;
+            };
+            for (_, payload) in image.app_segments().filter(|(n, _)| *n == 1) {
+                let gain_map = payload
+                    .strip_prefix(XMP_NAMESPACE)
+                    .and_then(|packet| xmp::parse(&String::from_utf8_lossy(packet)).ok())
+                    .map(|properties| GainMapXmp::from_properties(&properties));
+                match gain_map {
+                    Some(Ok(Some(gain_map))) => info!(
+                        gain_map_min = ?gain_map.gain_map_min,
+                        gain_map_max = ?gain_map.gain_map_max,
+                        gamma = ?gain_map.gamma,
+                        offset_sdr = ?gain_map.offset_sdr,
+                        offset_hdr = ?gain_map.offset_hdr,
+                        hdr_capacity_min = gain_map.hdr_capacity_min,
+                        hdr_capacity_max = gain_map.hdr_capacity_max,
+                        base_rendition_is_hdr = gain_map.base_rendition_is_hdr,
+                        "Reference already has a gain map, it is not reused"
+                    ),
+                    Some(Err(e)) => warn!(error = e, "Reference has malformed gain map metadata"),
+                    _ => {}
+                }
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+/// Add the descriptions of a reference XMP packet to one of ours. Ours is returned unchanged if the result would not fit in a segment
+pub fn merge_xmp(ours: String, reference: &str) -> String {
+    let Some(descriptions) = rdf_contents(reference) else {
+        return ours;
+    };
+    let Some(end) = ours.rfind("</rdf:RDF>") else {
+        return ours;
+    };
+    let merged = format!("{}{}{}", &ours[..end], descriptions, &ours[end..]);
+    if merged.len() + XMP_NAMESPACE.len() > MAX_SEGMENT_PAYLOAD {
+        warn!("Reference XMP is too large to be merged");
+        return ours;
+    }
+    merged
+}
+
+/// A packet keeping only the descriptions that use none of `namespaces`, such as what is left of an Ultra HDR primary image XMP without its GContainer directory. None if no description is left
+pub fn without_descriptions(xmp: &str, namespaces: &[&str]) -> Option<String> {
+    let mut contents = rdf_contents(xmp)?;
+    let mut kept = String::new();
+    while let Some(start) = contents.find("<rdf:Description") {
+        let rest = &contents[start..];
+        let open_end = rest.find('>')? + 1;
+        let end = if rest[..open_end].ends_with("/>") {
+            open_end
+        } else {
+            rest.find("</rdf:Description>")? + "</rdf:Description>".len()
+        };
+        let description = &rest[..end];
+        if !namespaces.iter().any(|n| description.contains(n)) {
+            kept.push_str(description);
+        }
+        contents = &rest[end..];
+    }
+    if kept.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"><rdf:RDF xmlns:rdf=\"{}\">{}</rdf:RDF></x:xmpmeta>",
+        xmp::RDF_NAMESPACE,
+        kept
+    ))
+}
+
+/// Everything inside the rdf:RDF element
+fn rdf_contents(xmp: &str) -> Option<&str> {
+    let start = xmp.find("<rdf:RDF")?;
+    let start = start + xmp[start..].find('>')? + 1;
+    let end = xmp.rfind("</rdf:RDF>")?;
+    xmp.get(start..end)
+}
+
+/// Log the images listed in a GContainer directory
+fn log_container(properties: &[xmp::Property]) {
+    match xmp::container_items(properties) {
+        Ok(items) => {
+            for item in items {
+                info!(
+                    semantic = item.semantic,
+                    mime = item.mime,
+                    length = item.length,
+                    "Reference container image"
+                )
+            }
+        }
+        Err(e) => warn!(error = e, "Reference has a malformed container directory"),
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    io::{BufReader, Cursor, Read, Seek},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{sync_channel, Receiver},
+    thread::Scope,
+};
+
+use exr::{
+    block::{
+        reader::{ChunksReader, ParallelBlockDecompressor},
+        BlockIndex,
+    },
+    image::{
+        read::{
+            image::{LayersReader, ReadLayers},
+            layers::ReadChannels,
+            read,
+        },
+        AnyChannels, FlatSamples, Image, Layer,
+    },
+    meta::{header::Header, MetaData},
+};
+use rayon_core::ThreadPoolBuilder;
+
+use crate::{
+    input_format::InputFormat,
+    mmap::Mapping,
+    pfm_input, png_input, radiance_input,
+    retry::{self, RetryingReader},
+    subsampled, tiff_input, ultra_hdr_input, App,
+};
+
+/// First valid layer of an EXR file, or its selected part, with every channel and attribute
+pub type ExrImage = Image<Layer<AnyChannels<FlatSamples>>>;
+
+/// Part of a multi-part EXR file, by index from 0 or by name
+#[derive(Clone, Debug)]
+pub enum Part {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for Part {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Part, String> {
+        Ok(s.parse()
+            .map_or_else(|_| Part::Name(s.to_string()), Part::Index))
+    }
+}
+
+impl Part {
+    /// Index of this part among `headers`
+    pub fn index(&self, headers: &[Header]) -> Result<usize, String> {
+        match self {
+            Part::Index(index) if *index < headers.len() => Ok(*index),
+            Part::Index(index) => Err(format!(
+                "No part {} in a file of {} parts",
+                index,
+                headers.len()
+            )),
+            Part::Name(name) => headers
+                .iter()
+                .position(|h| part_name(h).as_deref() == Some(name.as_str()))
+                .ok_or_else(|| {
+                    let names: Vec<String> = headers.iter().filter_map(part_name).collect();
+                    format!(
+                        "No part named {}, parts are named {}",
+                        name,
+                        names.join(", ")
+                    )
+                }),
+        }
+    }
+}
+
+/// Name of a part, only mandatory in multi-part files
+pub fn part_name(header: &Header) -> Option<String> {
+    header
+        .own_attributes
+        .layer_name
+        .as_ref()
+        .map(|name| name.to_string())
+}
+
+/// Read an EXR file, decompressing blocks on `threads` threads. 0 means one per core, 1 decompresses on the calling thread. With `mmap`, the file is memory-mapped instead of read. `part` selects a part of multi-part files, the first valid one otherwise. Transient I/O errors of read files are retried up to `retries` times
+pub fn read_exr(
+    path: &Path,
+    threads: usize,
+    mmap: bool,
+    part: Option<&Part>,
+    retries: u32,
+) -> Result<ExrImage, String> {
+    if mmap {
+        let mapping = Mapping::open(path)?;
+        decode_exr(Cursor::new(&mapping[..]), path, threads, part)
+    } else {
+        let file = retry::open(path, retries)
+            .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        let reader = RetryingReader {
+            inner: file,
+            path,
+            retries,
+        };
+        decode_exr(BufReader::new(reader), path, threads, part)
+    }
+}
+
+/// Read only the blocks of an EXR file holding every `row_step`th row of its data window, other rows being left at zero. Subsampled files are read whole
+pub fn read_exr_rows(
+    path: &Path,
+    threads: usize,
+    part: Option<&Part>,
+    retries: u32,
+    row_step: usize,
+) -> Result<ExrImage, String> {
+    let file = retry::open(path, retries)
+        .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    let reader = RetryingReader {
+        inner: file,
+        path,
+        retries,
+    };
+    decode_exr_rows(BufReader::new(reader), path, threads, part, row_step)
+}
+
+/// Decode an EXR file from the start of `file`, `path` identifying it in errors
+pub fn decode_exr(
+    file: impl Read + Seek + Send,
+    path: &Path,
+    threads: usize,
+    part: Option<&Part>,
+) -> Result<ExrImage, String> {
+    decode_exr_rows(file, path, threads, part, 1)
+}
+
+fn decode_exr_rows(
+    mut file: impl Read + Seek + Send,
+    path: &Path,
+    threads: usize,
+    part: Option<&Part>,
+    row_step: usize,
+) -> Result<ExrImage, String> {
+    let error = |e: exr::error::Error| format!("Could not read {}: {}", path.display(), e);
+
+    let headers = MetaData::read_from_buffered(&mut file, false)
+        .map_err(error)?
+        .headers;
+    // Headers before the selected part are hidden from the layers reader, block indices shifted to match
+    let first = match part {
+        Some(part) => part
+            .index(&headers)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?,
+        None => 0,
+    };
+    let last = if part.is_some() {
+        first + 1
+    } else {
+        headers.len()
+    };
+
+    // The exr crate refuses subsampled channels
+    if subsampled::is_subsampled(&headers) {
+        return subsampled::read(file, headers.into_vec())
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e));
+    }
+    file.rewind()
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+    let chunks = exr::block::read(file, false).map_err(error)?;
+    let attributes = chunks.headers()[0].shared_attributes.clone();
+
+    let read_layers = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .all_channels()
+        .first_valid_layer();
+    let mut layers_reader = read_layers
+        .create_layers_reader(&chunks.headers()[first..last])
+        .map_err(error)?;
+    let blocks = chunks
+        .filter_chunks(false, |meta, tile, block| {
+            // First row of the block that is a multiple of the step, if it is within the block
+            let rows =
+                block.pixel_position.y()..block.pixel_position.y() + block.pixel_size.height();
+            block.layer >= first
+                && rows.start.next_multiple_of(row_step) < rows.end
+                && layers_reader.filter_block(
+                    meta,
+                    tile,
+                    BlockIndex {
+                        layer: block.layer - first,
+                        ..block
+                    },
+                )
+        })
+        .map_err(error)?;
+
+    let pool = || {
+        ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|index| format!("EXR decode #{}", index))
+            .build()
+    };
+    let blocks = if threads == 1 {
+        Err(blocks)
+    } else {
+        ParallelBlockDecompressor::new_with_thread_pool(blocks, false, pool)
+    };
+    match blocks {
+        Ok(mut decompressor) => {
+            while let Some(block) = decompressor.next() {
+                let mut block = block.map_err(error)?;
+                block.index.layer -= first;
+                layers_reader
+                    .read_block(&decompressor.meta_data().headers[first..], block)
+                    .map_err(error)?;
+            }
+        }
+        // Uncompressed file or single thread
+        Err(blocks) => blocks
+            .decompress_sequential(false, |meta, mut block| {
+                block.index.layer -= first;
+                layers_reader.read_block(&meta.headers[first..], block)
+            })
+            .map_err(error)?,
+    }
+
+    Ok(Image {
+        attributes,
+        layer_data: layers_reader.into_layers(),
+    })
+}
+
+/// Read an input file of any supported format, told from its first bytes unless --input-format is given
+pub fn read_input(path: &Path, args: &App) -> Result<ExrImage, String> {
+    let format = match args.input_format {
+        Some(format) => format,
+        None => InputFormat::sniff(path, args.io_retries)?,
+    };
+    let data = || read_file(path, args.io_retries);
+    match format {
+        InputFormat::Exr => read_exr(
+            path,
+            args.decode_threads,
+            args.mmap,
+            args.part.as_ref(),
+            args.io_retries,
+        ),
+        InputFormat::Png => png_input::read(path, args.input_transfer, args.sdr_white_nits),
+        InputFormat::Hdr => radiance_input::read(&data()?, path),
+        InputFormat::Pfm => pfm_input::read(&data()?, path),
+        InputFormat::Tiff => tiff_input::read(&data()?, path),
+        InputFormat::UltraHdr => ultra_hdr_input::read(&data()?, path),
+    }
+}
+
+/// Whole contents of a file, retrying transient errors up to `retries` times
+fn read_file(path: &Path, retries: u32) -> Result<Vec<u8>, String> {
+    let file = retry::open(path, retries)
+        .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    let mut data = Vec::new();
+    RetryingReader {
+        inner: file,
+        path,
+        retries,
+    }
+    .read_to_end(&mut data)
+    .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    Ok(data)
+}
+
+/// Read files in order on another thread, staying one file ahead of the receiver so decoding overlaps converting
+pub fn prefetch<'scope>(
+    scope: &'scope Scope<'scope, '_>,
+    args: &'scope App,
+    paths: Vec<PathBuf>,
+) -> Receiver<Result<ExrImage, String>> {
+    let (sender, images) = sync_channel(1);
+    scope.spawn(move || {
+        for path in paths {
+            if sender.send(read_input(&path, args)).is_err() {
+                return;
+            }
+        }
+    });
+    images
+}
@@ -0,0 +1,34 @@
+use clap::ValueEnum;
+
+use crate::{color_spaces::ColorSpace, transfer_functions::Transfer};
+
+/// SDR display standards, pairing primaries and white point with the transfer function displays apply
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum DisplayTransform {
+    /// Rec. 709 primaries, piecewise sRGB curve
+    Srgb,
+    /// DCI-P3 primaries with a D65 white, piecewise sRGB curve
+    DisplayP3,
+    /// Rec. 709 primaries, BT.1886 (2.4 power curve on a display with true black)
+    Rec709,
+    /// Rec. 2020 primaries, BT.1886
+    Rec2020,
+}
+
+impl DisplayTransform {
+    pub fn color_space(&self) -> ColorSpace {
+        match self {
+            DisplayTransform::Srgb | DisplayTransform::Rec709 => ColorSpace::Rec709,
+            DisplayTransform::DisplayP3 => ColorSpace::DisplayP3,
+            DisplayTransform::Rec2020 => ColorSpace::Rec2020,
+        }
+    }
+
+    pub fn transfer(&self) -> Transfer {
+        match self {
+            DisplayTransform::Srgb | DisplayTransform::DisplayP3 => Transfer::Srgb,
+            // BT.1886 with a zero black level is a pure power curve
+            DisplayTransform::Rec709 | DisplayTransform::Rec2020 => Transfer::Gamma24,
+        }
+    }
+}
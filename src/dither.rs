@@ -0,0 +1,72 @@
+// https://en.wikipedia.org/wiki/Ordered_dithering
+// https://doi.org/10.1117/12.152707 (void-and-cluster blue noise, Ulichney 1993)
+
+use std::sync::OnceLock;
+
+/// Side of the tiled blue noise mask
+const BLUE_NOISE_SIZE: usize = 32;
+/// Spread of the gaussian measuring how clustered mask points are, in pixels
+const BLUE_NOISE_SIGMA: f32 = 1.5;
+
+/// 8x8 Bayer threshold matrix, thresholds are (value + 0.5) / 64
+const BAYER: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Reduce 16-bit values of an image `width` pixels wide to 8 bits with ordered dithering, so smooth gradients average to their 16-bit level instead of banding. Deterministic, unlike error diffusion run in parallel
+pub fn to_8_bits(values: &[u16], width: usize) -> Vec<u8> {
+    values
+        .chunks(width)
+        .enumerate()
+        .flat_map(|(y, row)| {
+            row.iter().enumerate().map(move |(x, &value)| {
+                let threshold = (BAYER[y % 8][x % 8] as u32 * 2 + 1) * u16::MAX as u32 / 128;
+                ((value as u32 * 255 + threshold) / u16::MAX as u32) as u8
+            })
+        })
+        .collect()
+}
+
+/// Blue noise offset in -0.5 - 0.5 at a pixel, from a tiled mask. Unlike ordered dithering it leaves no regular pattern, and unlike white noise no clumps
+pub fn blue_noise(x: usize, y: usize) -> f32 {
+    static MASK: OnceLock<Vec<u16>> = OnceLock::new();
+    let mask = MASK.get_or_init(void_and_cluster);
+    let rank = mask[(y % BLUE_NOISE_SIZE) * BLUE_NOISE_SIZE + x % BLUE_NOISE_SIZE];
+    (rank as f32 + 0.5) / (BLUE_NOISE_SIZE * BLUE_NOISE_SIZE) as f32 - 0.5
+}
+
+/// Rank of every pixel of the mask, pixels being added one by one in the largest void left by previous ones. Deterministic, ties going to the first pixel
+fn void_and_cluster() -> Vec<u16> {
+    let (size, area) = (BLUE_NOISE_SIZE, BLUE_NOISE_SIZE * BLUE_NOISE_SIZE);
+    // Gaussian of wrapped-around distances, the mask being tiled
+    let wrapped = |d: usize| d.min(size - d) as f32;
+    let kernel: Vec<f32> = (0..area)
+        .map(|i| {
+            let (dx, dy) = (wrapped(i % size), wrapped(i / size));
+            (-(dx * dx + dy * dy) / (2.0 * BLUE_NOISE_SIGMA * BLUE_NOISE_SIGMA)).exp()
+        })
+        .collect();
+
+    let mut energy = vec![0.0f32; area];
+    let mut ranks = vec![u16::MAX; area];
+    for rank in 0..area {
+        let void = (0..area)
+            .filter(|&i| ranks[i] == u16::MAX)
+            .min_by(|&a, &b| energy[a].total_cmp(&energy[b]))
+            .unwrap();
+        ranks[void] = rank as u16;
+        let (vx, vy) = (void % size, void / size);
+        for (i, e) in energy.iter_mut().enumerate() {
+            let (dx, dy) = ((i % size + size - vx) % size, (i / size + size - vy) % size);
+            *e += kernel[dy * size + dx];
+        }
+    }
+    ranks
+}
@@ -0,0 +1,466 @@
+// Small changes to existing Ultra HDR JPEGs without a round trip through the EXR. Images are only decoded and re-encoded when their pixels change, the container is rebuilt around them
+
+use std::{
+    fs::{self, File},
+    path::PathBuf,
+};
+
+use askama::Template;
+use clap::{builder::RangedU64ValueParser, Args};
+use jpeg_decoder::{Decoder, PixelFormat};
+use tracing::{info, warn};
+
+use crate::{
+    base_jpeg::BaseJpeg,
+    color_stuff::Pixel,
+    copy_metadata::{merge_xmp, without_descriptions},
+    exif::{strip_gps, tiff_from_exif},
+    jpeg_backend::{self, JpegBackend},
+    jpeg_bands::{self, EncoderMode, JpegSettings},
+    jpeg_container::JpegContainerBuilder,
+    mpf::{self, MpEntry, PRIMARY_IMAGE_ATTRIBUTE, UNDEFINED_IMAGE_ATTRIBUTE},
+    planar::{PlanarRgb, PlanarView},
+    process_pixel, quantize,
+    recovery_curve::{RecoveryCurve, PQ_CURVE_NAME, RECOVERY_CURVE_NAMESPACE},
+    resize::{downscale_box, fit_within},
+    transfer_functions::Transfer,
+    ultra_hdr_stuff::{make_xmp, GContainerTemplate, HDRGainMapMetadataTemplate, XMP_NAMESPACE},
+    xmp::{self, GainMapXmp, CONTAINER_NAMESPACE, GAIN_MAP_NAMESPACE},
+    JPEG_QUALITY, MAP_JPEG_QUALITY,
+};
+
+const APP0_SEGMENT: u8 = 0;
+/// Namespace header of ISO 21496-1 Gain Map metadata APP2 segments, as written by libultrahdr
+const ISO_21496_NAMESPACE: &[u8] = b"urn:iso:std:iso:ts:21496:-1\0";
+const EXIF_NAMESPACE: &str = "http://ns.adobe.com/exif/1.0/";
+
+/// Re-encode, downscale or retag an Ultra HDR JPEG. The Gain Map is resampled along with a downscaled image, never recomputed
+#[derive(Args)]
+pub struct EditArgs {
+    /// Ultra HDR JPEG to edit
+    input: PathBuf,
+    /// Where to write the edited Ultra HDR JPEG
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Re-encode the primary image at this JPEG quality. Downscaled images are encoded at 100 unless specified
+    #[arg(long, value_parser = RangedU64ValueParser::<u8>::new().range(1..=100))]
+    quality: Option<u8>,
+    /// Downscale so the longest side is at most this many pixels, and the Gain Map by the same factor
+    #[arg(long)]
+    max_size: Option<usize>,
+    /// Set hdrgm:HDRCapacityMax, the log2 display headroom at which the HDR rendition is fully shown. Only metadata changes
+    #[arg(long)]
+    hdr_capacity_max: Option<f32>,
+    /// Remove the GPS location from EXIF, and XMP descriptions holding one
+    #[arg(long)]
+    strip_gps: bool,
+}
+
+/// 8-bit samples of a decoded JPEG, gray or RGB
+struct Samples {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+    components: usize,
+}
+
+pub fn run(args: &EditArgs) -> Result<(), String> {
+    let name = args.input.display().to_string();
+    let context = |e: String| format!("{}: {}", name, e);
+    let data = fs::read(&args.input).map_err(|e| format!("Could not read {}: {}", name, e))?;
+    let images = mpf::images(&data)
+        .map_err(context)?
+        .filter(|images| images.len() >= 2)
+        .ok_or_else(|| context("No MPF index locating a Gain Map".to_string()))?;
+
+    let mut primary = BaseJpeg::parse(data.clone(), &name)?;
+    let mut gain_map = BaseJpeg::parse(data[images[1].clone()].to_vec(), &name)?;
+    // Thumbnails and other images are still valid after any change
+    let mut others = images[2..]
+        .iter()
+        .map(|range| BaseJpeg::parse(data[range.clone()].to_vec(), &name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let attributes: Vec<u32> = primary
+        .app_segments()
+        .find(|(n, payload)| *n == 2 && mpf::is_index(payload))
+        .map(|(_, payload)| mpf::parse(payload))
+        .transpose()
+        .map_err(context)?
+        .unwrap_or_default()
+        .iter()
+        .map(|entry| entry.attribute)
+        .collect();
+    let attribute = |index: usize, default: u32| attributes.get(index).copied().unwrap_or(default);
+
+    if args.strip_gps {
+        let mut stripped = 0;
+        for image in [&mut primary, &mut gain_map].into_iter().chain(&mut others) {
+            image.edit_app_segments(|n, payload| {
+                let Some(length) = tiff_from_exif(payload).map(<[u8]>::len).filter(|_| n == 1)
+                else {
+                    return;
+                };
+                let start = payload.len() - length;
+                match strip_gps(&mut payload[start..]) {
+                    Some(true) => stripped += 1,
+                    Some(false) => {}
+                    None => warn!("Malformed EXIF data, its GPS location may remain"),
+                }
+            });
+        }
+        info!(segments = stripped, "Stripped GPS location from EXIF");
+    }
+
+    // What the primary image XMP holds besides the GContainer directory
+    let extra_xmp = xmp_packet(&primary)
+        .and_then(|packet| {
+            without_descriptions(&packet, &[CONTAINER_NAMESPACE, GAIN_MAP_NAMESPACE])
+        })
+        .filter(|packet| {
+            let has_gps = xmp::parse(packet).is_ok_and(|properties| {
+                properties
+                    .iter()
+                    .any(|p| p.namespace == EXIF_NAMESPACE && p.name.starts_with("GPS"))
+            });
+            if args.strip_gps && has_gps {
+                warn!("Dropping primary image XMP, as it holds a GPS location");
+            }
+            !(args.strip_gps && has_gps)
+        });
+    let directory_xmp = |gain_map_image_len| {
+        let xmp = GContainerTemplate { gain_map_image_len }.render().unwrap();
+        make_xmp(match &extra_xmp {
+            Some(extra) => merge_xmp(xmp, extra),
+            None => xmp,
+        })
+    };
+
+    // Gain Map metadata, rewritten if it changes
+    let packet =
+        xmp_packet(&gain_map).ok_or_else(|| context("No XMP in Gain Map image".to_string()))?;
+    let metadata = xmp::parse(&packet)
+        .and_then(|p| GainMapXmp::from_properties(&p))
+        .map_err(context)?
+        .ok_or_else(|| context("No hdrgm:GainMapMax in Gain Map XMP".to_string()))?;
+    let new_metadata = args
+        .hdr_capacity_max
+        .map(|capacity| with_capacity(&metadata, capacity))
+        .transpose()
+        .map_err(context)?;
+    // Gains above a lowered capacity are clipped, so the HDR rendition peaks there
+    let recomputed = new_metadata
+        .as_ref()
+        .is_some_and(|new| new.gain_map_max != metadata.gain_map_max);
+    let gain_map_xmp = match &new_metadata {
+        Some(new) => {
+            let mut dropped = false;
+            gain_map.retain_app_segments(|n, payload| {
+                let iso = n == 2 && payload.starts_with(ISO_21496_NAMESPACE);
+                dropped |= iso;
+                !iso
+            });
+            if dropped {
+                warn!("Dropping ISO 21496-1 metadata of the Gain Map, only its XMP is updated");
+            }
+            info!(
+                from = metadata.hdr_capacity_max,
+                to = new.hdr_capacity_max,
+                recomputed,
+                "Changing HDR capacity"
+            );
+            make_xmp(render(new))
+        }
+        None => [XMP_NAMESPACE, packet.as_bytes()].concat(),
+    };
+
+    // New sizes
+    let (width, height) = (primary.width, primary.height);
+    let (new_width, new_height) = args
+        .max_size
+        .map_or((width, height), |size| fit_within(width, height, size));
+    let resized = (new_width, new_height) != (width, height);
+    let (gain_map_width, gain_map_height) = (gain_map.width, gain_map.height);
+    let (new_gain_map_width, new_gain_map_height) = if resized {
+        let longest = (gain_map_width.max(gain_map_height) * new_width.max(new_height))
+            .div_ceil(width.max(height));
+        fit_within(gain_map_width, gain_map_height, longest.max(1))
+    } else {
+        (gain_map_width, gain_map_height)
+    };
+    let reencode = resized || args.quality.is_some();
+    if resized {
+        info!(
+            width = new_width,
+            height = new_height,
+            gain_map_width = new_gain_map_width,
+            gain_map_height = new_gain_map_height,
+            "Downscaling"
+        );
+    }
+
+    let image_count = 2 + others.len();
+    let mpf_index = mpf::index(&vec![MpEntry::default(); image_count]);
+    let primary_jpeg = if reencode {
+        let samples = decode(&data[images[0].clone()]).map_err(context)?;
+        let pixels = downscale_sdr(&samples, new_width, new_height);
+        let mut jpeg = Vec::new();
+        jpeg_backend::encode(
+            &mut jpeg,
+            JpegBackend::JpegEncoder,
+            JpegSettings {
+                mode: EncoderMode::Quality,
+                quality: args.quality.unwrap_or(JPEG_QUALITY),
+                progressive: false,
+            },
+            pixels.view(new_width, new_height),
+            |encoder| {
+                for (n, payload) in kept_segments(&primary) {
+                    encoder.add_app_segment(n, payload)?;
+                }
+                encoder.add_app_segment(1, &directory_xmp(u64::MAX))?;
+                encoder.add_app_segment(2, &mpf_index)
+            },
+        )
+        .map_err(context)?;
+        jpeg
+    } else {
+        let mut jpeg = Vec::new();
+        primary
+            .write_with_segments(&mut jpeg, &[(1, &directory_xmp(u64::MAX)), (2, &mpf_index)])
+            .map_err(|e| context(e.to_string()))?;
+        jpeg
+    };
+
+    let gain_map_jpeg = if resized || recomputed {
+        let mut samples = decode(&data[images[1].clone()]).map_err(context)?;
+        if let Some(new) = new_metadata.as_ref().filter(|_| recomputed) {
+            for value in &mut samples.data {
+                *value = clip_recovery(*value, &metadata, new);
+            }
+        }
+        let downscaled = downscale_box(
+            &gray_or_rgb_pixels(&samples),
+            gain_map_width,
+            gain_map_height,
+            new_gain_map_width,
+            new_gain_map_height,
+        );
+        let (gray, rgb): (Vec<u8>, PlanarRgb);
+        let image = match samples.components {
+            1 => {
+                gray = downscaled.iter().map(|p| quantize(p.r)).collect();
+                PlanarView::gray(&gray, new_gain_map_width, new_gain_map_height)
+            }
+            _ => {
+                rgb = downscaled
+                    .iter()
+                    .map(|p| [p.r, p.g, p.b].map(quantize))
+                    .collect();
+                rgb.view(new_gain_map_width, new_gain_map_height)
+            }
+        };
+        let mut jpeg = Vec::new();
+        jpeg_bands::encode(
+            &mut jpeg,
+            JpegSettings {
+                mode: EncoderMode::Quality,
+                quality: MAP_JPEG_QUALITY,
+                progressive: false,
+            },
+            image,
+            |encoder| {
+                for (n, payload) in kept_segments(&gain_map) {
+                    encoder.add_app_segment(n, payload)?;
+                }
+                encoder.add_app_segment(1, &gain_map_xmp)
+            },
+        )
+        .map_err(|e| context(e.to_string()))?;
+        jpeg
+    } else {
+        let mut jpeg = Vec::new();
+        gain_map
+            .write_with_segments(&mut jpeg, &[(1, &gain_map_xmp)])
+            .map_err(|e| context(e.to_string()))?;
+        jpeg
+    };
+
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&args.output)
+        .map_err(|e| format!("Could not create {}: {}", args.output.display(), e))?;
+    let error = |e: std::io::Error| format!("Could not write {}: {}", args.output.display(), e);
+    let mut container = JpegContainerBuilder::new(file);
+    container
+        .add_image(attribute(0, PRIMARY_IMAGE_ATTRIBUTE), |writer| {
+            writer.write_all(&primary_jpeg)
+        })
+        .map_err(error)?;
+    container
+        .add_image(attribute(1, UNDEFINED_IMAGE_ATTRIBUTE), |writer| {
+            writer.write_all(&gain_map_jpeg)
+        })
+        .map_err(error)?;
+    for (index, image) in others.iter().enumerate() {
+        container
+            .add_image(attribute(index + 2, UNDEFINED_IMAGE_ATTRIBUTE), |writer| {
+                image.write_with_segments(writer, &[])
+            })
+            .map_err(error)?;
+    }
+    container
+        .finish(|images| Some(directory_xmp(images[1].length)))
+        .map_err(error)?;
+
+    info!(
+        file = %args.output.display(),
+        primary_reencoded = reencode,
+        gain_map_resampled = resized,
+        gain_map_recomputed = recomputed,
+        "Edited Ultra HDR JPEG"
+    );
+    Ok(())
+}
+
+/// XMP packet of an image, without namespace header
+fn xmp_packet(image: &BaseJpeg) -> Option<String> {
+    image
+        .app_segments()
+        .filter(|(n, _)| *n == 1)
+        .find_map(|(_, payload)| payload.strip_prefix(XMP_NAMESPACE))
+        .map(|packet| String::from_utf8_lossy(packet).into_owned())
+}
+
+/// APPn segments copied into a re-encoded image: all but JFIF, which the encoder writes, and the XMP and MPF segments written anew
+fn kept_segments(image: &BaseJpeg) -> impl Iterator<Item = (u8, &[u8])> {
+    image.app_segments().filter(|(n, payload)| {
+        !(*n == APP0_SEGMENT
+            || *n == 1 && payload.starts_with(XMP_NAMESPACE)
+            || *n == 2 && mpf::is_index(payload))
+    })
+}
+
+/// Gain Map metadata with a new HDR capacity. Below hdrgm:GainMapMax, the Gain Map range ends there too. Only metadata shared by every channel can be written
+fn with_capacity(metadata: &GainMapXmp, hdr_capacity_max: f32) -> Result<GainMapXmp, String> {
+    if metadata.base_rendition_is_hdr {
+        return Err("HDR base renditions are not supported".to_string());
+    }
+    let per_channel = [
+        metadata.gain_map_min,
+        metadata.gain_map_max,
+        metadata.gamma,
+        metadata.offset_sdr,
+        metadata.offset_hdr,
+    ]
+    .iter()
+    .any(|values| values.iter().any(|v| *v != values[0]));
+    if per_channel {
+        return Err("Per-channel Gain Map metadata cannot be rewritten".to_string());
+    }
+    let lowest = metadata.hdr_capacity_min.max(metadata.gain_map_min[0]);
+    if !hdr_capacity_max.is_finite() || hdr_capacity_max <= lowest {
+        return Err(format!(
+            "HDR capacity must be above {}, hdrgm:HDRCapacityMin and hdrgm:GainMapMin",
+            lowest
+        ));
+    }
+    Ok(GainMapXmp {
+        gain_map_max: metadata.gain_map_max.map(|max| max.min(hdr_capacity_max)),
+        hdr_capacity_max,
+        ..metadata.clone()
+    })
+}
+
+/// Encoded recovery `value` of the `old` Gain Map range, in the `new` one, gains above it clipped
+fn clip_recovery(value: u8, old: &GainMapXmp, new: &GainMapXmp) -> u8 {
+    let (min, old_max, new_max) = (
+        old.gain_map_min[0],
+        old.gain_map_max[0],
+        new.gain_map_max[0],
+    );
+    let (old_stops, new_stops) = (old_max - min, new_max - min);
+    let log_gain = min + old_stops * old.recovery_curve.decode(value as f32 / 255.0, old_stops);
+    let recovery = ((log_gain.min(new_max) - min) / new_stops).clamp(0.0, 1.0);
+    quantize(new.recovery_curve.encode(recovery, new_stops))
+}
+
+/// Gain Map XMP, single channel
+fn render(metadata: &GainMapXmp) -> String {
+    HDRGainMapMetadataTemplate {
+        gain_map_min: metadata.gain_map_min[0],
+        gain_map_max: metadata.gain_map_max[0],
+        gamma: metadata.gamma[0],
+        offset_sdr: metadata.offset_sdr[0],
+        offset_hdr: metadata.offset_hdr[0],
+        hdr_capacity_min: metadata.hdr_capacity_min,
+        hdr_capacity_max: metadata.hdr_capacity_max,
+        recovery_curve: match metadata.recovery_curve {
+            RecoveryCurve::Power(_) => None,
+            RecoveryCurve::Pq => Some(PQ_CURVE_NAME),
+        },
+        recovery_curve_namespace: RECOVERY_CURVE_NAMESPACE,
+    }
+    .render()
+    .unwrap()
+}
+
+fn decode(jpeg: &[u8]) -> Result<Samples, String> {
+    let mut decoder = Decoder::new(jpeg);
+    let data = decoder
+        .decode()
+        .map_err(|e| format!("Could not decode JPEG: {}", e))?;
+    let info = decoder.info().ok_or("JPEG has no frame")?;
+    let components = match info.pixel_format {
+        PixelFormat::L8 => 1,
+        PixelFormat::RGB24 => 3,
+        other => return Err(format!("JPEG pixel format {:?} is not supported", other)),
+    };
+    Ok(Samples {
+        data,
+        width: info.width as usize,
+        height: info.height as usize,
+        components,
+    })
+}
+
+/// Samples as 0 to 1 values, gray spread to every component
+fn gray_or_rgb_pixels(samples: &Samples) -> Vec<Pixel> {
+    samples
+        .data
+        .chunks_exact(samples.components)
+        .map(|pixel| {
+            let value = |c: usize| pixel[c.min(samples.components - 1)] as f32 / 255.0;
+            Pixel {
+                r: value(0),
+                g: value(1),
+                b: value(2),
+            }
+        })
+        .collect()
+}
+
+/// RGB samples of the primary image at a new size, averaged in linear light like a viewer decoding it as sRGB
+fn downscale_sdr(samples: &Samples, width: usize, height: usize) -> PlanarRgb {
+    let pixels = gray_or_rgb_pixels(samples);
+    if (width, height) == (samples.width, samples.height) {
+        return pixels
+            .iter()
+            .map(|p| [p.r, p.g, p.b].map(quantize))
+            .collect();
+    }
+    let linear: Vec<Pixel> = pixels
+        .iter()
+        .map(|p| Pixel {
+            r: Transfer::Srgb.decode(p.r),
+            g: Transfer::Srgb.decode(p.g),
+            b: Transfer::Srgb.decode(p.b),
+        })
+        .collect();
+    downscale_box(&linear, samples.width, samples.height, width, height)
+        .iter()
+        .map(|p| [p.r, p.g, p.b].map(|v| process_pixel(v, Transfer::Srgb)))
+        .collect()
+}
@@ -0,0 +1,27 @@
+// EXR2UHDR_* environment variables standing for flags, so batch environments such as containers can configure runs without long command lines
+
+use clap::{builder::BoolishValueParser, ArgAction, Command, CommandFactory};
+
+use crate::App;
+
+/// Followed by the flag's name in upper snake case, such as EXR2UHDR_GAIN_MAP_SCALE for --gain-map-scale
+const PREFIX: &str = "EXR2UHDR_";
+
+/// Flags that are not settings of a run
+const EXCLUDED: [&str; 1] = ["generate-man"];
+
+/// Command line definition where every flag of conversions can also be set by its environment variable, the command line taking precedence. Flags accept true, yes, on or 1, and false, no, off or 0
+pub fn command() -> Command {
+    App::command().mut_args(|arg| match arg.get_long() {
+        Some(long) if !EXCLUDED.contains(&long) => {
+            let variable = format!("{}{}", PREFIX, long.to_uppercase().replace('-', "_"));
+            let arg = arg.env(variable);
+            if let ArgAction::SetTrue = arg.get_action() {
+                arg.value_parser(BoolishValueParser::new())
+            } else {
+                arg
+            }
+        }
+        _ => arg,
+    })
+}
@@ -0,0 +1,97 @@
+use std::{
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+use tracing::info;
+
+use crate::precision::StoredPixel;
+
+/// Grayscale mask, and the exposure change it makes where it is white (dodge and burn)
+#[derive(Debug, Clone)]
+pub struct ExposureMask {
+    pub path: PathBuf,
+    pub ev: f32,
+}
+
+/// Parse `mask.png:ev`
+pub fn parse_exposure_mask(text: &str) -> Result<ExposureMask, String> {
+    let (path, ev) = text
+        .rsplit_once(':')
+        .ok_or_else(|| "expected MASK.png:EV".to_string())?;
+    let ev = ev
+        .parse()
+        .map_err(|e| format!("invalid exposure {:?}: {}", ev, e))?;
+    Ok(ExposureMask {
+        path: PathBuf::from(path),
+        ev,
+    })
+}
+
+impl ExposureMask {
+    /// Scale linear pixels by 2^(ev × mask value). A mask of another size is stretched over the image, so masks painted on a proxy still line up
+    pub fn apply<P: StoredPixel>(
+        &self,
+        pixels: &mut [P],
+        width: usize,
+        height: usize,
+    ) -> Result<(), String> {
+        let (mask, mask_width, mask_height) = read(&self.path)?;
+        info!(path = %self.path.display(), ev = self.ev, "Applying exposure mask");
+        let scale_x = mask_width as f32 / width as f32;
+        let scale_y = mask_height as f32 / height as f32;
+        let at =
+            |x: usize, y: usize| mask[y.min(mask_height - 1) * mask_width + x.min(mask_width - 1)];
+
+        for (y, row) in pixels.chunks_exact_mut(width).enumerate() {
+            // Bilinear, between mask pixel centers
+            let v = ((y as f32 + 0.5) * scale_y - 0.5).max(0.0);
+            let (top, fy) = (v as usize, v.fract());
+            for (x, stored) in row.iter_mut().enumerate() {
+                let u = ((x as f32 + 0.5) * scale_x - 0.5).max(0.0);
+                let (left, fx) = (u as usize, u.fract());
+                let weight = (at(left, top) * (1.0 - fx) + at(left + 1, top) * fx) * (1.0 - fy)
+                    + (at(left, top + 1) * (1.0 - fx) + at(left + 1, top + 1) * fx) * fy;
+                let gain = (self.ev * weight).exp2();
+                let mut pixel = stored.load();
+                pixel.r *= gain;
+                pixel.g *= gain;
+                pixel.b *= gain;
+                *stored = P::store(pixel);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mask values in 0.0 - 1.0, and size
+fn read(path: &Path) -> Result<(Vec<f32>, usize, usize), String> {
+    let error = |e: png::DecodingError| format!("Could not decode {}: {}", path.display(), e);
+    let file = File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).map_err(error)?;
+    if !matches!(
+        frame.color_type,
+        png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha
+    ) {
+        return Err(format!("Exposure mask {} is not grayscale", path.display()));
+    }
+
+    // Alpha is ignored
+    let components = frame.color_type.samples();
+    let mask = match frame.bit_depth {
+        png::BitDepth::Sixteen => buffer[..frame.buffer_size()]
+            .chunks_exact(2 * components)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as f32 / u16::MAX as f32)
+            .collect(),
+        _ => buffer[..frame.buffer_size()]
+            .chunks_exact(components)
+            .map(|b| b[0] as f32 / u8::MAX as f32)
+            .collect(),
+    };
+    Ok((mask, frame.width as usize, frame.height as usize))
+}
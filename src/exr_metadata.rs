@@ -0,0 +1,111 @@
+use exr::{
+    math::Vec2,
+    meta::{
+        attribute::AttributeValue,
+        header::{ImageAttributes, LayerAttributes},
+    },
+};
+
+/// Custom attribute names renderers use for exposure compensation, compared without case
+const EXPOSURE_ATTRIBUTES: [&str; 4] = ["exposure", "ev", "exposurevalue", "exposurecompensation"];
+
+/// Set to 1 by files following the ACES container specification
+const ACES_CONTAINER_ATTRIBUTE: &str = "acesImageContainerFlag";
+
+/// Exposure value (eV) suggested by EXR metadata, with where it was found. Checks custom exposure attributes, then `EV=` / `exposure:` in comments, then makes `whiteLuminance` match SDR white
+pub fn exposure(
+    image: &ImageAttributes,
+    layer: &LayerAttributes,
+    sdr_white_nits: f32,
+) -> Option<(f32, &'static str)> {
+    let custom = layer
+        .other
+        .iter()
+        .chain(image.other.iter())
+        .find_map(|(name, value)| {
+            let name = name.to_string().to_ascii_lowercase();
+            if !EXPOSURE_ATTRIBUTES.contains(&name.as_str()) {
+                return None;
+            }
+            match value {
+                AttributeValue::F32(v) => Some(*v),
+                AttributeValue::F64(v) => Some(*v as f32),
+                AttributeValue::I32(v) => Some(*v as f32),
+                _ => None,
+            }
+        });
+    if let Some(ev) = custom {
+        return Some((ev, "custom attribute"));
+    }
+
+    if let Some(ev) = layer
+        .comments
+        .as_ref()
+        .and_then(|c| from_comments(&c.to_string()))
+    {
+        return Some((ev, "comments"));
+    }
+
+    layer
+        .white_luminance
+        .filter(|nits| *nits > 0.0)
+        .map(|nits| ((nits / sdr_white_nits).log2(), "whiteLuminance"))
+}
+
+// https://pub.smpte.org/doc/st2065-4/
+/// Whether the file declares itself an ACES container (SMPTE ST 2065-4), whose RGB is always ACES2065-1 (AP0 primaries, ACES white)
+pub fn is_aces_container(image: &ImageAttributes, layer: &LayerAttributes) -> bool {
+    matches!(
+        layer
+            .other
+            .get(ACES_CONTAINER_ATTRIBUTE.as_bytes())
+            .or(image.other.get(ACES_CONTAINER_ATTRIBUTE.as_bytes())),
+        Some(AttributeValue::I32(1))
+    )
+}
+
+/// Region given by a box2i attribute, layer attributes first, clipped to the data window. Returns position relative to the data window then size
+pub fn region_of_interest(
+    image: &ImageAttributes,
+    layer: &LayerAttributes,
+    size: Vec2<usize>,
+    name: &str,
+) -> Result<(Vec2<usize>, Vec2<usize>), String> {
+    let bounds = match layer
+        .other
+        .get(name.as_bytes())
+        .or(image.other.get(name.as_bytes()))
+    {
+        Some(AttributeValue::IntegerBounds(bounds)) => bounds,
+        Some(_) => return Err(format!("EXR attribute {} is not a box2i", name)),
+        None => return Err(format!("No {} attribute in EXR", name)),
+    };
+
+    let start = bounds.position - layer.layer_position;
+    let end = start + bounds.size.to_i32();
+    let clip = |v: i32, max: usize| v.clamp(0, max as i32) as usize;
+    let (x0, y0) = (
+        clip(start.x(), size.width()),
+        clip(start.y(), size.height()),
+    );
+    let (x1, y1) = (clip(end.x(), size.width()), clip(end.y(), size.height()));
+    if x1 <= x0 || y1 <= y0 {
+        return Err(format!("EXR {} attribute is outside of the image", name));
+    }
+    Ok((Vec2(x0, y0), Vec2(x1 - x0, y1 - y0)))
+}
+
+/// Find a number following `EV` or `exposure`, then `=` or `:`
+fn from_comments(comments: &str) -> Option<f32> {
+    let lower = comments.to_ascii_lowercase();
+    ["exposure", "ev"].iter().find_map(|key| {
+        lower.match_indices(key).find_map(|(start, _)| {
+            let rest = lower[start + key.len()..].trim_start();
+            let rest = rest.strip_prefix(['=', ':'])?.trim_start();
+            let end = rest
+                .find(|c: char| !(c.is_ascii_digit() || "+-.".contains(c)))
+                .unwrap_or(rest.len());
+            rest[..end].parse().ok()
+        })
+    })
+}
@@ -0,0 +1,227 @@
+// Quick SDR proxies of EXRs from their preview attribute, read with the headers, or from a decimated decode of files without one
+
+use std::{
+    fs::File,
+    io::{BufReader, Write},
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use exr::meta::{header::Header, MetaData};
+use png::{Encoder as PNGEncoder, SrgbRenderingIntent};
+use tracing::{info, warn};
+
+use crate::{
+    aux_channels,
+    color_spaces::{ColorSpace, REC_709},
+    decode::read_exr_rows,
+    icc::{self, make_profile},
+    jpeg_backend,
+    jpeg_bands::JpegSettings,
+    output_template::Tokens,
+    planar::PlanarRgb,
+    process_pixel,
+    resize::fit_within,
+    retry,
+    sinks::write_file,
+    transfer_functions::Transfer,
+    App, Outputs, JPEG_QUALITY,
+};
+
+/// Longest side of proxies decoded from pixels, about the size of preview attributes written by OpenEXR tools
+const PROXY_SIZE: usize = 256;
+
+/// 8-bit sRGB proxy of an EXR
+struct Proxy {
+    width: usize,
+    height: usize,
+    image: PlanarRgb,
+}
+
+/// Write the PNG and JPEG outputs of every input as quick proxies, without converting. Other outputs are left out
+pub fn run(args: &App) -> Result<(), String> {
+    let jobs: Vec<(PathBuf, Outputs)> = if args.exr.len() > 1 {
+        args.exr
+            .iter()
+            .map(|exr| (exr.clone(), args.outputs.in_directories(exr)))
+            .collect()
+    } else {
+        vec![(args.exr[0].clone(), args.outputs.clone())]
+    };
+
+    for (exr, outputs) in &jobs {
+        let [year, month, day, ..] = icc::now();
+        let outputs = outputs.expand_templates(&Tokens {
+            stem: exr
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+            ev: args.requested_exposure().unwrap_or(0.0),
+            space: ColorSpace::identify(&REC_709)
+                .and_then(|c| c.to_possible_value())
+                .map_or("custom".to_string(), |v| v.get_name().to_string()),
+            date: format!("{:04}-{:02}-{:02}", year, month, day),
+        })?;
+        let written = [&outputs.png, &outputs.jpg];
+        if outputs
+            .paths(args)
+            .iter()
+            .any(|path| !written.contains(&&Some(path.clone())))
+        {
+            warn!("Fast previews only write PNG and JPEG SDR outputs, skipping the others");
+        }
+
+        let proxy = read(exr, args)?;
+        if let Some(path) = &outputs.png {
+            write_png(path, &proxy)?;
+        }
+        if let Some(path) = &outputs.jpg {
+            write_jpeg(path, &proxy, args)?;
+        }
+    }
+    Ok(())
+}
+
+/// Proxy from the preview attribute of the selected part, decoding pixels if it has none
+fn read(path: &Path, args: &App) -> Result<Proxy, String> {
+    let file = retry::open(path, args.io_retries)
+        .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+    let headers = MetaData::read_from_buffered(BufReader::new(file), false)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?
+        .headers;
+    let index = match &args.part {
+        Some(part) => part
+            .index(&headers)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?,
+        // Deep parts are the only ones the first valid layer skips
+        None => headers
+            .iter()
+            .position(|h| !h.deep)
+            .ok_or_else(|| format!("{} only has deep parts", path.display()))?,
+    };
+
+    match from_attribute(&headers[index]) {
+        Some(proxy) => {
+            info!(file = %path.display(), width = proxy.width, height = proxy.height, "Using preview attribute");
+            Ok(proxy)
+        }
+        None => decimated(path, args, &headers[index]),
+    }
+}
+
+/// RGBA preview attribute, alpha dropped. Values are already display-encoded
+fn from_attribute(header: &Header) -> Option<Proxy> {
+    let preview = header.own_attributes.preview.as_ref()?;
+    let (width, height) = (preview.size.0, preview.size.1);
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let image = preview
+        .pixel_data
+        .chunks_exact(4)
+        .map(|rgba| [rgba[0], rgba[1], rgba[2]].map(|v| v as u8))
+        .collect();
+    Some(Proxy {
+        width,
+        height,
+        image,
+    })
+}
+
+/// Proxy from every nth row and column of the pixels, only decompressing blocks holding those rows. Exposed, clipped and sRGB-encoded in the file's own primaries
+fn decimated(path: &Path, args: &App, header: &Header) -> Result<Proxy, String> {
+    let (full_width, full_height) = (header.layer_size.0, header.layer_size.1);
+    let (width, height) = fit_within(full_width, full_height, PROXY_SIZE);
+    let step = full_width.div_ceil(width).max(full_height.div_ceil(height));
+    info!(file = %path.display(), step, "No preview attribute, decoding a decimated proxy");
+
+    let image = read_exr_rows(
+        path,
+        args.decode_threads,
+        args.part.as_ref(),
+        args.io_retries,
+        step,
+    )?;
+    let layer = &image.layer_data;
+    let channel = |name: &str| {
+        layer
+            .channel_data
+            .list
+            .iter()
+            .find(|c| c.name.to_string() == name)
+            .map(|c| aux_channels::samples(&c.sample_data))
+    };
+    let rgb = match (channel("R"), channel("G"), channel("B")) {
+        (Some(r), Some(g), Some(b)) => [r, g, b],
+        _ => {
+            let y = channel("Y")
+                .ok_or_else(|| format!("{} has neither RGB nor Y channels", path.display()))?;
+            [y.clone(), y.clone(), y]
+        }
+    };
+
+    let factor = args.requested_exposure().unwrap_or(0.0).exp2();
+    let (width, height) = (layer.size.0.div_ceil(step), layer.size.1.div_ceil(step));
+    let mut image = PlanarRgb::with_capacity(width * height);
+    for y in (0..layer.size.1).step_by(step) {
+        for x in (0..layer.size.0).step_by(step) {
+            let index = y * layer.size.0 + x;
+            image.push(rgb.each_ref().map(|channel| {
+                process_pixel((channel[index] * factor).clamp(0.0, 1.0), Transfer::Srgb)
+            }));
+        }
+    }
+    Ok(Proxy {
+        width,
+        height,
+        image,
+    })
+}
+
+fn write_png(path: &Path, proxy: &Proxy) -> Result<(), String> {
+    let error = |e: png::EncodingError| format!("Could not write {}: {}", path.display(), e);
+    let file =
+        File::create(path).map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+    let mut encoder = PNGEncoder::new(file, proxy.width as u32, proxy.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_srgb(SrgbRenderingIntent::Perceptual);
+    let mut writer = encoder.write_header().map_err(error)?;
+    let mut stream = writer.stream_writer().map_err(error)?;
+    for row in proxy
+        .image
+        .view(proxy.width, proxy.height)
+        .interleaved_rows()
+    {
+        stream
+            .write_all(&row)
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    }
+    stream.finish().map_err(error)
+}
+
+fn write_jpeg(path: &Path, proxy: &Proxy, args: &App) -> Result<(), String> {
+    let profile = make_profile(
+        &REC_709,
+        Transfer::Srgb,
+        args.cat,
+        "sRGB",
+        None,
+        args.deterministic,
+    );
+    write_file(path, |writer| {
+        jpeg_backend::encode(
+            writer,
+            args.jpeg_backend,
+            JpegSettings {
+                mode: args.encoder,
+                quality: JPEG_QUALITY,
+                progressive: args.progressive,
+            },
+            proxy.image.view(proxy.width, proxy.height),
+            |encoder| encoder.add_icc_profile(&profile),
+        )
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    })
+}
@@ -0,0 +1,61 @@
+// Fireflies of path-traced renders, isolated pixels far brighter than their surroundings that would otherwise set the Gain Map maximum
+
+use tracing::info;
+
+use crate::{
+    color_stuff::{LuminanceCoefficients, Pixel},
+    precision::StoredPixel,
+};
+
+/// Scale down pixels whose luminance is above `k` times the median luminance of their 8 neighbors, to that limit. Hue is kept, and pixels of bright areas are left alone since their neighbors are bright too. Run on linear light before gain statistics
+pub fn clamp<P: StoredPixel>(
+    linear_light: &mut [P],
+    width: usize,
+    height: usize,
+    k: f32,
+    coefficients: &LuminanceCoefficients,
+) -> Result<(), String> {
+    if !k.is_finite() || k < 1.0 {
+        return Err(format!("Firefly clamp must be at least 1, got {}", k));
+    }
+    let luminance: Vec<f32> = linear_light
+        .iter()
+        .map(|p| coefficients.luminance(&p.load()))
+        .collect();
+
+    let mut clamped = 0;
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            // Neighbors inside the image, borders and corners having fewer
+            let mut neighbors = [0.0; 8];
+            let mut count = 0;
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    if (nx, ny) != (x, y) {
+                        neighbors[count] = luminance[ny * width + nx];
+                        count += 1;
+                    }
+                }
+            }
+            if count == 0 {
+                continue;
+            }
+            let neighbors = &mut neighbors[..count];
+            neighbors.sort_unstable_by(f32::total_cmp);
+            let limit = k * neighbors[count / 2];
+            if luminance[index] > limit {
+                let scale = limit / luminance[index];
+                let pixel = linear_light[index].load();
+                linear_light[index] = P::store(Pixel {
+                    r: pixel.r * scale,
+                    g: pixel.g * scale,
+                    b: pixel.b * scale,
+                });
+                clamped += 1;
+            }
+        }
+    }
+    info!(pixels = clamped, k, "Clamped fireflies");
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use tracing::warn;
+
+use crate::Outputs;
+
+/// Inclusive range of frame numbers, as `1001-1100` or a single `1001`
+#[derive(Debug, Copy, Clone)]
+pub struct FrameRange {
+    pub first: u32,
+    pub last: u32,
+}
+
+pub fn parse_frame_range(text: &str) -> Result<FrameRange, String> {
+    let (first, last) = text.split_once('-').unwrap_or((text, text));
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<u32>()
+            .map_err(|e| format!("invalid frame number {:?}: {}", n, e))
+    };
+    let range = FrameRange {
+        first: parse(first)?,
+        last: parse(last)?,
+    };
+    if range.last < range.first {
+        return Err(format!(
+            "last frame {} is before first frame {}",
+            range.last, range.first
+        ));
+    }
+    Ok(range)
+}
+
+/// What to do when a frame of the range does not exist on disk
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum MissingFrames {
+    /// Stop before converting anything
+    Error,
+    /// Leave a gap in outputs
+    Skip,
+    /// Repeat the last existing frame, written under the missing frame's number
+    Hold,
+}
+
+/// Replace the printf-style `%d` or `%0Nd` in a path with a frame number. None if there is no such pattern
+pub fn expand(path: &Path, frame: u32) -> Option<PathBuf> {
+    let text = path.to_str()?;
+    let start = text.find('%')?;
+    let rest = &text[start + 1..];
+    let digits = rest.find('d')?;
+    let width = &rest[..digits];
+    if !width.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let width: usize = width.parse().unwrap_or(0);
+    Some(PathBuf::from(format!(
+        "{}{:0width$}{}",
+        &text[..start],
+        frame,
+        &rest[digits + 1..],
+        width = width
+    )))
+}
+
+/// List every frame to convert with its outputs, following the missing frame policy
+pub fn list(
+    pattern: &Path,
+    range: FrameRange,
+    missing: MissingFrames,
+    outputs: &Outputs,
+) -> Result<Vec<(PathBuf, Outputs)>, String> {
+    if expand(pattern, range.first).is_none() {
+        return Err(format!(
+            "{} has no frame number pattern such as %04d",
+            pattern.display()
+        ));
+    }
+
+    let mut jobs = Vec::new();
+    let mut last_existing: Option<PathBuf> = None;
+    for frame in range.first..=range.last {
+        let input = expand(pattern, frame).unwrap();
+        let source = if input.exists() {
+            last_existing = Some(input.clone());
+            input.clone()
+        } else {
+            match (missing, &last_existing) {
+                (MissingFrames::Error, _) => {
+                    return Err(format!("Frame {} is missing: {}", frame, input.display()))
+                }
+                (MissingFrames::Hold, Some(held)) => {
+                    warn!(frame, held = %held.display(), "Missing frame, holding last one");
+                    held.clone()
+                }
+                (MissingFrames::Hold, None) | (MissingFrames::Skip, _) => {
+                    warn!(frame, file = %input.display(), "Missing frame, skipping");
+                    continue;
+                }
+            }
+        };
+        jobs.push((source, numbered(outputs, &input, frame)));
+    }
+    Ok(jobs)
+}
+
+/// Outputs of one frame. Output paths with a frame number pattern are expanded, others are directories with files named after the frame's input
+fn numbered(outputs: &Outputs, input: &Path, frame: u32) -> Outputs {
+    let in_directories = outputs.in_directories(input);
+    let pick = |pattern: &Option<PathBuf>, named: Option<PathBuf>| {
+        pattern.as_ref().and_then(|p| expand(p, frame)).or(named)
+    };
+
+    Outputs {
+        png: pick(&outputs.png, in_directories.png),
+        gain_map_png: pick(&outputs.gain_map_png, in_directories.gain_map_png),
+        jpg: pick(&outputs.jpg, in_directories.jpg),
+        ultra_hdr_jpg: pick(&outputs.ultra_hdr_jpg, in_directories.ultra_hdr_jpg),
+        gain_map_jpeg: pick(&outputs.gain_map_jpeg, in_directories.gain_map_jpeg),
+        sdr_preview: pick(&outputs.sdr_preview, in_directories.sdr_preview),
+        gamut_warning: pick(&outputs.gamut_warning, in_directories.gamut_warning),
+        histogram: pick(&outputs.histogram, in_directories.histogram),
+        waveform: pick(&outputs.waveform, in_directories.waveform),
+        web_export: pick(&outputs.web_export, in_directories.web_export),
+        aux_channel: outputs
+            .aux_channel
+            .iter()
+            .zip(in_directories.aux_channel)
+            .map(|(pattern, named)| match expand(&pattern.path, frame) {
+                Some(path) => pattern.with_path(path),
+                None => named,
+            })
+            .collect(),
+        emit: outputs.emit.clone(),
+        emit_to: pick(&outputs.emit_to, in_directories.emit_to),
+        contact_sheet_cell: outputs.contact_sheet_cell.clone(),
+    }
+}
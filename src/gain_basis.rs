@@ -0,0 +1,25 @@
+// What Gain Map gains are the ratio of, libultrahdr offering the same choices
+
+use clap::ValueEnum;
+
+use crate::gain::GainBasis;
+
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum Basis {
+    /// Relative luminance of the output color space. Saturated lights are under-boosted, their luminance being low
+    Luminance,
+    /// Largest component, boosting saturated lights by their brightest channel
+    MaxRgb,
+    /// Mean of components
+    MeanRgb,
+}
+
+impl From<Basis> for GainBasis {
+    fn from(value: Basis) -> Self {
+        match value {
+            Basis::Luminance => GainBasis::Luminance,
+            Basis::MaxRgb => GainBasis::MaxRgb,
+            Basis::MeanRgb => GainBasis::MeanRgb,
+        }
+    }
+}
@@ -0,0 +1,161 @@
+// Synthetic test EXRs, to check display chains and conversions without renders
+
+use std::{f32::consts::PI, path::PathBuf};
+
+use clap::{Args, ValueEnum};
+use exr::prelude::{Encoding, Image, Layer, LayerAttributes, SpecificChannels, WritableImage};
+use tracing::info;
+
+use crate::{
+    color_spaces::ColorSpace, color_stuff::to_exr_chromaticities, projection::parse_size,
+    SDR_WHITE_NITS,
+};
+
+/// Luminance of the HDR test chart patches, left to right, in nits
+const CHART_NITS: [f32; 12] = [
+    0.0, 0.1, 1.0, 10.0, 50.0, 100.0, 203.0, 400.0, 1000.0, 2000.0, 4000.0, 10000.0,
+];
+/// Lowest stop of ramps, relative to SDR white
+const RAMP_MIN_STOPS: f32 = -10.0;
+/// Brightest value of patterns, PQ's peak
+const PEAK_NITS: f32 = 10000.0;
+/// Squares per shortest side of the checkerboard
+const CHECKERBOARD_SQUARES: usize = 8;
+
+/// Write a synthetic scene-referred EXR, 1.0 being SDR white, to check a display chain or the conversion itself
+#[derive(Args)]
+pub struct GenerateArgs {
+    /// Test pattern to draw
+    pattern: Pattern,
+    /// EXR file to write
+    output: PathBuf,
+    /// Image size, as WIDTHxHEIGHT
+    #[arg(long, value_parser = parse_size, default_value = "1920x1080")]
+    size: (usize, usize),
+    /// Luminance of 1.0 in nits, written as whiteLuminance so conversions keep levels of patterns in nits
+    #[arg(long, default_value_t = SDR_WHITE_NITS)]
+    white_nits: f32,
+    /// Color space of the RGB values, written as chromaticities
+    #[arg(long, default_value = "rec709")]
+    chromaticities: ColorSpace,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone)]
+enum Pattern {
+    /// Neutral, red, green and blue bands ramping in stops from 10 below SDR white to 10000 nits, left to right
+    Gradient,
+    /// Fully saturated hues left to right, from 10000 nits at the top down to 10 stops below SDR white
+    Sweep,
+    /// Concentric rings getting finer up to the Nyquist frequency on the shortest side's edge, for resampling and compression artifacts
+    ZonePlate,
+    /// Neutral columns of known luminance: 0, 0.1, 1, 10, 50, 100, 203, 400, 1000, 2000, 4000 and 10000 nits
+    Chart,
+    /// Black and SDR white squares, for scaling and geometry
+    Checkerboard,
+}
+
+pub fn run(args: &GenerateArgs) -> Result<(), String> {
+    let (width, height) = args.size;
+    if width == 0 || height == 0 {
+        return Err("Image size must be positive".to_string());
+    }
+    let pattern = args.pattern;
+    let white = args.white_nits;
+    let channels = SpecificChannels::rgb(|position: exr::math::Vec2<usize>| {
+        pixel(pattern, position.x(), position.y(), width, height, white)
+    });
+    let mut image = Image::from_layer(Layer::new(
+        (width, height),
+        LayerAttributes {
+            white_luminance: Some(white),
+            ..LayerAttributes::default()
+        },
+        Encoding::SMALL_LOSSLESS,
+        channels,
+    ));
+    image.attributes.chromaticities =
+        Some(to_exr_chromaticities(args.chromaticities.chromaticities()));
+
+    if let Pattern::Chart = pattern {
+        info!(nits = ?CHART_NITS, "Chart patches, left to right");
+    }
+    image
+        .write()
+        .to_file(&args.output)
+        .map_err(|e| format!("Could not write {}: {}", args.output.display(), e))?;
+    info!(
+        path = %args.output.display(),
+        pattern = ?pattern,
+        width,
+        height,
+        "Wrote test pattern"
+    );
+    Ok(())
+}
+
+/// Linear RGB of a pattern's pixel, 1.0 being `white` nits
+fn pixel(
+    pattern: Pattern,
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    white: f32,
+) -> (f32, f32, f32) {
+    let peak_stops = (PEAK_NITS / white).log2();
+    // Position from 0.0 to 1.0 across the image
+    let across = |position: usize, size: usize| position as f32 / (size.max(2) - 1) as f32;
+    let stops = |t: f32| RAMP_MIN_STOPS + (peak_stops - RAMP_MIN_STOPS) * t;
+
+    match pattern {
+        Pattern::Gradient => {
+            let v = stops(across(x, width)).exp2();
+            match y * 4 / height {
+                0 => (v, v, v),
+                1 => (v, 0.0, 0.0),
+                2 => (0.0, v, 0.0),
+                _ => (0.0, 0.0, v),
+            }
+        }
+        Pattern::Sweep => {
+            let v = stops(1.0 - across(y, height)).exp2();
+            let (r, g, b) = hue(across(x, width));
+            (r * v, g * v, b * v)
+        }
+        Pattern::ZonePlate => {
+            let radius = width.min(height) as f32;
+            let dx = x as f32 + 0.5 - width as f32 / 2.0;
+            let dy = y as f32 + 0.5 - height as f32 / 2.0;
+            // Local frequency is distance / radius cycles per pixel, reaching 0.5 at radius / 2
+            let v = 0.5 + 0.5 * (PI * (dx * dx + dy * dy) / radius).cos();
+            (v, v, v)
+        }
+        Pattern::Chart => {
+            let column_width = width as f32 / CHART_NITS.len() as f32;
+            let column = ((x as f32 / column_width) as usize).min(CHART_NITS.len() - 1);
+            // Black gaps between columns, so neighbors do not bleed on displays with local dimming
+            let inside = x as f32 - column as f32 * column_width;
+            let gap = column_width / 8.0;
+            let v = if inside < gap || inside >= column_width - gap {
+                0.0
+            } else {
+                CHART_NITS[column] / white
+            };
+            (v, v, v)
+        }
+        Pattern::Checkerboard => {
+            let square = (width.min(height) / CHECKERBOARD_SQUARES).max(1);
+            let v = ((x / square + y / square) % 2) as f32;
+            (v, v, v)
+        }
+    }
+}
+
+/// Fully saturated color of a hue from 0.0 to 1.0, largest component being 1.0
+fn hue(h: f32) -> (f32, f32, f32) {
+    let component = |offset: f32| {
+        let k = (h * 6.0 + offset) % 6.0;
+        1.0 - (k.min(4.0 - k).clamp(0.0, 1.0))
+    };
+    (component(5.0), component(3.0), component(1.0))
+}
@@ -0,0 +1,221 @@
+use clap::ValueEnum;
+use tracing::warn;
+
+use crate::{gain_stats::GainStats, planar::PlanarRgb, precision::StoredPixel, PixelParameters};
+
+/// Where pixel processing runs
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum Device {
+    Cpu,
+    Gpu,
+}
+
+/// Pixel processing without GPU support compiled in, always falls back to CPU
+#[cfg(not(feature = "gpu"))]
+pub fn process<P: StoredPixel>(
+    _linear_light: &mut [P],
+    _parameters: &PixelParameters,
+) -> Option<(PlanarRgb, Vec<f32>, GainStats)> {
+    warn!("Built without the \"gpu\" feature, falling back to CPU");
+    None
+}
+
+/// Run `process_cpu` equivalent as a compute shader. Returns None if no usable GPU could be found
+#[cfg(feature = "gpu")]
+pub fn process<P: StoredPixel>(
+    linear_light: &mut [P],
+    parameters: &PixelParameters,
+) -> Option<(PlanarRgb, Vec<f32>, GainStats)> {
+    let output = pollster::block_on(gpu::process(linear_light, parameters));
+    if output.is_none() {
+        warn!("No usable GPU found, falling back to CPU");
+    }
+    output
+}
+
+#[cfg(feature = "gpu")]
+mod gpu {
+    use wgpu::util::DeviceExt;
+
+    use crate::{
+        color_stuff::Pixel, gain_stats::GainStats, planar::PlanarRgb, precision::StoredPixel,
+        Matrix3x3f, PixelParameters,
+    };
+
+    /// Pixels processed per dispatch, keeps buffers below default storage binding size limit
+    const CHUNK_PIXELS: usize = 1 << 22;
+    /// Must match shader
+    const WORKGROUP_SIZE: usize = 256;
+
+    pub async fn process<P: StoredPixel>(
+        linear_light: &mut [P],
+        parameters: &PixelParameters,
+    ) -> Option<(PlanarRgb, Vec<f32>, GainStats)> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor::default())
+            .await
+            .ok()?;
+
+        let module = device.create_shader_module(wgpu::include_wgsl!("shaders/process.wgsl"));
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("process"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let mut sdr_image = PlanarRgb::with_capacity(linear_light.len());
+        let mut pixel_gains = Vec::with_capacity(linear_light.len());
+        let mut stats = GainStats::default();
+        for chunk in linear_light.chunks_mut(CHUNK_PIXELS) {
+            let count = chunk.len();
+
+            let uniform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("parameters"),
+                contents: &uniform_bytes(parameters, count as u32),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let pixels_bytes: Vec<u8> = chunk
+                .iter()
+                .flat_map(|p| {
+                    let p = p.load();
+                    [p.r, p.g, p.b]
+                })
+                .flat_map(f32::to_le_bytes)
+                .collect();
+            let pixels = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("pixels"),
+                contents: &pixels_bytes,
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            });
+            let image = storage_buffer(&device, "image_data", count * 4);
+            let gains = storage_buffer(&device, "gains", count * 4);
+
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: uniform.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: pixels.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: image.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: gains.as_entire_binding(),
+                    },
+                ],
+            });
+
+            let mut encoder = device.create_command_encoder(&Default::default());
+            {
+                let mut pass = encoder.begin_compute_pass(&Default::default());
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.dispatch_workgroups(count.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+            }
+
+            // Read everything back
+            let pixels_read = read_buffer(&device, "pixels_read", pixels_bytes.len());
+            let image_read = read_buffer(&device, "image_data_read", count * 4);
+            let gains_read = read_buffer(&device, "gains_read", count * 4);
+            encoder.copy_buffer_to_buffer(&pixels, 0, &pixels_read, 0, None);
+            encoder.copy_buffer_to_buffer(&image, 0, &image_read, 0, None);
+            encoder.copy_buffer_to_buffer(&gains, 0, &gains_read, 0, None);
+            queue.submit([encoder.finish()]);
+
+            for buffer in [&pixels_read, &image_read, &gains_read] {
+                buffer.map_async(wgpu::MapMode::Read, .., |result| result.unwrap());
+            }
+            device.poll(wgpu::PollType::wait_indefinitely()).ok()?;
+
+            let pixels_view = pixels_read.get_mapped_range(..).ok()?;
+            for (pixel, values) in chunk.iter_mut().zip(pixels_view.chunks_exact(12)) {
+                *pixel = P::store(Pixel {
+                    r: f32::from_le_bytes(values[0..4].try_into().unwrap()),
+                    g: f32::from_le_bytes(values[4..8].try_into().unwrap()),
+                    b: f32::from_le_bytes(values[8..12].try_into().unwrap()),
+                });
+            }
+            let image_view = image_read.get_mapped_range(..).ok()?;
+            for packed in image_view.chunks_exact(4) {
+                sdr_image.push([packed[0], packed[1], packed[2]])
+            }
+            let gains_view = gains_read.get_mapped_range(..).ok()?;
+            for gain in gains_view.chunks_exact(4) {
+                let gain = f32::from_le_bytes(gain.try_into().unwrap());
+                stats.add(gain);
+                pixel_gains.push(gain)
+            }
+        }
+
+        Some((sdr_image, pixel_gains, stats))
+    }
+
+    fn storage_buffer(device: &wgpu::Device, label: &str, size: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn read_buffer(device: &wgpu::Device, label: &str, size: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(label),
+            size: size as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Lay out parameters following WGSL uniform alignment rules (mat3x3 columns are padded to 16 bytes)
+    fn uniform_bytes(parameters: &PixelParameters, count: u32) -> Vec<u8> {
+        let matrix = parameters
+            .conversion_matrix
+            .unwrap_or_else(Matrix3x3f::identity);
+
+        let mut bytes = Vec::with_capacity(112);
+        for column in 0..3 {
+            for row in 0..3 {
+                bytes.extend(matrix[(row, column)].to_le_bytes());
+            }
+            bytes.extend(0.0f32.to_le_bytes());
+        }
+        bytes.extend(parameters.coefficients.red.to_le_bytes());
+        bytes.extend(parameters.coefficients.green.to_le_bytes());
+        bytes.extend(parameters.coefficients.blue.to_le_bytes());
+        bytes.extend(parameters.factor.to_le_bytes());
+        bytes.extend((parameters.transfer as u32).to_le_bytes());
+        bytes.extend(parameters.offset_hdr.to_le_bytes());
+        bytes.extend(parameters.offset_sdr.to_le_bytes());
+        bytes.extend(count.to_le_bytes());
+        bytes.extend(parameters.trims.saturation.to_le_bytes());
+        bytes.extend(parameters.trims.contrast.to_le_bytes());
+        bytes.extend(parameters.trims.pivot.to_le_bytes());
+        bytes.extend(parameters.trims.knee_start.to_le_bytes());
+        bytes.extend(parameters.trims.knee_strength.to_le_bytes());
+        bytes.extend((parameters.gain_basis as u32).to_le_bytes());
+        // Struct size is rounded up to 16 bytes
+        bytes.extend([0; 8]);
+        bytes
+    }
+}
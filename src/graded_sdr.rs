@@ -0,0 +1,123 @@
+// SDR grade from a colorist defining the base image, the main EXR defining HDR and the Gain Map their ratio
+
+use std::path::Path;
+
+use clap::ValueEnum;
+use num_traits::{NumCast, ToPrimitive};
+use tracing::info;
+
+use crate::{
+    aux_channels, calculate_gain,
+    color_stuff::{from_exr_chromaticities, Chromaticities, Pixel},
+    decode::read_exr,
+    gain_stats::GainStats,
+    planar::PlanarRgb,
+    precision::{Component, StoredPixel},
+    quantize,
+    transfer_functions::Transfer,
+    App, PixelParameters,
+};
+
+/// How values of the SDR EXR are encoded
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum SdrLight {
+    /// Linear display light, 1.0 being SDR white
+    Linear,
+    /// Encoded with the output transfer function, as sent to the display
+    Display,
+}
+
+/// Linear display light of the SDR grade in `output` color space, display light being decoded with `transfer`. Its own color space is taken from its chromaticities, `input` ones if it has none. It must be `size`, the main EXR's size
+pub fn read(
+    path: &Path,
+    args: &App,
+    transfer: Transfer,
+    size: (usize, usize),
+    input: &Chromaticities,
+    output: &Chromaticities,
+) -> Result<Vec<Pixel>, String> {
+    let image = read_exr(path, args.decode_threads, args.mmap, None, args.io_retries)?;
+    let sdr_size = image.attributes.display_window.size;
+    if (sdr_size.0, sdr_size.1) != size {
+        return Err(format!(
+            "SDR EXR is {}x{}, the HDR one {}x{}",
+            sdr_size.0, sdr_size.1, size.0, size.1
+        ));
+    }
+
+    let mut pixels = vec![Pixel::default(); size.0 * size.1];
+    for channel in &image.layer_data.channel_data.list {
+        let store: fn(&mut Pixel, f32) = match channel.name.to_string().as_str() {
+            "R" => |p, v| p.r = v,
+            "G" => |p, v| p.g = v,
+            "B" => |p, v| p.b = v,
+            _ => continue,
+        };
+        let samples = aux_channels::samples(&channel.sample_data);
+        if samples.len() != pixels.len() {
+            return Err(format!(
+                "Channel {} of the SDR EXR is subsampled",
+                channel.name
+            ));
+        }
+        for (pixel, sample) in pixels.iter_mut().zip(samples) {
+            store(pixel, sample);
+        }
+    }
+
+    if let SdrLight::Display = args.sdr_exr_light {
+        let decode = |v: f32| transfer.decode(v.clamp(0.0, 1.0));
+        for pixel in &mut pixels {
+            *pixel = Pixel {
+                r: decode(pixel.r),
+                g: decode(pixel.g),
+                b: decode(pixel.b),
+            };
+        }
+    }
+
+    let chromaticities = image
+        .attributes
+        .chromaticities
+        .map_or(*input, from_exr_chromaticities);
+    let matrix = chromaticities
+        .rgb_space_conversion_matrix(output)
+        .ok_or_else(|| "SDR EXR chromaticities are invalid".to_string())?;
+    for pixel in &mut pixels {
+        *pixel = pixel.transform(&matrix);
+    }
+    info!(path = %path.display(), "Using SDR grade as base image");
+    Ok(pixels)
+}
+
+/// Gamma-encoded u8 RGB planes of the SDR grade, then gains from it to HDR `linear_light`, both in output color space, and gain statistics
+pub fn process<P: StoredPixel>(
+    linear_light: &[P],
+    sdr: &[Pixel],
+    parameters: &PixelParameters,
+) -> (PlanarRgb, Vec<P::Float>, GainStats) {
+    let coefficients = P::Float::coefficients(parameters);
+    let value = |v: f32| -> P::Float { NumCast::from(v).unwrap() };
+    let (offset_hdr, offset_sdr) = (value(parameters.offset_hdr), value(parameters.offset_sdr));
+    let mut stats = GainStats::default();
+    let mut image = PlanarRgb::with_capacity(sdr.len());
+    let pixel_gains = linear_light
+        .iter()
+        .zip(sdr)
+        .map(|(hdr, sdr)| {
+            let encode = |v: f32| quantize(parameters.transfer.encode(v.clamp(0.0, 1.0)));
+            image.push([encode(sdr.r), encode(sdr.g), encode(sdr.b)]);
+            let gain = calculate_gain(
+                &hdr.load_full(),
+                &sdr.cast(),
+                parameters.gain_basis,
+                &coefficients,
+                offset_hdr,
+                offset_sdr,
+            );
+            stats.add(gain.to_f32().unwrap_or_default());
+            gain
+        })
+        .collect();
+    (image, pixel_gains, stats)
+}
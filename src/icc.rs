@@ -0,0 +1,318 @@
+// https://www.color.org/specification/ICC.1-2022-05.pdf
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+
+use crate::{
+    chromatic_adaptation::Cat,
+    cicp::Cicp,
+    color_spaces::{ColorSpace, D50_ILLUMINANT, DISPLAY_P3, REC_2020, REC_709},
+    color_stuff::{CIEXYZCoords, CIExyCoords, Chromaticities},
+    transfer_functions::Transfer,
+    Matrix3x1f, Matrix3x3f,
+};
+
+/// Size of ICC profile header, tag table follows
+const HEADER_SIZE: usize = 128;
+/// Version 4.4
+const VERSION: u32 = 0x04400000;
+/// Creation date written by deterministic builds (year, month, day, hours, minutes, seconds)
+const FIXED_CREATION_DATE: [u16; 6] = [1970, 1, 1, 0, 0, 0];
+const COPYRIGHT: &str = "No copyright, use freely";
+
+/// Generate a serialized ICC v4 display profile: D50-adapted colorants using the given CAT, parametric TRC, a description naming the color space and optionally CICP code points. If deterministic, creation date is fixed so identical inputs give identical bytes
+pub fn make_profile(
+    chromaticities: &Chromaticities,
+    transfer: Transfer,
+    cat: Cat,
+    description: &str,
+    cicp: Option<Cicp>,
+    deterministic: bool,
+) -> Vec<u8> {
+    let adaptation = cat.adaptation_matrix(chromaticities.white, D50_ILLUMINANT);
+    // Colorants are columns of the RGB to XYZ matrix, adapted to the PCS white
+    let colorants = adaptation * chromaticities.rgb_to_xyz_matrix().unwrap();
+    let d50: CIEXYZCoords = D50_ILLUMINANT.with_luma(1.0).into();
+
+    let (function_type, parameters) = transfer.icc_parameters();
+    let curve = para(function_type, &parameters);
+
+    // Sorted by signature. TRCs share the same data
+    let mut tags: Vec<(&[u8; 4], Vec<u8>)> = vec![
+        (b"bTRC", curve.clone()),
+        (b"bXYZ", xyz(colorants.column(2).into())),
+        (b"chad", sf32(&adaptation)),
+        (b"cprt", mluc(COPYRIGHT)),
+        (b"desc", mluc(description)),
+        (b"gTRC", curve.clone()),
+        (b"gXYZ", xyz(colorants.column(1).into())),
+        (b"rTRC", curve),
+        (b"rXYZ", xyz(colorants.column(0).into())),
+        (b"wtpt", xyz(Matrix3x1f::from(d50))),
+    ];
+    if let Some(cicp) = cicp {
+        tags.push((b"cicp", cicp_tag(&cicp)));
+        tags.sort_by_key(|(signature, _)| **signature);
+    }
+
+    let date = if deterministic {
+        FIXED_CREATION_DATE
+    } else {
+        now()
+    };
+
+    let mut profile = header(date, d50);
+    profile.extend((tags.len() as u32).to_be_bytes());
+
+    // Lay out data after the tag table, placing identical data only once
+    let data_start = HEADER_SIZE + 4 + tags.len() * 12;
+    let mut data: Vec<u8> = Vec::new();
+    let mut placed: Vec<(&[u8], usize)> = Vec::new();
+    for (signature, content) in &tags {
+        let offset = match placed.iter().find(|(c, _)| *c == content.as_slice()) {
+            Some((_, offset)) => *offset,
+            None => {
+                let offset = data_start + data.len();
+                data.extend(content);
+                // Align next tag to 32 bits
+                while !data.len().is_multiple_of(4) {
+                    data.push(0)
+                }
+                placed.push((content, offset));
+                offset
+            }
+        };
+        profile.extend(*signature);
+        profile.extend((offset as u32).to_be_bytes());
+        profile.extend((content.len() as u32).to_be_bytes());
+    }
+    profile.extend(data);
+
+    let size = profile.len() as u32;
+    profile[0..4].copy_from_slice(&size.to_be_bytes());
+    profile
+}
+
+/// Largest difference between chromaticities read from a profile and those of a known color space taken as the same, s15Fixed16 colorants and chad rounding them
+const KNOWN_SPACE_TOLERANCE: f32 = 2e-3;
+/// Largest difference between parameters of a parametric curve and those of a known transfer taken as the same
+const CURVE_TOLERANCE: f64 = 1e-3;
+
+/// Chromaticities and transfer of a matrix/TRC RGB profile, such as those [make_profile] writes. A cicp tag wins over colorants and curves when its code points are known. Colorants are adapted back from D50 with the chad tag. None for other profiles, or curves that are none of the known transfers
+pub fn read_profile(profile: &[u8]) -> Option<(Chromaticities, Transfer)> {
+    if profile.len() < HEADER_SIZE + 4 || &profile[16..20] != b"RGB " {
+        return None;
+    }
+    let count = u32::from_be_bytes(profile[HEADER_SIZE..HEADER_SIZE + 4].try_into().unwrap());
+    let tag = |signature: &[u8; 4]| {
+        (0..count as usize).find_map(|i| {
+            let entry = profile.get(HEADER_SIZE + 4 + i * 12..HEADER_SIZE + 16 + i * 12)?;
+            if &entry[0..4] != signature {
+                return None;
+            }
+            let offset = u32::from_be_bytes(entry[4..8].try_into().unwrap()) as usize;
+            let size = u32::from_be_bytes(entry[8..12].try_into().unwrap()) as usize;
+            profile.get(offset..offset.checked_add(size)?)
+        })
+    };
+
+    if let Some(encoding) = tag(b"cicp").and_then(read_cicp) {
+        return Some(encoding);
+    }
+
+    let fixed = |data: &[u8], index: usize| -> Option<f64> {
+        let bytes = data.get(8 + index * 4..12 + index * 4)?;
+        Some(i32::from_be_bytes(bytes.try_into().unwrap()) as f64 / 65536.0)
+    };
+    let mut colorants = Matrix3x3f::zeros();
+    for (column, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].into_iter().enumerate() {
+        let data = tag(signature)?;
+        for row in 0..3 {
+            colorants[(row, column)] = fixed(data, row)? as f32;
+        }
+    }
+    let adaptation = match tag(b"chad") {
+        Some(data) => {
+            let mut matrix = Matrix3x3f::zeros();
+            for index in 0..9 {
+                matrix[(index / 3, index % 3)] = fixed(data, index)? as f32;
+            }
+            matrix
+        }
+        None => Matrix3x3f::identity(),
+    };
+    let rgb_to_xyz = adaptation.try_inverse()? * colorants;
+    let xy = |xyz: Matrix3x1f| CIEXYZCoords::from(xyz).to_xyy(D50_ILLUMINANT).coords;
+    let read = Chromaticities {
+        red: xy(rgb_to_xyz.column(0).into()),
+        green: xy(rgb_to_xyz.column(1).into()),
+        blue: xy(rgb_to_xyz.column(2).into()),
+        white: xy(rgb_to_xyz * Matrix3x1f::new(1.0, 1.0, 1.0)),
+    };
+    let chromaticities = ColorSpace::value_variants()
+        .iter()
+        .map(|space| space.chromaticities())
+        .find(|known| {
+            let close = |a: CIExyCoords, b: CIExyCoords| {
+                (a.x - b.x).abs() < KNOWN_SPACE_TOLERANCE
+                    && (a.y - b.y).abs() < KNOWN_SPACE_TOLERANCE
+            };
+            close(known.red, read.red)
+                && close(known.green, read.green)
+                && close(known.blue, read.blue)
+                && close(known.white, read.white)
+        })
+        .unwrap_or(read);
+
+    // Channels share one curve in profiles written here
+    let curve = tag(b"rTRC").filter(|data| data.starts_with(b"para"))?;
+    let function_type = u16::from_be_bytes(curve.get(8..10)?.try_into().unwrap());
+    let parameters: Vec<f64> = (1..).map_while(|index| fixed(curve, index)).collect();
+    let transfer = Transfer::value_variants()
+        .iter()
+        .copied()
+        .find(|transfer| {
+            let (known_type, known) = transfer.icc_parameters();
+            known_type == function_type
+                && known.len() <= parameters.len()
+                && known
+                    .iter()
+                    .zip(&parameters)
+                    .all(|(a, b)| (a - b).abs() < CURVE_TOLERANCE)
+        })?;
+    Some((chromaticities, transfer))
+}
+
+/// Chromaticities and transfer of known CICP code points, the inverse of [Cicp::for_output]
+fn read_cicp(tag: &[u8]) -> Option<(Chromaticities, Transfer)> {
+    let chromaticities = match tag.get(8)? {
+        1 => REC_709,
+        9 => REC_2020,
+        12 => DISPLAY_P3,
+        _ => return None,
+    };
+    let transfer = match tag.get(9)? {
+        13 => Transfer::Srgb,
+        4 => Transfer::Gamma22,
+        1 => Transfer::Bt1886,
+        _ => return None,
+    };
+    Some((chromaticities, transfer))
+}
+
+/// Display class RGB profile header, size left to be filled in
+fn header(date: [u16; 6], illuminant: CIEXYZCoords) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend(0u32.to_be_bytes()); // Size
+    header.extend(0u32.to_be_bytes()); // Preferred CMM
+    header.extend(VERSION.to_be_bytes());
+    header.extend(b"mntr");
+    header.extend(b"RGB ");
+    header.extend(b"XYZ ");
+    for value in date {
+        header.extend(value.to_be_bytes());
+    }
+    header.extend(b"acsp");
+    header.extend([0; 4]); // Platform
+    header.extend([0; 4]); // Flags
+    header.extend([0; 4]); // Manufacturer
+    header.extend([0; 4]); // Model
+    header.extend([0; 8]); // Attributes
+    header.extend(0u32.to_be_bytes()); // Perceptual intent
+    header.extend(xyz_number(illuminant));
+    header.extend([0; 4]); // Creator
+    header.extend([0; 16]); // Profile ID, zero means not computed
+    header.resize(HEADER_SIZE, 0);
+    header
+}
+
+fn s15_fixed16(value: f64) -> [u8; 4] {
+    ((value * 65536.0).round() as i32).to_be_bytes()
+}
+
+fn xyz_number(color: CIEXYZCoords) -> Vec<u8> {
+    [color.x, color.y, color.z]
+        .iter()
+        .flat_map(|v| s15_fixed16(*v as f64))
+        .collect()
+}
+
+/// XYZType
+fn xyz(color: Matrix3x1f) -> Vec<u8> {
+    let mut tag = b"XYZ \0\0\0\0".to_vec();
+    tag.extend(xyz_number(color.into()));
+    tag
+}
+
+/// s15Fixed16ArrayType holding a matrix in row order
+fn sf32(matrix: &Matrix3x3f) -> Vec<u8> {
+    let mut tag = b"sf32\0\0\0\0".to_vec();
+    for row in 0..3 {
+        for column in 0..3 {
+            tag.extend(s15_fixed16(matrix[(row, column)] as f64));
+        }
+    }
+    tag
+}
+
+/// cicpType
+fn cicp_tag(cicp: &Cicp) -> Vec<u8> {
+    let mut tag = b"cicp\0\0\0\0".to_vec();
+    tag.extend(cicp.bytes());
+    tag
+}
+
+/// parametricCurveType
+fn para(function_type: u16, parameters: &[f64]) -> Vec<u8> {
+    let mut tag = b"para\0\0\0\0".to_vec();
+    tag.extend(function_type.to_be_bytes());
+    tag.extend([0; 2]);
+    for parameter in parameters {
+        tag.extend(s15_fixed16(*parameter));
+    }
+    tag
+}
+
+/// multiLocalizedUnicodeType with a single en-US record
+fn mluc(text: &str) -> Vec<u8> {
+    let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let mut tag = b"mluc\0\0\0\0".to_vec();
+    tag.extend(1u32.to_be_bytes()); // Record count
+    tag.extend(12u32.to_be_bytes()); // Record size
+    tag.extend(b"enUS");
+    tag.extend((utf16.len() as u32).to_be_bytes());
+    tag.extend(28u32.to_be_bytes()); // Offset of string from tag start
+    tag.extend(utf16);
+    tag
+}
+
+/// Current UTC date and time (year, month, day, hours, minutes, seconds)
+pub fn now() -> [u16; 6] {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    [
+        year as u16,
+        month as u16,
+        day as u16,
+        (time / 3600) as u16,
+        (time % 3600 / 60) as u16,
+        (time % 60) as u16,
+    ]
+}
@@ -0,0 +1,81 @@
+// Input files told apart by their first bytes rather than their extension, so a single positional argument takes every format
+
+use std::{io::Read, path::Path};
+
+use clap::ValueEnum;
+use exr::prelude::{AnyChannel, AnyChannels, FlatSamples, Image, SmallVec};
+
+use crate::{
+    color_stuff::{to_exr_chromaticities, Chromaticities},
+    decode::ExrImage,
+    retry,
+};
+
+/// Formats of input images
+#[derive(ValueEnum, Debug, Copy, Clone, PartialEq)]
+pub enum InputFormat {
+    /// OpenEXR
+    Exr,
+    /// PQ or HLG PNG, such as a frame of HDR video
+    Png,
+    /// Radiance RGBE
+    Hdr,
+    /// Portable float map
+    Pfm,
+    /// TIFF with floating-point samples
+    Tiff,
+    /// Ultra HDR JPEG, read as its HDR rendition to be re-encoded
+    UltraHdr,
+}
+
+/// Enough bytes to tell every format apart
+const MAGIC_LENGTH: usize = 10;
+
+impl InputFormat {
+    /// Format of a file starting with `magic`
+    pub fn detect(magic: &[u8]) -> Option<InputFormat> {
+        let format = match magic {
+            [0x76, 0x2f, 0x31, 0x01, ..] => InputFormat::Exr,
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n', ..] => InputFormat::Png,
+            [b'#', b'?', b'R', b'A', b'D', b'I', b'A', b'N', b'C', b'E', ..]
+            | [b'#', b'?', b'R', b'G', b'B', b'E', ..] => InputFormat::Hdr,
+            [b'P', b'F' | b'f', space, ..] if space.is_ascii_whitespace() => InputFormat::Pfm,
+            [b'I', b'I', 42, 0, ..] | [b'M', b'M', 0, 42, ..] => InputFormat::Tiff,
+            [0xff, 0xd8, 0xff, ..] => InputFormat::UltraHdr,
+            _ => return None,
+        };
+        Some(format)
+    }
+
+    /// Format of the file at `path` from its first bytes, retrying transient errors up to `retries` times
+    pub fn sniff(path: &Path, retries: u32) -> Result<InputFormat, String> {
+        let file = retry::open(path, retries)
+            .map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        let mut magic = Vec::with_capacity(MAGIC_LENGTH);
+        file.take(MAGIC_LENGTH as u64)
+            .read_to_end(&mut magic)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        InputFormat::detect(&magic).ok_or_else(|| {
+            format!(
+                "{} is not an EXR, PNG, Radiance HDR, PFM, TIFF or Ultra HDR JPEG file, specify --input-format",
+                path.display()
+            )
+        })
+    }
+}
+
+/// Image of R, G and B channels, as if read from an EXR. Without chromaticities, Rec. 709 is assumed like for EXRs
+pub fn rgb_image(
+    width: usize,
+    height: usize,
+    [r, g, b]: [Vec<f32>; 3],
+    chromaticities: Option<Chromaticities>,
+) -> ExrImage {
+    let mut list: SmallVec<[AnyChannel<FlatSamples>; 4]> = SmallVec::new();
+    list.push(AnyChannel::new("R", FlatSamples::F32(r)));
+    list.push(AnyChannel::new("G", FlatSamples::F32(g)));
+    list.push(AnyChannel::new("B", FlatSamples::F32(b)));
+    let mut image = Image::from_channels((width, height), AnyChannels::sort(list));
+    image.attributes.chromaticities = chromaticities.map(to_exr_chromaticities);
+    image
+}
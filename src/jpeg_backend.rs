@@ -0,0 +1,115 @@
+// Alternative encoders for primary images, run as their command line tools
+
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use clap::ValueEnum;
+use jpeg_encoder::{ColorType, Encoder, EncodingError};
+
+use crate::{
+    jpeg_bands::{self, JpegSettings},
+    planar::PlanarView,
+};
+
+/// Encoder used for the SDR image, the Gain Map always uses jpeg-encoder
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum JpegBackend {
+    /// Built in, pure Rust
+    JpegEncoder,
+    /// mozjpeg's `cjpeg`: trellis quantization, progressive scans
+    Mozjpeg,
+    /// `cjpegli` from libjxl: adaptive quantization
+    Jpegli,
+}
+
+/// Distinguishes temporary files of concurrent conversions
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Encode an RGB image to `writer`, read from its planes. The built-in encoder streams to it, external ones go through temporary files. `configure` adds segments to the header, which are transplanted into the output of external encoders
+pub fn encode(
+    writer: &mut dyn Write,
+    backend: JpegBackend,
+    settings: JpegSettings,
+    image: PlanarView,
+    configure: impl Fn(&mut Encoder<&mut dyn Write>) -> Result<(), EncodingError> + Sync,
+) -> Result<(), String> {
+    let quality_arg = settings.quality.to_string();
+    let (tool, arguments): (_, &[&str]) = match (backend, settings.progressive) {
+        (JpegBackend::JpegEncoder, _) => {
+            return jpeg_bands::encode(writer, settings, image, configure)
+                .map_err(|e| e.to_string())
+        }
+        // Both tools default to progressive
+        (JpegBackend::Mozjpeg, true) => ("cjpeg", &["-quality", &quality_arg, "-outfile"]),
+        (JpegBackend::Mozjpeg, false) => (
+            "cjpeg",
+            &["-quality", &quality_arg, "-baseline", "-outfile"],
+        ),
+        (JpegBackend::Jpegli, true) => ("cjpegli", &["-q", &quality_arg]),
+        (JpegBackend::Jpegli, false) => ("cjpegli", &["-q", &quality_arg, "--progressive_level=0"]),
+    };
+
+    let stem = std::env::temp_dir().join(format!(
+        "exr2ultra-hdr-{}-{}",
+        std::process::id(),
+        TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let (input, output) = (stem.with_extension("ppm"), stem.with_extension("jpg"));
+    write_ppm(&input, image).map_err(|e| format!("Could not write {}: {}", input.display(), e))?;
+
+    // cjpeg takes the output as an option, cjpegli as a second argument
+    let mut command = Command::new(tool);
+    match backend {
+        JpegBackend::Mozjpeg => command.args(arguments).arg(&output).arg(&input),
+        _ => command.arg(&input).arg(&output).args(arguments),
+    };
+    let status = command.status();
+    let encoded = fs::read(&output);
+    let _ = fs::remove_file(&input);
+    let _ = fs::remove_file(&output);
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => return Err(format!("{} failed ({})", tool, status)),
+        Err(e) => return Err(format!("Could not run {}: {}", tool, e)),
+    }
+    let encoded = encoded.map_err(|e| format!("Could not read {} output: {}", tool, e))?;
+
+    // Swap the encoder's own application segments for ours
+    let mut header = Vec::new();
+    let mut encoder = Encoder::new(&mut header as &mut dyn Write, settings.quality);
+    configure(&mut encoder).map_err(|e| e.to_string())?;
+    encoder
+        .encode(&[0; 3], 1, 1, ColorType::Rgb)
+        .map_err(|e| e.to_string())?;
+
+    writer
+        .write_all(&header[..application_segments_end(&header)])
+        .and_then(|_| writer.write_all(&encoded[application_segments_end(&encoded)..]))
+        .map_err(|e| e.to_string())
+}
+
+/// Binary PPM input of the command line tools, interleaved a row at a time
+fn write_ppm(path: &Path, image: PlanarView) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write!(writer, "P6\n{} {}\n255\n", image.width, image.height)?;
+    let mut row = Vec::with_capacity(image.width * 3);
+    for y in 0..image.height {
+        image.interleave_row(y, &mut row);
+        writer.write_all(&row)?;
+    }
+    writer.flush()
+}
+
+/// Position after the start of image marker and the application and comment segments following it
+fn application_segments_end(jpeg: &[u8]) -> usize {
+    let mut position = 2;
+    while position + 4 <= jpeg.len() && (matches!(jpeg[position + 1], 0xE0..=0xEF | 0xFE)) {
+        position += 2 + u16::from_be_bytes([jpeg[position + 2], jpeg[position + 3]]) as usize;
+    }
+    position
+}
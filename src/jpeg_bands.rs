@@ -0,0 +1,135 @@
+// JPEG encoding, optionally splitting the image in horizontal bands encoded in parallel
+
+use std::{io::Write, thread};
+
+use clap::ValueEnum;
+use jpeg_encoder::{Encoder, EncodingError};
+
+use crate::planar::PlanarView;
+
+/// Bands are made of a multiple of this many MCU rows, so restart markers numbering (modulo 8) carries over from band to band
+const BAND_MCU_ROWS: usize = 8;
+const SOF0: u8 = 0xC0;
+const SOS: u8 = 0xDA;
+const RST7: u8 = 0xD7;
+
+/// How JPEG images are encoded
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum EncoderMode {
+    /// Single thread, smallest files
+    Quality,
+    /// Bands of MCU rows on every core, joined with restart markers. Files are slightly larger
+    Fast,
+}
+
+/// Settings of a single JPEG image
+#[derive(Debug, Copy, Clone)]
+pub struct JpegSettings {
+    pub mode: EncoderMode,
+    pub quality: u8,
+    /// Progressive scans, always encoded on a single thread
+    pub progressive: bool,
+}
+
+/// Encode a gray or RGB image to a JPEG datastream written to `writer`, read from its planes. `configure` adds segments to the header, and must not change anything affecting the MCU layout
+pub fn encode(
+    writer: &mut dyn Write,
+    settings: JpegSettings,
+    image: PlanarView,
+    configure: impl Fn(&mut Encoder<&mut dyn Write>) -> Result<(), EncodingError> + Sync,
+) -> Result<(), EncodingError> {
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    encode_bands(writer, settings, threads, image, configure)
+}
+
+/// Same as `encode`, with fast mode splitting the image in bands for this many threads. Single images stream to `writer`, bands are buffered until joined
+pub fn encode_bands(
+    writer: &mut dyn Write,
+    settings: JpegSettings,
+    threads: usize,
+    image: PlanarView,
+    configure: impl Fn(&mut Encoder<&mut dyn Write>) -> Result<(), EncodingError> + Sync,
+) -> Result<(), EncodingError> {
+    let (width, height) = (image.width, image.height);
+    let encode_band = |writer: &mut dyn Write, rows: PlanarView, restart_interval: Option<u16>| {
+        let mut encoder = Encoder::new(writer, settings.quality);
+        encoder.set_progressive(settings.progressive);
+        configure(&mut encoder)?;
+        if let Some(interval) = restart_interval {
+            encoder.set_restart_interval(interval);
+        }
+        encoder.encode_image(rows)
+    };
+
+    let (mcu_width, mcu_height) = encoder_mcu(settings.quality, image.is_gray());
+    let band_height = height
+        .div_ceil(threads)
+        .next_multiple_of(mcu_height * BAND_MCU_ROWS);
+    // One restart interval per MCU row, so every band starts with fresh DC predictions
+    let restart_interval = u16::try_from(width.div_ceil(mcu_width)).ok();
+    if matches!(settings.mode, EncoderMode::Quality)
+        || settings.progressive
+        || band_height >= height
+        || restart_interval.is_none()
+    {
+        return encode_band(writer, image, None);
+    }
+
+    let bands = thread::scope(|scope| {
+        let handles: Vec<_> = (0..height)
+            .step_by(band_height)
+            .map(|start| {
+                let rows = image.rows(start, (start + band_height).min(height));
+                scope.spawn(move || {
+                    let mut band = Vec::new();
+                    encode_band(&mut band, rows, restart_interval)?;
+                    Ok::<_, EncodingError>(band)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    // Header of the first band, with the full height
+    let (first, rest) = bands.split_first().unwrap();
+    let (sof, _) = find_segment(first, SOF0).unwrap();
+    writer.write_all(&first[..sof + 5])?;
+    writer.write_all(&u16::try_from(height).unwrap().to_be_bytes())?;
+    writer.write_all(&first[sof + 7..first.len() - 2])?;
+    // Then entropy coded data of every band, without end of image markers
+    for band in rest {
+        let (_, scan) = find_segment(band, SOS).unwrap();
+        writer.write_all(&[0xFF, RST7])?;
+        writer.write_all(&band[scan..band.len() - 2])?;
+    }
+    writer.write_all(&[0xFF, 0xD9])?;
+    Ok(())
+}
+
+/// MCU size jpeg-encoder picks, its default sampling factor depends on quality
+fn encoder_mcu(quality: u8, gray: bool) -> (usize, usize) {
+    if gray {
+        return (8, 8);
+    }
+    let factors = Encoder::new(Vec::new(), quality).sampling_factor() as u8;
+    (
+        ((factors >> 4) & 0x07) as usize * 8,
+        (factors & 0x0F) as usize * 8,
+    )
+}
+
+/// Position of a marker segment, and of the byte following it
+fn find_segment(jpeg: &[u8], marker: u8) -> Option<(usize, usize)> {
+    let mut position = 2;
+    while position + 4 <= jpeg.len() {
+        let length = u16::from_be_bytes([jpeg[position + 2], jpeg[position + 3]]) as usize;
+        if jpeg[position + 1] == marker {
+            return Some((position, position + 2 + length));
+        }
+        position += 2 + length;
+    }
+    None
+}
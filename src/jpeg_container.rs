@@ -0,0 +1,159 @@
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+
+use crate::{
+    mpf::{self, MpEntry},
+    ultra_hdr_stuff::XMP_NAMESPACE,
+};
+
+const APP1_MARKER: u8 = 0xE1;
+const APP2_MARKER: u8 = 0xE2;
+const SOS_MARKER: u8 = 0xDA;
+
+/// Where an image ended up in the container
+#[derive(Copy, Clone, Debug)]
+pub struct ImageExtent {
+    /// Offset from the start of the file
+    pub offset: u64,
+    /// Size from SOI to EOI
+    pub length: u64,
+}
+
+/// Where a segment payload is in the file
+#[derive(Copy, Clone, Debug)]
+struct SegmentPayload {
+    offset: u64,
+    length: usize,
+}
+
+/// Writes JPEG images one after another straight to the output, then back-patches the MPF index and XMP of the primary image once every size is known.
+///
+/// The primary image must contain an MPF segment reserved with `mpf::index` and as many entries as images that will be added, and optionally an XMP segment at least as long as the final one.
+pub struct JpegContainerBuilder<F: Read + Write + Seek> {
+    file: F,
+    images: Vec<(u32, ImageExtent)>,
+}
+
+impl<F: Read + Write + Seek> JpegContainerBuilder<F> {
+    pub fn new(file: F) -> JpegContainerBuilder<F> {
+        JpegContainerBuilder {
+            file,
+            images: Vec::new(),
+        }
+    }
+
+    /// Encode an image at the end of the container. First image added is the primary one. Errors of `encode`, such as an encoder that failed, are passed on
+    pub fn add_image(
+        &mut self,
+        attribute: u32,
+        encode: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> io::Result<ImageExtent> {
+        let offset = self.file.seek(SeekFrom::End(0))?;
+        {
+            let mut writer = BufWriter::new(&mut self.file);
+            encode(&mut writer)?;
+            writer.flush()?;
+        }
+        let end = self.file.stream_position()?;
+
+        let extent = ImageExtent {
+            offset,
+            length: end - offset,
+        };
+        self.images.push((attribute, extent));
+        Ok(extent)
+    }
+
+    /// Patch MPF index with final sizes and offsets, and replace primary XMP with what `xmp` returns. The new XMP is padded with whitespace to fill the reserved segment
+    pub fn finish(mut self, xmp: impl FnOnce(&[ImageExtent]) -> Option<Vec<u8>>) -> io::Result<F> {
+        let segments = self.primary_segments()?;
+        let extents: Vec<ImageExtent> = self.images.iter().map(|(_, e)| *e).collect();
+
+        if let Some(new_xmp) = xmp(&extents) {
+            let reserved = segments
+                .iter()
+                .find(|(marker, _, payload)| {
+                    *marker == APP1_MARKER && payload.starts_with(XMP_NAMESPACE)
+                })
+                .map(|(_, s, _)| *s)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "no reserved XMP segment in primary image",
+                    )
+                })?;
+            self.patch(reserved, new_xmp, b' ')?;
+        }
+
+        let reserved = segments
+            .iter()
+            .find(|(marker, _, payload)| *marker == APP2_MARKER && mpf::is_index(payload))
+            .map(|(_, s, _)| *s)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no reserved MPF segment in primary image",
+                )
+            })?;
+        let base = reserved.offset + mpf::OFFSET_BASE as u64;
+        let entries: Vec<MpEntry> = self
+            .images
+            .iter()
+            .enumerate()
+            .map(|(index, (attribute, extent))| MpEntry {
+                attribute: *attribute,
+                size: extent.length.try_into().unwrap(),
+                // First image offset is always zero
+                offset: if index == 0 {
+                    0
+                } else {
+                    (extent.offset - base).try_into().unwrap()
+                },
+                ..Default::default()
+            })
+            .collect();
+        self.patch(reserved, mpf::index(&entries), 0)?;
+
+        self.file.flush()?;
+        Ok(self.file)
+    }
+
+    /// Overwrite a segment payload, padding if new data is shorter
+    fn patch(&mut self, segment: SegmentPayload, mut data: Vec<u8>, padding: u8) -> io::Result<()> {
+        if data.len() > segment.length {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "new segment data is larger than reserved space",
+            ));
+        }
+        data.resize(segment.length, padding);
+
+        self.file.seek(SeekFrom::Start(segment.offset))?;
+        self.file.write_all(&data)
+    }
+
+    /// List marker, position and contents of every segment before the image data of the primary image
+    fn primary_segments(&mut self) -> io::Result<Vec<(u8, SegmentPayload, Vec<u8>)>> {
+        let start = self.images.first().map(|(_, e)| e.offset).unwrap_or(0);
+        self.file.seek(SeekFrom::Start(start + 2))?; // Skip SOI
+
+        let mut segments = Vec::new();
+        loop {
+            let mut marker = [0u8; 2];
+            self.file.read_exact(&mut marker)?;
+            if marker[1] == SOS_MARKER {
+                break;
+            }
+
+            let mut length = [0u8; 2];
+            self.file.read_exact(&mut length)?;
+            let length = u16::from_be_bytes(length) as usize - 2;
+
+            let offset = self.file.stream_position()?;
+            let mut payload = vec![0u8; length];
+            self.file.read_exact(&mut payload)?;
+            segments.push((marker[1], SegmentPayload { offset, length }, payload));
+        }
+
+        Ok(segments)
+    }
+}
@@ -0,0 +1,45 @@
+// https://www.w3.org/TR/png-3/#cLLi-chunk, following CTA-861.3 definitions
+
+use crate::precision::StoredPixel;
+
+/// Unit of cLLi values, in nits
+const CLLI_UNIT: f32 = 0.0001;
+
+/// HDR10-style content light level of the HDR rendition
+#[derive(Debug, Copy, Clone)]
+pub struct ContentLight {
+    /// Brightest component of any pixel, in nits
+    pub max_cll: f32,
+    /// Average over the frame of the brightest component of every pixel, in nits
+    pub max_fall: f32,
+}
+
+impl ContentLight {
+    /// Measure linear light where 1.0 is `white_nits`, with light levels limited to the mastering display peak
+    pub fn measure<P: StoredPixel>(
+        linear_light: &[P],
+        white_nits: f32,
+        peak_nits: f32,
+    ) -> ContentLight {
+        let mut max_cll = 0.0f32;
+        let mut sum = 0.0f64;
+        for pixel in linear_light {
+            let pixel = pixel.load();
+            let level = (pixel.r.max(pixel.g).max(pixel.b) * white_nits).clamp(0.0, peak_nits);
+            max_cll = max_cll.max(level);
+            sum += level as f64;
+        }
+        ContentLight {
+            max_cll,
+            max_fall: (sum / linear_light.len().max(1) as f64) as f32,
+        }
+    }
+
+    /// Content of a PNG cLLi chunk
+    pub fn png_chunk(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(8);
+        data.extend(((self.max_cll / CLLI_UNIT).round() as u32).to_be_bytes());
+        data.extend(((self.max_fall / CLLI_UNIT).round() as u32).to_be_bytes());
+        data
+    }
+}
@@ -0,0 +1,190 @@
+// https://people.csail.mit.edu/sparis/publi/2007/siggraph/Chen_07_Real-time_Edge-Aware.pdf
+// https://people.csail.mit.edu/fredo/PUBLI/Siggraph2002/DurandBilateral.pdf
+// Local tone mapping of the SDR rendition: bright areas are brought down by regions, keeping their local contrast, and the Gain Map restores them for HDR displays
+
+use clap::ValueEnum;
+use tracing::info;
+use ultra_hdr_core::gain::sdr_pixel;
+
+use crate::{color_stuff::Pixel, precision::StoredPixel, PixelParameters};
+
+/// Grid cells along the longest side of the image, the size of regions getting their own exposure
+const SPATIAL_CELLS: usize = 16;
+/// Stops per grid cell along luminance, edges steeper than this are not blurred across
+const RANGE_STOPS: f32 = 1.0;
+/// Exposed luminance where compression of bright regions starts, darker ones keep the chosen exposure
+const KNEE: f32 = 0.18;
+/// Exposed luminance the brightest region lands on, leaving headroom for its detail
+const BRIGHTEST: f32 = 0.5;
+/// Darkest log2 luminance considered, lower ones are black for the grid
+const FLOOR_LOG2: f32 = -16.0;
+
+/// How the SDR rendition is made from exposed HDR
+#[derive(ValueEnum, Debug, Copy, Clone, Default)]
+pub enum SdrToneMap {
+    /// Same curve for every pixel, clipping highlights above SDR white
+    #[default]
+    Global,
+    /// Bright regions brought under SDR white as a whole, keeping their detail, like a window seen from a dark room. Edge-aware, through a bilateral grid of log luminance
+    Local,
+}
+
+/// SDR rendition of `linear_light` exposed by `factor` and trimmed, in output color space, where each pixel also gets the exposure of its region
+pub fn render<P: StoredPixel>(
+    linear_light: &[P],
+    width: usize,
+    height: usize,
+    factor: f32,
+    parameters: &PixelParameters,
+) -> Vec<Pixel> {
+    let trims = &parameters.trims;
+    let coefficients = &parameters.coefficients;
+    let exposed_log2: Vec<f32> = linear_light
+        .iter()
+        .map(|p| {
+            let y = coefficients.luminance(&p.load()) * factor;
+            y.max(0.0).log2().max(FLOOR_LOG2)
+        })
+        .collect();
+
+    let base = BilateralGrid::new(&exposed_log2, width, height).slice(&exposed_log2, width);
+
+    // Bases above the knee are compressed so the brightest one lands under SDR white
+    let (knee, target) = (KNEE.log2(), BRIGHTEST.log2());
+    let brightest = base.iter().copied().fold(f32::MIN, f32::max);
+    let compression = if brightest > target {
+        (target - knee) / (brightest - knee)
+    } else {
+        1.0
+    };
+    info!(
+        brightest_region_stops = brightest,
+        compression, "Local tone mapping"
+    );
+
+    linear_light
+        .iter()
+        .zip(&base)
+        .map(|(pixel, &base)| {
+            let mapped = if base > knee {
+                knee + (base - knee) * compression
+            } else {
+                base
+            };
+            let factor = factor * (mapped - base).exp2();
+            sdr_pixel(&pixel.load(), factor, trims, coefficients)
+        })
+        .collect()
+}
+
+/// Log luminance averaged over cells of position and luminance, so averages do not mix both sides of strong edges
+struct BilateralGrid {
+    /// Size of a cell in pixels
+    cell: f32,
+    /// Log2 luminance at the first luminance cell
+    low: f32,
+    size: [usize; 3],
+    /// Sum of log luminance and weight, per cell
+    cells: Vec<[f32; 2]>,
+}
+
+impl BilateralGrid {
+    fn new(log2: &[f32], width: usize, height: usize) -> BilateralGrid {
+        let cell = (width.max(height) as f32 / SPATIAL_CELLS as f32).max(1.0);
+        let low = log2.iter().copied().fold(f32::MAX, f32::min);
+        let high = log2.iter().copied().fold(f32::MIN, f32::max);
+        // A cell of margin around the image and luminance range, for the blur to spill into
+        let size = [
+            (width as f32 / cell).ceil() as usize + 3,
+            (height as f32 / cell).ceil() as usize + 3,
+            ((high - low) / RANGE_STOPS).ceil() as usize + 3,
+        ];
+        let mut grid = BilateralGrid {
+            cell,
+            low,
+            size,
+            cells: vec![[0.0; 2]; size[0] * size[1] * size[2]],
+        };
+
+        for (index, &value) in log2.iter().enumerate() {
+            let [x, y, z] = grid
+                .position(index % width, index / width, value)
+                .map(|p| p.round() as usize);
+            let cell = grid.index(x, y, z);
+            grid.cells[cell][0] += value;
+            grid.cells[cell][1] += 1.0;
+        }
+        for axis in 0..3 {
+            grid.blur(axis);
+        }
+        grid
+    }
+
+    /// Position in cells of a pixel and its log luminance
+    fn position(&self, x: usize, y: usize, log2: f32) -> [f32; 3] {
+        [
+            x as f32 / self.cell + 1.0,
+            y as f32 / self.cell + 1.0,
+            (log2 - self.low) / RANGE_STOPS + 1.0,
+        ]
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.size[1] + y) * self.size[0] + x
+    }
+
+    /// 1 2 1 binomial blur along an axis, borders staying empty
+    fn blur(&mut self, axis: usize) {
+        let stride = [1, self.size[0], self.size[0] * self.size[1]][axis];
+        let source = self.cells.clone();
+        for z in 0..self.size[2] {
+            for y in 0..self.size[1] {
+                for x in 0..self.size[0] {
+                    let position = [x, y, z][axis];
+                    if position == 0 || position + 1 == self.size[axis] {
+                        continue;
+                    }
+                    let index = self.index(x, y, z);
+                    let [before, here, after] =
+                        [index - stride, index, index + stride].map(|i| source[i]);
+                    self.cells[index] =
+                        [0, 1].map(|c| (before[c] + 2.0 * here[c] + after[c]) / 4.0);
+                }
+            }
+        }
+    }
+
+    /// Smoothed log luminance of every pixel, interpolated trilinearly from the cells around it
+    fn slice(&self, log2: &[f32], width: usize) -> Vec<f32> {
+        log2.iter()
+            .enumerate()
+            .map(|(index, &value)| {
+                let position = self.position(index % width, index / width, value);
+                let start = position.map(|p| p.floor() as usize);
+                let fraction = [0, 1, 2].map(|axis| position[axis] - start[axis] as f32);
+                let mut sum = [0.0; 2];
+                for corner in 0..8 {
+                    let offset = [corner & 1, corner >> 1 & 1, corner >> 2 & 1];
+                    let weight = (0..3)
+                        .map(|axis| match offset[axis] {
+                            0 => 1.0 - fraction[axis],
+                            _ => fraction[axis],
+                        })
+                        .product::<f32>();
+                    let cell = self.cells[self.index(
+                        start[0] + offset[0],
+                        start[1] + offset[1],
+                        start[2] + offset[2],
+                    )];
+                    sum[0] += weight * cell[0];
+                    sum[1] += weight * cell[1];
+                }
+                if sum[1] > 0.0 {
+                    sum[0] / sum[1]
+                } else {
+                    value
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    env,
+    fs::OpenOptions,
+    io::{self, IsTerminal},
+    path::Path,
+    sync::Mutex,
+};
+
+use clap::ValueEnum;
+use tracing::Level;
+use tracing_subscriber::{
+    filter::LevelFilter, fmt, fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt,
+    Layer,
+};
+
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    /// Also reports time spent in every pipeline stage
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for Level {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Error => Level::ERROR,
+            LogLevel::Warn => Level::WARN,
+            LogLevel::Info => Level::INFO,
+            LogLevel::Debug => Level::DEBUG,
+            LogLevel::Trace => Level::TRACE,
+        }
+    }
+}
+
+/// Send logs to stderr, colored only on terminals and without a NO_COLOR environment variable, and also to `log_file` as JSON lines when given, at info level or more verbose. Pipeline stages are spans, their duration is logged when they close at debug level and above. Errors opening the log file are returned once stderr logging is set up
+pub fn init(format: LogFormat, level: LogLevel, log_file: Option<&Path>) -> Result<(), String> {
+    let ansi = io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty());
+    let level = Level::from(level);
+    let span_events = || {
+        if level >= Level::DEBUG {
+            FmtSpan::CLOSE
+        } else {
+            FmtSpan::NONE
+        }
+    };
+    let stderr = fmt::layer()
+        .with_writer(io::stderr)
+        .with_ansi(ansi)
+        .with_span_events(span_events());
+    let stderr = match format {
+        LogFormat::Text => stderr.boxed(),
+        LogFormat::Json => stderr.json().boxed(),
+    }
+    .with_filter(LevelFilter::from_level(level));
+
+    // Appended to, so runs sharing a log file keep every entry
+    let file = log_file
+        .map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("Could not open log file {}: {}", path.display(), e))
+        })
+        .transpose();
+    let (file, error) = match file {
+        Ok(file) => (file, None),
+        Err(e) => (None, Some(e)),
+    };
+    // At least info, so entries keep the span naming the file being converted
+    let file = file.map(|file| {
+        fmt::layer()
+            .json()
+            .with_writer(Mutex::new(file))
+            .with_ansi(false)
+            .with_span_events(span_events())
+            .with_filter(LevelFilter::from_level(level.max(Level::INFO)))
+    });
+
+    tracing_subscriber::registry()
+        .with(stderr)
+        .with(file)
+        .init();
+    error.map_or(Ok(()), Err)
+}
@@ -0,0 +1,209 @@
+// https://en.wikipedia.org/wiki/3D_lookup_table
+
+use tracing::info;
+
+use crate::{
+    color_stuff::{Pixel, Rgb},
+    precision::Component,
+    sdr_pixel, PixelParameters,
+};
+
+/// Highest exposed linear component covered, 8 stops above SDR white. Brighter or negative pixels are computed exactly
+const DOMAIN_MAX_LOG2: i32 = 8;
+/// Stops covered below the domain maximum, when the grid has enough points for a cell per stop
+const MIN_OCTAVES: usize = 24;
+/// Bits of a float mantissa, the exponent grows by one every stop above them
+const MANTISSA_BITS: u32 = 23;
+
+pub fn parse_lut_size(text: &str) -> Result<usize, String> {
+    let size: usize = text
+        .parse()
+        .map_err(|e| format!("expected a number of grid points: {}", e))?;
+    if !(2..=256).contains(&size) {
+        return Err("grid points per axis must be between 2 and 256".to_string());
+    }
+    Ok(size)
+}
+
+/// SDR rendition of output color space pixels sampled on a grid, for images where it is costly to compute
+pub struct BakedLut {
+    shaper: Shaper,
+    /// Grid points per axis
+    size: usize,
+    /// Exposure the LUT was baked with
+    factor: f32,
+    /// Gamma-encoded then linear SDR RGB, red varying fastest
+    entries: Vec<[f32; 6]>,
+}
+
+impl BakedLut {
+    /// Sample the whole SDR rendition of `parameters` on a grid of up to `size`³ points, logging the worst interpolation error
+    pub fn bake(size: usize, parameters: &PixelParameters) -> BakedLut {
+        let shaper = Shaper::new(size);
+        // Cells split stops evenly, which can leave a few points of the requested size unused
+        let size = shaper.cells() + 1;
+        let factor = parameters.factor;
+        let node = |i: usize| shaper.unshape(i as f32) / factor;
+        let entries = (0..size * size * size)
+            .map(|i| {
+                let pixel = Pixel {
+                    r: node(i % size),
+                    g: node(i / size % size),
+                    b: node(i / (size * size)),
+                };
+                exact(&pixel, parameters)
+            })
+            .collect();
+        let lut = BakedLut {
+            shaper,
+            size,
+            factor,
+            entries,
+        };
+
+        // Interpolation is furthest from grid points at cell centers
+        let center = |i: usize| shaper.unshape(i as f32 + 0.5) / factor;
+        let cells = size - 1;
+        let mut max_error = 0.0f32;
+        for i in 0..cells * cells * cells {
+            let pixel = Pixel {
+                r: center(i % cells),
+                g: center(i / cells % cells),
+                b: center(i / (cells * cells)),
+            };
+            if let Some(baked) = lut.sample(&pixel) {
+                let expected = exact(&pixel, parameters);
+                for (b, e) in baked[..3].iter().zip(&expected[..3]) {
+                    max_error = max_error.max((b.clamp(0.0, 1.0) - e.clamp(0.0, 1.0)).abs());
+                }
+            }
+        }
+        info!(
+            size,
+            stops = shaper.octaves,
+            max_error_code_values = max_error * 255.0,
+            "Baked SDR rendition into a 3D LUT, error measured at cell centers"
+        );
+        lut
+    }
+
+    /// Interpolated `exact` values, None outside of the domain covered by the grid
+    pub fn sample(&self, pixel: &Pixel) -> Option<[f32; 6]> {
+        let strides = [1, self.size, self.size * self.size];
+        let last_cell = (self.size - 2) as u32;
+        let mut base = 0;
+        let mut fraction = [0.0; 3];
+        for ((value, stride), fraction) in [pixel.r, pixel.g, pixel.b]
+            .into_iter()
+            .zip(strides)
+            .zip(&mut fraction)
+        {
+            let exposed = value * self.factor;
+            // Also rejects NaN
+            if !(0.0..=self.shaper.domain_max()).contains(&exposed) {
+                return None;
+            }
+            let position = self.shaper.shape(exposed);
+            let index = (position as u32).min(last_cell);
+            base += index as usize * stride;
+            *fraction = position - index as f32;
+        }
+
+        // The cell splits in 6 tetrahedra around its diagonal. The one containing the pixel goes from the lower corner along the axis of largest fraction, and leaves out the axis of smallest fraction before reaching the upper corner
+        let [r, g, b] = fraction;
+        let largest = r.max(g).max(b);
+        let smallest = r.min(g).min(b);
+        let middle = r + g + b - largest - smallest;
+        let first = if r == largest {
+            strides[0]
+        } else if g == largest {
+            strides[1]
+        } else {
+            strides[2]
+        };
+        let left_out = if b == smallest {
+            strides[2]
+        } else if g == smallest {
+            strides[1]
+        } else {
+            strides[0]
+        };
+        let diagonal = strides[0] + strides[1] + strides[2];
+        let corners = [
+            self.entries[base],
+            self.entries[base + first],
+            self.entries[base + diagonal - left_out],
+            self.entries[base + diagonal],
+        ];
+        let weights = [1.0 - largest, largest - middle, middle - smallest, smallest];
+        let mut result = [0.0; 6];
+        for (corner, weight) in corners.iter().zip(weights) {
+            for (r, c) in result.iter_mut().zip(corner) {
+                *r += weight * c;
+            }
+        }
+        Some(result)
+    }
+}
+
+/// Gamma-encoded and linear SDR RGB of a pixel in output color space, as computed without LUT. Values are not clipped yet, so clipping does not happen between grid points where interpolation would blur it
+pub fn exact<F: Component>(pixel: &Rgb<F>, parameters: &PixelParameters) -> [F; 6] {
+    let sdr = sdr_pixel(
+        pixel,
+        F::from(parameters.factor).unwrap(),
+        &parameters.trims,
+        &F::coefficients(parameters),
+    );
+    // Mirrored below 0, where out-of-gamut components end up black anyway
+    let encode = |v: F| parameters.transfer.encode(v.abs()).copysign(v);
+    [
+        encode(sdr.r),
+        encode(sdr.g),
+        encode(sdr.b),
+        sdr.r,
+        sdr.g,
+        sdr.b,
+    ]
+}
+
+/// Maps exposed linear values to grid positions with the bits of a float, which grow linearly within a stop and by the same amount every stop. Cheaper than a logarithm, and grid points land on every stop so interpolation never crosses one
+#[derive(Copy, Clone)]
+struct Shaper {
+    /// Stops covered, the lowest one going down to 0 linearly
+    octaves: usize,
+    cells_per_octave: usize,
+    /// Lowest stop, added to values so 0 maps to 0
+    knee: f32,
+}
+
+impl Shaper {
+    fn new(size: usize) -> Shaper {
+        let cells_per_octave = ((size - 1) / MIN_OCTAVES).max(1);
+        let octaves = (size - 1) / cells_per_octave;
+        Shaper {
+            octaves,
+            cells_per_octave,
+            knee: (DOMAIN_MAX_LOG2 as f32 - octaves as f32).exp2(),
+        }
+    }
+
+    fn cells(&self) -> usize {
+        self.octaves * self.cells_per_octave
+    }
+
+    /// Highest exposed value, at the last grid point once the knee is added
+    fn domain_max(&self) -> f32 {
+        (DOMAIN_MAX_LOG2 as f32).exp2() - self.knee
+    }
+
+    /// Exposed linear value to position in cells
+    fn shape(&self, exposed: f32) -> f32 {
+        let steps = (exposed + self.knee).to_bits() - self.knee.to_bits();
+        steps as f32 * self.cells_per_octave as f32 / (1 << MANTISSA_BITS) as f32
+    }
+
+    fn unshape(&self, position: f32) -> f32 {
+        let steps = position * (1 << MANTISSA_BITS) as f32 / self.cells_per_octave as f32;
+        f32::from_bits(self.knee.to_bits() + steps.round() as u32) - self.knee
+    }
+}
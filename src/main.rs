@@ -1,64 +1,536 @@
 use std::{
-    fs::File,
-    io::{BufWriter, Cursor, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    sync::Arc,
+    thread,
 };
 
-use askama::Template;
-use clap::Parser;
-use exr::image::read::{image::ReadLayers, layers::ReadChannels, read};
-use jpeg_encoder::Encoder as JPEGEncoder;
-use nalgebra::SMatrix;
-use png::{Encoder as PNGEncoder, ScaledFloat};
-use rcms::IccProfile;
+use clap::{
+    builder::RangedU64ValueParser, ArgMatches, Args, FromArgMatches, Parser, Subcommand, ValueEnum,
+};
+use exr::{image::FlatSamples, math::Vec2};
+use png::chunk::ChunkType;
+use serde::{Deserialize, Serialize};
+use tracing::{debug_span, error, info, info_span, warn};
 
-use color_spaces::{ColorSpace, Illuminant, REC_709};
-use color_stuff::{Chromaticities, LuminanceCoefficients, Pixel};
-use transfer_functions::gamma as gamma_transfer;
-use ultra_hdr_stuff::{make_xmp, GContainerTemplate, HDRGainMapMetadataTemplate, BOGUS_MPF_HEADER};
+use analyze::AnalyzeArgs;
+use anchor::{parse_anchor, Anchor};
+use aux_channels::{parse_aux_channel, AuxChannel};
+use bench::BenchArgs;
+use cache::Cache;
+use camera_logs::CameraLog;
+use channels::{color_samples, ChannelType, LuminanceChroma};
+use chromatic_adaptation::Cat;
+use cicp::{Cicp, ColorMetadata};
+use color_spaces::{ColorSpace, Illuminant, ACES_AP0, REC_709};
+use color_stuff::{from_exr_chromaticities, LuminanceCoefficients, Pixel, Rgb};
+use completions::CompletionsArgs;
+use contact_sheet::{CellSlot, ContactCellSink, ContactSheetArgs};
+use copy_metadata::ReferenceMetadata;
+use decode::{read_input, ExrImage, Part};
+use display::DisplayTransform;
+use edit::EditArgs;
+use exif::{make_tiff, ExifValue, NORMAL_ORIENTATION, ORIENTATION_TAG};
+use exposure_mask::{parse_exposure_mask, ExposureMask};
+use frames::{parse_frame_range, FrameRange, MissingFrames};
+use gain_basis::Basis;
+use gain_stats::GainStats;
+use generate::GenerateArgs;
+use gpu_stuff::Device;
+use graded_sdr::SdrLight;
+use icc::make_profile;
+use input_format::InputFormat;
+use jpeg_backend::JpegBackend;
+use jpeg_bands::EncoderMode;
+use light_level::ContentLight;
+use local_tone_map::SdrToneMap;
+use logging::{LogFormat, LogLevel};
+use lut::{parse_lut_size, BakedLut};
+use map_gamma::{parse_map_gamma, MapCurve, MapGamma};
+use num_traits::{clamp, Float, NumCast, ToPrimitive, Zero};
+use offsets::OffsetMode;
+use orientation::{exif_orientation, transform, Flip, Rotation};
+use output_template::Tokens;
+use pixel_filter::{parse_ca_correction, parse_channel_alignment, ChannelAlignment, LateralCa};
+use planar::PlanarRgb;
+use precision::{update, Component, DoublePixel, HalfPixel, Precision, StoredPixel};
+use probe::parse_probe;
+use projection::{extract_view, parse_size, parse_view, Projection, View};
+use recovery::RecoveryEncoding;
+use recovery_curve::RecoveryCurve;
+use resize::{aligned_gain_map_scale, crop, fit_within, stretch, GainMapDownscale};
+use resolution::Resolution;
+use sanitize::{sanitize, subtract_black, NegativePolicy};
+use sdr_preview::SdrPreviewSink;
+use self_check::SelfCheckSink;
+use sequence::SequenceStats;
+use sinks::{
+    EmitFormat, GainMapJpegSink, GainMapPngSink, GamutWarningSink, HistogramSink, JpegSink,
+    OutputMetadata, OutputSink, Planes, PngSink, UltraHdrJpegSink, WaveformSink, WebExportSink,
+    WEB_EXPORT_FILES,
+};
+use tone_map::{DownConversion, ToneMap};
+use transfer_functions::{parse_knee, HdrTransfer, Transfer};
+use trims::SdrTrims;
+use ultra_hdr_core::{
+    apple, bt2446, exif,
+    gain::{self, calculate_gain, sdr_pixel, GainBasis},
+    gain_stats, iso21496, mpf, recovery_curve, trims, xmp, Matrix3x1f, Matrix3x3d, Matrix3x3f,
+};
+use ultra_hdr_decode::DecodeArgs;
+use validate::ValidateArgs;
+use white_proof::{parse_simulated_white, SimulatedWhite};
 
+mod analyze;
+mod anchor;
+mod aux_channels;
+mod banding;
+mod base_jpeg;
+mod bench;
+mod cache;
+mod camera_logs;
+mod channels;
+mod chromatic_adaptation;
+mod cicp;
 mod color_spaces;
 mod color_stuff;
+mod completions;
+mod contact_sheet;
+mod copy_metadata;
+mod decode;
+mod display;
+mod dither;
+mod edit;
+mod environment;
+mod exposure_mask;
+mod exr_metadata;
+mod fast_preview;
+mod firefly;
+mod frames;
+mod gain_basis;
+mod generate;
+mod gpu_stuff;
+mod graded_sdr;
+mod icc;
+mod input_format;
+mod jpeg_backend;
+mod jpeg_bands;
+mod jpeg_container;
+mod light_level;
+mod local_tone_map;
+mod logging;
+mod lut;
+mod manifest;
+mod map_gamma;
+mod metadata_csv;
+mod mmap;
+mod offsets;
+mod orientation;
+mod output_template;
+mod parity;
+mod patches;
+mod pfm_input;
+mod pixel_filter;
+mod planar;
+mod png_input;
+mod precision;
+mod preview;
+mod probe;
+mod projection;
+mod qc;
+mod radiance_input;
+mod recovery;
+mod resize;
+mod resolution;
+mod retry;
+mod sanitize;
+mod scopes;
+mod sdr_preview;
+mod self_check;
+mod sequence;
+mod sha256;
+mod sinks;
+mod subsampled;
+mod tiff_input;
+mod tone_map;
 mod transfer_functions;
+mod ultra_hdr_decode;
+mod ultra_hdr_input;
 mod ultra_hdr_stuff;
+mod validate;
+mod verify;
+mod watch;
+mod white_proof;
 
 // ----- Constants
 
-const GAMMA: f32 = 2.4;
 const JPEG_QUALITY: u8 = 100;
+/// Luminance of SDR reference white, in nits
+// https://www.itu.int/pub/R-REP-BT.2408
+const SDR_WHITE_NITS: f32 = 203.0;
 /// Gain Map SDR offset
 const OFFSET_SDR: f32 = 1.0 / 64.0;
 /// Gain Map HDR offset
 const OFFSET_HDR: f32 = 1.0 / 64.0;
-/// Gamma value used for encoding Gain Map to JPEG
-const MAP_GAMMA: f32 = 1.0;
 /// JPEG Quality of Gain Map
 const MAP_JPEG_QUALITY: u8 = 100;
-
-// ----- Matrix type definitions
-
-type Matrix3x1f = SMatrix<f32, 3, 1>;
-type Matrix3x3f = SMatrix<f32, 3, 3>;
+/// PNG chunk holding EXIF data
+const EXIF_CHUNK: ChunkType = ChunkType(*b"eXIf");
+/// PNG chunk holding CICP code points
+const CICP_CHUNK: ChunkType = ChunkType(*b"cICP");
 
 // -----
 
+/// Convert scene-referred OpenEXR images to Ultra HDR JPEGs, and to SDR or Gain Map outputs
 #[derive(Parser)]
+#[command(version, subcommand_negates_reqs = true)]
 struct App {
+    #[command(subcommand)]
+    command: Option<Command>,
     /// Manually specify what the linear-light RGB channels refer to
     #[arg(short, long)]
     input_chromaticities: Option<ColorSpace>,
     /// Manually override the input white point
     #[arg(long)]
     input_white: Option<Illuminant>,
-    /// Re-expose the shot by specifying an exposition value (eV)
+    /// Input RGB values are camera log-encoded, decode them to linear light. Implies the curve's native gamut unless input chromaticities are specified
+    #[arg(long)]
+    input_log: Option<CameraLog>,
+    /// Format of input files, instead of telling it from their first bytes
+    #[arg(long)]
+    input_format: Option<InputFormat>,
+    /// Transfer of PNG inputs, such as frames extracted from HDR video. Taken from the PNG cICP chunk if not specified
+    #[arg(long)]
+    input_transfer: Option<HdrTransfer>,
+    /// Interpret u32 integer samples in R, G or B channels this way instead of refusing the file
+    #[arg(long)]
+    force_channel_type: Option<ChannelType>,
+    /// How negative components of input pixels are handled. NaN and infinite values are always replaced
+    #[arg(long, default_value = "clamp")]
+    negative: NegativePolicy,
+    /// Despeckle fireflies of path-traced renders before gain statistics: pixels brighter than K times the median luminance of their neighbors are scaled down to it. 10 is a good start
+    #[arg(long, value_name = "K")]
+    firefly_clamp: Option<f32>,
+    /// Re-expose the shot by specifying an exposition value (eV). If not specified, taken from EXR metadata when available
     #[arg(short, long, allow_hyphen_values = true)]
     exposure: Option<f32>,
+    /// Set exposure so a linear value of the scene is shown at a display level, as LEVEL=VALUE: gray18=0.36 shows 0.36 as 18% middle gray, white=4 shows 4.0 as SDR white
+    #[arg(long, value_parser = parse_anchor, conflicts_with = "exposure")]
+    anchor: Option<Anchor>,
+    /// Also write the SDR PNG and JPEG outputs at these exposure offsets in eV (such as -2,0,+2), named with an _ev suffix. Pixels are decoded and converted once. An SDR grade from --sdr-exr is scaled by the offset
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    bracket: Vec<f32>,
+    /// Also write the SDR PNG and JPEG outputs as seen by eyes adapted to this daylight white (such as 5000K), adapted from the output white with --cat and named with a _5000K suffix, to proof how the SDR rendition reads under warmer or cooler viewing light
+    #[arg(long, value_name = "KELVIN", value_parser = parse_simulated_white)]
+    simulate_white: Option<SimulatedWhite>,
+    /// Locally change exposure in linear light by EV where a grayscale PNG mask is white, as MASK.png:EV. Can be repeated, masks are stretched to the input size
+    #[arg(long, value_parser = parse_exposure_mask)]
+    exposure_mask: Vec<ExposureMask>,
+    /// Move and scale one channel over the others in linear light, before any other processing, against misregistered scans or captures: CHANNEL:DX,DY[,SCALE], CHANNEL being r, g or b, offsets in pixels towards the right and bottom, and scale about the image center. Can be repeated
+    #[arg(long, value_parser = parse_channel_alignment, allow_hyphen_values = true)]
+    align_channel: Vec<ChannelAlignment>,
+    /// Correct lateral chromatic aberration in linear light, before any other processing, as RED,BLUE magnifications about the image center, green staying in place (0.9995,1.0004 shrinks red and enlarges blue)
+    #[arg(long, value_parser = parse_ca_correction)]
+    ca_correction: Option<LateralCa>,
+    /// Do not take exposure from EXR exposure attributes, comments or whiteLuminance
+    #[arg(long)]
+    ignore_exr_exposure: bool,
+    /// Do not assume ACES2065-1 (AP0) for EXRs flagged as ACES containers that lack chromaticities
+    #[arg(long)]
+    no_aces_autodetect: bool,
+    /// Only convert the region given by this box2i EXR attribute, cropRect if no name is given
+    #[arg(long, num_args = 0..=1, default_missing_value = "cropRect")]
+    roi_attribute: Option<String>,
+    /// Keep the non-square pixels of anamorphic EXRs, writing their aspect ratio to EXIF, instead of resampling them to square pixels
+    #[arg(long)]
+    keep_pixel_aspect: bool,
+    /// Layout of environment map inputs. Taken from the EXR envmap attribute if not specified
+    #[arg(long)]
+    projection: Option<Projection>,
+    /// Convert a rectilinear view of an environment map instead of the whole map, as yaw,pitch,fov in degrees
+    #[arg(long, value_parser = parse_view, allow_hyphen_values = true)]
+    view: Option<View>,
+    /// Size of the --view image, as WIDTHxHEIGHT. Defaults to the map's pixel density at a 16:9 aspect ratio
+    #[arg(long, value_parser = parse_size, requires = "view")]
+    view_size: Option<(usize, usize)>,
+    /// Linear value representing diffuse white. Scales both the SDR rendition and HDR luminance, so it decides how much headroom highlights get
+    #[arg(long, default_value_t = 1.0)]
+    scene_white: f32,
+    /// Luminance of SDR white in nits, used to turn EXR whiteLuminance into exposure and to report HDR peak luminance
+    #[arg(long, default_value_t = SDR_WHITE_NITS)]
+    sdr_white_nits: f32,
+    /// Saturation of the SDR rendition, 0 for grayscale. Luminance is kept, and the Gain Map restores original HDR colors
+    #[arg(long, default_value_t = 1.0)]
+    sdr_saturation: f32,
+    /// Contrast of the SDR rendition, as a slope in log luminance around the pivot. The Gain Map restores original HDR contrast
+    #[arg(long, default_value_t = 1.0)]
+    sdr_contrast: f32,
+    /// Exposed linear luminance left unchanged by --sdr-contrast
+    #[arg(long, default_value_t = 0.18)]
+    sdr_contrast_pivot: f32,
+    /// Roll highlights of the SDR rendition off smoothly above START (exposed linear value below 1), instead of clipping them at 1, as START,STRENGTH. Strength goes from 0 (hard clip) to 1 (highlights approach white without reaching it). The Gain Map restores original HDR highlights
+    #[arg(long, value_parser = parse_knee)]
+    knee: Option<(f32, f32)>,
+    /// Down-convert HDR to the SDR base image with an ITU-R BT.2446 method instead of clipping it, like broadcast SDR renditions. Method A takes --peak-nits as the mastering peak, 1000 nits by default. Exposed linear light of 1.0 stands for HDR reference white (203 nits), and the Gain Map restores HDR. Processed on CPU
+    #[arg(long)]
+    sdr_down_conversion: Option<DownConversion>,
+    /// Tone mapping of the SDR rendition. Local tone mapping brings bright regions, like windows of an interior, under SDR white while keeping their detail, other trims applying on top. HDR stays global, the Gain Map making up for the difference. Processed on CPU
+    #[arg(long, default_value = "global", conflicts_with = "sdr_exr")]
+    tonemap: SdrToneMap,
+    /// Display rendering of --sdr-preview outputs
+    #[arg(long, default_value = "aces")]
+    sdr_preview_tone_map: ToneMap,
+    /// Peak luminance of the mastering display in nits. Measures MaxCLL and MaxFALL of the HDR rendition, light levels above the peak being clipped, and writes them to HDR PNGs
+    #[arg(long)]
+    peak_nits: Option<f32>,
+    /// Linear level of veiling flare, subtracted from every component
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    flare: f32,
+    /// Linear value, after flare subtraction, mapped to black while scene white stays in place. Useful for scanned film with lifted blacks
+    #[arg(long, default_value_t = 0.0, allow_hyphen_values = true)]
+    black_point: f32,
+    /// Display the outputs are meant for, setting both output chromaticities and transfer
+    #[arg(long, conflicts_with_all = ["output_chromaticities", "transfer"])]
+    output: Option<DisplayTransform>,
     /// What the output will be encoded in. If not specified, will be the same as input
     #[arg(short, long)]
     output_chromaticities: Option<ColorSpace>,
     /// Manually override the output white point
     #[arg(long)]
     output_white: Option<Illuminant>,
+    /// Transfer function encoding display-referred outputs
+    #[arg(long, default_value = "gamma24")]
+    transfer: Transfer,
+    /// Color description written in outputs. CICP needs a known output space and a transfer with a code point (not gamma24)
+    #[arg(long, default_value = "icc")]
+    color_metadata: ColorMetadata,
+    /// Chromatic adaptation transform used to adapt embedded ICC profiles to their D50 connection space
+    #[arg(long, default_value = "bradford")]
+    cat: Cat,
+    #[command(flatten)]
+    outputs: Outputs,
+    /// Rotate output clockwise
+    #[arg(long)]
+    rotate: Option<Rotation>,
+    /// Mirror output, after rotation
+    #[arg(long)]
+    flip: Option<Flip>,
+    /// Instead of transforming pixels, write rotation and flip as an EXIF orientation tag for viewers to apply
+    #[arg(long)]
+    orientation_exif: bool,
+    /// Also write the HDR headroom as an Apple maker note in EXIF, read by Photos on iOS and macOS. Apple caps it at 3 stops
+    #[arg(long)]
+    apple_headroom: bool,
+    /// Print resolution in dots per inch, written as JFIF density, PNG pHYs and EXIF resolution so images import at the right size
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    dpi: Option<u16>,
+    /// Force Gain Map minimum log2 boost instead of computing it from the image, to keep a sequence of frames consistent
+    #[arg(long, allow_hyphen_values = true)]
+    gain_map_min: Option<f32>,
+    /// Force Gain Map maximum log2 boost instead of computing it from the image
+    #[arg(long, allow_hyphen_values = true)]
+    gain_map_max: Option<f32>,
+    /// Set Gain Map minimum boost to this percentile of pixel gains instead of the lowest one
+    #[arg(long)]
+    gain_map_min_percentile: Option<f32>,
+    /// Set Gain Map maximum boost to this percentile of pixel gains instead of the highest one, so a few very bright pixels do not waste the map's precision
+    #[arg(long)]
+    gain_map_max_percentile: Option<f32>,
+    /// Gamma used for encoding Gain Map recovery values, or "auto" to pick the one with least quantization error
+    #[arg(long, default_value = "1", value_parser = parse_map_gamma)]
+    map_gamma: MapGamma,
+    /// Curve of Gain Map recovery values
+    #[arg(long, default_value = "power", conflicts_with = "map_gamma")]
+    map_curve: MapCurve,
+    /// Compute Gain Map recovery values with 16 bits, against banding on extreme dynamic range scenes. PNG Gain Maps (--gain-map-png, --png-gain-map) keep 16 bits, JPEG ones are dithered to 8 bits
+    #[arg(long)]
+    gain_map_16bit: bool,
+    /// Reconstruct HDR from the 8-bit Gain Map and report the largest luminance step between neighbouring samples of smooth areas, in nits. Steps above --banding-threshold get the Gain Map dithered with blue noise, unless it comes from 16-bit values already dithered
+    #[arg(long)]
+    analyze_banding: bool,
+    /// Step of --analyze-banding in nits above which the Gain Map is dithered
+    #[arg(long, default_value_t = banding::DEFAULT_THRESHOLD_NITS, requires = "analyze_banding")]
+    banding_threshold: f32,
+    /// Make the Gain Map this many times smaller than the SDR image in both dimensions, for smaller files. Factors above 1 are rounded up to even ones, so Gain Map blocks line up with the chroma of 4:2:0 primary images. Dimensions are rounded up, the last row and column of blocks averaging the remaining pixels
+    #[arg(long, default_value_t = 1, value_parser = RangedU64ValueParser::<usize>::new().range(1..))]
+    gain_map_scale: usize,
+    /// How gains are averaged into Gain Map samples when it is smaller than the SDR image. edge-aware follows edges of the SDR image, against halos around bright areas
+    #[arg(long, default_value = "box")]
+    gain_map_downscale: GainMapDownscale,
+    /// Gain Map SDR offset, keeps gain defined for black pixels
+    #[arg(long, default_value_t = OFFSET_SDR)]
+    offset_sdr: f32,
+    /// Gain Map HDR offset
+    #[arg(long, default_value_t = OFFSET_HDR)]
+    offset_hdr: f32,
+    /// Pick both Gain Map offsets from the image. Offsets would change between frames, so locked sequences keep fixed ones
+    #[arg(long, conflicts_with_all = ["offset_sdr", "offset_hdr", "sequence", "range_from"])]
+    offset: Option<OffsetMode>,
+    /// What gains are the ratio of between HDR and SDR pixels. Luminance under-boosts saturated colored lights, max-rgb boosts them by their brightest channel
+    #[arg(long, default_value = "luminance")]
+    gain_basis: Basis,
+    /// Reuse this already encoded SDR JPEG as the Ultra HDR primary image without re-encoding it, only adding the gain map and container metadata. It should be a rendering of the same EXR with the same settings
+    #[arg(long)]
+    base_jpeg: Option<PathBuf>,
+    /// Use this EXR, graded for SDR by a colorist, as the base image instead of the built-in SDR rendition. The Gain Map then goes from it to the main EXR. It must have the same size as the main EXR
+    #[arg(long, conflicts_with_all = ["base_jpeg", "preview"])]
+    sdr_exr: Option<PathBuf>,
+    /// How values of --sdr-exr are encoded
+    #[arg(long, default_value = "linear", requires = "sdr_exr")]
+    sdr_exr_light: SdrLight,
+    /// Copy EXIF (including GPS) and XMP from this JPEG into outputs, for EXR files developed from a camera photo. Gain map XMP is left out
+    #[arg(long)]
+    copy_metadata: Option<PathBuf>,
+    /// Per-file exposure and metadata for batches, from a CSV with a header naming columns: file, and optionally exposure, title, artist, copyright, latitude, longitude, altitude. Rows are matched to inputs by file name, and their exposure overrides --exposure
+    #[arg(long)]
+    metadata_csv: Option<PathBuf>,
+    /// Embed the Gain Map and its ISO 21496-1 metadata in the PNG output, so it renders as HDR in browsers supporting PNG gain maps
+    #[arg(long)]
+    png_gain_map: bool,
+    /// Embed a thumbnail with this longest side (in pixels) in the Ultra HDR JPEG, for fast previews in file browsers. It is downscaled from the base image, graded or locally tone mapped
+    #[arg(long)]
+    thumbnail_size: Option<usize>,
+    /// Also write the Ultra HDR JPEG downscaled to these longest sides (such as 4096,2048,1024), named with a _SIZE suffix. Pixels are decoded and converted once, and every size shares the Gain Map range
+    #[arg(long, value_delimiter = ',')]
+    sizes: Vec<usize>,
+    /// Check color space conversion against a reference CMS (rcms) on a grid of colors and report the largest ΔE2000, for development
+    #[arg(long)]
+    verify_color: bool,
+    /// Print the EXR, linear, SDR 8-bit, gain and Gain Map values of the pixel at x,y of the converted image. Can be repeated
+    #[arg(long, value_parser = parse_probe)]
+    probe: Vec<(usize, usize)>,
+    /// Measure patches of known color in the SDR output and report their ΔE2000. CSV with a header naming columns x, y, L, a, b (or X, Y, Z) and optionally name, expected values being D50 relative
+    #[arg(long)]
+    verify_patches: Option<PathBuf>,
+    /// Rebuild the HDR rendition from the 8-bit SDR image and Gain Map as viewers do, and report PSNR, ΔE ITP and CAM16-UCS ΔE against the source, to see how lossy the Gain Map settings are. JPEG compression is not included
+    #[arg(long)]
+    self_check: bool,
+    /// Compare the Ultra HDR JPEG output with this one written by libultrahdr from the same source: Gain Map metadata field by field, and HDR reconstructed by both per pixel as PSNR and ΔE ITP
+    #[arg(long, value_name = "FILE")]
+    compare_libultrahdr: Option<PathBuf>,
+    /// Fail the conversion, with a nonzero exit status, when more than this percentage of pixels clip in the SDR rendition. Outputs are still written
+    #[arg(long)]
+    fail_on_clipping: Option<f32>,
+    /// Fail the conversion, with a nonzero exit status, when more than this percentage of pixels are outside of the output gamut. Outputs are still written
+    #[arg(long)]
+    fail_on_gamut: Option<f32>,
+    /// Open a window to adjust exposure interactively before converting, then print the chosen settings as flags. Requires building with the "preview" feature
+    #[arg(long)]
+    preview: bool,
+    /// Where to run pixel processing. GPU requires building with the "gpu" feature, falls back to CPU if unavailable
+    #[arg(long, default_value = "cpu")]
+    device: Device,
+    /// JPEG encoding. "fast" encodes bands of the image on every core, for very large outputs
+    #[arg(long, default_value = "quality")]
+    encoder: EncoderMode,
+    /// Encoder of the SDR image. mozjpeg and jpegli give smaller files at the same quality, they run as `cjpeg` and `cjpegli` found in PATH
+    #[arg(long, default_value = "jpeg-encoder")]
+    jpeg_backend: JpegBackend,
+    /// Progressive scans for the SDR image, preferred for web delivery
+    #[arg(long)]
+    progressive: bool,
+    /// Progressive scans for the Gain Map too
+    #[arg(long)]
+    progressive_gain_map: bool,
+    /// Threads decompressing EXR blocks, 0 for one per core. Sequences also decode the next frame while converting the current one
+    #[arg(long, default_value_t = 0)]
+    decode_threads: usize,
+    /// Precision of linear light held in memory while converting. f16 halves memory use of large plates, f32 keeps the full precision of float EXRs, f64 also processes pixels in double precision for reference images
+    #[arg(long, default_value = "f16")]
+    precision: Precision,
+    /// Memory-map EXR inputs instead of reading them, fewer read calls for large files on network mounts
+    #[arg(long)]
+    mmap: bool,
+    /// Part of multi-part EXR files to convert, by index from 0 or by name, the first valid one by default. The analyze command lists them
+    #[arg(long)]
+    part: Option<Part>,
+    /// Watch a directory and convert every EXR file appearing in it. Outputs are then directories, files are named after inputs
+    #[arg(long)]
+    watch: Option<PathBuf>,
+    /// In watch mode, wait for files to stop changing for this long (in milliseconds) before converting them
+    #[arg(long, default_value_t = 1000)]
+    watch_debounce: u64,
+    /// Maximum number of files converted at once, in batches and watch mode. Each idle job takes the next file, so slow files do not hold others back. With 1, the next file is read during conversion instead
+    #[arg(long, default_value_t = 1)]
+    jobs: usize,
+    /// Convert this range of frames (such as 1001-1100). Input is then a printf-style path such as render.%04d.exr, and outputs either such paths or directories
+    #[arg(long, value_parser = parse_frame_range)]
+    frames: Option<FrameRange>,
+    /// What to do with frames of the range missing on disk
+    #[arg(long, default_value = "error")]
+    missing_frames: MissingFrames,
+    /// Lock Gain Map range across all inputs, measured by a first pass over every frame, so HDR brightness does not flicker in a sequence
+    #[arg(long)]
+    sequence: bool,
+    /// Lock Gain Map range and exposure of all inputs to the ones in a statistics file, skipping the measuring pass
+    #[arg(long, conflicts_with = "sequence")]
+    range_from: Option<PathBuf>,
+    /// Write the locked range of a sequence to a statistics file, to reuse with --range-from
+    #[arg(long)]
+    range_to: Option<PathBuf>,
+    /// Only write the PNG and JPEG outputs, as quick sRGB proxies taken from the preview attribute of EXRs, or from every nth pixel of those without one. Other outputs and settings but --exposure are ignored
+    #[arg(long)]
+    fast_preview: bool,
+    /// With several inputs, keep converting the other files when one fails instead of stopping. The exit status is still an error, and a summary lists every file
+    #[arg(long)]
+    continue_on_error: bool,
+    /// Retry reads of input files failing with transient I/O errors (timeouts, reset connections of network mounts) this many times, waiting longer each time
+    #[arg(long, default_value_t = 2)]
+    io_retries: u32,
+    /// Make outputs bit-exact across runs: fixed ICC creation date, and CPU processing only. Dithering needs no seed, its Bayer and blue noise masks being fixed patterns
+    #[arg(long)]
+    deterministic: bool,
+    /// Bake the SDR rendition (exposure, trims and transfer function) into a 3D LUT with this many grid points per axis, evaluated with tetrahedral interpolation. Speeds up CPU processing when the rendition is costly, as with --sdr-contrast or --sdr-saturation, for a small loss of accuracy logged when baking. `bench --bake-lut` measures the difference
+    #[arg(long, value_parser = parse_lut_size)]
+    bake_lut: Option<usize>,
+    /// Write a JSON manifest of the conversion to this file: tool version, every effective setting, and SHA-256 of inputs and outputs, to audit and reproduce deliverables. Not written in watch mode
+    #[arg(long, conflicts_with = "watch")]
+    manifest: Option<PathBuf>,
+    /// Keep outputs in this directory, keyed by SHA-256 of input contents and effective settings, so re-running a batch only converts inputs that changed. Outputs of cached conversions are copied back where missing or changed. With --sequence, frames are still measured. Not used in watch mode
+    #[arg(long, conflicts_with = "watch")]
+    cache_dir: Option<PathBuf>,
+    /// Convert every input even if --cache-dir holds its outputs, refreshing the cache
+    #[arg(long, requires = "cache_dir")]
+    force: bool,
+    /// How logs are written to stderr
+    #[arg(long, default_value = "text")]
+    log_format: LogFormat,
+    /// Least severe log level shown
+    #[arg(long, default_value = "info")]
+    log_level: LogLevel,
+    /// Also append logs to this file as JSON lines, at least at info level and with the file being converted in each entry's spans, for unattended runs on render farms. Stdout is left to output meant for other programs
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+    /// Print a roff man page of every flag and subcommand to stdout, such as `exr2ultra-hdr --generate-man > exr2ultra-hdr.1`
+    #[arg(long, exclusive = true)]
+    generate_man: bool,
+    /// Path to scene-referred linear-light image: OpenEXR, Radiance HDR, PFM or float TIFF. PQ or HLG PNGs and Ultra HDR JPEGs are read as HDR too. With several, outputs are directories and files are named after inputs
+    #[arg(required_unless_present = "watch")]
+    exr: Vec<PathBuf>,
+}
+
+impl App {
+    /// Exposure value (eV) given on the command line, directly or through an anchor
+    fn requested_exposure(&self) -> Option<f32> {
+        self.exposure.or(self.anchor.map(|a| a.exposure()))
+    }
+}
+
+/// Other tasks than converting every input to the requested outputs
+#[derive(Subcommand)]
+enum Command {
+    ContactSheet(ContactSheetArgs),
+    Bench(BenchArgs),
+    Validate(ValidateArgs),
+    Analyze(AnalyzeArgs),
+    Generate(GenerateArgs),
+    Edit(EditArgs),
+    Decode(DecodeArgs),
+    Completions(CompletionsArgs),
+}
+
+/// Where to write every output of a conversion
+#[derive(Args, Serialize, Deserialize, Clone, Default)]
+struct Outputs {
     /// Write SDR display-referred gamma-encoded output to a PNG file
     #[arg(long)]
     png: Option<PathBuf>,
@@ -74,33 +546,497 @@ struct App {
     /// Write Ultra HDR Gain Map to a separate JPEG file for diagnostics
     #[arg(long)]
     gain_map_jpeg: Option<PathBuf>,
-    /// Path to scene-referred linear-light OpenEXR image
-    exr: PathBuf,
+    /// Write an sRGB JPEG for review where Ultra HDR is not shown, rendered with --sdr-preview-tone-map rather than clipped like the base image
+    #[arg(long)]
+    sdr_preview: Option<PathBuf>,
+    /// Write SDR output to a PNG file with pixels outside of the output gamut painted magenta, to help choosing output chromaticities
+    #[arg(long)]
+    gamut_warning: Option<PathBuf>,
+    /// Write a histogram of log-scaled scene luminance to a PNG file, SDR white marked in red
+    #[arg(long)]
+    histogram: Option<PathBuf>,
+    /// Write a waveform of log-scaled scene luminance across image columns to a PNG file, SDR white marked in red
+    #[arg(long)]
+    waveform: Option<PathBuf>,
+    /// Write SDR image, Gain Map and metadata as sdr.jpg, gainmap.jpg and metadata.json in this directory, as loaded by gainmap-js for three.js
+    #[arg(long)]
+    web_export: Option<PathBuf>,
+    /// Also write other EXR channels as PNGs normalized to their range, as NAME=CHANNEL:PATH (depth=Z:depth.png), or NAME=CHANNEL,CHANNEL,CHANNEL:PATH for RGB (normals=N.X,N.Y,N.Z:normals.png). Can be repeated
+    #[arg(long, value_parser = parse_aux_channel)]
+    aux_channel: Vec<AuxChannel>,
+    /// Write these outputs, comma-separated, to the --emit-to path followed by their suffix: .jpg for jpeg, _ultra_hdr.jpg, .png, _gain_map.png and _gain_map.jpg. They share one processing pass, like outputs of their own flags, which take precedence
+    #[arg(long, value_delimiter = ',', requires = "emit_to")]
+    emit: Vec<EmitFormat>,
+    /// Path of --emit outputs without their suffix, such as renders/shot_010 for renders/shot_010_ultra_hdr.jpg
+    #[arg(long, requires = "emit")]
+    emit_to: Option<PathBuf>,
+    /// Downscaled SDR rendition kept in memory for a contact sheet
+    #[arg(skip)]
+    #[serde(skip)]
+    contact_sheet_cell: Option<Arc<CellSlot>>,
+}
+
+impl Outputs {
+    /// Treat every requested output as a directory, and name files after input. Templates already name files, they are kept
+    fn in_directories(&self, input: &Path) -> Outputs {
+        let stem = input.file_stem().unwrap_or_default();
+        let file_in = |directory: &Path, suffix: &str| {
+            if output_template::is_template(directory) {
+                return directory.to_path_buf();
+            }
+            let mut file_name = stem.to_os_string();
+            file_name.push(suffix);
+            directory.join(file_name)
+        };
+        let name = |directory: &Option<PathBuf>, suffix: &str| {
+            directory.as_deref().map(|d| file_in(d, suffix))
+        };
+
+        Outputs {
+            png: name(&self.png, ".png"),
+            gain_map_png: name(&self.gain_map_png, "_gain_map.png"),
+            jpg: name(&self.jpg, ".jpg"),
+            ultra_hdr_jpg: name(&self.ultra_hdr_jpg, "_ultra_hdr.jpg"),
+            gain_map_jpeg: name(&self.gain_map_jpeg, "_gain_map.jpg"),
+            sdr_preview: name(&self.sdr_preview, "_sdr_preview.jpg"),
+            gamut_warning: name(&self.gamut_warning, "_gamut_warning.png"),
+            histogram: name(&self.histogram, "_histogram.png"),
+            waveform: name(&self.waveform, "_waveform.png"),
+            web_export: name(&self.web_export, ""),
+            aux_channel: self
+                .aux_channel
+                .iter()
+                .map(|aux| aux.with_path(file_in(&aux.path, &format!("_{}.png", aux.name))))
+                .collect(),
+            emit: self.emit.clone(),
+            emit_to: name(&self.emit_to, ""),
+            contact_sheet_cell: self.contact_sheet_cell.clone(),
+        }
+    }
+
+    /// Outputs with template tokens replaced by the values of one file, and `--emit` outputs given their paths
+    fn expand_templates(&self, tokens: &Tokens) -> Result<Outputs, String> {
+        let expand = |path: &Option<PathBuf>| {
+            path.as_deref()
+                .map(|p| output_template::expand(p, tokens))
+                .transpose()
+        };
+        let emit_to = expand(&self.emit_to)?;
+        let emitted = |path: &Option<PathBuf>, format: EmitFormat| -> Result<_, String> {
+            Ok(expand(path)?.or_else(|| {
+                let emit_to = emit_to.as_ref()?;
+                self.emit.contains(&format).then(|| {
+                    let mut path = emit_to.as_os_str().to_os_string();
+                    path.push(format.suffix());
+                    PathBuf::from(path)
+                })
+            }))
+        };
+
+        Ok(Outputs {
+            png: emitted(&self.png, EmitFormat::Png)?,
+            gain_map_png: emitted(&self.gain_map_png, EmitFormat::GainMapPng)?,
+            jpg: emitted(&self.jpg, EmitFormat::Jpeg)?,
+            ultra_hdr_jpg: emitted(&self.ultra_hdr_jpg, EmitFormat::UltraHdrJpeg)?,
+            gain_map_jpeg: emitted(&self.gain_map_jpeg, EmitFormat::GainMapJpeg)?,
+            sdr_preview: expand(&self.sdr_preview)?,
+            gamut_warning: expand(&self.gamut_warning)?,
+            histogram: expand(&self.histogram)?,
+            waveform: expand(&self.waveform)?,
+            web_export: expand(&self.web_export)?,
+            aux_channel: self
+                .aux_channel
+                .iter()
+                .map(|aux| Ok(aux.with_path(output_template::expand(&aux.path, tokens)?)))
+                .collect::<Result<_, String>>()?,
+            emit: Vec::new(),
+            emit_to: None,
+            contact_sheet_cell: self.contact_sheet_cell.clone(),
+        })
+    }
+
+    /// Every file a conversion writes
+    fn paths(&self, args: &App) -> Vec<PathBuf> {
+        let files = [
+            &self.png,
+            &self.gain_map_png,
+            &self.jpg,
+            &self.ultra_hdr_jpg,
+            &self.gain_map_jpeg,
+            &self.sdr_preview,
+            &self.gamut_warning,
+            &self.histogram,
+            &self.waveform,
+        ];
+        let variants = [&self.png, &self.jpg]
+            .into_iter()
+            .flatten()
+            .flat_map(|path| {
+                args.bracket
+                    .iter()
+                    .map(|offset| bracket_suffix(*offset))
+                    .chain(args.simulate_white.map(|white| white.suffix()))
+                    .map(|suffix| suffixed_path(path, &suffix))
+            });
+        let sizes = self.ultra_hdr_jpg.iter().flat_map(|path| {
+            args.sizes
+                .iter()
+                .map(|size| suffixed_path(path, &format!("_{}", size)))
+        });
+        let web_export = self.web_export.iter().flat_map(|directory| {
+            WEB_EXPORT_FILES
+                .into_iter()
+                .map(|file| directory.join(file))
+        });
+        files
+            .into_iter()
+            .flatten()
+            .cloned()
+            .chain(variants)
+            .chain(sizes)
+            .chain(self.aux_channel.iter().map(|aux| aux.path.clone()))
+            .chain(web_export)
+            .collect()
+    }
+
+    /// Other renditions of the SDR PNG and JPEG outputs, such as exposure brackets, named with a suffix
+    fn sdr_variant_sinks(&self, args: &App, suffix: &str) -> Vec<Box<dyn OutputSink>> {
+        let name = |path: &PathBuf| suffixed_path(path, suffix);
+
+        let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+        if let Some(path) = &self.png {
+            // The Gain Map was computed for the main rendition
+            sinks.push(Box::new(PngSink {
+                path: name(path),
+                embed_gain_map: false,
+            }))
+        }
+        if let Some(path) = &self.jpg {
+            sinks.push(Box::new(JpegSink {
+                path: name(path),
+                encoder: args.encoder,
+                progressive: args.progressive,
+                backend: args.jpeg_backend,
+            }))
+        }
+        sinks
+    }
+
+    /// One sink per requested output, all fed from the same processing pass
+    fn sinks(&self, args: &App) -> Result<Vec<Box<dyn OutputSink>>, String> {
+        let mut sinks: Vec<Box<dyn OutputSink>> = Vec::new();
+        if let Some(path) = &self.png {
+            sinks.push(Box::new(PngSink {
+                path: path.clone(),
+                embed_gain_map: args.png_gain_map,
+            }))
+        }
+        if let Some(path) = &self.gain_map_png {
+            sinks.push(Box::new(GainMapPngSink(path.clone())))
+        }
+        if let Some(path) = &self.jpg {
+            sinks.push(Box::new(JpegSink {
+                path: path.clone(),
+                encoder: args.encoder,
+                progressive: args.progressive,
+                backend: args.jpeg_backend,
+            }))
+        }
+        if let Some(path) = &self.gain_map_jpeg {
+            sinks.push(Box::new(GainMapJpegSink {
+                path: path.clone(),
+                encoder: args.encoder,
+                progressive: args.progressive_gain_map,
+            }))
+        }
+        if let Some(path) = &self.sdr_preview {
+            sinks.push(Box::new(SdrPreviewSink {
+                path: path.clone(),
+                tone_map: args.sdr_preview_tone_map,
+                peak_nits: args.peak_nits,
+                encoder: args.encoder,
+                backend: args.jpeg_backend,
+                progressive: args.progressive,
+                cat: args.cat,
+                deterministic: args.deterministic,
+            }))
+        }
+        if let Some(path) = &self.gamut_warning {
+            sinks.push(Box::new(GamutWarningSink(path.clone())))
+        }
+        if let Some(path) = &self.histogram {
+            sinks.push(Box::new(HistogramSink(path.clone())))
+        }
+        if let Some(path) = &self.waveform {
+            sinks.push(Box::new(WaveformSink(path.clone())))
+        }
+        if let Some(directory) = &self.web_export {
+            sinks.push(Box::new(WebExportSink {
+                directory: directory.clone(),
+                encoder: args.encoder,
+                backend: args.jpeg_backend,
+                progressive: args.progressive,
+                progressive_gain_map: args.progressive_gain_map,
+            }))
+        }
+        if args.self_check {
+            sinks.push(Box::new(SelfCheckSink {
+                sdr_white_nits: args.sdr_white_nits,
+            }))
+        }
+        if let Some(slot) = &self.contact_sheet_cell {
+            sinks.push(Box::new(ContactCellSink(slot.clone())))
+        }
+        if let Some(path) = &self.ultra_hdr_jpg {
+            sinks.push(Box::new(UltraHdrJpegSink::new(
+                path,
+                args.thumbnail_size,
+                args.base_jpeg.as_deref(),
+                args.encoder,
+                args.jpeg_backend,
+                args.progressive,
+                args.progressive_gain_map,
+            )?))
+        }
+        Ok(sinks)
+    }
+}
+
+/// Suffix of an exposure bracket output, such as _ev-2 for render_ev-2.png
+fn bracket_suffix(offset: f32) -> String {
+    format!("_ev{:+}", offset)
+}
+
+/// Path with a suffix added to the file name, before the extension
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut file_name = path.file_stem().unwrap_or_default().to_os_string();
+    file_name.push(suffix);
+    if let Some(extension) = path.extension() {
+        file_name.push(".");
+        file_name.push(extension);
+    }
+    path.with_file_name(file_name)
 }
 
 // -----
 
 fn main() {
-    let args = App::parse();
+    // Raw matches are kept to record effective settings in manifests
+    let matches = environment::command().get_matches();
+    let args = App::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    if let Err(e) = logging::init(args.log_format, args.log_level, args.log_file.as_deref()) {
+        error!("{}", e);
+        std::process::exit(1)
+    }
+
+    if args.generate_man {
+        if let Err(e) = completions::print_man_page() {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Some(Command::Completions(shell)) = &args.command {
+        completions::print_completions(shell)
+    } else if let Some(Command::ContactSheet(sheet)) = &args.command {
+        if let Err(e) = contact_sheet::run(&args, sheet) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Some(Command::Bench(bench)) = &args.command {
+        if let Err(e) = bench::run(bench) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Some(Command::Validate(validate)) = &args.command {
+        if let Err(e) = validate::run(validate) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Some(Command::Analyze(analyze)) = &args.command {
+        if let Err(e) = analyze::run(analyze) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Some(Command::Generate(generate)) = &args.command {
+        if let Err(e) = generate::run(generate) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Some(Command::Edit(edit)) = &args.command {
+        if let Err(e) = edit::run(edit) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Some(Command::Decode(decode)) = &args.command {
+        if let Err(e) = ultra_hdr_decode::run(decode) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if args.fast_preview {
+        if let Err(e) = fast_preview::run(&args) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Some(directory) = &args.watch {
+        if let Err(e) = watch::run(&args, directory) {
+            error!("{}", e);
+            std::process::exit(1)
+        }
+    } else if let Err(e) = convert_inputs(&args, &matches) {
+        error!("{}", e);
+        std::process::exit(1)
+    }
+}
+
+/// Convert every input given on the command line, then write the manifest if requested
+fn convert_inputs(args: &App, matches: &ArgMatches) -> Result<(), String> {
+    let cache = args
+        .cache_dir
+        .as_deref()
+        .map(|directory| Cache::new(directory, matches))
+        .transpose()?;
+    let jobs = if let Some(range) = args.frames {
+        if args.exr.len() > 1 {
+            return Err("Only one input path pattern can be used with --frames".to_string());
+        }
+        let jobs = frames::list(&args.exr[0], range, args.missing_frames, &args.outputs)?;
+        sequence::run(args, &jobs, cache.as_ref())?
+    } else if args.exr.len() > 1 || args.sequence || args.range_from.is_some() {
+        let jobs: Vec<(PathBuf, Outputs)> = args
+            .exr
+            .iter()
+            .map(|exr| (exr.clone(), args.outputs.in_directories(exr)))
+            .collect();
+        sequence::run(args, &jobs, cache.as_ref())?
+    } else {
+        let exr = &args.exr[0];
+        let key = cache.as_ref().map(|c| c.key(exr, None)).transpose()?;
+        let restored = match (&cache, &key) {
+            (Some(cache), Some(key)) if !args.force => cache.restore(key),
+            _ => None,
+        };
+        let outputs = if let Some((_, outputs)) = restored {
+            info!(file = %exr.display(), "Outputs restored from cache");
+            outputs
+        } else {
+            let (stats, outputs) = convert(args, exr, &args.outputs, None)?;
+            if let (Some(cache), Some(key)) = (&cache, &key) {
+                cache.store(key, stats, &outputs, args)?;
+            }
+            outputs
+        };
+        vec![(exr.clone(), outputs)]
+    };
+
+    if let Some(path) = &args.manifest {
+        manifest::write(path, args, matches, &jobs)?;
+    }
+    Ok(())
+}
+
+/// Convert a single EXR file to every requested output, returning the range it was encoded with and the outputs with templates expanded. A locked range replaces the computed one, unless overridden. Errors are for inputs that cannot be converted as requested
+fn convert(
+    args: &App,
+    exr: &Path,
+    outputs: &Outputs,
+    locked: Option<&SequenceStats>,
+) -> Result<(SequenceStats, Outputs), String> {
+    let image = {
+        let _span = info_span!("convert", file = %exr.display()).entered();
+        let _stage = debug_span!("decode").entered();
+        read_input(exr, args)?
+    };
+    convert_image(args, exr, image, outputs, locked)
+}
+
+/// Same as `convert`, for an already decoded file
+fn convert_image(
+    args: &App,
+    exr: &Path,
+    image: ExrImage,
+    outputs: &Outputs,
+    locked: Option<&SequenceStats>,
+) -> Result<(SequenceStats, Outputs), String> {
+    match args.precision {
+        Precision::F16 => convert_pixels::<HalfPixel>(args, exr, image, outputs, locked, false),
+        Precision::F32 => convert_pixels::<Pixel>(args, exr, image, outputs, locked, false),
+        Precision::F64 => convert_pixels::<DoublePixel>(args, exr, image, outputs, locked, false),
+    }
+}
+
+/// Gain Map range and exposure a conversion of an already decoded file would use, for the first pass of a sequence. Stops once they are known: nothing is written, previewed, verified or checked
+fn measure_image(args: &App, exr: &Path, image: ExrImage) -> Result<SequenceStats, String> {
+    let outputs = &Outputs::default();
+    match args.precision {
+        Precision::F16 => convert_pixels::<HalfPixel>(args, exr, image, outputs, None, true),
+        Precision::F32 => convert_pixels::<Pixel>(args, exr, image, outputs, None, true),
+        Precision::F64 => convert_pixels::<DoublePixel>(args, exr, image, outputs, None, true),
+    }
+    .map(|(stats, _)| stats)
+}
+
+/// Same as `convert_image`, holding linear light as `P` between processing steps. If `measure_only`, stops once the Gain Map range is known
+fn convert_pixels<P: StoredPixel>(
+    args: &App,
+    exr: &Path,
+    image: ExrImage,
+    outputs: &Outputs,
+    locked: Option<&SequenceStats>,
+    measure_only: bool,
+) -> Result<(SequenceStats, Outputs), String> {
+    let _span = info_span!("convert", file = %exr.display()).entered();
 
     // ----- Input
 
-    let image = read()
-        .no_deep_data()
-        .largest_resolution_level()
-        .all_channels()
-        .first_valid_layer()
-        .all_attributes()
-        .from_file(args.exr)
-        .unwrap();
+    let stage = debug_span!("read").entered();
+
+    // Settings and metadata of this file in a batch
+    let file_metadata = match &args.metadata_csv {
+        Some(path) => {
+            let row = metadata_csv::row(path, exr)?;
+            if row.is_none() {
+                warn!(csv = %path.display(), "No row for this file in metadata CSV");
+            }
+            row
+        }
+        None => None,
+    };
+    let requested_exposure = file_metadata
+        .as_ref()
+        .and_then(|m| m.exposure)
+        .or(args.requested_exposure());
+
+    // Exposure suggested by the file itself
+    let metadata_exposure = if requested_exposure.is_some() || args.ignore_exr_exposure {
+        None
+    } else {
+        exr_metadata::exposure(
+            &image.attributes,
+            &image.layer_data.attributes,
+            args.sdr_white_nits,
+        )
+        .map(|(ev, source)| {
+            info!(ev, source, "Using exposure from EXR metadata");
+            ev
+        })
+    };
 
     // Get input chromaticities
     let mut input_chromaticities = if let Some(c) = args.input_chromaticities {
         c.chromaticities()
+    } else if let Some(l) = args.input_log {
+        l.native_gamut()
     } else if let Some(c) = image.attributes.chromaticities {
-        c.into()
+        from_exr_chromaticities(c)
+    } else if !args.no_aces_autodetect
+        && exr_metadata::is_aces_container(&image.attributes, &image.layer_data.attributes)
+    {
+        info!("Input EXR is an ACES container, using ACES2065-1 (AP0) color space");
+        ACES_AP0
     } else {
-        eprintln!("Warning: Assuming Rec. 709 (sRGB) color space for input EXR.");
+        warn!(
+            assumed = "Rec. 709",
+            "No chromaticities in input EXR, assuming Rec. 709 (sRGB) color space"
+        );
         REC_709
     };
 
@@ -109,8 +1045,13 @@ fn main() {
         input_chromaticities.white = i.white();
     }
 
-    // Get output chromaticities
-    let mut output_chromaticities = args.output_chromaticities.map(|c| c.chromaticities());
+    // Get output chromaticities and transfer
+    let mut output_chromaticities = args
+        .output
+        .map(|d| d.color_space())
+        .or(args.output_chromaticities)
+        .map(|c| c.chromaticities());
+    let transfer = args.output.map_or(args.transfer, |d| d.transfer());
 
     // Override output white point
     if let Some(i) = args.output_white {
@@ -125,267 +1066,931 @@ fn main() {
     }
 
     // Load pixels to own vec
-    let width = image.attributes.display_window.size.0;
-    let height = image.attributes.display_window.size.1;
-    let mut linear_light = vec![Pixel::default(); width * height];
+    let mut width = image.attributes.display_window.size.0;
+    let mut height = image.attributes.display_window.size.1;
+    let mut linear_light = vec![P::default(); width * height];
+    let mut has_rgb = false;
+    let mut luminance_chroma = LuminanceChroma::default();
+    let mut aux_samples = Vec::new();
     for channel in image.layer_data.channel_data.list {
-        for (index, sample) in channel.sample_data.values_as_f32().enumerate() {
-            if channel.name.to_string() == "R" {
-                linear_light[index].r = sample;
-            } else if channel.name.to_string() == "G" {
-                linear_light[index].g = sample;
-            } else if channel.name.to_string() == "B" {
-                linear_light[index].b = sample;
+        let name = channel.name.to_string();
+        if aux_channels::is_requested(&outputs.aux_channel, &name) {
+            aux_samples.push((name.clone(), aux_channels::samples(&channel.sample_data)));
+        }
+        let store: fn(&mut Pixel, f32) = match name.as_str() {
+            "R" => |p, v| p.r = v,
+            "G" => |p, v| p.g = v,
+            "B" => |p, v| p.b = v,
+            "Y" | "RY" | "BY" => {
+                let samples = color_samples(&name, &channel.sample_data, args.force_channel_type)?;
+                luminance_chroma.insert(&name, samples.collect(), channel.sampling);
+                continue;
             }
+            _ => {
+                if matches!(channel.sample_data, FlatSamples::U32(_)) {
+                    warn!(channel = name, "Ignoring non-color integer channel");
+                }
+                continue;
+            }
+        };
+
+        has_rgb = true;
+        let samples = color_samples(&name, &channel.sample_data, args.force_channel_type)?;
+        for (stored, sample) in linear_light.iter_mut().zip(samples) {
+            let mut pixel = stored.load();
+            store(&mut pixel, sample);
+            *stored = P::store(pixel);
         }
     }
 
-    // ----- Process
+    // Luminance-only or luminance / chroma images
+    if !has_rgb {
+        if let Some(pixels) = luminance_chroma.into_rgb(
+            width,
+            height,
+            &input_chromaticities.luminance_values().unwrap(),
+        )? {
+            info!("Reconstructing RGB from luminance channels");
+            linear_light = pixels;
+        }
+    }
 
-    // Convert to desired color space
-    if let Some(output_chromaticities) = output_chromaticities {
-        if !output_chromaticities.contains_space(&input_chromaticities) {
-            eprintln!("Warning: Output color space is smaller than input, check output for any artifacts.")
+    // Other channels written as they are, following pixels through cropping, reprojection and rotation
+    let mut aux_images =
+        aux_channels::assemble(&outputs.aux_channel, &aux_samples, width * height)?;
+    drop(aux_samples);
+
+    // Base image graded by a colorist, following pixels like other channels
+    let mut graded_sdr = args
+        .sdr_exr
+        .as_deref()
+        .map(|path| {
+            graded_sdr::read(
+                path,
+                args,
+                transfer,
+                (width, height),
+                &input_chromaticities,
+                &output_chromaticities.unwrap_or(input_chromaticities),
+            )
+        })
+        .transpose()?;
+
+    // Width over height of pixels, other than 1 for anamorphic plates
+    let pixel_aspect = image.attributes.pixel_aspect;
+    let resample_pixels = pixel_aspect != 1.0 && !args.keep_pixel_aspect;
+
+    // EXR values of probed pixels, while they are still in place
+    let pixels_move = args.roi_attribute.is_some()
+        || resample_pixels
+        || args.view.is_some()
+        || !args.orientation_exif && (args.rotate.is_some() || args.flip.is_some());
+    let probes = probe::capture(&args.probe, &linear_light, width, !pixels_move);
+
+    // Decode camera log curve
+    if let Some(log) = args.input_log {
+        update(&mut linear_light, |pixel| {
+            pixel.r = log.decode(pixel.r);
+            pixel.g = log.decode(pixel.g);
+            pixel.b = log.decode(pixel.b);
+        });
+    }
+
+    // Corrections of the source, such as misregistered channels
+    pixel_filter::apply_all(
+        &pixel_filter::from_args(args),
+        &mut linear_light,
+        width,
+        height,
+    );
+
+    // Local exposure changes, painted over the whole input
+    for mask in &args.exposure_mask {
+        mask.apply(&mut linear_light, width, height)?;
+    }
+
+    // Only keep the region of interest, for quick proofing of large plates
+    if let Some(name) = &args.roi_attribute {
+        let (position, size) = exr_metadata::region_of_interest(
+            &image.attributes,
+            &image.layer_data.attributes,
+            Vec2(width, height),
+            name,
+        )?;
+        info!(
+            x = position.x(),
+            y = position.y(),
+            width = size.width(),
+            height = size.height(),
+            "Converting region of interest only"
+        );
+        linear_light = crop(&linear_light, width, position.into(), size.into());
+        for aux in aux_images.iter_mut().chain(&mut graded_sdr) {
+            *aux = crop(aux, width, position.into(), size.into());
+        }
+        (width, height) = size.into();
+    }
+
+    // Square pixels, enlarging the axis pixels are longest on so no detail is lost
+    if resample_pixels {
+        let (new_width, new_height) = if pixel_aspect > 1.0 {
+            ((width as f32 * pixel_aspect).round() as usize, height)
+        } else {
+            (width, (height as f32 / pixel_aspect).round() as usize)
+        };
+        info!(
+            pixel_aspect,
+            width = new_width,
+            height = new_height,
+            "Resampling anamorphic pixels to square ones"
+        );
+        for aux in aux_images.iter_mut().chain(&mut graded_sdr) {
+            *aux = stretch(aux, width, height, new_width, new_height);
         }
+        linear_light = stretch(&linear_light, width, height, new_width, new_height);
+        (width, height) = (new_width, new_height);
+    } else if pixel_aspect != 1.0 {
+        info!(
+            pixel_aspect,
+            "Keeping non-square pixels, writing their aspect ratio to metadata"
+        );
+    }
+
+    // Look in one direction of an environment map
+    let projection =
+        args.projection
+            .or(image.layer_data.attributes.environment_map.map(Into::into));
+    if let Some(view) = args.view {
+        let projection = projection.ok_or_else(|| {
+            "A view needs --projection, the input has no envmap attribute".to_string()
+        })?;
+        info!(
+            ?projection,
+            yaw = view.yaw,
+            pitch = view.pitch,
+            fov = view.fov,
+            "Extracting view of environment map"
+        );
+        for aux in aux_images.iter_mut().chain(&mut graded_sdr) {
+            (*aux, _, _) = extract_view(aux, width, height, projection, view, args.view_size)?;
+        }
+        (linear_light, width, height) = extract_view(
+            &linear_light,
+            width,
+            height,
+            projection,
+            view,
+            args.view_size,
+        )?;
+    } else if let Some(projection) = projection {
+        info!(
+            ?projection,
+            "Input is an environment map, converting it whole"
+        );
+    }
+
+    // ----- Process
 
-        let conversion_matrix = input_chromaticities
-            .rgb_space_conversion_matrix(&output_chromaticities)
-            .unwrap();
-        for pixel in &mut linear_light {
-            let v: Matrix3x1f = (*pixel).into();
-            *pixel = (conversion_matrix * v).into()
+    drop(stage);
+    let stage = debug_span!("process").entered();
+
+    // Rotate and flip pixels, unless viewers are told to do it
+    let orientation = if args.orientation_exif {
+        exif_orientation(args.rotate, args.flip)
+    } else {
+        if args.rotate.is_some() | args.flip.is_some() {
+            for aux in aux_images.iter_mut().chain(&mut graded_sdr) {
+                (*aux, _, _) = transform(aux, width, height, args.rotate, args.flip);
+            }
+            (linear_light, width, height) =
+                transform(&linear_light, width, height, args.rotate, args.flip);
         }
+        NORMAL_ORIENTATION
+    };
+    let reference = args
+        .copy_metadata
+        .as_deref()
+        .map(|path| ReferenceMetadata::read(path, orientation))
+        .transpose()?;
+    let mut exif_entries = file_metadata
+        .as_ref()
+        .map_or_else(Vec::new, |m| m.exif_entries());
+    if args.orientation_exif {
+        exif_entries.push((ORIENTATION_TAG, ExifValue::Short(orientation)));
+    }
+    let resolution = Resolution::new(args.dpi, if resample_pixels { 1.0 } else { pixel_aspect });
+    if let Some(resolution) = resolution {
+        exif_entries.extend(resolution.exif_entries());
+    }
+
+    sanitize(
+        &mut linear_light,
+        args.negative,
+        &input_chromaticities.luminance_values().unwrap(),
+    )?;
+    if let Some(k) = args.firefly_clamp {
+        firefly::clamp(
+            &mut linear_light,
+            width,
+            height,
+            k,
+            &input_chromaticities.luminance_values().unwrap(),
+        )?;
+    }
+
+    if !args.scene_white.is_finite() || args.scene_white <= 0.0 {
+        return Err(format!(
+            "Scene white must be positive, got {}",
+            args.scene_white
+        ));
+    }
+
+    // Remove lifted blacks, which would otherwise waste Gain Map range
+    if args.flare != 0.0 || args.black_point != 0.0 {
+        subtract_black(
+            &mut linear_light,
+            args.flare,
+            args.black_point,
+            args.scene_white,
+        )?;
+    }
+
+    // Make diffuse white 1.0, as both SDR and HDR renditions expect
+    if args.scene_white != 1.0 {
+        let scale = args.scene_white.recip();
+        update(&mut linear_light, |pixel| {
+            pixel.r *= scale;
+            pixel.g *= scale;
+            pixel.b *= scale;
+        });
     }
 
+    // Get matrix converting to desired color space
+    let conversion_matrix_f64 = output_chromaticities.map(|output_chromaticities| {
+        if !output_chromaticities.contains_space(&input_chromaticities) {
+            warn!(input = ?input_chromaticities, output = ?output_chromaticities, "Output color space is smaller than input, check output for any artifacts")
+        }
+
+        input_chromaticities
+            .rgb_space_conversion_matrix_f64(&output_chromaticities)
+            .unwrap()
+    });
+    let conversion_matrix = conversion_matrix_f64.map(|m| m.cast());
+
     let write_chromaticities = output_chromaticities.unwrap_or(input_chromaticities);
 
+    if args.verify_color && !measure_only {
+        match &conversion_matrix {
+            Some(matrix) => verify::report(&input_chromaticities, &write_chromaticities, matrix),
+            None => info!("No color space conversion to verify"),
+        }
+    }
+
     // Get multiplication factor
-    let factor = if let Some(ev) = args.exposure {
+    if let (Some(locked), Some(requested)) =
+        (locked, file_metadata.as_ref().and_then(|m| m.exposure))
+    {
+        if requested != locked.exposure {
+            warn!(
+                requested,
+                locked = locked.exposure,
+                "Exposure of metadata CSV differs from the locked sequence one, using the locked one"
+            )
+        }
+    }
+    let mut exposure = locked
+        .map(|l| l.exposure)
+        .or(requested_exposure)
+        .or(metadata_exposure);
+    let mut factor = if let Some(ev) = exposure {
         2.0f32.powf(ev)
     } else {
         1.0
     };
 
-    // Apply transfer function and limit to 1.0 (convert to display-referred) and convert to u8, all while calculating gain map
-    let mut image_data = Vec::with_capacity(width * height);
-    let mut pixel_gains = Vec::with_capacity(width * height);
-    let coefficients = write_chromaticities.luminance_values().unwrap();
-    for pixel in linear_light {
-        pixel_gains.push(calculate_gain(
-            &pixel,
-            factor,
-            &coefficients,
-            OFFSET_HDR,
-            OFFSET_SDR,
-        ));
+    let trims = SdrTrims {
+        down_conversion: args.sdr_down_conversion.map(|d| d.method(args.peak_nits)),
+        saturation: args.sdr_saturation,
+        contrast: args.sdr_contrast,
+        pivot: args.sdr_contrast_pivot,
+        knee_start: args.knee.map_or(1.0, |(start, _)| start),
+        knee_strength: args.knee.map_or(0.0, |(_, strength)| strength),
+    };
+    let mut parameters = PixelParameters {
+        conversion_matrix,
+        conversion_matrix_f64,
+        factor,
+        transfer,
+        coefficients: write_chromaticities.luminance_values().unwrap(),
+        coefficients_f64: write_chromaticities.luminance_values_f64().unwrap(),
+        offset_hdr: args.offset_hdr,
+        offset_sdr: args.offset_sdr,
+        gain_basis: args.gain_basis.into(),
+        trims,
+        lut: None,
+    };
+
+    // Let the user pick exposure by eye, then print it as a flag for later runs
+    if args.preview && !measure_only {
+        let chosen = preview::choose_exposure(
+            &linear_light,
+            width,
+            height,
+            &parameters,
+            exposure.unwrap_or(0.0),
+        )
+        .ok_or_else(|| "Preview cancelled".to_string())?;
+        println!("--exposure {}", chosen);
+        exposure = Some(chosen);
+        factor = 2.0f32.powf(chosen);
+        parameters.factor = factor;
+    }
+
+    // Name outputs now that exposure and color space are known
+    let outputs = &outputs.expand_templates(&Tokens {
+        stem: exr
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+        ev: exposure.unwrap_or(0.0),
+        space: ColorSpace::identify(&write_chromaticities)
+            .and_then(|c| c.to_possible_value())
+            .map_or("custom".to_string(), |v| v.get_name().to_string()),
+        date: {
+            let [year, month, day, ..] = icc::now();
+            format!("{:04}-{:02}-{:02}", year, month, day)
+        },
+    })?;
+    let sinks = if measure_only {
+        Vec::new()
+    } else {
+        outputs.sinks(args)?
+    };
+
+    // Offsets suited to the image's shadows, for the chosen exposure
+    if let Some(OffsetMode::Auto) = args.offset {
+        if let Some((offset_sdr, offset_hdr)) = offsets::optimize(&linear_light, &parameters) {
+            parameters.offset_sdr = offset_sdr;
+            parameters.offset_hdr = offset_hdr;
+        } else {
+            warn!("No dark pixels to pick Gain Map offsets from, keeping default ones");
+        }
+    }
+
+    let double = matches!(args.precision, Precision::F64);
+    if let (Some(_), true) = (args.bake_lut, double) {
+        warn!("A baked LUT would round processing to f32, computing every pixel in f64 instead");
+    } else if let Some(size) = args.bake_lut {
+        if let Device::Gpu = args.device {
+            warn!("A baked LUT only speeds up CPU processing, it is not used on GPU");
+        }
+        parameters.lut = Some(BakedLut::bake(size, &parameters));
+    }
+
+    // Convert color space, apply transfer function and limit to 1.0 (convert to display-referred) and convert to u8, all while calculating gain map
+    let gpu_output = match args.device {
+        // GPU floating point results may differ between devices and drivers
+        Device::Gpu if args.deterministic => {
+            warn!("Deterministic output requested, processing on CPU instead of GPU");
+            None
+        }
+        Device::Gpu if double => {
+            warn!("f64 precision requested, processing on CPU instead of GPU");
+            None
+        }
+        Device::Gpu if trims.down_conversion.is_some() => {
+            warn!("BT.2446 down-conversion requested, processing on CPU instead of GPU");
+            None
+        }
+        Device::Gpu => gpu_stuff::process(&mut linear_light, &parameters).map(
+            |(sdr_image, pixel_gains, gain_stats)| {
+                (sdr_image, P::Float::gains_from_f32(pixel_gains), gain_stats)
+            },
+        ),
+        Device::Cpu => None,
+    };
+    let (mut sdr_image, mut pixel_gains, mut gain_stats) =
+        gpu_output.unwrap_or_else(|| process_cpu(&mut linear_light, &parameters, 0));
 
-        let r = process_pixel(pixel.r, factor, GAMMA);
-        let g = process_pixel(pixel.g, factor, GAMMA);
-        let b = process_pixel(pixel.b, factor, GAMMA);
-        image_data.extend([r, g, b])
+    // Graded or locally tone mapped SDR replaces the built-in rendition, now that linear light is in output color space
+    let replaced = replaced_sdr(
+        args,
+        &linear_light,
+        width,
+        height,
+        graded_sdr.as_deref(),
+        &parameters,
+        0.0,
+    );
+    if let Some(sdr) = &replaced {
+        (sdr_image, pixel_gains, gain_stats) = graded_sdr::process(&linear_light, sdr, &parameters);
     }
 
     // Compute encoded gain map, as specified in Google documentation
-    let min_content_boost = pixel_gains
-        .iter()
-        .min_by(|x, y| x.partial_cmp(y).unwrap())
-        .unwrap();
-    let max_content_boost = pixel_gains
-        .iter()
-        .max_by(|x, y| x.partial_cmp(y).unwrap())
-        .unwrap();
-    let map_min_log2 = min_content_boost.log2();
-    let map_max_log2 = max_content_boost.log2();
-    let mut encoded_recoveries = Vec::with_capacity(width * height);
-    for pixel_gain in pixel_gains {
-        let log_recovery = (pixel_gain.log2() - map_min_log2) / (map_max_log2 - map_min_log2);
-        let clamped_recovery = log_recovery.clamp(0.0, 1.0);
-        let recovery = clamped_recovery.powf(MAP_GAMMA);
-        encoded_recoveries.push((recovery * 255.0).round() as u8)
+    let forced_min = args.gain_map_min.or(locked.map(|l| l.gain_map_min));
+    let forced_max = args.gain_map_max.or(locked.map(|l| l.gain_map_max));
+    let map_min_log2 = forced_min.unwrap_or_else(|| match args.gain_map_min_percentile {
+        Some(p) => gain_stats.percentile_log2(p),
+        None => gain_stats.min.log2(),
+    });
+    let map_max_log2 = forced_max.unwrap_or_else(|| match args.gain_map_max_percentile {
+        Some(p) => gain_stats.percentile_log2(p),
+        None => gain_stats.max.log2(),
+    });
+    if (forced_min.is_some() || forced_max.is_some()) && map_max_log2 <= map_min_log2 {
+        return Err(format!(
+            "Gain Map maximum ({}) must be greater than minimum ({})",
+            map_max_log2, map_min_log2
+        ));
+    }
+    info!(
+        nits = args.sdr_white_nits * map_max_log2.exp2(),
+        "HDR peak luminance"
+    );
+    let stats = SequenceStats {
+        gain_map_min: map_min_log2,
+        gain_map_max: map_max_log2,
+        exposure: exposure.unwrap_or(0.0),
+    };
+    if measure_only {
+        return Ok((stats, outputs.clone()));
+    }
+    let mut encoding = RecoveryEncoding {
+        min_log2: map_min_log2,
+        max_log2: map_max_log2,
+        curve: RecoveryCurve::Power(1.0),
+        scale: aligned_gain_map_scale(args.gain_map_scale),
+        downscale: args.gain_map_downscale,
+        wide: args.gain_map_16bit,
+        dither: false,
+    };
+    encoding.curve = match (args.map_curve, args.map_gamma) {
+        (MapCurve::Pq, _) => {
+            warn!("Only exr2ultra-hdr decodes the PQ recovery curve, other viewers show wrong HDR boosts");
+            RecoveryCurve::Pq
+        }
+        (MapCurve::Power, MapGamma::Fixed(gamma)) => RecoveryCurve::Power(gamma),
+        (MapCurve::Power, MapGamma::Auto) => {
+            let gamma = map_gamma::optimize(
+                pixel_gains
+                    .iter()
+                    .map(|g| encoding.clamped(*g).to_f32().unwrap_or_default()),
+            );
+            info!(gamma, "Picked Gain Map gamma");
+            RecoveryCurve::Power(gamma)
+        }
+    };
+    let recovery_curve = encoding.curve;
+    // hdrgm:Gamma, other curves being read as linear by other decoders
+    let map_gamma = match recovery_curve {
+        RecoveryCurve::Power(gamma) => gamma,
+        RecoveryCurve::Pq => 1.0,
+    };
+    let gain_map_scale = encoding.scale;
+    if gain_map_scale != args.gain_map_scale {
+        warn!(
+            requested = args.gain_map_scale,
+            scale = gain_map_scale,
+            "Gain Map scale rounded up to an even factor, to line up with 4:2:0 chroma"
+        );
+    }
+    let (mut encoded_recoveries, mut wide_recoveries) =
+        encoding.encode(&pixel_gains, sdr_image.view(width, height));
+    if args.analyze_banding {
+        let banding = banding::analyze(
+            &encoded_recoveries,
+            sdr_image.view(width, height),
+            &encoding,
+            &parameters,
+            args.sdr_white_nits,
+        );
+        banding.report(args.banding_threshold);
+        if banding.max_step_nits > args.banding_threshold && !encoding.wide {
+            info!("Dithering Gain Map with blue noise");
+            encoding.dither = true;
+            (encoded_recoveries, wide_recoveries) =
+                encoding.encode(&pixel_gains, sdr_image.view(width, height));
+        }
+    }
+    probe::print(
+        &probes,
+        width,
+        &linear_light,
+        sdr_image.view(width, height),
+        &pixel_gains,
+        &encoded_recoveries,
+        gain_map_scale,
+    );
+    drop(pixel_gains);
+
+    // HDR10-style light levels, for delivery specs
+    let content_light = args.peak_nits.map(|peak_nits| {
+        let light = ContentLight::measure(&linear_light, args.sdr_white_nits, peak_nits);
+        info!(
+            max_cll = light.max_cll,
+            max_fall = light.max_fall,
+            "Content light level"
+        );
+        light
+    });
+
+    // Color accuracy of the SDR rendition
+    if let Some(path) = &args.verify_patches {
+        let coefficients = write_chromaticities.luminance_values().unwrap();
+        let sdr_linear: Vec<Pixel> = linear_light
+            .iter()
+            .map(|p| sdr_pixel(&p.load(), factor, &trims, &coefficients))
+            .collect();
+        patches::report(
+            &patches::read(path)?,
+            &sdr_linear,
+            width,
+            height,
+            &write_chromaticities,
+            args.cat,
+        )?;
     }
 
     // ----- Output
 
-    // TODO: Could optimize by only encoding JPEGs once
+    drop(stage);
+    let _stage = debug_span!("output").entered();
+
+    // CICP code points, if wanted and possible
+    let cicp = if args.color_metadata.cicp() {
+        let cicp = Cicp::for_output(&write_chromaticities, transfer);
+        if cicp.is_none() {
+            warn!(
+                transfer = ?transfer,
+                "No CICP code points for output color space and transfer, writing ICC-style metadata only"
+            )
+        }
+        cicp
+    } else {
+        None
+    };
+
+    // Generate ICC profile for JPEGs
+    let description = format!(
+        "{}, {}",
+        ColorSpace::identify(&write_chromaticities).map_or("Custom RGB", |c| c.name()),
+        transfer.name()
+    );
+    let profile_bytes = make_profile(
+        &write_chromaticities,
+        transfer,
+        args.cat,
+        &description,
+        cicp,
+        args.deterministic,
+    );
 
-    // Write SDR PNG image
-    if let Some(png_path) = args.png {
-        encode_png(png_path, &image_data, width, height, write_chromaticities)
+    if args.apple_headroom {
+        exif_entries.push(apple::headroom_entry(map_max_log2));
     }
+    let exif = match reference.as_ref().and_then(|r| r.exif.clone()) {
+        Some(exif) => {
+            if file_metadata
+                .as_ref()
+                .is_some_and(|m| !m.exif_entries().is_empty())
+            {
+                warn!("EXIF is copied from --copy-metadata, ignoring metadata CSV title, artist, copyright and GPS");
+            }
+            if args.apple_headroom {
+                warn!("EXIF is copied from --copy-metadata, not writing Apple HDR headroom");
+            }
+            if resolution.is_some() {
+                warn!("EXIF is copied from --copy-metadata, not writing resolution nor pixel aspect ratio");
+            }
+            Some(exif)
+        }
+        None if !exif_entries.is_empty() => Some(make_tiff(&exif_entries)),
+        None => None,
+    };
 
-    // Write Gain Map PNG image
-    if let Some(path) = args.gain_map_png {
-        encode_gain_map_png(path, &encoded_recoveries, width, height)
+    let planes = Planes {
+        width,
+        height,
+        image: sdr_image.view(width, height),
+        gain_map: &encoded_recoveries,
+        gain_map_scale,
+        gain_map_16bit: wide_recoveries.as_deref(),
+        linear_light: P::slice(&linear_light),
+    };
+    let metadata = OutputMetadata {
+        chromaticities: write_chromaticities,
+        transfer,
+        factor,
+        cicp,
+        legacy_color_chunks: args.color_metadata.icc() || cicp.is_none(),
+        icc_profile: &profile_bytes,
+        exif: exif.as_deref(),
+        xmp: reference.as_ref().and_then(|r| r.xmp.as_deref()),
+        gain_map_min: map_min_log2,
+        gain_map_max: map_max_log2,
+        map_gamma,
+        recovery_curve,
+        offset_sdr: parameters.offset_sdr,
+        offset_hdr: parameters.offset_hdr,
+        trims,
+        content_light,
+        resolution,
+    };
+    for sink in &sinks {
+        sink.write(&planes, &metadata)?;
     }
+    aux_channels::write(&outputs.aux_channel, &aux_images, width, height)?;
 
-    // Generate ICC profile for JPEGs
-    let mut profile_bytes = Cursor::new(Vec::new());
-    let profile = IccProfile::new_rgb(
-        write_chromaticities.white.with_luma(1.0).into(),
-        (
-            write_chromaticities.red.with_luma(1.0).into(),
-            write_chromaticities.green.with_luma(1.0).into(),
-            write_chromaticities.blue.with_luma(1.0).into(),
-        ),
-        GAMMA.into(),
-    )
-    .unwrap();
-    profile.serialize(&mut profile_bytes).unwrap();
-    let profile_bytes = profile_bytes.into_inner();
-
-    // Write SDR JPG image
-    if let Some(jpg_path) = args.jpg {
-        let mut encoder = JPEGEncoder::new_file(jpg_path, JPEG_QUALITY).unwrap();
-        encoder.add_icc_profile(&profile_bytes).unwrap();
-        encoder
-            .encode(
-                &image_data,
-                width.try_into().unwrap(),
-                height.try_into().unwrap(),
-                jpeg_encoder::ColorType::Rgb,
-            )
-            .unwrap();
-    }
-
-    // Write Gain Map JPEG image
-    if let Some(path) = args.gain_map_jpeg {
-        let gain_map_encoder = JPEGEncoder::new_file(path, MAP_JPEG_QUALITY).unwrap();
-        gain_map_encoder
-            .encode(
-                &encoded_recoveries,
-                width.try_into().unwrap(),
-                height.try_into().unwrap(),
-                jpeg_encoder::ColorType::Luma,
-            )
-            .unwrap();
-    }
-
-    // Write HDR JPEG image
-    if let Some(jpg_path) = args.ultra_hdr_jpg {
-        // Create new file
-        let mut write_file = BufWriter::new(File::create(jpg_path).unwrap());
-
-        // Gen Gain Map XMP data
-        let hdr_xmp = HDRGainMapMetadataTemplate {
-            gain_map_min: map_min_log2,
-            gain_map_max: map_max_log2,
-            gamma: MAP_GAMMA,
-            offset_sdr: OFFSET_SDR,
-            offset_hdr: OFFSET_HDR,
-            hdr_capacity_min: map_min_log2,
-            hdr_capacity_max: map_max_log2,
-        }
-        .render()
-        .unwrap();
-
-        // Encode gain map image
-        let mut gain_map_image_bytes = Cursor::new(Vec::new());
-        let mut gain_map_encoder = JPEGEncoder::new(&mut gain_map_image_bytes, MAP_JPEG_QUALITY);
-        gain_map_encoder
-            .add_app_segment(1, &make_xmp(hdr_xmp))
-            .unwrap();
-        gain_map_encoder
-            .encode(
-                &encoded_recoveries,
-                width.try_into().unwrap(),
-                height.try_into().unwrap(),
-                jpeg_encoder::ColorType::Luma,
-            )
-            .unwrap();
-        let gain_map_image_bytes = gain_map_image_bytes.into_inner();
-
-        // Gen directory XMP
-        let directory_xmp = GContainerTemplate {
-            gain_map_image_len: gain_map_image_bytes.len(),
-        }
-        .render()
-        .unwrap();
-
-        // Encode main image
-        let mut main_encoder = JPEGEncoder::new(&mut write_file, JPEG_QUALITY);
-        main_encoder.add_icc_profile(&profile_bytes).unwrap();
-        main_encoder
-            .add_app_segment(1, &make_xmp(directory_xmp))
-            .unwrap();
-        // Add wrong MPF header, file still works in Chrome though
-        main_encoder.add_app_segment(2, BOGUS_MPF_HEADER).unwrap();
-        main_encoder
-            .encode(
-                &image_data,
-                width.try_into().unwrap(),
-                height.try_into().unwrap(),
-                jpeg_encoder::ColorType::Rgb,
-            )
-            .unwrap();
+    // Exposure bracket, reusing converted pixels
+    if !args.bracket.is_empty() && outputs.png.is_none() && outputs.jpg.is_none() {
+        warn!("Exposure bracket only applies to PNG and JPEG SDR outputs, none requested");
+    }
+    let coefficients = write_chromaticities.luminance_values().unwrap();
+    for &offset in &args.bracket {
+        let bracket_factor = factor * offset.exp2();
+        let replaced = replaced_sdr(
+            args,
+            &linear_light,
+            width,
+            height,
+            graded_sdr.as_deref(),
+            &parameters,
+            offset,
+        );
+        let sdr = replaced.unwrap_or_else(|| {
+            linear_light
+                .iter()
+                .map(|p| sdr_pixel(&p.load(), bracket_factor, &trims, &coefficients))
+                .collect()
+        });
+        let sdr_image: PlanarRgb = sdr
+            .iter()
+            .map(|sdr| [sdr.r, sdr.g, sdr.b].map(|v| process_pixel(v.clamp(0.0, 1.0), transfer)))
+            .collect();
+        let planes = Planes {
+            image: sdr_image.view(width, height),
+            ..planes
+        };
+        let metadata = OutputMetadata {
+            factor: bracket_factor,
+            ..metadata
+        };
+        for sink in outputs.sdr_variant_sinks(args, &bracket_suffix(offset)) {
+            sink.write(&planes, &metadata)?;
+        }
+    }
+
+    // Proof under another viewing white, from the SDR rendition
+    if let Some(white) = args.simulate_white {
+        if outputs.png.is_none() && outputs.jpg.is_none() {
+            warn!(
+                "White point simulation only applies to PNG and JPEG SDR outputs, none requested"
+            );
+        }
+        info!(kelvin = white.kelvin, "Simulating viewing white");
+        let sdr_image = white.proof(planes.image, &write_chromaticities, transfer, args.cat);
+        let planes = Planes {
+            image: sdr_image.view(width, height),
+            ..planes
+        };
+        for sink in outputs.sdr_variant_sinks(args, &white.suffix()) {
+            sink.write(&planes, &metadata)?;
+        }
+    }
+
+    // Downscaled set, reusing converted pixels and the Gain Map range
+    if !args.sizes.is_empty() && outputs.ultra_hdr_jpg.is_none() {
+        warn!("Output sizes only apply to the Ultra HDR JPEG output, none requested");
+    }
+    // Linear light is already in output color space
+    parameters.conversion_matrix = None;
+    parameters.conversion_matrix_f64 = None;
+    for &size in &args.sizes {
+        let Some(path) = &outputs.ultra_hdr_jpg else {
+            break;
+        };
+        let (sized_width, sized_height) = fit_within(width, height, size);
+        let mut sized_linear_light =
+            P::slice(&linear_light).downscale_box(width, height, sized_width, sized_height);
+        // Averaged in linear display light, like HDR
+        let sized_graded_sdr = graded_sdr
+            .as_deref()
+            .map(|sdr| Pixel::slice(sdr).downscale_box(width, height, sized_width, sized_height));
+        let (mut sdr_image, mut pixel_gains, _) =
+            process_cpu(&mut sized_linear_light, &parameters, 0);
+        let replaced = replaced_sdr(
+            args,
+            &sized_linear_light,
+            sized_width,
+            sized_height,
+            sized_graded_sdr.as_deref(),
+            &parameters,
+            0.0,
+        );
+        if let Some(sdr) = replaced {
+            (sdr_image, pixel_gains, _) =
+                graded_sdr::process(&sized_linear_light, &sdr, &parameters);
+        }
+        let (encoded_recoveries, wide_recoveries) =
+            encoding.encode(&pixel_gains, sdr_image.view(sized_width, sized_height));
+        let planes = Planes {
+            width: sized_width,
+            height: sized_height,
+            image: sdr_image.view(sized_width, sized_height),
+            gain_map: &encoded_recoveries,
+            gain_map_scale,
+            gain_map_16bit: wide_recoveries.as_deref(),
+            linear_light: Pixel::slice(&sized_linear_light),
+        };
+        // The base JPEG only matches the full size
+        let sink = UltraHdrJpegSink::new(
+            &suffixed_path(path, &format!("_{}", size)),
+            args.thumbnail_size,
+            None,
+            args.encoder,
+            args.jpeg_backend,
+            args.progressive,
+            args.progressive_gain_map,
+        )?;
+        sink.write(&planes, &metadata)?;
+    }
 
-        // Put gain map image next
-        write_file.write_all(&gain_map_image_bytes).unwrap()
+    // libultrahdr parity, reading back the written file as a viewer would
+    if args.compare_libultrahdr.is_some() && outputs.ultra_hdr_jpg.is_none() {
+        warn!("libultrahdr comparison only applies to the Ultra HDR JPEG output, none requested");
     }
+    if let (Some(reference), Some(path)) = (&args.compare_libultrahdr, &outputs.ultra_hdr_jpg) {
+        parity::compare(reference, path, args.sdr_white_nits)?;
+    }
+
+    // Automated QC, once outputs are written so failures can be inspected
+    qc::check(
+        args,
+        &linear_light,
+        replaced.as_deref(),
+        factor,
+        &trims,
+        &coefficients,
+    )?;
+
+    Ok((stats, outputs.clone()))
 }
 
-/// Compute gain value for this pixel, used to build gain map for Ultra HDR JPEG
-fn calculate_gain(
-    pixel: &Pixel,
-    factor: f32,
-    coefficients: &LuminanceCoefficients,
-    offset_hdr: f32,
-    offset_sdr: f32,
-) -> f32 {
-    let hdr_luminance =
-        pixel.r * coefficients.red + pixel.g * coefficients.green + pixel.b * coefficients.blue;
-
-    let sdr_pixel = Pixel {
-        r: (pixel.r * factor).clamp(0.0, 1.0),
-        g: (pixel.g * factor).clamp(0.0, 1.0),
-        b: (pixel.b * factor).clamp(0.0, 1.0),
-    };
+/// SDR rendition replacing the one of the built-in curve, in output color space and linear display light, exposed `offset` eV above the chosen exposure: the SDR grade `graded`, or local tone mapping. None when the built-in curve is used
+fn replaced_sdr<P: StoredPixel>(
+    args: &App,
+    linear_light: &[P],
+    width: usize,
+    height: usize,
+    graded: Option<&[Pixel]>,
+    parameters: &PixelParameters,
+    offset: f32,
+) -> Option<Vec<Pixel>> {
+    if let Some(graded) = graded {
+        let scale = offset.exp2();
+        return Some(
+            graded
+                .iter()
+                .map(|p| Pixel {
+                    r: p.r * scale,
+                    g: p.g * scale,
+                    b: p.b * scale,
+                })
+                .collect(),
+        );
+    }
+    match args.tonemap {
+        SdrToneMap::Local => Some(local_tone_map::render(
+            linear_light,
+            width,
+            height,
+            parameters.factor * offset.exp2(),
+            parameters,
+        )),
+        SdrToneMap::Global => None,
+    }
+}
 
-    let sdr_luminance = sdr_pixel.r * coefficients.red
-        + sdr_pixel.g * coefficients.green
-        + sdr_pixel.b * coefficients.blue;
+/// Everything needed to process a single pixel, shared by CPU and GPU implementations
+pub struct PixelParameters {
+    /// Color space conversion, if any
+    pub conversion_matrix: Option<Matrix3x3f>,
+    /// Same conversion derived in f64, for f64 processing
+    pub conversion_matrix_f64: Option<Matrix3x3d>,
+    /// Exposure multiplication factor
+    pub factor: f32,
+    pub transfer: Transfer,
+    pub coefficients: LuminanceCoefficients,
+    /// Same coefficients derived in f64, for f64 processing
+    pub coefficients_f64: LuminanceCoefficients<f64>,
+    pub offset_hdr: f32,
+    pub offset_sdr: f32,
+    pub gain_basis: GainBasis,
+    pub trims: SdrTrims,
+    /// SDR rendition baked into a 3D LUT, CPU only
+    pub lut: Option<BakedLut>,
+}
 
-    (hdr_luminance + offset_hdr) / (sdr_luminance + offset_sdr)
+/// Convert pixels in place to output color space, returns gamma-encoded u8 RGB planes, gain of every pixel and gain statistics. Chunks of the image are processed on `threads` threads, 0 meaning one per core
+fn process_cpu<P: StoredPixel>(
+    linear_light: &mut [P],
+    parameters: &PixelParameters,
+    threads: usize,
+) -> (PlanarRgb, Vec<P::Float>, GainStats) {
+    let threads = match threads {
+        0 => thread::available_parallelism().map_or(1, |n| n.get()),
+        threads => threads,
+    };
+    // Threads write their chunk of every plane in place, no copy or reallocation when joining
+    let mut image = PlanarRgb::new(linear_light.len());
+    let mut pixel_gains = vec![P::Float::zero(); linear_light.len()];
+    let chunk_size = linear_light.len().div_ceil(threads).max(1);
+    let [red, green, blue] = &mut image.planes;
+    let stats = thread::scope(|scope| {
+        let handles: Vec<_> = linear_light
+            .chunks_mut(chunk_size)
+            .zip(red.chunks_mut(chunk_size))
+            .zip(green.chunks_mut(chunk_size))
+            .zip(blue.chunks_mut(chunk_size))
+            .zip(pixel_gains.chunks_mut(chunk_size))
+            .map(|((((chunk, red), green), blue), chunk_gains)| {
+                scope.spawn(|| process_chunk(chunk, [red, green, blue], chunk_gains, parameters))
+            })
+            .collect();
+        let mut stats = GainStats::default();
+        for handle in handles {
+            stats.merge(&handle.join().unwrap());
+        }
+        stats
+    });
+
+    (image, pixel_gains, stats)
 }
 
-/// Go from scene-referred linear light value to scene-referred gamma-encoded u8 pixel component
-fn process_pixel(linear_value: f32, factor: f32, gamma: f32) -> u8 {
-    (gamma_transfer(linear_value * factor, gamma) * 255.0)
-        .clamp(0.0, 255.0)
-        .round() as u8
+/// Process a chunk of pixels, writing its gamma-encoded RGB values and pixel gains to the matching chunks of the planes
+fn process_chunk<P: StoredPixel>(
+    linear_light: &mut [P],
+    [red, green, blue]: [&mut [u8]; 3],
+    pixel_gains: &mut [P::Float],
+    parameters: &PixelParameters,
+) -> GainStats {
+    let conversion_matrix = P::Float::conversion_matrix(parameters);
+    let coefficients = P::Float::coefficients(parameters);
+    let value = |v: f32| -> P::Float { NumCast::from(v).unwrap() };
+    let (offset_hdr, offset_sdr) = (value(parameters.offset_hdr), value(parameters.offset_sdr));
+    let mut stats = GainStats::default();
+    for ((((stored, red), green), blue), pixel_gain) in linear_light
+        .iter_mut()
+        .zip(red)
+        .zip(green)
+        .zip(blue)
+        .zip(pixel_gains)
+    {
+        let mut pixel = stored.load_full();
+        if let Some(conversion_matrix) = &conversion_matrix {
+            pixel = pixel.transform(conversion_matrix);
+            *stored = P::store_full(pixel);
+        }
+
+        let [r, g, b, sdr_r, sdr_g, sdr_b] = parameters
+            .lut
+            .as_ref()
+            .and_then(|lut| lut.sample(&pixel.cast()))
+            .map(|sampled| sampled.map(value))
+            .unwrap_or_else(|| lut::exact(&pixel, parameters));
+        let sdr = Rgb {
+            r: sdr_r,
+            g: sdr_g,
+            b: sdr_b,
+        };
+        let gain = calculate_gain(
+            &pixel,
+            &sdr,
+            parameters.gain_basis,
+            &coefficients,
+            offset_hdr,
+            offset_sdr,
+        );
+        stats.add(gain.to_f32().unwrap_or_default());
+        *pixel_gain = gain;
+
+        [*red, *green, *blue] = [r, g, b].map(quantize);
+    }
+
+    stats
 }
 
-fn encode_gain_map_png(png_path: PathBuf, image_data: &[u8], width: usize, height: usize) {
-    let mut encoder = PNGEncoder::new(
-        BufWriter::new(File::create(png_path).unwrap()),
-        width.try_into().unwrap(),
-        height.try_into().unwrap(),
-    );
-    encoder.set_color(png::ColorType::Grayscale);
-    encoder.set_depth(png::BitDepth::Eight);
-    encoder.set_source_gamma(ScaledFloat::new(MAP_GAMMA.recip()));
-    let mut writer = encoder.write_header().unwrap();
-    writer.write_image_data(image_data).unwrap();
+/// Go from exposed linear SDR value to gamma-encoded u8 pixel component
+fn process_pixel(sdr_value: f32, transfer: Transfer) -> u8 {
+    quantize(transfer.encode(sdr_value))
 }
 
-fn encode_png(
-    png_path: PathBuf,
-    image_data: &[u8],
-    width: usize,
-    height: usize,
-    write_chromaticities: Chromaticities,
-) {
-    let mut encoder = PNGEncoder::new(
-        BufWriter::new(File::create(png_path).unwrap()),
-        width.try_into().unwrap(),
-        height.try_into().unwrap(),
-    );
-    encoder.set_color(png::ColorType::Rgb);
-    encoder.set_depth(png::BitDepth::Eight);
-    encoder.set_source_gamma(ScaledFloat::new(GAMMA.recip()));
-    if write_chromaticities.has_negatives() {
-        eprint!("Warning: Some output chromaticities have negative values, PNGs clamps these to 0. Color WILL be affected.")
-    }
-    encoder.set_source_chromaticities(write_chromaticities.into());
-    let mut writer = encoder.write_header().unwrap();
-    writer.write_image_data(image_data).unwrap();
+/// Go from gamma-encoded value to u8 pixel component
+fn quantize<F: Float>(encoded: F) -> u8 {
+    let scaled = encoded * F::from(255.0).unwrap();
+    clamp(scaled, F::zero(), F::from(255.0).unwrap())
+        .round()
+        .to_u8()
+        .unwrap_or_default()
 }
@@ -0,0 +1,114 @@
+// Record of a conversion, to audit deliverables and reproduce them
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{parser::ValueSource, ArgMatches, CommandFactory};
+use serde::Serialize;
+use tracing::info;
+
+use crate::{cache, sha256::hash_file, App, Outputs};
+
+#[derive(Serialize)]
+struct Manifest {
+    tool: &'static str,
+    version: &'static str,
+    /// Every option with its effective value, defaults included
+    settings: BTreeMap<String, Setting>,
+    /// Files read besides inputs, such as --base-jpeg or --sdr-exr
+    references: Vec<HashedFile>,
+    conversions: Vec<Conversion>,
+}
+
+#[derive(Serialize)]
+struct Setting {
+    values: Vec<String>,
+    /// Whether the value was given on the command line or is the default
+    source: &'static str,
+}
+
+#[derive(Serialize)]
+struct HashedFile {
+    path: PathBuf,
+    sha256: String,
+}
+
+#[derive(Serialize)]
+struct Conversion {
+    input: HashedFile,
+    outputs: Vec<HashedFile>,
+}
+
+/// Write a JSON manifest of a finished conversion: tool version, settings, and SHA-256 of every input and output file
+pub fn write(
+    path: &Path,
+    args: &App,
+    matches: &ArgMatches,
+    jobs: &[(PathBuf, Outputs)],
+) -> Result<(), String> {
+    let hashed = |path: &Path| {
+        hash_file(path)
+            .map(|sha256| HashedFile {
+                path: path.to_path_buf(),
+                sha256,
+            })
+            .map_err(|e| format!("Could not hash {}: {}", path.display(), e))
+    };
+
+    let mut settings = BTreeMap::new();
+    for argument in App::command().get_arguments() {
+        let id = argument.get_id().as_str();
+        // Inputs and the manifest itself are listed with their hashes
+        if id == "exr" || id == "manifest" {
+            continue;
+        }
+        let (Some(values), Some(source)) = (matches.get_raw(id), matches.value_source(id)) else {
+            continue;
+        };
+        settings.insert(
+            id.to_string(),
+            Setting {
+                values: values.map(|v| v.to_string_lossy().into_owned()).collect(),
+                source: match source {
+                    ValueSource::DefaultValue => "default",
+                    ValueSource::EnvVariable => "environment",
+                    _ => "command line",
+                },
+            },
+        );
+    }
+
+    let references = cache::references(matches)
+        .iter()
+        .map(|path| hashed(path))
+        .collect::<Result<_, _>>()?;
+
+    let conversions = jobs
+        .iter()
+        .map(|(input, outputs)| {
+            Ok(Conversion {
+                input: hashed(input)?,
+                outputs: outputs
+                    .paths(args)
+                    .iter()
+                    .map(|path| hashed(path))
+                    .collect::<Result<_, String>>()?,
+            })
+        })
+        .collect::<Result<_, String>>()?;
+
+    let manifest = Manifest {
+        tool: env!("CARGO_PKG_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+        settings,
+        references,
+        conversions,
+    };
+    fs::write(path, serde_json::to_string_pretty(&manifest).unwrap())
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    info!(path = %path.display(), "Wrote manifest");
+    Ok(())
+}
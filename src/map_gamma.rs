@@ -0,0 +1,71 @@
+use clap::ValueEnum;
+
+/// Curve of encoded Gain Map recovery values
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum MapCurve {
+    /// Power curve of --map-gamma, understood by every decoder
+    Power,
+    /// PQ over the Gain Map boosts, against banding when they span more than 10 stops. Written as an exr2ultra-hdr XMP property, other decoders read the Gain Map as linear in stops and get boosts wrong
+    Pq,
+}
+
+/// Gamma applied to encoded Gain Map recovery values
+#[derive(Debug, Copy, Clone)]
+pub enum MapGamma {
+    Fixed(f32),
+    /// Pick the gamma minimizing quantization error of the reconstructed HDR image
+    Auto,
+}
+
+pub fn parse_map_gamma(text: &str) -> Result<MapGamma, String> {
+    if text == "auto" {
+        return Ok(MapGamma::Auto);
+    }
+    let gamma: f32 = text
+        .parse()
+        .map_err(|e| format!("expected \"auto\" or a number: {}", e))?;
+    if gamma <= 0.0 {
+        return Err("gamma must be positive".to_string());
+    }
+    Ok(MapGamma::Fixed(gamma))
+}
+
+/// Candidate gammas are spread evenly in log2 space between these
+const SEARCH_MIN_LOG2: f32 = -2.0;
+const SEARCH_MAX_LOG2: f32 = 2.0;
+const SEARCH_STEPS: usize = 41;
+/// Recovery values are binned before measuring error, keeps search fast on large images
+const HISTOGRAM_BINS: usize = 4096;
+
+/// Gamma minimizing mean squared error of 8-bit encoded then decoded recovery values, which is proportional to error in stops of the reconstructed HDR image
+pub fn optimize(recoveries: impl Iterator<Item = f32>) -> f32 {
+    let mut histogram = vec![0u64; HISTOGRAM_BINS];
+    for recovery in recoveries {
+        let bin = (recovery * (HISTOGRAM_BINS - 1) as f32).round() as usize;
+        histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+    }
+
+    let error = |gamma: f32| -> f64 {
+        histogram
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bin, count)| {
+                let recovery = bin as f32 / (HISTOGRAM_BINS - 1) as f32;
+                let encoded = (recovery.powf(gamma) * 255.0).round() / 255.0;
+                let decoded = encoded.powf(gamma.recip());
+                (decoded - recovery).powi(2) as f64 * *count as f64
+            })
+            .sum()
+    };
+
+    (0..SEARCH_STEPS)
+        .map(|step| {
+            let t = step as f32 / (SEARCH_STEPS - 1) as f32;
+            2.0f32.powf(SEARCH_MIN_LOG2 + t * (SEARCH_MAX_LOG2 - SEARCH_MIN_LOG2))
+        })
+        .map(|gamma| (gamma, error(gamma)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+        .0
+}
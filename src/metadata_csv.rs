@@ -0,0 +1,129 @@
+// Per-file settings and metadata of a batch, from a spreadsheet
+
+use std::{fs, path::Path};
+
+use crate::exif::{gps_entry, ExifValue, ARTIST_TAG, COPYRIGHT_TAG, IMAGE_DESCRIPTION_TAG};
+
+/// Settings and metadata of one file, empty cells leaving them unset
+#[derive(Debug, Default)]
+pub struct FileMetadata {
+    pub exposure: Option<f32>,
+    title: Option<String>,
+    artist: Option<String>,
+    copyright: Option<String>,
+    /// Latitude and longitude in degrees, north and east positive
+    position: Option<(f64, f64)>,
+    /// Meters above sea level
+    altitude: Option<f64>,
+}
+
+impl FileMetadata {
+    /// IFD0 entries written to EXIF
+    pub fn exif_entries(&self) -> Vec<(u16, ExifValue)> {
+        let mut entries: Vec<(u16, ExifValue)> = [
+            (IMAGE_DESCRIPTION_TAG, &self.title),
+            (ARTIST_TAG, &self.artist),
+            (COPYRIGHT_TAG, &self.copyright),
+        ]
+        .into_iter()
+        .filter_map(|(tag, text)| Some((tag, ExifValue::Ascii(text.clone()?))))
+        .collect();
+        if let Some((latitude, longitude)) = self.position {
+            entries.push(gps_entry(latitude, longitude, self.altitude));
+        }
+        entries
+    }
+}
+
+/// Row of a CSV file matching an input by file name. The header names columns: file, and optionally exposure (eV), title, artist, copyright, latitude and longitude (degrees, south and west negative), altitude (meters). Fields containing commas are double-quoted
+pub fn row(path: &Path, input: &Path) -> Result<Option<FileMetadata>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let header = split(
+        lines
+            .next()
+            .ok_or_else(|| format!("{} is empty", path.display()))?
+            .1,
+    );
+    let column = |name: &str| header.iter().position(|c| c == name);
+    let file_column =
+        column("file").ok_or_else(|| format!("{} has no file column", path.display()))?;
+
+    for (index, line) in lines {
+        let fields = split(line);
+        let matches = fields
+            .get(file_column)
+            .and_then(|file| Path::new(file).file_name())
+            .is_some_and(|name| Some(name) == input.file_name());
+        if !matches {
+            continue;
+        }
+
+        let invalid =
+            |name: &str| format!("{} line {}: invalid {}", path.display(), index + 1, name);
+        let text = |name: &str| {
+            column(name)
+                .and_then(|c| fields.get(c))
+                .filter(|f| !f.is_empty())
+                .cloned()
+        };
+        let number = |name: &str| -> Result<Option<f64>, String> {
+            text(name)
+                .map(|f| f.parse().map_err(|_| invalid(name)))
+                .transpose()
+        };
+        let position = match (number("latitude")?, number("longitude")?) {
+            (Some(latitude), Some(longitude)) => {
+                if latitude.abs() > 90.0 {
+                    return Err(invalid("latitude"));
+                }
+                if longitude.abs() > 180.0 {
+                    return Err(invalid("longitude"));
+                }
+                Some((latitude, longitude))
+            }
+            (None, None) => None,
+            _ => {
+                return Err(format!(
+                    "{} line {}: latitude and longitude go together",
+                    path.display(),
+                    index + 1
+                ))
+            }
+        };
+        return Ok(Some(FileMetadata {
+            exposure: number("exposure")?.map(|ev| ev as f32),
+            title: text("title"),
+            artist: text("artist"),
+            copyright: text("copyright"),
+            position,
+            altitude: number("altitude")?,
+        }));
+    }
+    Ok(None)
+}
+
+/// Trimmed fields of a CSV line. Double quotes protect commas, and are escaped by doubling them
+fn split(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut characters = line.chars().peekable();
+    while let Some(c) = characters.next() {
+        match c {
+            '"' if quoted && characters.peek() == Some(&'"') => {
+                field.push('"');
+                characters.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            c => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
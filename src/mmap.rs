@@ -0,0 +1,76 @@
+// Read-only memory mapping of input files, so large files on network mounts are paged in as the decoder reaches them instead of copied through read calls
+
+use std::{ops::Deref, path::Path};
+
+/// A whole file mapped in memory
+pub struct Mapping {
+    #[cfg(unix)]
+    address: *mut libc::c_void,
+    #[cfg(unix)]
+    length: usize,
+}
+
+// The mapping is read-only and private
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    #[cfg(unix)]
+    pub fn open(path: &Path) -> Result<Mapping, String> {
+        use std::{fs::File, os::fd::AsRawFd};
+
+        let error = |e: std::io::Error| format!("Could not map {}: {}", path.display(), e);
+        let file =
+            File::open(path).map_err(|e| format!("Could not open {}: {}", path.display(), e))?;
+        let length = file.metadata().map_err(error)?.len() as usize;
+        if length == 0 {
+            return Err(format!("Could not map {}: empty file", path.display()));
+        }
+        // The mapping stays valid after the file is closed
+        let address = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                length,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if address == libc::MAP_FAILED {
+            return Err(error(std::io::Error::last_os_error()));
+        }
+        // Blocks are mostly read in file order, a failed hint changes nothing
+        unsafe { libc::madvise(address, length, libc::MADV_SEQUENTIAL) };
+        Ok(Mapping { address, length })
+    }
+
+    #[cfg(not(unix))]
+    pub fn open(path: &Path) -> Result<Mapping, String> {
+        Err(format!(
+            "Could not map {}: memory mapping is only supported on Unix",
+            path.display()
+        ))
+    }
+}
+
+impl Deref for Mapping {
+    type Target = [u8];
+
+    #[cfg(unix)]
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.address as *const u8, self.length) }
+    }
+
+    #[cfg(not(unix))]
+    fn deref(&self) -> &[u8] {
+        &[]
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.address, self.length) };
+    }
+}
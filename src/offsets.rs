@@ -0,0 +1,91 @@
+use clap::ValueEnum;
+use tracing::info;
+
+use crate::{color_stuff::Pixel, precision::StoredPixel, sdr_pixel, Matrix3x1f, PixelParameters};
+
+/// How Gain Map offsets are chosen, instead of --offset-sdr and --offset-hdr
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum OffsetMode {
+    /// From the shadow noise floor, minimizing reconstruction error of dark pixels
+    Auto,
+}
+
+/// Pixels looked at, evenly spread over the image
+const SAMPLES: usize = 1 << 16;
+/// Percentage of lit pixels darker than the noise floor
+const NOISE_FLOOR_PERCENTILE: f32 = 1.0;
+/// Exposed HDR luminance below which pixels are dark, middle gray
+const DARK_LIMIT: f32 = 0.18;
+/// Candidate HDR offsets, in half stops around the noise floor
+const SEARCH_HALF_STOPS: i32 = 8;
+
+/// SDR and HDR offsets minimizing reconstruction error of dark pixels. Large offsets grow the absolute error of 8-bit gains in shadows, small ones stretch the Gain Map range to cover dark pixels that SDR trims darken or clip, coarsening every gain. Error below the noise floor is not visible, and not counted
+pub fn optimize<P: StoredPixel>(
+    linear_light: &[P],
+    parameters: &PixelParameters,
+) -> Option<(f32, f32)> {
+    let coefficients = &parameters.coefficients;
+    let value = |p: &Pixel| parameters.gain_basis.value(p, coefficients);
+    let step = (linear_light.len() / SAMPLES).max(1);
+    // HDR value on the gain basis, and SDR value as calculate_gain clips it
+    let samples: Vec<(f32, f32)> = linear_light
+        .iter()
+        .step_by(step)
+        .map(|pixel| {
+            let pixel = match parameters.conversion_matrix {
+                Some(matrix) => (matrix * Matrix3x1f::from(pixel.load())).into(),
+                None => pixel.load(),
+            };
+            let sdr = sdr_pixel(&pixel, parameters.factor, &parameters.trims, coefficients);
+            let clipped = Pixel {
+                r: sdr.r.clamp(0.0, 1.0),
+                g: sdr.g.clamp(0.0, 1.0),
+                b: sdr.b.clamp(0.0, 1.0),
+            };
+            (value(&pixel).max(0.0), value(&clipped))
+        })
+        .collect();
+
+    let mut lit: Vec<f32> = samples.iter().map(|s| s.0).filter(|l| *l > 0.0).collect();
+    if lit.is_empty() {
+        return None;
+    }
+    lit.sort_unstable_by(f32::total_cmp);
+    let noise_floor = lit[((lit.len() - 1) as f32 * NOISE_FLOOR_PERCENTILE / 100.0) as usize];
+    let dark: Vec<&(f32, f32)> = samples
+        .iter()
+        .filter(|(hdr, _)| hdr * parameters.factor < DARK_LIMIT)
+        .collect();
+    if dark.is_empty() {
+        return None;
+    }
+
+    let error = |offset_hdr: f32, offset_sdr: f32| -> f64 {
+        let (min, max) = samples
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), (hdr, sdr)| {
+                let gain = ((hdr + offset_hdr) / (sdr + offset_sdr)).log2();
+                (min.min(gain), max.max(gain))
+            });
+        // Half of an 8-bit step of log2 gain, as a linear error of the reconstructed HDR
+        let quantization = (max - min) / 255.0 / 2.0 * std::f32::consts::LN_2;
+        dark.iter()
+            .map(|(hdr, _)| ((hdr + offset_hdr) * quantization / hdr.max(noise_floor)) as f64)
+            .sum::<f64>()
+            / dark.len() as f64
+    };
+
+    // SDR offset covers the same scene light as the HDR one
+    let (offset_hdr, offset_sdr) = (-SEARCH_HALF_STOPS..=SEARCH_HALF_STOPS)
+        .map(|half_stops| {
+            let offset_hdr = noise_floor * (half_stops as f32 / 2.0).exp2();
+            (offset_hdr, (offset_hdr * parameters.factor).min(1.0))
+        })
+        .min_by(|a, b| error(a.0, a.1).total_cmp(&error(b.0, b.1)))
+        .unwrap();
+    info!(
+        noise_floor,
+        offset_sdr, offset_hdr, "Picked Gain Map offsets"
+    );
+    Some((offset_sdr, offset_hdr))
+}
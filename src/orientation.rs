@@ -0,0 +1,91 @@
+use clap::ValueEnum;
+
+/// Clockwise rotation
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum Rotation {
+    #[value(name = "90")]
+    Cw90,
+    #[value(name = "180")]
+    Cw180,
+    #[value(name = "270")]
+    Cw270,
+}
+
+impl Rotation {
+    fn degrees(&self) -> u32 {
+        match self {
+            Rotation::Cw90 => 90,
+            Rotation::Cw180 => 180,
+            Rotation::Cw270 => 270,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum Flip {
+    /// Mirror left and right
+    H,
+    /// Mirror top and bottom
+    V,
+}
+
+/// Rotate then flip an image. Returns new width and height
+pub fn transform<T: Copy>(
+    pixels: &[T],
+    width: usize,
+    height: usize,
+    rotation: Option<Rotation>,
+    flip: Option<Flip>,
+) -> (Vec<T>, usize, usize) {
+    let (new_width, new_height) = match rotation {
+        Some(Rotation::Cw90) | Some(Rotation::Cw270) => (height, width),
+        _ => (width, height),
+    };
+
+    let mut output = Vec::with_capacity(pixels.len());
+    for y in 0..new_height {
+        for x in 0..new_width {
+            // Undo flip first, as it is applied last
+            let (x, y) = match flip {
+                Some(Flip::H) => (new_width - 1 - x, y),
+                Some(Flip::V) => (x, new_height - 1 - y),
+                None => (x, y),
+            };
+            // Find where this pixel was before rotation
+            let (source_x, source_y) = match rotation {
+                Some(Rotation::Cw90) => (y, height - 1 - x),
+                Some(Rotation::Cw180) => (width - 1 - x, height - 1 - y),
+                Some(Rotation::Cw270) => (width - 1 - y, x),
+                None => (x, y),
+            };
+            output.push(pixels[source_y * width + source_x])
+        }
+    }
+
+    (output, new_width, new_height)
+}
+
+// https://www.cipa.jp/std/documents/e/DC-X008-Translation-2019-E.pdf
+/// EXIF Orientation tag value telling viewers to rotate then flip the image when displaying it
+pub fn exif_orientation(rotation: Option<Rotation>, flip: Option<Flip>) -> u16 {
+    let degrees = rotation.map(|r| r.degrees()).unwrap_or(0);
+
+    // EXIF describes a horizontal mirror first, then a clockwise rotation. Flipping reverses rotation direction, and a vertical flip is a horizontal one rotated by 180°
+    let (mirrored, degrees) = match flip {
+        None => (false, degrees),
+        Some(Flip::H) => (true, (360 - degrees) % 360),
+        Some(Flip::V) => (true, (540 - degrees) % 360),
+    };
+
+    match (mirrored, degrees) {
+        (false, 0) => 1,
+        (false, 90) => 6,
+        (false, 180) => 3,
+        (false, 270) => 8,
+        (true, 0) => 2,
+        (true, 90) => 7,
+        (true, 180) => 4,
+        (true, 270) => 5,
+        _ => unreachable!(),
+    }
+}
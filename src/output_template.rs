@@ -0,0 +1,90 @@
+// Output paths with tokens such as renders/{stem}_{ev:+.1}ev_{space}.jpg, expanded once a file's settings are known
+
+use std::path::{Path, PathBuf};
+
+/// Values tokens expand to, for one converted file
+pub struct Tokens {
+    /// Input file name without extension
+    pub stem: String,
+    /// Exposure value (eV) the file was converted with
+    pub ev: f32,
+    /// Output color space, as named on the command line
+    pub space: String,
+    /// Conversion date, as YYYY-MM-DD
+    pub date: String,
+}
+
+/// Whether a path has tokens to expand, in which case it names files by itself rather than being a directory
+pub fn is_template(path: &Path) -> bool {
+    path.to_string_lossy().contains('{')
+}
+
+/// Replace `{stem}`, `{ev}`, `{space}` and `{date}` in a path. `{ev}` takes a format such as `{ev:+.1}` (sign, then digits after the decimal point), `{{` and `}}` are literal braces
+pub fn expand(path: &Path, tokens: &Tokens) -> Result<PathBuf, String> {
+    let text = path
+        .to_str()
+        .ok_or_else(|| format!("Output template {} is not UTF-8", path.display()))?;
+    let mut expanded = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['{', '}']) {
+        expanded.push_str(&rest[..start]);
+        let brace = &rest[start..start + 1];
+        rest = &rest[start + 1..];
+        if rest.starts_with(brace) {
+            expanded.push_str(brace);
+            rest = &rest[1..];
+            continue;
+        }
+        if brace == "}" {
+            return Err(format!("Unmatched }} in output template {}", text));
+        }
+        let end = rest
+            .find('}')
+            .ok_or_else(|| format!("Unclosed {{ in output template {}", text))?;
+        let (name, format) = match rest[..end].split_once(':') {
+            Some((name, format)) => (name, Some(format)),
+            None => (&rest[..end], None),
+        };
+        let value = match (name, format) {
+            ("stem", None) => tokens.stem.clone(),
+            ("space", None) => tokens.space.clone(),
+            ("date", None) => tokens.date.clone(),
+            ("ev", format) => format_number(tokens.ev, format.unwrap_or("")).ok_or_else(|| {
+                format!("Invalid format {:?} for {{ev}}", format.unwrap_or_default())
+            })?,
+            (name, Some(_)) if ["stem", "space", "date"].contains(&name) => {
+                return Err(format!("{{{}}} takes no format", name))
+            }
+            (name, _) => {
+                return Err(format!(
+                    "Unknown token {{{}}} in output template, expected stem, ev, space or date",
+                    name
+                ))
+            }
+        };
+        expanded.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(PathBuf::from(expanded))
+}
+
+/// Number formatted like Rust's `{:+.1}`: an optional `+` to always show the sign, then an optional precision
+fn format_number(value: f32, format: &str) -> Option<String> {
+    let (sign, precision) = match format.strip_prefix('+') {
+        Some(precision) => (true, precision),
+        None => (false, format),
+    };
+    let text = match precision {
+        "" => format!("{}", value),
+        precision => {
+            let digits: usize = precision.strip_prefix('.')?.parse().ok()?;
+            format!("{:.*}", digits, value)
+        }
+    };
+    Some(if sign && !text.starts_with('-') {
+        format!("+{}", text)
+    } else {
+        text
+    })
+}
@@ -0,0 +1,280 @@
+// https://github.com/google/libultrahdr
+// Interoperability audit: an Ultra HDR JPEG written by libultrahdr against ours, compared in metadata and in reconstructed HDR
+
+use std::{fs, path::Path};
+
+use jpeg_decoder::{Decoder, PixelFormat};
+use tracing::{info, warn};
+use ultra_hdr_core::transfer::pq_inverse_eotf;
+
+use crate::{
+    color_spaces::{REC_2020, REC_709},
+    color_stuff::{Chromaticities, Pixel},
+    icc::read_profile,
+    mpf,
+    recovery_curve::RecoveryCurve,
+    self_check::{delta_e_itp, LMS_FROM_REC_2020, NOTICEABLE_DELTA_E_ITP},
+    transfer_functions::Transfer,
+    xmp::{self, GainMapXmp},
+    Matrix3x1f, Matrix3x3f,
+};
+
+/// Metadata values closer than this are reported as matching
+const METADATA_TOLERANCE: f32 = 1e-3;
+
+/// Primary image and Gain Map of an Ultra HDR JPEG, decoded as a viewer does
+pub struct UltraHdrImage {
+    pub width: usize,
+    pub height: usize,
+    /// Primaries of the primary image, read from its ICC profile
+    pub chromaticities: Chromaticities,
+    /// Transfer the primary image is encoded with, read from its ICC profile
+    transfer: Transfer,
+    /// RGB, 8 bits per component
+    primary: Vec<u8>,
+    gain_map_width: usize,
+    gain_map_height: usize,
+    /// 1 for a single Gain Map shared by components, 3 for one per component
+    gain_map_channels: usize,
+    gain_map: Vec<u8>,
+    metadata: GainMapXmp,
+}
+
+/// Compare the Ultra HDR JPEG `ours` with `reference` written by libultrahdr, each in the color space of its ICC profile. Differing metadata and reconstructed HDR are logged as warnings, images that cannot be compared are errors
+pub fn compare(reference: &Path, ours: &Path, sdr_white_nits: f32) -> Result<(), String> {
+    let theirs = UltraHdrImage::read(reference)?;
+    let ours = UltraHdrImage::read(ours)?;
+    compare_metadata(&theirs, &ours);
+
+    if (theirs.width, theirs.height) != (ours.width, ours.height) {
+        return Err(format!(
+            "libultrahdr image is {}x{}, ours {}x{}, pixels cannot be compared",
+            theirs.width, theirs.height, ours.width, ours.height
+        ));
+    }
+    let to_rec_2020 = |image: &UltraHdrImage| {
+        image
+            .chromaticities
+            .rgb_space_conversion_matrix(&REC_2020)
+            .ok_or("Primary image chromaticities have no RGB to XYZ matrix")
+    };
+    let (their_matrix, our_matrix) = (to_rec_2020(&theirs)?, to_rec_2020(&ours)?);
+    // Linear values relative to SDR white, as PQ-encoded Rec. 2020 RGB and LMS
+    let pq = |matrix: Matrix3x3f, pixel: Pixel| -> ([f32; 3], [f32; 3]) {
+        let rec_2020 = matrix * Matrix3x1f::from(pixel);
+        let lms = LMS_FROM_REC_2020 * rec_2020;
+        let encode = |v: Matrix3x1f| [v.x, v.y, v.z].map(|c| pq_inverse_eotf(c * sdr_white_nits));
+        (encode(rec_2020), encode(lms))
+    };
+
+    let mut squared_error = 0.0f64;
+    let mut delta_es = Vec::with_capacity(ours.width * ours.height);
+    for y in 0..ours.height {
+        for x in 0..ours.width {
+            let ((expected_rgb, expected_lms), (actual_rgb, actual_lms)) = (
+                pq(their_matrix, theirs.reconstruct(x, y)),
+                pq(our_matrix, ours.reconstruct(x, y)),
+            );
+            squared_error += expected_rgb
+                .iter()
+                .zip(actual_rgb)
+                .map(|(e, a)| ((e - a) as f64).powi(2))
+                .sum::<f64>();
+            delta_es.push(delta_e_itp(expected_lms, actual_lms));
+        }
+    }
+    if delta_es.is_empty() {
+        return Ok(());
+    }
+
+    let mean_squared_error = squared_error / (delta_es.len() * 3) as f64;
+    let psnr = -10.0 * mean_squared_error.log10();
+    let mean = delta_es.iter().sum::<f64>() / delta_es.len() as f64;
+    delta_es.sort_unstable_by(f64::total_cmp);
+    let p99 = delta_es[(delta_es.len() - 1) * 99 / 100];
+    let max = delta_es[delta_es.len() - 1];
+    if mean > NOTICEABLE_DELTA_E_ITP {
+        warn!(
+            psnr_db = psnr,
+            mean_delta_e_itp = mean,
+            p99_delta_e_itp = p99,
+            max_delta_e_itp = max,
+            "Reconstructed HDR visibly differs from libultrahdr's"
+        )
+    } else {
+        info!(
+            psnr_db = psnr,
+            mean_delta_e_itp = mean,
+            p99_delta_e_itp = p99,
+            max_delta_e_itp = max,
+            "Reconstructed HDR matches libultrahdr's"
+        )
+    }
+    Ok(())
+}
+
+/// Warn about every Gain Map field that differs
+fn compare_metadata(theirs: &UltraHdrImage, ours: &UltraHdrImage) {
+    let (a, b) = (&theirs.metadata, &ours.metadata);
+    let fields: [(&str, &[f32], &[f32]); 7] = [
+        ("GainMapMin", &a.gain_map_min, &b.gain_map_min),
+        ("GainMapMax", &a.gain_map_max, &b.gain_map_max),
+        ("Gamma", &a.gamma, &b.gamma),
+        ("OffsetSDR", &a.offset_sdr, &b.offset_sdr),
+        ("OffsetHDR", &a.offset_hdr, &b.offset_hdr),
+        (
+            "HDRCapacityMin",
+            &[a.hdr_capacity_min],
+            &[b.hdr_capacity_min],
+        ),
+        (
+            "HDRCapacityMax",
+            &[a.hdr_capacity_max],
+            &[b.hdr_capacity_max],
+        ),
+    ];
+    let mut differences = 0;
+    for (name, theirs, ours) in fields {
+        let close = theirs
+            .iter()
+            .zip(ours)
+            .all(|(t, o)| (t - o).abs() <= METADATA_TOLERANCE);
+        if !close {
+            warn!(libultrahdr = ?theirs, ours = ?ours, "hdrgm:{} differs", name);
+            differences += 1;
+        }
+    }
+    let mut flag = |name: &str, theirs: String, ours: String| {
+        if theirs != ours {
+            warn!(libultrahdr = theirs, ours = ours, "{} differs", name);
+            differences += 1;
+        }
+    };
+    flag(
+        "Recovery curve",
+        format!("{:?}", a.recovery_curve),
+        format!("{:?}", b.recovery_curve),
+    );
+    flag(
+        "Gain Map size",
+        format!("{}x{}", theirs.gain_map_width, theirs.gain_map_height),
+        format!("{}x{}", ours.gain_map_width, ours.gain_map_height),
+    );
+    flag(
+        "Gain Map channels",
+        theirs.gain_map_channels.to_string(),
+        ours.gain_map_channels.to_string(),
+    );
+    if differences == 0 {
+        info!("Gain Map metadata matches libultrahdr's");
+    }
+}
+
+impl UltraHdrImage {
+    pub fn read(path: &Path) -> Result<UltraHdrImage, String> {
+        let data =
+            fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        UltraHdrImage::parse(&data, path)
+    }
+
+    /// Decode the Ultra HDR JPEG `data`, `path` identifying it in errors
+    pub fn parse(data: &[u8], path: &Path) -> Result<UltraHdrImage, String> {
+        let context = |e: String| format!("{}: {}", path.display(), e);
+        let gain_map_range = mpf::images(data)
+            .map_err(context)?
+            .and_then(|images| images.get(1).cloned())
+            .ok_or_else(|| context("No MPF index locating a Gain Map".to_string()))?;
+
+        let primary = decode(data).map_err(context)?;
+        let gain_map = decode(&data[gain_map_range]).map_err(context)?;
+        let packet = gain_map
+            .xmp
+            .ok_or_else(|| context("No XMP in Gain Map image".to_string()))?;
+        let metadata = xmp::parse(&String::from_utf8_lossy(&packet))
+            .and_then(|p| GainMapXmp::from_properties(&p))
+            .map_err(context)?
+            .ok_or_else(|| context("No hdrgm:GainMapMax in Gain Map XMP".to_string()))?;
+        if metadata.base_rendition_is_hdr {
+            return Err(context("HDR base renditions are not supported".to_string()));
+        }
+        if primary.samples.len() != primary.width * primary.height * 3 {
+            return Err(context("Primary image is not RGB".to_string()));
+        }
+        let (chromaticities, transfer) = match primary.icc.as_deref().map(read_profile) {
+            Some(Some(encoding)) => encoding,
+            known => {
+                let reason = match known {
+                    Some(_) => "ICC profile is not a known matrix/TRC RGB profile",
+                    None => "No ICC profile",
+                };
+                warn!(file = %path.display(), "{}, decoding primary image as sRGB", reason);
+                (REC_709, Transfer::Srgb)
+            }
+        };
+        Ok(UltraHdrImage {
+            width: primary.width,
+            height: primary.height,
+            chromaticities,
+            transfer,
+            primary: primary.samples,
+            gain_map_width: gain_map.width,
+            gain_map_height: gain_map.height,
+            gain_map_channels: gain_map.samples.len() / (gain_map.width * gain_map.height),
+            gain_map: gain_map.samples,
+            metadata,
+        })
+    }
+
+    /// HDR rendition at x,y with the Gain Map applied at full HDR capacity, nearest Gain Map sample
+    pub fn reconstruct(&self, x: usize, y: usize) -> Pixel {
+        let metadata = &self.metadata;
+        let map_x = x * self.gain_map_width / self.width;
+        let map_y = y * self.gain_map_height / self.height;
+        let map_index = (map_y * self.gain_map_width + map_x) * self.gain_map_channels;
+        let index = (y * self.width + x) * 3;
+        let [r, g, b] = [0, 1, 2].map(|c| {
+            let encoded = self.gain_map[map_index + c.min(self.gain_map_channels - 1)];
+            let curve = match metadata.recovery_curve {
+                RecoveryCurve::Pq => RecoveryCurve::Pq,
+                RecoveryCurve::Power(_) => RecoveryCurve::Power(metadata.gamma[c]),
+            };
+            let stops = metadata.gain_map_max[c] - metadata.gain_map_min[c];
+            let recovery = curve.decode(encoded as f32 / 255.0, stops);
+            let boost = (metadata.gain_map_min[c] + stops * recovery).exp2();
+            let sdr = self.transfer.decode(self.primary[index + c] as f32 / 255.0);
+            (sdr + metadata.offset_sdr[c]) * boost - metadata.offset_hdr[c]
+        });
+        Pixel { r, g, b }
+    }
+}
+
+/// 8-bit samples of a JPEG stream, gray or RGB
+pub struct DecodedJpeg {
+    samples: Vec<u8>,
+    width: usize,
+    height: usize,
+    xmp: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
+}
+
+/// Decode a JPEG stream to 8-bit gray or RGB samples, with its XMP and ICC profile
+pub fn decode(jpeg: &[u8]) -> Result<DecodedJpeg, String> {
+    let mut decoder = Decoder::new(jpeg);
+    let samples = decoder
+        .decode()
+        .map_err(|e| format!("Could not decode JPEG: {}", e))?;
+    let info = decoder.info().ok_or("JPEG has no frame")?;
+    if !matches!(info.pixel_format, PixelFormat::L8 | PixelFormat::RGB24) {
+        return Err(format!(
+            "JPEG pixel format {:?} is not supported",
+            info.pixel_format
+        ));
+    }
+    Ok(DecodedJpeg {
+        samples,
+        width: info.width as usize,
+        height: info.height as usize,
+        xmp: decoder.xmp_data().map(<[u8]>::to_vec),
+        icc: decoder.icc_profile(),
+    })
+}
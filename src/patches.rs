@@ -0,0 +1,146 @@
+// Color accuracy check against patches of known color, like a rendered color checker chart
+
+use std::{fs, path::Path};
+
+use tracing::{info, warn};
+
+use crate::{
+    chromatic_adaptation::Cat,
+    color_spaces::D50_ILLUMINANT,
+    color_stuff::{delta_e_2000, lab, CIEXYZCoords, Chromaticities, Pixel},
+    Matrix3x1f,
+};
+
+/// Pixels around the given coordinates that are averaged, to ignore noise
+const PATCH_RADIUS: usize = 2;
+/// Differences above this fail the check
+const TOLERATED_DELTA_E: f32 = 2.0;
+
+/// A patch and its expected color, as D50 CIE Lab
+pub struct Patch {
+    pub name: String,
+    pub x: usize,
+    pub y: usize,
+    pub expected: [f32; 3],
+}
+
+/// Read patches from a CSV file with a header naming columns: x and y (pixel coordinates in the output), L, a and b or X, Y and Z (Y of white being 100), and optionally name. Expected values are D50 relative, as published for color checkers
+pub fn read(path: &Path) -> Result<Vec<Patch>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let header: Vec<&str> = lines
+        .next()
+        .ok_or_else(|| format!("{} is empty", path.display()))?
+        .1
+        .split(',')
+        .map(str::trim)
+        .collect();
+    let column = |name: &str| header.iter().position(|c| *c == name);
+    let columns = |names: [&str; 3]| -> Option<[usize; 3]> {
+        Some([column(names[0])?, column(names[1])?, column(names[2])?])
+    };
+    let (x_column, y_column) = column("x")
+        .zip(column("y"))
+        .ok_or_else(|| format!("{} has no x and y columns", path.display()))?;
+    let (value_columns, is_lab) = match (columns(["L", "a", "b"]), columns(["X", "Y", "Z"])) {
+        (Some(columns), _) => (columns, true),
+        (None, Some(columns)) => (columns, false),
+        (None, None) => {
+            return Err(format!(
+                "{} has neither L, a, b nor X, Y, Z columns",
+                path.display()
+            ))
+        }
+    };
+    let white: CIEXYZCoords = D50_ILLUMINANT.with_luma(1.0).into();
+
+    lines
+        .map(|(index, line)| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let invalid = || format!("{} line {}: invalid patch", path.display(), index + 1);
+            let field = |column: usize| fields.get(column).copied().ok_or_else(invalid);
+            let number = |column: usize| field(column)?.parse::<f32>().map_err(|_| invalid());
+            let index_of = |column: usize| field(column)?.parse::<usize>().map_err(|_| invalid());
+
+            let values = [
+                number(value_columns[0])?,
+                number(value_columns[1])?,
+                number(value_columns[2])?,
+            ];
+            let expected = if is_lab {
+                values
+            } else {
+                let [x, y, z] = values.map(|v| v / 100.0);
+                lab(Matrix3x1f::new(x, y, z).into(), white)
+            };
+            Ok(Patch {
+                name: match column("name") {
+                    Some(column) => field(column)?.to_string(),
+                    None => format!("line {}", index + 1),
+                },
+                x: index_of(x_column)?,
+                y: index_of(y_column)?,
+                expected,
+            })
+        })
+        .collect()
+}
+
+/// Measure patches on the SDR rendition, given as linear light in output color space, and log their CIEDE2000 difference
+pub fn report(
+    patches: &[Patch],
+    sdr_linear: &[Pixel],
+    width: usize,
+    height: usize,
+    chromaticities: &Chromaticities,
+    cat: Cat,
+) -> Result<(), String> {
+    let to_xyz = chromaticities
+        .rgb_to_xyz_matrix()
+        .ok_or("Output color space has no XYZ conversion")?;
+    let to_d50 = cat.adaptation_matrix(chromaticities.white, D50_ILLUMINANT) * to_xyz;
+    let white: CIEXYZCoords = D50_ILLUMINANT.with_luma(1.0).into();
+
+    let mut worst = 0.0f32;
+    let mut total = 0.0;
+    let mut failed = 0;
+    for patch in patches {
+        if patch.x >= width || patch.y >= height {
+            return Err(format!(
+                "Patch {} at {}, {} is outside of the {}x{} output",
+                patch.name, patch.x, patch.y, width, height
+            ));
+        }
+        // Average what the SDR output holds, values are clipped there
+        let xs = patch.x.saturating_sub(PATCH_RADIUS)..(patch.x + PATCH_RADIUS + 1).min(width);
+        let ys = patch.y.saturating_sub(PATCH_RADIUS)..(patch.y + PATCH_RADIUS + 1).min(height);
+        let count = (xs.len() * ys.len()) as f32;
+        let sum = ys
+            .flat_map(|y| xs.clone().map(move |x| sdr_linear[y * width + x]))
+            .map(|p| Matrix3x1f::from(p).map(|v| v.clamp(0.0, 1.0)))
+            .sum::<Matrix3x1f>();
+        let measured = lab((to_d50 * sum / count).into(), white);
+
+        let delta_e = delta_e_2000(measured, patch.expected);
+        worst = worst.max(delta_e);
+        total += delta_e;
+        if delta_e > TOLERATED_DELTA_E {
+            failed += 1;
+            warn!(patch = patch.name, delta_e, ?measured, expected = ?patch.expected, "Patch differs noticeably");
+        } else {
+            info!(patch = patch.name, delta_e, ?measured, expected = ?patch.expected, "Patch");
+        }
+    }
+    info!(
+        patches = patches.len(),
+        failed,
+        mean_delta_e = total / patches.len().max(1) as f32,
+        max_delta_e = worst,
+        "Patch verification"
+    );
+    Ok(())
+}
@@ -0,0 +1,72 @@
+// http://www.pauldebevec.com/Research/HDR/PFM/
+// Portable float maps: a text header and raw 32-bit float samples, bottom row first
+
+use std::path::Path;
+
+use crate::{decode::ExrImage, input_format::rgb_image};
+
+/// Read a PFM file, color (PF) or gray (Pf). Gray is spread to every channel
+pub fn read(data: &[u8], path: &Path) -> Result<ExrImage, String> {
+    let error = |message: &str| format!("{} is not a valid PFM file: {}", path.display(), message);
+
+    // Three whitespace-separated tokens after the identifier, then a single whitespace character
+    let mut position = 0;
+    let mut token = || {
+        while data.get(position).is_some_and(u8::is_ascii_whitespace) {
+            position += 1;
+        }
+        let start = position;
+        while data.get(position).is_some_and(|b| !b.is_ascii_whitespace()) {
+            position += 1;
+        }
+        std::str::from_utf8(&data[start..position]).unwrap_or_default()
+    };
+    let components = match token() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(error("unknown identifier")),
+    };
+    let width: usize = token().parse().map_err(|_| error("invalid width"))?;
+    let height: usize = token().parse().map_err(|_| error("invalid height"))?;
+    let scale: f32 = token().parse().map_err(|_| error("invalid scale"))?;
+    let samples = &data[(position + 1).min(data.len())..];
+    if width == 0 || height == 0 {
+        return Err(error("empty image"));
+    }
+
+    // Checked before reserving memory, so a header cannot ask for more than the file holds
+    let size = width
+        .checked_mul(height)
+        .and_then(|n| n.checked_mul(components * 4))
+        .ok_or_else(|| error("image too large"))?;
+    if samples.len() < size {
+        return Err(error("truncated samples"));
+    }
+    // The sign of the scale gives the byte order, its magnitude is unused
+    let little_endian = scale < 0.0;
+    let samples: Vec<f32> = samples[..size]
+        .chunks_exact(4)
+        .map(|b| {
+            let bytes = [b[0], b[1], b[2], b[3]];
+            if little_endian {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            }
+        })
+        .collect();
+
+    let mut channels = [
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+    ];
+    for row in samples.chunks_exact(width * components).rev() {
+        for pixel in row.chunks_exact(components) {
+            for (c, channel) in channels.iter_mut().enumerate() {
+                channel.push(pixel[c.min(components - 1)]);
+            }
+        }
+    }
+    Ok(rgb_image(width, height, channels, None))
+}
@@ -0,0 +1,177 @@
+// https://en.wikipedia.org/wiki/Chromatic_aberration#Lateral_chromatic_aberration
+// Corrections of the input in its own linear light, before any other processing, for scanned or captured HDR sources
+
+use tracing::info;
+
+use crate::{color_stuff::Pixel, precision::StoredPixel, App};
+
+/// Correction of the input image, applied in linear light of the input color space before exposure, cropping or any other processing
+pub trait PixelFilter {
+    /// Name shown in logs
+    fn name(&self) -> &'static str;
+    /// Correct pixels of an image `width` by `height` pixels in place
+    fn apply(&self, pixels: &mut [Pixel], width: usize, height: usize);
+}
+
+/// Filters requested on the command line, in the order they apply
+pub fn from_args(args: &App) -> Vec<Box<dyn PixelFilter>> {
+    let mut filters: Vec<Box<dyn PixelFilter>> = Vec::new();
+    for alignment in &args.align_channel {
+        filters.push(Box::new(*alignment));
+    }
+    if let Some(ca) = args.ca_correction {
+        filters.push(Box::new(ca));
+    }
+    filters
+}
+
+/// Run `filters` over stored pixels. Filters see f32 pixels, so f64 precision is rounded where any runs
+pub fn apply_all<P: StoredPixel>(
+    filters: &[Box<dyn PixelFilter>],
+    stored: &mut [P],
+    width: usize,
+    height: usize,
+) {
+    if filters.is_empty() {
+        return;
+    }
+    let mut pixels: Vec<Pixel> = stored.iter().map(|p| p.load()).collect();
+    for filter in filters {
+        info!(filter = filter.name(), "Applying pixel filter");
+        filter.apply(&mut pixels, width, height);
+    }
+    for (stored, pixel) in stored.iter_mut().zip(pixels) {
+        *stored = P::store(pixel);
+    }
+}
+
+// ----- Channel alignment
+
+/// Move and scale one channel over the others, for scans or captures with misregistered channels
+#[derive(Debug, Copy, Clone)]
+pub struct ChannelAlignment {
+    /// 0 for red, 1 for green, 2 for blue
+    pub channel: usize,
+    /// Offset in pixels, positive towards the right and bottom
+    pub offset: (f32, f32),
+    /// Magnification about the image center
+    pub scale: f32,
+}
+
+/// Parse `CHANNEL:DX,DY` or `CHANNEL:DX,DY,SCALE`, with CHANNEL r, g or b
+pub fn parse_channel_alignment(text: &str) -> Result<ChannelAlignment, String> {
+    let (channel, values) = text
+        .split_once(':')
+        .ok_or_else(|| "expected CHANNEL:DX,DY[,SCALE]".to_string())?;
+    let channel = match channel.to_ascii_lowercase().as_str() {
+        "r" => 0,
+        "g" => 1,
+        "b" => 2,
+        _ => return Err(format!("channel {:?} is not r, g or b", channel)),
+    };
+    let values = values
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f32>()
+                .map_err(|e| format!("{:?}: {}", v, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let (dx, dy, scale) = match values[..] {
+        [dx, dy] => (dx, dy, 1.0),
+        [dx, dy, scale] => (dx, dy, scale),
+        _ => return Err("expected CHANNEL:DX,DY[,SCALE]".to_string()),
+    };
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(format!("scale must be positive, got {}", scale));
+    }
+    Ok(ChannelAlignment {
+        channel,
+        offset: (dx, dy),
+        scale,
+    })
+}
+
+impl PixelFilter for ChannelAlignment {
+    fn name(&self) -> &'static str {
+        "channel alignment"
+    }
+
+    fn apply(&self, pixels: &mut [Pixel], width: usize, height: usize) {
+        warp_channel(pixels, width, height, self.channel, self.offset, self.scale);
+    }
+}
+
+// ----- Lateral chromatic aberration
+
+/// Lateral chromatic aberration as magnifications of red and blue relative to green, which stays in place. Lenses focusing each wavelength at another size leave colored fringes growing towards the edges
+#[derive(Debug, Copy, Clone)]
+pub struct LateralCa {
+    pub red: f32,
+    pub blue: f32,
+}
+
+/// Parse `RED,BLUE` magnifications
+pub fn parse_ca_correction(text: &str) -> Result<LateralCa, String> {
+    let values = text
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f32>()
+                .map_err(|e| format!("{:?}: {}", v, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let [red, blue] = values[..] else {
+        return Err("expected RED,BLUE".to_string());
+    };
+    if ![red, blue].iter().all(|s| s.is_finite() && *s > 0.0) {
+        return Err("magnifications must be positive".to_string());
+    }
+    Ok(LateralCa { red, blue })
+}
+
+impl PixelFilter for LateralCa {
+    fn name(&self) -> &'static str {
+        "lateral chromatic aberration correction"
+    }
+
+    fn apply(&self, pixels: &mut [Pixel], width: usize, height: usize) {
+        warp_channel(pixels, width, height, 0, (0.0, 0.0), self.red);
+        warp_channel(pixels, width, height, 2, (0.0, 0.0), self.blue);
+    }
+}
+
+// -----
+
+/// Move one channel by `offset` pixels and scale it by `scale` about the image center, sampling it bilinearly. Samples outside the image repeat the edge
+fn warp_channel(
+    pixels: &mut [Pixel],
+    width: usize,
+    height: usize,
+    channel: usize,
+    offset: (f32, f32),
+    scale: f32,
+) {
+    if width == 0 || height == 0 {
+        return;
+    }
+    let get = |p: &Pixel| [p.r, p.g, p.b][channel];
+    let plane: Vec<f32> = pixels.iter().map(get).collect();
+    let at = |x: usize, y: usize| plane[y.min(height - 1) * width + x.min(width - 1)];
+    let center = ((width - 1) as f32 / 2.0, (height - 1) as f32 / 2.0);
+
+    for (index, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = ((index % width) as f32, (index / width) as f32);
+        let u = ((x - center.0 - offset.0) / scale + center.0).clamp(0.0, (width - 1) as f32);
+        let v = ((y - center.1 - offset.1) / scale + center.1).clamp(0.0, (height - 1) as f32);
+        let (left, top) = (u as usize, v as usize);
+        let (fx, fy) = (u - left as f32, v - top as f32);
+        let value = (at(left, top) * (1.0 - fx) + at(left + 1, top) * fx) * (1.0 - fy)
+            + (at(left, top + 1) * (1.0 - fx) + at(left + 1, top + 1) * fx) * fy;
+        match channel {
+            0 => pixel.r = value,
+            1 => pixel.g = value,
+            _ => pixel.b = value,
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// 8-bit images stored as one plane per channel. Sinks and encoders read them through views, interleaving a row at a time where their format needs it
+
+use jpeg_encoder::{rgb_to_ycbcr, ImageBuffer, JpegColorType};
+
+/// Gamma-encoded RGB image, one plane per channel
+#[derive(Default)]
+pub struct PlanarRgb {
+    pub planes: [Vec<u8>; 3],
+}
+
+impl PlanarRgb {
+    /// Black image of `len` pixels
+    pub fn new(len: usize) -> PlanarRgb {
+        PlanarRgb {
+            planes: [vec![0; len], vec![0; len], vec![0; len]],
+        }
+    }
+
+    pub fn with_capacity(len: usize) -> PlanarRgb {
+        PlanarRgb {
+            planes: [
+                Vec::with_capacity(len),
+                Vec::with_capacity(len),
+                Vec::with_capacity(len),
+            ],
+        }
+    }
+
+    pub fn push(&mut self, rgb: [u8; 3]) {
+        for (plane, value) in self.planes.iter_mut().zip(rgb) {
+            plane.push(value);
+        }
+    }
+
+    pub fn view(&self, width: usize, height: usize) -> PlanarView<'_> {
+        let [r, g, b] = &self.planes;
+        PlanarView::rgb([r, g, b], width, height)
+    }
+}
+
+impl FromIterator<[u8; 3]> for PlanarRgb {
+    fn from_iter<I: IntoIterator<Item = [u8; 3]>>(pixels: I) -> PlanarRgb {
+        let mut image = PlanarRgb::default();
+        for rgb in pixels {
+            image.push(rgb);
+        }
+        image
+    }
+}
+
+/// Borrowed planes of a gray or RGB image, or of a band of its rows
+#[derive(Clone, Copy)]
+pub struct PlanarView<'a> {
+    pub width: usize,
+    pub height: usize,
+    /// Only the first is used by gray images
+    planes: [&'a [u8]; 3],
+    channels: usize,
+}
+
+impl<'a> PlanarView<'a> {
+    pub fn gray(plane: &'a [u8], width: usize, height: usize) -> PlanarView<'a> {
+        PlanarView {
+            width,
+            height,
+            planes: [plane, &[], &[]],
+            channels: 1,
+        }
+    }
+
+    pub fn rgb(planes: [&'a [u8]; 3], width: usize, height: usize) -> PlanarView<'a> {
+        PlanarView {
+            width,
+            height,
+            planes,
+            channels: 3,
+        }
+    }
+
+    pub fn is_gray(&self) -> bool {
+        self.channels == 1
+    }
+
+    /// RGB values of the pixel at `index`, gray ones being repeated
+    pub fn pixel(&self, index: usize) -> [u8; 3] {
+        match self.channels {
+            1 => [self.planes[0][index]; 3],
+            _ => self.planes.map(|plane| plane[index]),
+        }
+    }
+
+    /// RGB values of every pixel, in row order
+    pub fn pixels(self) -> impl Iterator<Item = [u8; 3]> + 'a {
+        (0..self.width * self.height).map(move |index| self.pixel(index))
+    }
+
+    /// Rows `start` to `end` (excluded)
+    pub fn rows(&self, start: usize, end: usize) -> PlanarView<'a> {
+        let range = start * self.width..end * self.width;
+        PlanarView {
+            height: end - start,
+            planes: self
+                .planes
+                .map(|plane| plane.get(range.clone()).unwrap_or_default()),
+            ..*self
+        }
+    }
+
+    /// Interleave row `y` into `row`, replacing its contents
+    pub fn interleave_row(&self, y: usize, row: &mut Vec<u8>) {
+        let range = y * self.width..(y + 1) * self.width;
+        row.clear();
+        match self.channels {
+            1 => row.extend_from_slice(&self.planes[0][range]),
+            _ => {
+                let [r, g, b] = self.planes.map(|plane| &plane[range.clone()]);
+                for ((r, g), b) in r.iter().zip(g).zip(b) {
+                    row.extend([*r, *g, *b]);
+                }
+            }
+        }
+    }
+
+    /// Every row, interleaved. Rows are allocated one at a time, for row by row encoders
+    pub fn interleaved_rows(self) -> impl Iterator<Item = Vec<u8>> + 'a {
+        (0..self.height).map(move |y| {
+            let mut row = Vec::with_capacity(self.width * self.channels);
+            self.interleave_row(y, &mut row);
+            row
+        })
+    }
+}
+
+/// Fed to jpeg-encoder directly, RGB going to YCbCr from the planes
+impl ImageBuffer for PlanarView<'_> {
+    fn get_jpeg_color_type(&self) -> JpegColorType {
+        match self.channels {
+            1 => JpegColorType::Luma,
+            _ => JpegColorType::Ycbcr,
+        }
+    }
+
+    fn width(&self) -> u16 {
+        self.width.try_into().unwrap()
+    }
+
+    fn height(&self) -> u16 {
+        self.height.try_into().unwrap()
+    }
+
+    fn fill_buffers(&self, y: u16, buffers: &mut [Vec<u8>; 4]) {
+        let range = y as usize * self.width..(y as usize + 1) * self.width;
+        match self.channels {
+            1 => buffers[0].extend_from_slice(&self.planes[0][range]),
+            _ => {
+                let [r, g, b] = self.planes.map(|plane| &plane[range.clone()]);
+                for ((r, g), b) in r.iter().zip(g).zip(b) {
+                    let (y, cb, cr) = rgb_to_ycbcr(*r, *g, *b);
+                    buffers[0].push(y);
+                    buffers[1].push(cb);
+                    buffers[2].push(cr);
+                }
+            }
+        }
+    }
+}
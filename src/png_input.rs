@@ -0,0 +1,107 @@
+use std::{fs, io::Cursor, path::Path};
+
+use tracing::{info, warn};
+
+use crate::{
+    color_spaces::{DISPLAY_P3, REC_2020, REC_709},
+    decode::ExrImage,
+    input_format::rgb_image,
+    transfer_functions::HdrTransfer,
+};
+
+const CICP_CHUNK: &[u8; 4] = b"cICP";
+
+/// Read a PNG frame of HDR video as linear light where 1.0 is SDR white. Transfer and primaries come from the cICP chunk, unless transfer is given. Primaries default to Rec. 2020
+pub fn read(
+    path: &Path,
+    transfer: Option<HdrTransfer>,
+    sdr_white_nits: f32,
+) -> Result<ExrImage, String> {
+    let data = fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let cicp = find_cicp(&data);
+
+    let chromaticities = match cicp.map(|c| c[0]) {
+        None | Some(9) => REC_2020,
+        Some(1) => REC_709,
+        Some(12) => DISPLAY_P3,
+        Some(other) => {
+            warn!(
+                colour_primaries = other,
+                "Unsupported CICP primaries in PNG, assuming Rec. 2020"
+            );
+            REC_2020
+        }
+    };
+    let transfer = transfer
+        .or_else(|| cicp.and_then(|c| HdrTransfer::from_cicp(c[1])))
+        .ok_or_else(|| {
+            format!(
+                "{} has no PQ or HLG cICP chunk, specify --input-transfer",
+                path.display()
+            )
+        })?;
+    info!(?transfer, "Linearizing HDR PNG");
+
+    let mut decoder = png::Decoder::new(Cursor::new(&data));
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder
+        .read_info()
+        .map_err(|e| format!("Could not decode {}: {}", path.display(), e))?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader
+        .next_frame(&mut buffer)
+        .map_err(|e| format!("Could not decode {}: {}", path.display(), e))?;
+    let (width, height) = (frame.width as usize, frame.height as usize);
+
+    // Normalized samples, whatever the bit depth
+    let samples: Vec<f32> = match frame.bit_depth {
+        png::BitDepth::Sixteen => buffer[..frame.buffer_size()]
+            .chunks_exact(2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as f32 / u16::MAX as f32)
+            .collect(),
+        _ => buffer[..frame.buffer_size()]
+            .iter()
+            .map(|b| *b as f32 / u8::MAX as f32)
+            .collect(),
+    };
+    // Alpha is dropped, gray is spread to every channel
+    let components = frame.color_type.samples();
+    let coefficients = chromaticities.luminance_values().unwrap();
+    let mut channels = [
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+    ];
+    for pixel in samples.chunks_exact(components) {
+        let rgb = if components < 3 {
+            [pixel[0]; 3]
+        } else {
+            [pixel[0], pixel[1], pixel[2]]
+        };
+        let nits = transfer.decode_nits(rgb, &coefficients);
+        for (channel, value) in channels.iter_mut().zip(nits) {
+            channel.push(value / sdr_white_nits)
+        }
+    }
+
+    Ok(rgb_image(width, height, channels, Some(chromaticities)))
+}
+
+/// Contents of the cICP chunk, if any. The png crate does not parse it
+fn find_cicp(data: &[u8]) -> Option<[u8; 4]> {
+    let mut position = 8;
+    while position + 8 <= data.len() {
+        let length = u32::from_be_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        let chunk_type = &data[position + 4..position + 8];
+        let contents = data.get(position + 8..position + 8 + length)?;
+        if chunk_type == CICP_CHUNK && length == 4 {
+            return contents.try_into().ok();
+        }
+        if chunk_type == b"IDAT" {
+            // cICP must come before image data
+            return None;
+        }
+        position += length + 12;
+    }
+    None
+}
@@ -0,0 +1,245 @@
+// Storage of the working copy of the image, pixels being converted to f32 where they are processed, or kept in f64 for f64 storage
+
+use std::slice;
+
+use clap::ValueEnum;
+use half::f16;
+use nalgebra::{SMatrix, Scalar};
+use num_traits::Float;
+
+use crate::{
+    color_stuff::{LuminanceCoefficients, Pixel, Rgb},
+    resize::downscale_box,
+    PixelParameters,
+};
+
+/// Precision of linear light held in memory during a conversion
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum Precision {
+    /// Half float, 6 bytes per pixel. Values above 65504 do not fit and are treated as infinite
+    F16,
+    /// Single float, 12 bytes per pixel
+    F32,
+    /// Double float, 24 bytes per pixel. Matrices and per-pixel math (conversion, SDR rendition, gain) are computed in f64 too, for reference images. Slower, and always on CPU
+    F64,
+}
+
+/// Float type pixels are processed with
+pub trait Component: Float + Scalar + Default + Send + Sync {
+    /// Color space conversion, derived in this precision
+    fn conversion_matrix(parameters: &PixelParameters) -> Option<SMatrix<Self, 3, 3>>;
+    /// Luminance coefficients, derived in this precision
+    fn coefficients(parameters: &PixelParameters) -> LuminanceCoefficients<Self>;
+    /// Pixel gains computed in f32, such as on GPU
+    fn gains_from_f32(gains: Vec<f32>) -> Vec<Self>;
+}
+
+impl Component for f32 {
+    fn conversion_matrix(parameters: &PixelParameters) -> Option<SMatrix<f32, 3, 3>> {
+        parameters.conversion_matrix
+    }
+
+    fn coefficients(parameters: &PixelParameters) -> LuminanceCoefficients {
+        parameters.coefficients
+    }
+
+    fn gains_from_f32(gains: Vec<f32>) -> Vec<f32> {
+        gains
+    }
+}
+
+impl Component for f64 {
+    fn conversion_matrix(parameters: &PixelParameters) -> Option<SMatrix<f64, 3, 3>> {
+        parameters.conversion_matrix_f64
+    }
+
+    fn coefficients(parameters: &PixelParameters) -> LuminanceCoefficients<f64> {
+        parameters.coefficients_f64
+    }
+
+    fn gains_from_f32(gains: Vec<f32>) -> Vec<f64> {
+        gains.into_iter().map(f64::from).collect()
+    }
+}
+
+/// Linear-light pixel as stored in the working buffer
+pub trait StoredPixel: Copy + Default + Send + Sync {
+    /// Precision pixels are processed with
+    type Float: Component;
+
+    fn load(self) -> Pixel;
+    fn store(pixel: Pixel) -> Self;
+    /// Load without rounding to f32
+    fn load_full(self) -> Rgb<Self::Float>;
+    fn store_full(pixel: Rgb<Self::Float>) -> Self;
+    /// Stored pixels as seen by output sinks
+    fn slice(pixels: &[Self]) -> LinearSlice<'_>;
+}
+
+impl StoredPixel for Pixel {
+    type Float = f32;
+
+    fn load(self) -> Pixel {
+        self
+    }
+
+    fn store(pixel: Pixel) -> Self {
+        pixel
+    }
+
+    fn load_full(self) -> Pixel {
+        self
+    }
+
+    fn store_full(pixel: Pixel) -> Self {
+        pixel
+    }
+
+    fn slice(pixels: &[Self]) -> LinearSlice<'_> {
+        LinearSlice::F32(pixels)
+    }
+}
+
+/// Linear-light pixel with half float components
+#[derive(Default, Copy, Clone, Debug)]
+pub struct HalfPixel {
+    r: f16,
+    g: f16,
+    b: f16,
+}
+
+impl StoredPixel for HalfPixel {
+    type Float = f32;
+
+    fn load(self) -> Pixel {
+        Pixel {
+            r: self.r.to_f32(),
+            g: self.g.to_f32(),
+            b: self.b.to_f32(),
+        }
+    }
+
+    fn store(pixel: Pixel) -> Self {
+        HalfPixel {
+            r: f16::from_f32(pixel.r),
+            g: f16::from_f32(pixel.g),
+            b: f16::from_f32(pixel.b),
+        }
+    }
+
+    fn load_full(self) -> Pixel {
+        self.load()
+    }
+
+    fn store_full(pixel: Pixel) -> Self {
+        HalfPixel::store(pixel)
+    }
+
+    fn slice(pixels: &[Self]) -> LinearSlice<'_> {
+        LinearSlice::F16(pixels)
+    }
+}
+
+/// Linear-light pixel with double float components
+pub type DoublePixel = Rgb<f64>;
+
+impl StoredPixel for DoublePixel {
+    type Float = f64;
+
+    fn load(self) -> Pixel {
+        self.cast()
+    }
+
+    fn store(pixel: Pixel) -> Self {
+        pixel.cast()
+    }
+
+    fn load_full(self) -> DoublePixel {
+        self
+    }
+
+    fn store_full(pixel: DoublePixel) -> Self {
+        pixel
+    }
+
+    fn slice(pixels: &[Self]) -> LinearSlice<'_> {
+        LinearSlice::F64(pixels)
+    }
+}
+
+/// Change every stored pixel, working on f32 values
+pub fn update<P: StoredPixel>(pixels: &mut [P], mut change: impl FnMut(&mut Pixel)) {
+    for stored in pixels {
+        let mut pixel = stored.load();
+        change(&mut pixel);
+        *stored = P::store(pixel);
+    }
+}
+
+/// Stored pixels of either precision
+#[derive(Copy, Clone)]
+pub enum LinearSlice<'a> {
+    F32(&'a [Pixel]),
+    F16(&'a [HalfPixel]),
+    F64(&'a [DoublePixel]),
+}
+
+impl<'a> LinearSlice<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            LinearSlice::F32(pixels) => pixels.len(),
+            LinearSlice::F16(pixels) => pixels.len(),
+            LinearSlice::F64(pixels) => pixels.len(),
+        }
+    }
+
+    pub fn iter(&self) -> Pixels<'a> {
+        match self {
+            LinearSlice::F32(pixels) => Pixels::F32(pixels.iter()),
+            LinearSlice::F16(pixels) => Pixels::F16(pixels.iter()),
+            LinearSlice::F64(pixels) => Pixels::F64(pixels.iter()),
+        }
+    }
+
+    /// See `downscale_box`
+    pub fn downscale_box(
+        &self,
+        width: usize,
+        height: usize,
+        new_width: usize,
+        new_height: usize,
+    ) -> Vec<Pixel> {
+        match self {
+            LinearSlice::F32(pixels) => downscale_box(pixels, width, height, new_width, new_height),
+            LinearSlice::F16(pixels) => downscale_box(pixels, width, height, new_width, new_height),
+            LinearSlice::F64(pixels) => downscale_box(pixels, width, height, new_width, new_height),
+        }
+    }
+}
+
+/// Iterator over stored pixels, converted to f32
+pub enum Pixels<'a> {
+    F32(slice::Iter<'a, Pixel>),
+    F16(slice::Iter<'a, HalfPixel>),
+    F64(slice::Iter<'a, DoublePixel>),
+}
+
+impl Iterator for Pixels<'_> {
+    type Item = Pixel;
+
+    fn next(&mut self) -> Option<Pixel> {
+        match self {
+            Pixels::F32(pixels) => pixels.next().copied(),
+            Pixels::F16(pixels) => pixels.next().map(|p| p.load()),
+            Pixels::F64(pixels) => pixels.next().map(|p| p.load()),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Pixels::F32(pixels) => pixels.size_hint(),
+            Pixels::F16(pixels) => pixels.size_hint(),
+            Pixels::F64(pixels) => pixels.size_hint(),
+        }
+    }
+}
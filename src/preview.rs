@@ -0,0 +1,149 @@
+#[cfg(not(feature = "preview"))]
+use tracing::warn;
+
+#[cfg(feature = "preview")]
+use crate::color_stuff::Pixel;
+use crate::{precision::StoredPixel, PixelParameters};
+
+/// Exposure change per key press (eV)
+#[cfg(feature = "preview")]
+const EXPOSURE_STEP: f32 = 1.0 / 3.0;
+/// Longest side of the preview window, in pixels
+#[cfg(feature = "preview")]
+const WINDOW_SIZE: usize = 1024;
+
+/// Preview without window support compiled in, keeps exposure as is
+#[cfg(not(feature = "preview"))]
+pub fn choose_exposure<P: StoredPixel>(
+    _linear_light: &[P],
+    _width: usize,
+    _height: usize,
+    _parameters: &PixelParameters,
+    exposure: f32,
+) -> Option<f32> {
+    warn!("Built without the \"preview\" feature, skipping preview");
+    Some(exposure)
+}
+
+/// Show SDR result in a window and let the user adjust exposure. Up/Down change exposure, Tab switches between SDR and HDR boost views, Enter accepts and Escape cancels. Returns chosen exposure (eV), None if cancelled
+#[cfg(feature = "preview")]
+pub fn choose_exposure<P: StoredPixel>(
+    linear_light: &[P],
+    width: usize,
+    height: usize,
+    parameters: &PixelParameters,
+    exposure: f32,
+) -> Option<f32> {
+    use minifb::{Key, KeyRepeat, Window, WindowOptions};
+    use tracing::{info, warn};
+
+    use crate::{
+        resize::{downscale_box, fit_within},
+        Matrix3x1f,
+    };
+
+    let (preview_width, preview_height) = fit_within(width, height, WINDOW_SIZE);
+    let mut pixels = downscale_box(linear_light, width, height, preview_width, preview_height);
+    if let Some(conversion_matrix) = parameters.conversion_matrix {
+        for pixel in &mut pixels {
+            let v: Matrix3x1f = (*pixel).into();
+            *pixel = (conversion_matrix * v).into()
+        }
+    }
+
+    let mut window = match Window::new(
+        "exr2ultra-hdr preview",
+        preview_width,
+        preview_height,
+        WindowOptions::default(),
+    ) {
+        Ok(w) => w,
+        Err(e) => {
+            warn!(error = %e, "Could not open preview window, keeping exposure");
+            return Some(exposure);
+        }
+    };
+    window.set_target_fps(30);
+
+    let mut exposure = exposure;
+    let mut show_boost = false;
+    let mut buffer = vec![0u32; preview_width * preview_height];
+    let mut dirty = true;
+    while window.is_open() {
+        for key in window.get_keys_pressed(KeyRepeat::Yes) {
+            match key {
+                Key::Up => exposure += EXPOSURE_STEP,
+                Key::Down => exposure -= EXPOSURE_STEP,
+                Key::Tab => show_boost = !show_boost,
+                Key::Enter => {
+                    info!(exposure, "Preview accepted");
+                    return Some(exposure);
+                }
+                Key::Escape => return None,
+                _ => continue,
+            }
+            dirty = true;
+        }
+
+        if dirty {
+            render(&pixels, parameters, exposure, show_boost, &mut buffer);
+            window.set_title(&format!(
+                "exr2ultra-hdr preview, {} view, exposure {:+.2} eV (Up/Down, Tab, Enter, Escape)",
+                if show_boost { "HDR boost" } else { "SDR" },
+                exposure
+            ));
+            dirty = false;
+        }
+        window
+            .update_with_buffer(&buffer, preview_width, preview_height)
+            .unwrap();
+    }
+
+    // Closing the window is the same as cancelling
+    None
+}
+
+/// Fill 0RGB buffer with either the SDR image, or gain as grayscale from no boost (black) to the largest boost (white)
+#[cfg(feature = "preview")]
+fn render(
+    pixels: &[Pixel],
+    parameters: &PixelParameters,
+    exposure: f32,
+    show_boost: bool,
+    buffer: &mut [u32],
+) {
+    use crate::{calculate_gain, process_pixel, sdr_pixel};
+
+    let factor = 2.0f32.powf(exposure);
+    if show_boost {
+        let gains: Vec<f32> = pixels
+            .iter()
+            .map(|p| {
+                let sdr = sdr_pixel(p, factor, &parameters.trims, &parameters.coefficients);
+                calculate_gain(
+                    p,
+                    &sdr,
+                    parameters.gain_basis,
+                    &parameters.coefficients,
+                    parameters.offset_hdr,
+                    parameters.offset_sdr,
+                )
+                .log2()
+                .max(0.0)
+            })
+            .collect();
+        let max = gains.iter().copied().fold(f32::EPSILON, f32::max);
+        for (value, gain) in buffer.iter_mut().zip(gains) {
+            let level = (gain / max * 255.0).round() as u32;
+            *value = (level << 16) | (level << 8) | level;
+        }
+    } else {
+        for (value, pixel) in buffer.iter_mut().zip(pixels) {
+            let sdr = sdr_pixel(pixel, factor, &parameters.trims, &parameters.coefficients);
+            let r = process_pixel(sdr.r, parameters.transfer) as u32;
+            let g = process_pixel(sdr.g, parameters.transfer) as u32;
+            let b = process_pixel(sdr.b, parameters.transfer) as u32;
+            *value = (r << 16) | (g << 8) | b;
+        }
+    }
+}
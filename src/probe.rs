@@ -0,0 +1,87 @@
+// Values of single pixels through the conversion, to debug color issues
+
+use num_traits::Float;
+use tracing::warn;
+
+use crate::{color_stuff::Pixel, planar::PlanarView, precision::StoredPixel};
+
+pub fn parse_probe(text: &str) -> Result<(usize, usize), String> {
+    let (x, y) = text
+        .split_once(',')
+        .ok_or_else(|| "expected x,y in pixels".to_string())?;
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<usize>()
+            .map_err(|e| format!("invalid coordinate {:?}: {}", n, e))
+    };
+    Ok((parse(x)?, parse(y)?))
+}
+
+/// A probed pixel of the converted image
+pub struct Probe {
+    pub x: usize,
+    pub y: usize,
+    /// Decoded EXR values, None if pixels moved before conversion or the pixel is outside of the input
+    pub raw: Option<Pixel>,
+}
+
+/// Probes with the EXR values of their pixels, taken from freshly decoded linear light. `in_place` is false when cropping, reprojecting or rotating moves pixels afterwards
+pub fn capture<P: StoredPixel>(
+    coordinates: &[(usize, usize)],
+    linear_light: &[P],
+    width: usize,
+    in_place: bool,
+) -> Vec<Probe> {
+    coordinates
+        .iter()
+        .map(|&(x, y)| Probe {
+            x,
+            y,
+            raw: (in_place && x < width)
+                .then(|| linear_light.get(y * width + x))
+                .flatten()
+                .map(|p| p.load()),
+        })
+        .collect()
+}
+
+/// Print every stage of the probed pixels to stdout. Linear light is in output color space, before exposure. Recoveries are the Gain Map, `gain_map_scale` times smaller than the image
+pub fn print<P: StoredPixel, F: Float>(
+    probes: &[Probe],
+    width: usize,
+    linear_light: &[P],
+    image: PlanarView,
+    pixel_gains: &[F],
+    recoveries: &[u8],
+    gain_map_scale: usize,
+) {
+    let gain_map_width = width.div_ceil(gain_map_scale);
+    for probe in probes {
+        let index = probe.y * width + probe.x;
+        if probe.x >= width || index >= linear_light.len() {
+            warn!(
+                x = probe.x,
+                y = probe.y,
+                "Probed pixel is outside of the converted image"
+            );
+            continue;
+        }
+        let rgb = |p: &Pixel| format!("{:.6} {:.6} {:.6}", p.r, p.g, p.b);
+        let gain = pixel_gains[index];
+        let (gain, stops) = (gain.to_f64().unwrap(), gain.log2().to_f64().unwrap());
+
+        println!("probe {},{}", probe.x, probe.y);
+        match &probe.raw {
+            Some(raw) => println!("  EXR RGB        {}", rgb(raw)),
+            None => println!("  EXR RGB        unavailable, pixels were moved"),
+        }
+        println!("  linear RGB     {}", rgb(&linear_light[index].load()));
+        let [r, g, b] = image.pixel(index);
+        println!("  SDR 8-bit      {} {} {}", r, g, b);
+        println!("  gain           {:.6} ({:+.3} stops)", gain, stops);
+        println!(
+            "  recovery       {}",
+            recoveries[probe.y / gain_map_scale * gain_map_width + probe.x / gain_map_scale]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+// Environment maps, following the conventions of OpenEXR's ImfEnvmap: +Y is up, lat-long longitude 0 (image center) looks along +Z, and cube maps stack the +X, -X, +Y, -Y, +Z, -Z faces vertically
+// https://openexr.com/en/latest/TechnicalIntroduction.html#environment-maps
+
+use std::f32::consts::PI;
+
+use clap::ValueEnum;
+use exr::meta::attribute::EnvironmentMap;
+
+use crate::{color_stuff::Pixel, precision::StoredPixel};
+
+/// How an environment map is laid out
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum Projection {
+    /// Equirectangular, longitude across and latitude down
+    Latlong,
+    /// Six square faces stacked vertically: +X, -X, +Y, -Y, +Z, -Z
+    Cubemap,
+}
+
+impl From<EnvironmentMap> for Projection {
+    fn from(map: EnvironmentMap) -> Projection {
+        match map {
+            EnvironmentMap::LatitudeLongitude => Projection::Latlong,
+            EnvironmentMap::Cube => Projection::Cubemap,
+        }
+    }
+}
+
+/// Direction and horizontal field of view of a rectilinear view, in degrees
+#[derive(Debug, Copy, Clone)]
+pub struct View {
+    /// Positive turns right
+    pub yaw: f32,
+    /// Positive looks up
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+pub fn parse_view(text: &str) -> Result<View, String> {
+    let values = text
+        .split(',')
+        .map(|v| {
+            v.trim()
+                .parse::<f32>()
+                .map_err(|e| format!("invalid angle {:?}: {}", v, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let [yaw, pitch, fov] = values[..] else {
+        return Err("expected yaw,pitch,fov in degrees".to_string());
+    };
+    if !(fov > 0.0 && fov < 180.0) {
+        return Err(format!(
+            "field of view must be between 0 and 180 degrees, got {}",
+            fov
+        ));
+    }
+    Ok(View { yaw, pitch, fov })
+}
+
+/// Parse a `WIDTHxHEIGHT` size
+pub fn parse_size(text: &str) -> Result<(usize, usize), String> {
+    let (width, height) = text
+        .split_once(['x', 'X'])
+        .ok_or_else(|| "expected WIDTHxHEIGHT".to_string())?;
+    let parse = |n: &str| match n.trim().parse::<usize>() {
+        Ok(0) => Err("size must be positive".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(format!("invalid size {:?}: {}", n, e)),
+    };
+    Ok((parse(width)?, parse(height)?))
+}
+
+/// Render a rectilinear view of an environment map. Without a size, keeps the map's pixel density, at a 16:9 aspect ratio
+pub fn extract_view<P: StoredPixel>(
+    pixels: &[P],
+    width: usize,
+    height: usize,
+    projection: Projection,
+    view: View,
+    size: Option<(usize, usize)>,
+) -> Result<(Vec<P>, usize, usize), String> {
+    // Degrees covered by one side of the source
+    let (source_degrees, source_side) = match projection {
+        Projection::Latlong => (360.0, width),
+        Projection::Cubemap => {
+            if height != width * 6 {
+                return Err(format!(
+                    "A cube map is 6 square faces stacked vertically, {}x{} is not",
+                    width, height
+                ));
+            }
+            (90.0, width)
+        }
+    };
+    let (view_width, view_height) = size.unwrap_or_else(|| {
+        let view_width = ((source_side as f32 * view.fov / source_degrees).round() as usize).max(1);
+        (view_width, (view_width * 9 / 16).max(1))
+    });
+
+    let (yaw, pitch) = (view.yaw.to_radians(), view.pitch.to_radians());
+    let half_width = (view.fov.to_radians() / 2.0).tan();
+    let half_height = half_width * view_height as f32 / view_width as f32;
+    let mut output = Vec::with_capacity(view_width * view_height);
+    for y in 0..view_height {
+        for x in 0..view_width {
+            // Camera looking along +Z, +X being on the left as in OpenEXR
+            let right = half_width * (2.0 * (x as f32 + 0.5) / view_width as f32 - 1.0);
+            let up = half_height * (1.0 - 2.0 * (y as f32 + 0.5) / view_height as f32);
+            let (dx, dy, dz) = (-right, up, 1.0);
+            // Pitch around the X axis, then yaw around the Y axis
+            let (dy, dz) = (
+                dy * pitch.cos() + dz * pitch.sin(),
+                dz * pitch.cos() - dy * pitch.sin(),
+            );
+            let (dx, dz) = (
+                dx * yaw.cos() - dz * yaw.sin(),
+                dz * yaw.cos() + dx * yaw.sin(),
+            );
+
+            output.push(P::store(match projection {
+                Projection::Latlong => {
+                    let length = (dx * dx + dy * dy + dz * dz).sqrt();
+                    let longitude = dx.atan2(dz);
+                    let latitude = (dy / length).asin();
+                    let u = (0.5 - longitude / (2.0 * PI)) * width as f32;
+                    let v = (0.5 - latitude / PI) * height as f32;
+                    bilinear(pixels, width, height, u, v, true)
+                }
+                Projection::Cubemap => {
+                    let (face, u, v) = cube_face(dx, dy, dz, width);
+                    let face_pixels = &pixels[face * width * width..][..width * width];
+                    bilinear(face_pixels, width, width, u, v, false)
+                }
+            }))
+        }
+    }
+    Ok((output, view_width, view_height))
+}
+
+/// Face index and position within the face (in pixels, centers at half integers) a direction hits
+fn cube_face(x: f32, y: f32, z: f32, side: usize) -> (usize, f32, f32) {
+    let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+    // Face, then position on it from -1 to 1 as ImfEnvmap's direction() expects
+    let (face, a, b) = if ax >= ay && ax >= az {
+        (if x > 0.0 { 0 } else { 1 }, y / ax, z / ax)
+    } else if ay >= az {
+        (if y > 0.0 { 2 } else { 3 }, x / ay, z / ay)
+    } else {
+        (if z > 0.0 { 4 } else { 5 }, x / az, y / az)
+    };
+    let to_pixels = |p: f32| (p + 1.0) / 2.0 * (side - 1) as f32 + 0.5;
+    let (a, b) = (to_pixels(a), to_pixels(b));
+    let last = side as f32;
+    // Where ImfEnvmap's pixelPosition() puts that position in the face
+    let (u, v) = match face {
+        0 => (b, last - a),
+        1 => (last - b, last - a),
+        2 => (a, last - b),
+        3 => (a, b),
+        4 => (last - a, last - b),
+        _ => (a, last - b),
+    };
+    (face, u, v)
+}
+
+/// Sample between pixel centers, wrapping horizontally for lat-long maps and clamping otherwise
+fn bilinear<P: StoredPixel>(
+    pixels: &[P],
+    width: usize,
+    height: usize,
+    u: f32,
+    v: f32,
+    wrap: bool,
+) -> Pixel {
+    let (u, v) = (u - 0.5, v - 0.5);
+    let (x0, y0) = (u.floor(), v.floor());
+    let (fx, fy) = (u - x0, v - y0);
+    let column = |x: f32| {
+        let x = x as isize;
+        if wrap {
+            x.rem_euclid(width as isize) as usize
+        } else {
+            x.clamp(0, width as isize - 1) as usize
+        }
+    };
+    let row = |y: f32| (y as isize).clamp(0, height as isize - 1) as usize;
+    let (left, right, top, bottom) = (column(x0), column(x0 + 1.0), row(y0), row(y0 + 1.0));
+
+    let mut result = Pixel::default();
+    for (x, y, weight) in [
+        (left, top, (1.0 - fx) * (1.0 - fy)),
+        (right, top, fx * (1.0 - fy)),
+        (left, bottom, (1.0 - fx) * fy),
+        (right, bottom, fx * fy),
+    ] {
+        let p = pixels[y * width + x].load();
+        result.r += p.r * weight;
+        result.g += p.g * weight;
+        result.b += p.b * weight;
+    }
+    result
+}
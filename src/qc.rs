@@ -0,0 +1,67 @@
+// Automated quality control of a conversion, failing it past thresholds
+
+use tracing::info;
+
+use crate::{
+    color_stuff::{LuminanceCoefficients, Pixel},
+    precision::StoredPixel,
+    sdr_pixel,
+    sinks::is_out_of_gamut,
+    trims::SdrTrims,
+    App,
+};
+
+/// Check the share of pixels clipped by the SDR rendition, and of pixels outside of the output gamut, against --fail-on-clipping and --fail-on-gamut. Linear light is in output color space, before exposure. `replaced` is the SDR rendition of the base image in linear display light when it is not the built-in curve's, graded or locally tone mapped
+pub fn check<P: StoredPixel>(
+    args: &App,
+    linear_light: &[P],
+    replaced: Option<&[Pixel]>,
+    factor: f32,
+    trims: &SdrTrims,
+    coefficients: &LuminanceCoefficients,
+) -> Result<(), String> {
+    if args.fail_on_clipping.is_none() && args.fail_on_gamut.is_none() {
+        return Ok(());
+    }
+
+    let (mut clipped, mut out_of_gamut) = (0usize, 0usize);
+    for (index, pixel) in linear_light.iter().enumerate() {
+        let pixel = pixel.load();
+        let sdr = match replaced {
+            Some(replaced) => replaced[index],
+            None => sdr_pixel(&pixel, factor, trims, coefficients),
+        };
+        if sdr.r.max(sdr.g).max(sdr.b) > 1.0 {
+            clipped += 1;
+        }
+        if is_out_of_gamut(&pixel) {
+            out_of_gamut += 1;
+        }
+    }
+    let percent = |count: usize| 100.0 * count as f32 / linear_light.len().max(1) as f32;
+    let (clipped, out_of_gamut) = (percent(clipped), percent(out_of_gamut));
+    info!(
+        clipped_percent = clipped,
+        out_of_gamut_percent = out_of_gamut,
+        "Clipping of SDR rendition"
+    );
+
+    let mut failures = Vec::new();
+    if let Some(limit) = args.fail_on_clipping.filter(|limit| clipped > *limit) {
+        failures.push(format!(
+            "{:.2}% of pixels clip in the SDR rendition (limit {}%)",
+            clipped, limit
+        ));
+    }
+    if let Some(limit) = args.fail_on_gamut.filter(|limit| out_of_gamut > *limit) {
+        failures.push(format!(
+            "{:.2}% of pixels are outside of the output gamut (limit {}%)",
+            out_of_gamut, limit
+        ));
+    }
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Quality control failed: {}", failures.join(", ")))
+    }
+}
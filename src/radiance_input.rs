@@ -0,0 +1,182 @@
+// https://radsite.lbl.gov/radiance/refer/filefmts.pdf
+// Radiance RGBE pictures: 8-bit mantissas sharing an exponent, flat or run-length encoded per scanline
+
+use std::path::Path;
+
+use tracing::warn;
+
+use crate::{
+    color_stuff::{CIExyCoords, Chromaticities},
+    decode::ExrImage,
+    input_format::rgb_image,
+};
+
+/// Read a Radiance picture in RGBE format and the standard -Y H +X W orientation. Values are divided by the product of EXPOSURE lines to get back what the renderer computed
+pub fn read(data: &[u8], path: &Path) -> Result<ExrImage, String> {
+    let error = |message: &str| {
+        format!(
+            "{} is not a supported Radiance picture: {}",
+            path.display(),
+            message
+        )
+    };
+    let mut lines = data.split(|b| *b == b'\n');
+    let mut position = 0;
+    let mut next_line = || {
+        let line = lines.next()?;
+        position += line.len() + 1;
+        Some(String::from_utf8_lossy(line).into_owned())
+    };
+
+    // Header lines up to an empty one
+    let mut exposure = 1.0;
+    let mut chromaticities = None;
+    loop {
+        let line = next_line().ok_or_else(|| error("header does not end"))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format.trim() != "32-bit_rle_rgbe" {
+                return Err(error(&format!("{} pixels", format.trim())));
+            }
+        } else if let Some(value) = line.strip_prefix("EXPOSURE=") {
+            exposure *= value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| error("invalid EXPOSURE"))?;
+        } else if let Some(values) = line.strip_prefix("PRIMARIES=") {
+            chromaticities = parse_primaries(values);
+            if chromaticities.is_none() {
+                warn!(primaries = values, "Ignoring invalid Radiance PRIMARIES");
+            }
+        }
+    }
+    let resolution = next_line().ok_or_else(|| error("no resolution"))?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse(), width.parse()),
+        _ => return Err(error(&format!("orientation {:?}", resolution.trim()))),
+    };
+    let (height, width): (usize, usize) = (
+        height.map_err(|_| error("invalid height"))?,
+        width.map_err(|_| error("invalid width"))?,
+    );
+
+    let mut pixels = &data[position.min(data.len())..];
+    if width == 0 || height == 0 {
+        return Err(error("empty image"));
+    }
+    // Checked before reserving memory, so a header cannot ask for more than the file holds
+    let fits = scanline_min_len(width)
+        .checked_mul(height)
+        .is_some_and(|len| len <= pixels.len());
+    if !fits {
+        return Err(error("truncated pixels"));
+    }
+    let mut channels = [
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+    ];
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        pixels = read_scanline(pixels, &mut scanline).ok_or_else(|| error("truncated pixels"))?;
+        for rgbe in &scanline {
+            for (channel, value) in channels.iter_mut().zip(decode(*rgbe)) {
+                channel.push(value / exposure);
+            }
+        }
+    }
+    Ok(rgb_image(width, height, channels, chromaticities))
+}
+
+/// Fewest bytes a scanline `width` pixels long can take, every component being runs of 127 values if run-length encoded
+fn scanline_min_len(width: usize) -> usize {
+    if is_rle_width(width) {
+        4 + 4 * 2 * width.div_ceil(127)
+    } else {
+        4 * width
+    }
+}
+
+/// Scanlines this long may be run-length encoded
+fn is_rle_width(width: usize) -> bool {
+    (8..0x8000).contains(&width)
+}
+
+/// Fill `scanline` from the start of `data`, returning what follows it
+fn read_scanline<'a>(data: &'a [u8], scanline: &mut [[u8; 4]]) -> Option<&'a [u8]> {
+    let width = scanline.len();
+    match data {
+        // Each component run-length encoded in turn
+        [2, 2, high, low, rest @ ..]
+            if is_rle_width(width) && usize::from(*high) << 8 | usize::from(*low) == width =>
+        {
+            let mut data = rest;
+            for component in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let (&count, rest) = data.split_first()?;
+                    if count > 128 {
+                        // Run of a single value
+                        let (&value, rest) = rest.split_first()?;
+                        let count = usize::from(count - 128);
+                        for pixel in scanline.get_mut(x..x + count)? {
+                            pixel[component] = value;
+                        }
+                        x += count;
+                        data = rest;
+                    } else {
+                        let count = usize::from(count);
+                        let values = rest.get(..count)?;
+                        for (pixel, value) in scanline.get_mut(x..x + count)?.iter_mut().zip(values)
+                        {
+                            pixel[component] = *value;
+                        }
+                        x += count;
+                        data = &rest[count..];
+                    }
+                }
+            }
+            Some(data)
+        }
+        // Flat pixels. Old-style runs, repeating the previous pixel, are not supported
+        _ => {
+            let flat = data.get(..width * 4)?;
+            for (pixel, rgbe) in scanline.iter_mut().zip(flat.chunks_exact(4)) {
+                if rgbe[..3] == [1, 1, 1] {
+                    return None;
+                }
+                pixel.copy_from_slice(rgbe);
+            }
+            Some(&data[width * 4..])
+        }
+    }
+}
+
+/// Linear RGB of an RGBE pixel, mantissas taken at the middle of their interval like Radiance does
+fn decode([r, g, b, e]: [u8; 4]) -> [f32; 3] {
+    if e == 0 {
+        return [0.0; 3];
+    }
+    let factor = (e as f32 - (128.0 + 8.0)).exp2();
+    [r, g, b].map(|m| (m as f32 + 0.5) * factor)
+}
+
+/// Red, green, blue and white x,y from a PRIMARIES line
+fn parse_primaries(values: &str) -> Option<Chromaticities> {
+    let values: Vec<f32> = values
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let [rx, ry, gx, gy, bx, by, wx, wy] = values[..] else {
+        return None;
+    };
+    Some(Chromaticities {
+        red: CIExyCoords { x: rx, y: ry },
+        green: CIExyCoords { x: gx, y: gy },
+        blue: CIExyCoords { x: bx, y: by },
+        white: CIExyCoords { x: wx, y: wy },
+    })
+}
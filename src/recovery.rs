@@ -0,0 +1,84 @@
+// Pixel gains to encoded Gain Map recovery values, as specified in Google documentation
+
+use num_traits::{clamp, Float};
+
+use crate::{
+    dither,
+    planar::PlanarView,
+    recovery_curve::RecoveryCurve,
+    resize::{downscale_gains, downscale_gains_guided, gain_map_size, GainMapDownscale},
+};
+
+/// How pixel gains become Gain Map recovery values
+pub struct RecoveryEncoding {
+    /// Gain Map minimum and maximum log2 boosts
+    pub min_log2: f32,
+    pub max_log2: f32,
+    pub curve: RecoveryCurve,
+    /// How many times smaller the Gain Map is than the image
+    pub scale: usize,
+    pub downscale: GainMapDownscale,
+    /// Also keep 16-bit values, the 8-bit ones being dithered from them
+    pub wide: bool,
+    /// Add blue noise to 8-bit values before rounding, trading banding for fine grain
+    pub dither: bool,
+}
+
+impl RecoveryEncoding {
+    /// Log2 gain mapped to 0.0 - 1.0 between the Gain Map minimum and maximum, before the curve
+    pub fn clamped<F: Float>(&self, pixel_gain: F) -> F {
+        let (min, max) = (
+            F::from(self.min_log2).unwrap(),
+            F::from(self.max_log2).unwrap(),
+        );
+        clamp((pixel_gain.log2() - min) / (max - min), F::zero(), F::one())
+    }
+
+    /// Gain Map of the gamma-encoded RGB primary image `base_image`, as 8-bit values and 16-bit ones when wide. The primary image guides edge-aware downscaling
+    pub fn encode<F: Float>(
+        &self,
+        pixel_gains: &[F],
+        base_image: PlanarView,
+    ) -> (Vec<u8>, Option<Vec<u16>>) {
+        let (width, height) = (base_image.width, base_image.height);
+        let pixel_gains = &match self.downscale {
+            GainMapDownscale::Box => downscale_gains(pixel_gains, width, height, self.scale),
+            GainMapDownscale::EdgeAware => {
+                downscale_gains_guided(pixel_gains, base_image, self.scale)
+            }
+        };
+        let (width, _) = gain_map_size(width, height, self.scale);
+        let stops = F::from(self.max_log2 - self.min_log2).unwrap();
+        let quantize = |pixel_gain: &F, max: f32| {
+            let recovery = self.curve.encode(self.clamped(*pixel_gain), stops);
+            (recovery * F::from(max).unwrap())
+                .round()
+                .to_f32()
+                .unwrap_or_default()
+        };
+        if self.wide {
+            let wide: Vec<u16> = pixel_gains
+                .iter()
+                .map(|pixel_gain| quantize(pixel_gain, u16::MAX as f32) as u16)
+                .collect();
+            (dither::to_8_bits(&wide, width), Some(wide))
+        } else {
+            let encoded: Vec<u8> = pixel_gains
+                .iter()
+                .enumerate()
+                .map(|(index, pixel_gain)| {
+                    if self.dither {
+                        let recovery = self.curve.encode(self.clamped(*pixel_gain), stops);
+                        let noise = dither::blue_noise(index % width, index / width);
+                        (recovery.to_f32().unwrap_or_default() * 255.0 + noise)
+                            .round()
+                            .clamp(0.0, 255.0) as u8
+                    } else {
+                        quantize(pixel_gain, 255.0) as u8
+                    }
+                })
+                .collect();
+            (encoded, None)
+        }
+    }
+}
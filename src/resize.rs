@@ -0,0 +1,217 @@
+use clap::ValueEnum;
+use num_traits::Float;
+
+use crate::{color_stuff::Pixel, planar::PlanarView, precision::StoredPixel};
+
+/// Size of an image fitting in a square of `longest_side`, keeping aspect ratio. Never upscales
+pub fn fit_within(width: usize, height: usize, longest_side: usize) -> (usize, usize) {
+    if width.max(height) <= longest_side {
+        return (width, height);
+    }
+
+    if width >= height {
+        (longest_side, (height * longest_side / width).max(1))
+    } else {
+        ((width * longest_side / height).max(1), longest_side)
+    }
+}
+
+/// Copy a rectangle out of an image
+pub fn crop<T: Copy>(
+    pixels: &[T],
+    width: usize,
+    (x, y): (usize, usize),
+    (new_width, new_height): (usize, usize),
+) -> Vec<T> {
+    pixels
+        .chunks_exact(width)
+        .skip(y)
+        .take(new_height)
+        .flat_map(|row| &row[x..x + new_width])
+        .copied()
+        .collect()
+}
+
+/// Downscale linear-light pixels by averaging every source pixel covered by a destination pixel
+pub fn downscale_box<P: StoredPixel>(
+    pixels: &[P],
+    width: usize,
+    height: usize,
+    new_width: usize,
+    new_height: usize,
+) -> Vec<Pixel> {
+    let mut output = Vec::with_capacity(new_width * new_height);
+    for y in 0..new_height {
+        let (y0, y1) = source_span(y, height, new_height);
+        for x in 0..new_width {
+            let (x0, x1) = source_span(x, width, new_width);
+
+            let mut sum = Pixel::default();
+            for row in pixels[y0 * width..y1 * width].chunks_exact(width) {
+                for pixel in &row[x0..x1] {
+                    let pixel = pixel.load();
+                    sum.r += pixel.r;
+                    sum.g += pixel.g;
+                    sum.b += pixel.b;
+                }
+            }
+            let count = ((x1 - x0) * (y1 - y0)) as f32;
+            output.push(Pixel {
+                r: sum.r / count,
+                g: sum.g / count,
+                b: sum.b / count,
+            })
+        }
+    }
+    output
+}
+
+/// Resize linear-light pixels with bilinear interpolation, meant for enlarging where box filtering has nothing to average
+pub fn stretch<P: StoredPixel>(
+    pixels: &[P],
+    width: usize,
+    height: usize,
+    new_width: usize,
+    new_height: usize,
+) -> Vec<P> {
+    // Source position of a destination index, pixel centers lined up, and the weight of the next source pixel
+    let source = |index: usize, size: usize, new_size: usize| {
+        let position = ((index as f32 + 0.5) * size as f32 / new_size as f32 - 0.5).max(0.0);
+        let first = (position as usize).min(size - 1);
+        (first, (first + 1).min(size - 1), position - first as f32)
+    };
+    let lerp = |a: Pixel, b: Pixel, t: f32| Pixel {
+        r: a.r + (b.r - a.r) * t,
+        g: a.g + (b.g - a.g) * t,
+        b: a.b + (b.b - a.b) * t,
+    };
+
+    let mut output = Vec::with_capacity(new_width * new_height);
+    for y in 0..new_height {
+        let (y0, y1, ty) = source(y, height, new_height);
+        for x in 0..new_width {
+            let (x0, x1, tx) = source(x, width, new_width);
+            let at = |x: usize, y: usize| pixels[y * width + x].load();
+            let top = lerp(at(x0, y0), at(x1, y0), tx);
+            let bottom = lerp(at(x0, y1), at(x1, y1), tx);
+            output.push(P::store(lerp(top, bottom, ty)));
+        }
+    }
+    output
+}
+
+/// Range of source indices covered by a destination index, always at least one wide
+fn source_span(index: usize, size: usize, new_size: usize) -> (usize, usize) {
+    let start = index * size / new_size;
+    let end = ((index + 1) * size / new_size).max(start + 1).min(size);
+    (start, end)
+}
+
+/// Chroma of 4:2:0 JPEGs, as written by mozjpeg and most cameras, is sampled once per 2x2 pixels
+const CHROMA_SUBSAMPLING: usize = 2;
+
+/// Gain Map scale whose blocks cover whole chroma samples of a 4:2:0 primary image: 1, or an even factor
+pub fn aligned_gain_map_scale(scale: usize) -> usize {
+    if scale <= 1 {
+        1
+    } else {
+        scale.next_multiple_of(CHROMA_SUBSAMPLING)
+    }
+}
+
+/// How per-pixel gains are averaged into Gain Map samples
+#[derive(ValueEnum, Debug, Copy, Clone, Default)]
+pub enum GainMapDownscale {
+    /// Every pixel of a block weighs the same
+    #[default]
+    Box,
+    /// Pixels unlike most of their block in the base image weigh less, so blocks straddling an edge take the gain of one side instead of a mix haloing both
+    EdgeAware,
+}
+
+/// Edge-aware downscaling: spread of base image luma (0.0 - 1.0) over which pixels stop counting as the same surface
+const EDGE_LUMA_SIGMA: f32 = 0.1;
+
+/// Size of a Gain Map `scale` times smaller than its primary image, rounded up as libultrahdr does so the last row and column cover the remaining pixels
+pub fn gain_map_size(width: usize, height: usize, scale: usize) -> (usize, usize) {
+    (width.div_ceil(scale), height.div_ceil(scale))
+}
+
+/// Downscale per-pixel gains by averaging their log2 over `scale`x`scale` blocks aligned on the top left corner. Blocks on the right and bottom borders only average the pixels inside the image, rather than repeating edge pixels
+pub fn downscale_gains<F: Float>(gains: &[F], width: usize, height: usize, scale: usize) -> Vec<F> {
+    if scale == 1 {
+        return gains.to_vec();
+    }
+    let (new_width, new_height) = gain_map_size(width, height, scale);
+    let mut output = Vec::with_capacity(new_width * new_height);
+    for y in 0..new_height {
+        let rows = y * scale..((y + 1) * scale).min(height);
+        for x in 0..new_width {
+            let columns = x * scale..((x + 1) * scale).min(width);
+            let sum = gains[rows.start * width..rows.end * width]
+                .chunks_exact(width)
+                .flat_map(|row| &row[columns.clone()])
+                .fold(F::zero(), |sum, gain| sum + gain.log2());
+            let count = F::from(rows.len() * columns.len()).unwrap();
+            output.push((sum / count).exp2());
+        }
+    }
+    output
+}
+
+/// Downscale per-pixel gains like `downscale_gains`, weighing pixels with a joint bilateral filter guided by the gamma-encoded RGB planes of `base_image`. Weights fall off with distance from the block center, the same in every direction, and with the luma difference to the block's median luma
+pub fn downscale_gains_guided<F: Float>(
+    gains: &[F],
+    base_image: PlanarView,
+    scale: usize,
+) -> Vec<F> {
+    if scale == 1 {
+        return gains.to_vec();
+    }
+    let (width, height) = (base_image.width, base_image.height);
+    // Luma as JPEG encoders compute it, BT.601 weights on encoded values
+    let luma: Vec<f32> = base_image
+        .pixels()
+        .map(|rgb| (0.299 * rgb[0] as f32 + 0.587 * rgb[1] as f32 + 0.114 * rgb[2] as f32) / 255.0)
+        .collect();
+    let spatial_sigma = scale as f32 / 2.0;
+
+    let (new_width, new_height) = gain_map_size(width, height, scale);
+    let mut output = Vec::with_capacity(new_width * new_height);
+    let mut block_luma = Vec::with_capacity(scale * scale);
+    for y in 0..new_height {
+        let rows = y * scale..((y + 1) * scale).min(height);
+        let center_y = (rows.start + rows.end - 1) as f32 / 2.0;
+        for x in 0..new_width {
+            let columns = x * scale..((x + 1) * scale).min(width);
+            let center_x = (columns.start + columns.end - 1) as f32 / 2.0;
+
+            // Median luma, the lower one of an even count so ties are settled the same everywhere
+            block_luma.clear();
+            for row in rows.clone() {
+                block_luma.extend(&luma[row * width + columns.start..row * width + columns.end]);
+            }
+            block_luma.sort_unstable_by(f32::total_cmp);
+            let median = block_luma[(block_luma.len() - 1) / 2];
+
+            let (mut sum, mut total_weight) = (F::zero(), F::zero());
+            for row in rows.clone() {
+                for column in columns.clone() {
+                    let index = row * width + column;
+                    let distance_squared =
+                        (column as f32 - center_x).powi(2) + (row as f32 - center_y).powi(2);
+                    let luma_difference = luma[index] - median;
+                    let weight = (-distance_squared / (2.0 * spatial_sigma * spatial_sigma)
+                        - luma_difference * luma_difference
+                            / (2.0 * EDGE_LUMA_SIGMA * EDGE_LUMA_SIGMA))
+                        .exp();
+                    let weight = F::from(weight).unwrap();
+                    sum = sum + weight * gains[index].log2();
+                    total_weight = total_weight + weight;
+                }
+            }
+            output.push((sum / total_weight).exp2());
+        }
+    }
+    output
+}
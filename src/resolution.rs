@@ -0,0 +1,73 @@
+// Print resolution and pixel aspect ratio, written as JFIF density, PNG pHYs and EXIF resolution
+
+use jpeg_encoder::Density;
+use png::{PixelDimensions, Unit};
+
+use crate::exif::{resolution_entries, ExifValue, RESOLUTION_UNIT_INCH, RESOLUTION_UNIT_NONE};
+
+const METERS_PER_INCH: f32 = 0.0254;
+/// Pixels per unit of resolutions only giving the pixel aspect ratio, large enough to keep its precision in integers
+const ASPECT_ONLY_SCALE: f32 = 1000.0;
+
+/// Horizontal and vertical pixels per inch, or per an unspecified unit when only the pixel aspect ratio is known
+#[derive(Copy, Clone, Debug)]
+pub struct Resolution {
+    pub x: f32,
+    pub y: f32,
+    pub per_inch: bool,
+}
+
+impl Resolution {
+    /// Resolution of `dpi` vertically, pixels `pixel_aspect` times wider than tall. None for square pixels without a resolution
+    pub fn new(dpi: Option<u16>, pixel_aspect: f32) -> Option<Resolution> {
+        match dpi {
+            Some(dpi) => Some(Resolution {
+                x: dpi as f32 / pixel_aspect,
+                y: dpi as f32,
+                per_inch: true,
+            }),
+            None if pixel_aspect != 1.0 => Some(Resolution {
+                x: 1.0 / pixel_aspect,
+                y: 1.0,
+                per_inch: false,
+            }),
+            None => None,
+        }
+    }
+
+    /// JFIF only has integer densities, and no unit for aspect ratios with jpeg-encoder
+    pub fn jfif_density(&self) -> Density {
+        let density = |resolution: f32| resolution.round().clamp(1.0, u16::MAX as f32) as u16;
+        if self.per_inch {
+            Density::Inch {
+                x: density(self.x),
+                y: density(self.y),
+            }
+        } else {
+            Density::None
+        }
+    }
+
+    /// pHYs is in pixels per meter
+    pub fn png_dimensions(&self) -> PixelDimensions {
+        let (scale, unit) = if self.per_inch {
+            (METERS_PER_INCH.recip(), Unit::Meter)
+        } else {
+            (ASPECT_ONLY_SCALE, Unit::Unspecified)
+        };
+        PixelDimensions {
+            xppu: (self.x * scale).round().max(1.0) as u32,
+            yppu: (self.y * scale).round().max(1.0) as u32,
+            unit,
+        }
+    }
+
+    pub fn exif_entries(&self) -> Vec<(u16, ExifValue)> {
+        let unit = if self.per_inch {
+            RESOLUTION_UNIT_INCH
+        } else {
+            RESOLUTION_UNIT_NONE
+        };
+        resolution_entries(self.x, self.y, unit)
+    }
+}
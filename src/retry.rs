@@ -0,0 +1,85 @@
+// Transient I/O failures of network mounts and busy file servers, retried instead of failing the file
+
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read, Seek, SeekFrom},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use tracing::warn;
+
+/// Wait before the first retry, doubled for each following one
+const FIRST_DELAY: Duration = Duration::from_millis(200);
+
+/// Whether an I/O error may go away by trying again, rather than coming from the file itself
+pub fn is_transient(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::BrokenPipe
+            | ErrorKind::ResourceBusy
+            | ErrorKind::StaleNetworkFileHandle
+    )
+}
+
+/// Run `operation` again up to `retries` times while it fails with transient errors, waiting longer each time
+fn with_retries<T>(
+    path: &Path,
+    retries: u32,
+    mut operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut delay = FIRST_DELAY;
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Err(e) if attempt < retries && is_transient(&e) => {
+                attempt += 1;
+                warn!(file = %path.display(), error = %e, attempt, "Transient I/O error, retrying");
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Open a file for reading, retrying transient errors
+pub fn open(path: &Path, retries: u32) -> io::Result<File> {
+    with_retries(path, retries, || File::open(path))
+}
+
+/// Reader retrying reads and seeks failing with transient errors
+pub struct RetryingReader<'a, R> {
+    pub inner: R,
+    pub path: &'a Path,
+    pub retries: u32,
+}
+
+impl<R: Read + Seek> Read for RetryingReader<'_, R> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        if self.retries == 0 {
+            return self.inner.read(buffer);
+        }
+        // A failed read may have moved the position, so every attempt starts from the same place
+        let position = self.inner.stream_position()?;
+        let inner = &mut self.inner;
+        with_retries(self.path, self.retries, || {
+            inner.seek(SeekFrom::Start(position))?;
+            inner.read(buffer)
+        })
+    }
+}
+
+impl<R: Seek> Seek for RetryingReader<'_, R> {
+    fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+        let inner = &mut self.inner;
+        with_retries(self.path, self.retries, || inner.seek(position))
+    }
+}
@@ -0,0 +1,138 @@
+use clap::ValueEnum;
+use tracing::{info, warn};
+
+use crate::{
+    color_stuff::{LuminanceCoefficients, Pixel},
+    precision::{update, StoredPixel},
+};
+
+/// What to do with negative components, left by debayering, denoising or out-of-gamut footage
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum NegativePolicy {
+    /// Set negative components to zero
+    Clamp,
+    /// Desaturate towards gray just enough for every component to be positive, keeping luminance
+    Absorb,
+    /// Refuse the image
+    Error,
+}
+
+/// Replace NaN and infinite values, then handle negative components. Run on linear light before any processing so gain statistics stay meaningful
+pub fn sanitize<P: StoredPixel>(
+    linear_light: &mut [P],
+    policy: NegativePolicy,
+    coefficients: &LuminanceCoefficients,
+) -> Result<(), String> {
+    // Infinity becomes the brightest finite value, NaN and negative infinity become 0
+    let brightest = linear_light
+        .iter()
+        .flat_map(|p| {
+            let p = p.load();
+            [p.r, p.g, p.b]
+        })
+        .filter(|v| v.is_finite())
+        .fold(0.0f32, f32::max);
+    let mut non_finite = 0;
+    update(linear_light, |pixel| {
+        for value in [&mut pixel.r, &mut pixel.g, &mut pixel.b] {
+            if !value.is_finite() {
+                non_finite += 1;
+                *value = if *value == f32::INFINITY {
+                    brightest
+                } else {
+                    0.0
+                };
+            }
+        }
+    });
+    if non_finite > 0 {
+        warn!(values = non_finite, "Replaced NaN or infinite values");
+    }
+
+    let negative_pixels = linear_light
+        .iter()
+        .map(|p| p.load())
+        .filter(|p| p.r < 0.0 || p.g < 0.0 || p.b < 0.0)
+        .count();
+    if negative_pixels == 0 {
+        return Ok(());
+    }
+
+    match policy {
+        NegativePolicy::Error => {
+            return Err(format!(
+            "{} pixels have negative components, use --negative clamp or absorb to convert anyway",
+            negative_pixels
+        ))
+        }
+        NegativePolicy::Clamp => {
+            update(linear_light, |pixel| {
+                pixel.r = pixel.r.max(0.0);
+                pixel.g = pixel.g.max(0.0);
+                pixel.b = pixel.b.max(0.0);
+            });
+        }
+        NegativePolicy::Absorb => {
+            update(linear_light, |pixel| *pixel = absorb(*pixel, coefficients));
+        }
+    }
+    warn!(pixels = negative_pixels, policy = ?policy, "Removed negative components");
+
+    Ok(())
+}
+
+/// Mix pixel with the gray of same luminance until its smallest component reaches zero
+fn absorb(pixel: Pixel, coefficients: &LuminanceCoefficients) -> Pixel {
+    let min = pixel.r.min(pixel.g).min(pixel.b);
+    if min >= 0.0 {
+        return pixel;
+    }
+
+    let luminance =
+        pixel.r * coefficients.red + pixel.g * coefficients.green + pixel.b * coefficients.blue;
+    if luminance <= 0.0 {
+        return Pixel::default();
+    }
+
+    let t = luminance / (luminance - min);
+    let mix = |v: f32| (luminance + t * (v - luminance)).max(0.0);
+    Pixel {
+        r: mix(pixel.r),
+        g: mix(pixel.g),
+        b: mix(pixel.b),
+    }
+}
+
+/// Subtract a uniform flare level, then map the black point to 0 while keeping `white` in place. Values pushed below 0 are clipped
+pub fn subtract_black<P: StoredPixel>(
+    linear_light: &mut [P],
+    flare: f32,
+    black_point: f32,
+    white: f32,
+) -> Result<(), String> {
+    if black_point >= white {
+        return Err(format!(
+            "Black point ({}) must be below scene white ({})",
+            black_point, white
+        ));
+    }
+
+    let scale = white / (white - black_point);
+    let mut clipped = 0;
+    update(linear_light, |pixel| {
+        for value in [&mut pixel.r, &mut pixel.g, &mut pixel.b] {
+            let lifted = (*value - flare - black_point) * scale;
+            if lifted < 0.0 {
+                clipped += 1;
+            }
+            *value = lifted.max(0.0);
+        }
+    });
+    if clipped > 0 {
+        info!(
+            components = clipped,
+            "Components below black level were clipped to 0"
+        )
+    }
+    Ok(())
+}
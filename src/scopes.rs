@@ -0,0 +1,95 @@
+// Diagnostic renders of scene luminance, as found in grading tools
+
+/// Luminance range shown, in stops relative to SDR white
+const MIN_STOP: f32 = -12.0;
+const MAX_STOP: f32 = 8.0;
+/// Size of the histogram image, one column per bin
+pub const HISTOGRAM_WIDTH: usize = 480;
+pub const HISTOGRAM_HEIGHT: usize = 240;
+/// Maximum waveform width, wider images are squeezed
+const WAVEFORM_MAX_WIDTH: usize = 1024;
+pub const WAVEFORM_HEIGHT: usize = 256;
+
+const BACKGROUND: [u8; 3] = [16, 16, 16];
+const TRACE: [u8; 3] = [220, 220, 220];
+/// Marks SDR white, above which only the HDR rendition has detail
+const SDR_WHITE_LINE: [u8; 3] = [200, 40, 40];
+/// Marks every other stop
+const GRID_LINE: [u8; 3] = [48, 48, 48];
+
+/// Position of a luminance in the shown range, from 0 to 1. None for black or negative values
+fn position(luminance: f32) -> Option<f32> {
+    (luminance > 0.0)
+        .then(|| ((luminance.log2() - MIN_STOP) / (MAX_STOP - MIN_STOP)).clamp(0.0, 1.0))
+}
+
+/// Stop lines, as positions from 0 to 1
+fn grid() -> impl Iterator<Item = (f32, [u8; 3])> {
+    (MIN_STOP as i32..=MAX_STOP as i32).step_by(2).map(|stop| {
+        let color = if stop == 0 { SDR_WHITE_LINE } else { GRID_LINE };
+        ((stop as f32 - MIN_STOP) / (MAX_STOP - MIN_STOP), color)
+    })
+}
+
+/// Histogram of log2 luminance, as RGB u8 data. Columns go from dark (left) to bright (right)
+pub fn histogram(luminances: impl Iterator<Item = f32>) -> Vec<u8> {
+    let mut bins = [0u64; HISTOGRAM_WIDTH];
+    for p in luminances.filter_map(position) {
+        bins[((p * HISTOGRAM_WIDTH as f32) as usize).min(HISTOGRAM_WIDTH - 1)] += 1;
+    }
+    let highest = bins.iter().copied().max().unwrap_or(0).max(1);
+
+    let mut image = vec![BACKGROUND; HISTOGRAM_WIDTH * HISTOGRAM_HEIGHT];
+    for (p, color) in grid() {
+        let x = ((p * HISTOGRAM_WIDTH as f32) as usize).min(HISTOGRAM_WIDTH - 1);
+        for y in 0..HISTOGRAM_HEIGHT {
+            image[y * HISTOGRAM_WIDTH + x] = color
+        }
+    }
+    for (x, count) in bins.iter().enumerate() {
+        let bar = (*count as f32 / highest as f32 * HISTOGRAM_HEIGHT as f32).round() as usize;
+        for y in HISTOGRAM_HEIGHT - bar..HISTOGRAM_HEIGHT {
+            image[y * HISTOGRAM_WIDTH + x] = TRACE
+        }
+    }
+    image.into_iter().flatten().collect()
+}
+
+/// Width of the waveform of an image this wide
+pub fn waveform_width(width: usize) -> usize {
+    width.clamp(1, WAVEFORM_MAX_WIDTH)
+}
+
+/// Waveform of log2 luminance, as RGB u8 data. Every scanline is traced over the previous ones: a column shows the luminance of every pixel in the matching image column, brighter where many pixels share a level
+pub fn waveform(luminances: &[f32], width: usize) -> Vec<u8> {
+    let waveform_width = waveform_width(width);
+    let mut counts = vec![0u32; waveform_width * WAVEFORM_HEIGHT];
+    for scanline in luminances.chunks_exact(width) {
+        for (x, luminance) in scanline.iter().enumerate() {
+            let Some(p) = position(*luminance) else {
+                continue;
+            };
+            let column = x * waveform_width / width;
+            let row = WAVEFORM_HEIGHT - 1 - (p * (WAVEFORM_HEIGHT - 1) as f32).round() as usize;
+            counts[row * waveform_width + column] += 1;
+        }
+    }
+    // Log scale density, a few pixels should still be visible next to a flat area
+    let highest = (counts.iter().copied().max().unwrap_or(0) as f32 + 1.0).ln();
+
+    let mut image = vec![BACKGROUND; waveform_width * WAVEFORM_HEIGHT];
+    for (p, color) in grid() {
+        let row = WAVEFORM_HEIGHT - 1 - (p * (WAVEFORM_HEIGHT - 1) as f32).round() as usize;
+        for x in 0..waveform_width {
+            image[row * waveform_width + x] = color
+        }
+    }
+    for (pixel, count) in image.iter_mut().zip(counts) {
+        if count > 0 {
+            let density = (count as f32 + 1.0).ln() / highest;
+            let level = (64.0 + density * 191.0) as u8;
+            *pixel = [level; 3]
+        }
+    }
+    image.into_iter().flatten().collect()
+}
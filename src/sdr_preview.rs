@@ -0,0 +1,84 @@
+// SDR rendition for review by email or chat, tone mapped rather than clipped, separate from the technically defined base image of Ultra HDR files
+
+use std::path::PathBuf;
+
+use crate::{
+    chromatic_adaptation::Cat,
+    color_spaces::REC_709,
+    exif::make_exif,
+    icc::make_profile,
+    jpeg_backend::{self, JpegBackend},
+    jpeg_bands::{EncoderMode, JpegSettings},
+    planar::PlanarRgb,
+    process_pixel,
+    sinks::{write_file, OutputMetadata, OutputSink, Planes},
+    tone_map::ToneMap,
+    transfer_functions::Transfer,
+    JPEG_QUALITY,
+};
+
+/// Exposed linear light rendered with a tone map to an sRGB JPEG
+pub struct SdrPreviewSink {
+    pub path: PathBuf,
+    pub tone_map: ToneMap,
+    /// Mastering peak for BT.2446 Method A
+    pub peak_nits: Option<f32>,
+    pub encoder: EncoderMode,
+    pub backend: JpegBackend,
+    pub progressive: bool,
+    /// Of the sRGB ICC profile
+    pub cat: Cat,
+    pub deterministic: bool,
+}
+
+impl OutputSink for SdrPreviewSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        // Exposure is applied along with the conversion
+        let to_rec_709 = metadata
+            .chromaticities
+            .rgb_space_conversion_matrix(&REC_709)
+            .ok_or_else(|| "Output chromaticities are invalid".to_string())?
+            * metadata.factor;
+        let image: PlanarRgb = planes
+            .linear_light
+            .iter()
+            .map(|pixel| {
+                let rendered = self
+                    .tone_map
+                    .render(pixel.transform(&to_rec_709), self.peak_nits);
+                [rendered.r, rendered.g, rendered.b].map(|v| process_pixel(v, Transfer::Srgb))
+            })
+            .collect();
+
+        let profile = make_profile(
+            &REC_709,
+            Transfer::Srgb,
+            self.cat,
+            "sRGB",
+            None,
+            self.deterministic,
+        );
+        write_file(&self.path, |writer| {
+            jpeg_backend::encode(
+                writer,
+                self.backend,
+                JpegSettings {
+                    mode: self.encoder,
+                    quality: JPEG_QUALITY,
+                    progressive: self.progressive,
+                },
+                image.view(planes.width, planes.height),
+                |encoder| {
+                    if let Some(resolution) = metadata.resolution {
+                        encoder.set_density(resolution.jfif_density());
+                    }
+                    if let Some(exif) = metadata.exif {
+                        encoder.add_app_segment(1, &make_exif(exif))?;
+                    }
+                    encoder.add_icc_profile(&profile)
+                },
+            )
+            .map_err(|e| format!("Could not write {}: {}", self.path.display(), e))
+        })
+    }
+}
@@ -0,0 +1,156 @@
+// https://developer.android.com/media/platform/hdr-image-format#decode
+// https://www.itu.int/rec/R-REC-BT.2124
+// https://doi.org/10.1002/col.22131
+
+use tracing::{info, warn};
+use ultra_hdr_core::transfer::pq_inverse_eotf;
+
+use crate::{
+    color_spaces::REC_2020,
+    color_stuff::{delta_e_cam16_ucs, Pixel, Surround, ViewingConditions},
+    sinks::{OutputMetadata, OutputSink, Planes},
+    Matrix3x1f, Matrix3x3f,
+};
+
+/// Mean ΔE ITP above which reconstruction is reported as visibly lossy
+pub const NOTICEABLE_DELTA_E_ITP: f64 = 1.0;
+
+/// Rebuild the HDR rendition from the 8-bit SDR image and Gain Map as a viewer would, and report how far it is from the source, as ΔE ITP and as CAM16-UCS differences seen on a display in a dim room. Measured before JPEG compression
+pub struct SelfCheckSink {
+    pub sdr_white_nits: f32,
+}
+
+impl OutputSink for SelfCheckSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        let to_rec_2020 = metadata
+            .chromaticities
+            .rgb_space_conversion_matrix(&REC_2020)
+            .ok_or("Output chromaticities have no RGB to XYZ matrix")?;
+        // Linear values relative to SDR white, as PQ-encoded Rec. 2020 RGB and LMS
+        let pq = |pixel: Pixel| -> ([f32; 3], [f32; 3]) {
+            let rec_2020 = to_rec_2020 * Matrix3x1f::from(pixel);
+            let lms = LMS_FROM_REC_2020 * rec_2020;
+            let encode =
+                |v: Matrix3x1f| [v.x, v.y, v.z].map(|c| pq_inverse_eotf(c * self.sdr_white_nits));
+            (encode(rec_2020), encode(lms))
+        };
+        // CAM16-UCS with SDR white as the adopted white, at Y 100
+        let to_xyz = metadata
+            .chromaticities
+            .rgb_to_xyz_matrix()
+            .ok_or("Output chromaticities have no RGB to XYZ matrix")?;
+        let conditions = ViewingConditions::new(
+            metadata.chromaticities.white.to_xyz_f64() * 100.0,
+            0.2 * self.sdr_white_nits as f64,
+            20.0,
+            Surround::Dim,
+        );
+        let ucs = |pixel: Pixel| {
+            conditions.cam16_ucs((to_xyz * Matrix3x1f::from(pixel)).cast::<f64>() * 100.0)
+        };
+        let mut cam16_total = 0.0f64;
+
+        let mut squared_error = 0.0f64;
+        let mut delta_es = Vec::with_capacity(planes.linear_light.len());
+        let scale = planes.gain_map_scale;
+        let (gain_map_width, _) = planes.gain_map_size();
+        for (index, (source, sdr)) in planes
+            .linear_light
+            .iter()
+            .zip(planes.image.pixels())
+            .enumerate()
+        {
+            // Nearest Gain Map sample
+            let (x, y) = (index % planes.width, index / planes.width);
+            let recovery = planes.gain_map[y / scale * gain_map_width + x / scale];
+            let reconstructed = reconstruct(sdr, recovery, metadata);
+            let ((expected_rgb, expected_lms), (actual_rgb, actual_lms)) =
+                (pq(source), pq(reconstructed));
+            squared_error += expected_rgb
+                .iter()
+                .zip(actual_rgb)
+                .map(|(e, a)| ((e - a) as f64).powi(2))
+                .sum::<f64>();
+            delta_es.push(delta_e_itp(expected_lms, actual_lms));
+            cam16_total += delta_e_cam16_ucs(ucs(source), ucs(reconstructed));
+        }
+        if delta_es.is_empty() {
+            return Ok(());
+        }
+
+        let mean_squared_error = squared_error / (delta_es.len() * 3) as f64;
+        let psnr = -10.0 * mean_squared_error.log10();
+        let mean = delta_es.iter().sum::<f64>() / delta_es.len() as f64;
+        let mean_cam16 = cam16_total / delta_es.len() as f64;
+        delta_es.sort_unstable_by(f64::total_cmp);
+        let p99 = delta_es[(delta_es.len() - 1) * 99 / 100];
+        let max = delta_es[delta_es.len() - 1];
+        if mean > NOTICEABLE_DELTA_E_ITP {
+            warn!(
+                psnr_db = psnr,
+                mean_delta_e_itp = mean,
+                p99_delta_e_itp = p99,
+                max_delta_e_itp = max,
+                mean_delta_e_cam16_ucs = mean_cam16,
+                "Reconstructed HDR visibly differs from source"
+            )
+        } else {
+            info!(
+                psnr_db = psnr,
+                mean_delta_e_itp = mean,
+                p99_delta_e_itp = p99,
+                max_delta_e_itp = max,
+                mean_delta_e_cam16_ucs = mean_cam16,
+                "Reconstructed HDR matches source"
+            )
+        }
+        Ok(())
+    }
+}
+
+/// Apply the Gain Map at full HDR capacity. Gains take the exposed SDR image back to unexposed source light
+fn reconstruct(sdr: [u8; 3], recovery: u8, metadata: &OutputMetadata) -> Pixel {
+    let stops = metadata.gain_map_max - metadata.gain_map_min;
+    let recovery = metadata
+        .recovery_curve
+        .decode(recovery as f32 / 255.0, stops);
+    let log_boost = metadata.gain_map_min + stops * recovery;
+    let boost = log_boost.exp2();
+    let [r, g, b] = sdr.map(|v| {
+        (metadata.transfer.decode(v as f32 / 255.0) + metadata.offset_sdr) * boost
+            - metadata.offset_hdr
+    });
+    Pixel { r, g, b }
+}
+
+/// ΔE ITP of two colors given as PQ-encoded LMS, 1 being a just noticeable difference
+pub fn delta_e_itp(a: [f32; 3], b: [f32; 3]) -> f64 {
+    let itp = |lms: [f32; 3]| -> [f64; 3] {
+        let [l, m, s] = lms.map(|v| v as f64);
+        [
+            0.5 * l + 0.5 * m,
+            // T is half of Ct
+            0.5 * (6610.0 * l - 13613.0 * m + 7003.0 * s) / 4096.0,
+            (17933.0 * l - 17390.0 * m - 543.0 * s) / 4096.0,
+        ]
+    };
+    let (a, b) = (itp(a), itp(b));
+    720.0
+        * a.iter()
+            .zip(b)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt()
+}
+
+pub const LMS_FROM_REC_2020: Matrix3x3f = Matrix3x3f::new(
+    1688.0 / 4096.0,
+    2146.0 / 4096.0,
+    262.0 / 4096.0,
+    683.0 / 4096.0,
+    2951.0 / 4096.0,
+    462.0 / 4096.0,
+    99.0 / 4096.0,
+    309.0 / 4096.0,
+    3688.0 / 4096.0,
+);
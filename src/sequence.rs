@@ -0,0 +1,261 @@
+use std::{
+    fs,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    cache::Cache,
+    convert_image,
+    decode::{prefetch, read_input, ExrImage},
+    measure_image, App, Outputs,
+};
+
+/// Values locked across every frame of a sequence, so HDR brightness does not flicker
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct SequenceStats {
+    /// Gain Map minimum log2 boost
+    pub gain_map_min: f32,
+    /// Gain Map maximum log2 boost
+    pub gain_map_max: f32,
+    /// Exposure value (eV) the range was measured with
+    pub exposure: f32,
+}
+
+impl SequenceStats {
+    /// Widen range to cover both
+    fn union(self, other: SequenceStats) -> SequenceStats {
+        SequenceStats {
+            gain_map_min: self.gain_map_min.min(other.gain_map_min),
+            gain_map_max: self.gain_map_max.max(other.gain_map_max),
+            exposure: self.exposure,
+        }
+    }
+
+    fn read(path: &Path) -> Result<SequenceStats, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+    }
+
+    fn write(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, serde_json::to_string_pretty(self).unwrap())
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))
+    }
+}
+
+/// Convert several frames, returning them with the outputs they were written to, and print a summary of every frame. The first failure stops the run unless `--continue-on-error` is given. With `--sequence` or `--range-from`, Gain Map range and exposure are the same for every frame. Frames found in `cache` are restored instead of converted
+pub fn run(
+    args: &App,
+    frames: &[(PathBuf, Outputs)],
+    cache: Option<&Cache>,
+) -> Result<Vec<(PathBuf, Outputs)>, String> {
+    let locked = if let Some(path) = &args.range_from {
+        let stats = SequenceStats::read(path)?;
+        if let Some(requested) = args.requested_exposure().filter(|ev| *ev != stats.exposure) {
+            warn!(
+                requested,
+                locked = stats.exposure,
+                "Exposure differs from the one in the statistics file, using the file's"
+            )
+        }
+        Some(stats)
+    } else if args.sequence {
+        // First pass, only gather statistics
+        info!(
+            frames = frames.len(),
+            "Measuring Gain Map range of sequence"
+        );
+        let measured: Mutex<Vec<Option<Result<SequenceStats, String>>>> =
+            Mutex::new(vec![None; frames.len()]);
+        let all: Vec<usize> = (0..frames.len()).collect();
+        for_each_frame(args, frames, &all, |index, image| {
+            let (frame, _) = &frames[index];
+            let result = isolate(frame, || {
+                image.and_then(|image| measure_image(args, frame, image))
+            });
+            let keep_going = result.is_ok() || args.continue_on_error;
+            measured.lock().unwrap()[index] = Some(result);
+            keep_going
+        });
+        // Folded in frame order, the first frame measured giving the exposure
+        let mut stats: Option<SequenceStats> = None;
+        for ((frame, _), result) in frames.iter().zip(measured.into_inner().unwrap()) {
+            match result {
+                Some(Ok(frame_stats)) => {
+                    stats = Some(stats.map_or(frame_stats, |s| s.union(frame_stats)))
+                }
+                Some(Err(e)) if args.continue_on_error => {
+                    warn!(file = %frame.display(), error = e, "Left out of the sequence range")
+                }
+                Some(Err(e)) => return Err(e),
+                None => {}
+            }
+        }
+        stats
+    } else {
+        None
+    };
+
+    if let Some(stats) = &locked {
+        info!(
+            gain_map_min = stats.gain_map_min,
+            gain_map_max = stats.gain_map_max,
+            exposure = stats.exposure,
+            "Locked sequence range"
+        );
+        if let Some(path) = &args.range_to {
+            stats.write(path)?
+        }
+    }
+
+    // Frames converted before with the same settings come back from the cache, without being read
+    let keys = frames
+        .iter()
+        .map(|(frame, _)| cache.map(|c| c.key(frame, locked.as_ref())).transpose())
+        .collect::<Result<Vec<_>, _>>()?;
+    let results: Vec<Option<(Outcome, Option<Outputs>)>> = keys
+        .iter()
+        .map(|key| match (cache, key) {
+            (Some(cache), Some(key)) if !args.force => cache
+                .restore(key)
+                .map(|(_, outputs)| (Outcome::Cached, Some(outputs))),
+            _ => None,
+        })
+        .collect();
+    let to_convert: Vec<usize> = (0..frames.len())
+        .filter(|&index| results[index].is_none())
+        .collect();
+
+    // Frames left without a result were skipped after a failure
+    let results = Mutex::new(results);
+    for_each_frame(args, frames, &to_convert, |index, image| {
+        let (frame, outputs) = &frames[index];
+        let start = Instant::now();
+        let converted = isolate(frame, || {
+            image
+                .and_then(|image| convert_image(args, frame, image, outputs, locked.as_ref()))
+                .and_then(|(stats, outputs)| {
+                    if let (Some(cache), Some(key)) = (cache, &keys[index]) {
+                        cache.store(key, stats, &outputs, args)?;
+                    }
+                    Ok(outputs)
+                })
+        });
+        let (result, keep_going) = match converted {
+            Ok(outputs) => ((Outcome::Converted(start.elapsed()), Some(outputs)), true),
+            Err(e) => {
+                error!(file = %frame.display(), error = e, "Failed to convert");
+                ((Outcome::Failed(e), None), args.continue_on_error)
+            }
+        };
+        results.lock().unwrap()[index] = Some(result);
+        keep_going
+    });
+    let results = results.into_inner().unwrap();
+    print_summary(frames, &results);
+
+    let failed = results
+        .iter()
+        .filter(|r| matches!(r, Some((Outcome::Failed(_), _))))
+        .count();
+    let skipped = results.iter().filter(|r| r.is_none()).count();
+    if skipped > 0 {
+        Err(format!(
+            "{} of {} frames failed, {} skipped. --continue-on-error converts the others",
+            failed,
+            frames.len(),
+            skipped
+        ))
+    } else if failed > 0 {
+        Err(format!("{} of {} frames failed", failed, frames.len()))
+    } else {
+        Ok(frames
+            .iter()
+            .zip(results)
+            .filter_map(|((frame, _), result)| Some((frame.clone(), result?.1?)))
+            .collect())
+    }
+}
+
+/// Read the frames at `indices` and hand them to `handle` with their index. With --jobs 1, frames go in order, the next one being read during conversion. Otherwise as many frames as jobs are read and converted at once, each thread taking the next frame as soon as it is done with one, so slow frames do not hold the others back. No frame is started after `handle` returns false
+fn for_each_frame(
+    args: &App,
+    frames: &[(PathBuf, Outputs)],
+    indices: &[usize],
+    handle: impl Fn(usize, Result<ExrImage, String>) -> bool + Sync,
+) {
+    if args.jobs <= 1 {
+        thread::scope(|scope| {
+            let paths = indices.iter().map(|&i| frames[i].0.clone()).collect();
+            // Dropping the receiver stops reading ahead
+            for (&index, image) in indices.iter().zip(prefetch(scope, args, paths)) {
+                if !handle(index, image) {
+                    break;
+                }
+            }
+        });
+        return;
+    }
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        for _ in 0..args.jobs.min(indices.len()) {
+            scope.spawn(|| {
+                while !stop.load(Ordering::Relaxed) {
+                    let Some(&index) = indices.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        return;
+                    };
+                    if !handle(index, read_input(&frames[index].0, args)) {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+            });
+        }
+    });
+}
+
+/// Run the conversion of one frame, a panic failing that frame only
+fn isolate<T>(frame: &Path, convert: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    catch_unwind(AssertUnwindSafe(convert))
+        .unwrap_or_else(|_| Err(format!("Conversion of {} panicked", frame.display())))
+}
+
+/// What became of a frame
+enum Outcome {
+    /// Converted in this long, decoding excluded
+    Converted(Duration),
+    /// Outputs restored from the cache
+    Cached,
+    Failed(String),
+}
+
+/// Table of every frame with its outcome on stdout, frames without one having been skipped
+fn print_summary(frames: &[(PathBuf, Outputs)], results: &[Option<(Outcome, Option<Outputs>)>]) {
+    println!("{:<9} {:>8}  File", "Status", "Time");
+    for ((frame, _), result) in frames.iter().zip(results) {
+        match result.as_ref().map(|(outcome, _)| outcome) {
+            Some(Outcome::Converted(time)) => println!(
+                "{:<9} {:>7.2}s  {}",
+                "converted",
+                time.as_secs_f32(),
+                frame.display()
+            ),
+            Some(Outcome::Cached) => println!("{:<9} {:>8}  {}", "cached", "", frame.display()),
+            Some(Outcome::Failed(e)) => {
+                println!("{:<9} {:>8}  {}: {}", "failed", "", frame.display(), e)
+            }
+            None => println!("{:<9} {:>8}  {}", "skipped", "", frame.display()),
+        }
+    }
+}
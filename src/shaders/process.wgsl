@@ -0,0 +1,129 @@
+// GPU version of `process_cpu` in main.rs, keep both in sync
+
+struct Parameters {
+    conversion: mat3x3<f32>,
+    coefficients: vec3<f32>,
+    factor: f32,
+    /// Transfer enum discriminant
+    transfer: u32,
+    offset_hdr: f32,
+    offset_sdr: f32,
+    count: u32,
+    sdr_saturation: f32,
+    sdr_contrast: f32,
+    sdr_contrast_pivot: f32,
+    knee_start: f32,
+    knee_strength: f32,
+    /// GainBasis discriminant
+    gain_basis: u32,
+}
+
+@group(0) @binding(0) var<uniform> parameters: Parameters;
+/// Linear-light RGB values, converted in place
+@group(0) @binding(1) var<storage, read_write> pixels: array<f32>;
+/// Gamma-encoded RGB values, packed as one u32 per pixel
+@group(0) @binding(2) var<storage, read_write> image_data: array<u32>;
+@group(0) @binding(3) var<storage, read_write> gains: array<f32>;
+
+fn luminance(pixel: vec3<f32>) -> f32 {
+    return dot(pixel, parameters.coefficients);
+}
+
+/// Same as `GainBasis::value`
+fn basis_value(pixel: vec3<f32>) -> f32 {
+    switch parameters.gain_basis {
+        // Max RGB
+        case 1u: {
+            return max(max(pixel.r, pixel.g), pixel.b);
+        }
+        // Mean RGB
+        case 2u: {
+            return (pixel.r + pixel.g + pixel.b) / 3.0;
+        }
+        // Luminance
+        default: {
+            return luminance(pixel);
+        }
+    }
+}
+
+/// Same as `BT1886_BLACK` in transfer_functions.rs
+const BT1886_BLACK: f32 = 0.001;
+
+fn encode(linear_value: f32) -> f32 {
+    switch parameters.transfer {
+        // sRGB
+        case 1u: {
+            if (linear_value <= 0.0031308) {
+                return 12.92 * linear_value;
+            }
+            return 1.055 * pow(linear_value, 1.0 / 2.4) - 0.055;
+        }
+        // Gamma 2.2
+        case 2u: {
+            return pow(linear_value, 1.0 / 2.2);
+        }
+        // BT.1886
+        case 3u: {
+            let range = 1.0 - pow(BT1886_BLACK, 1.0 / 2.4);
+            let lifted = BT1886_BLACK + (1.0 - BT1886_BLACK) * linear_value;
+            return pow(lifted, 1.0 / 2.4) / range - pow(BT1886_BLACK, 1.0 / 2.4) / range;
+        }
+        // Gamma 2.4
+        default: {
+            return pow(linear_value, 1.0 / 2.4);
+        }
+    }
+}
+
+/// Same as `SdrTrims::apply`
+fn trim(pixel: vec3<f32>) -> vec3<f32> {
+    var graded = pixel;
+    if (parameters.sdr_saturation != 1.0 || parameters.sdr_contrast != 1.0) {
+        var y = luminance(pixel);
+        var scale = 1.0;
+        if (y > 0.0 && parameters.sdr_contrast != 1.0) {
+            scale = parameters.sdr_contrast_pivot * pow(y / parameters.sdr_contrast_pivot, parameters.sdr_contrast) / y;
+        }
+        y = y * scale;
+        graded = vec3<f32>(y) + (pixel * scale - vec3<f32>(y)) * parameters.sdr_saturation;
+    }
+    if (parameters.knee_strength == 0.0) {
+        return graded;
+    }
+    return vec3<f32>(shoulder(graded.r), shoulder(graded.g), shoulder(graded.b));
+}
+
+/// Same as `SdrTrims::shoulder`
+fn shoulder(value: f32) -> f32 {
+    if (value <= parameters.knee_start) {
+        return value;
+    }
+    let range = 1.0 - parameters.knee_start;
+    let x = (value - parameters.knee_start) / range;
+    return parameters.knee_start + range * (1.0 - exp(-parameters.knee_strength * x)) / parameters.knee_strength;
+}
+
+fn process_component(sdr_value: f32) -> u32 {
+    let encoded = encode(max(sdr_value, 0.0));
+    return u32(round(clamp(encoded * 255.0, 0.0, 255.0)));
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= parameters.count) {
+        return;
+    }
+
+    let linear = parameters.conversion * vec3<f32>(pixels[index * 3u], pixels[index * 3u + 1u], pixels[index * 3u + 2u]);
+    pixels[index * 3u] = linear.r;
+    pixels[index * 3u + 1u] = linear.g;
+    pixels[index * 3u + 2u] = linear.b;
+
+    let sdr = trim(linear * parameters.factor);
+    let clipped = clamp(sdr, vec3<f32>(0.0), vec3<f32>(1.0));
+    gains[index] = (max(basis_value(linear), 0.0) + parameters.offset_hdr) / (basis_value(clipped) + parameters.offset_sdr);
+
+    image_data[index] = process_component(sdr.r) | (process_component(sdr.g) << 8u) | (process_component(sdr.b) << 16u);
+}
@@ -0,0 +1,719 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use askama::Template;
+use clap::ValueEnum;
+use jpeg_encoder::Encoder as JPEGEncoder;
+use png::{chunk::ChunkType, Encoder as PNGEncoder, ScaledFloat};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{
+    base_jpeg::BaseJpeg,
+    cicp::Cicp,
+    color_stuff::{to_png_chromaticities, Chromaticities, Pixel},
+    copy_metadata::merge_xmp,
+    exif::make_exif,
+    iso21496::GainMapMetadata,
+    jpeg_backend::{self, JpegBackend},
+    jpeg_bands::{self, EncoderMode, JpegSettings},
+    jpeg_container::JpegContainerBuilder,
+    light_level::ContentLight,
+    mpf::{self, MpEntry, PRIMARY_IMAGE_ATTRIBUTE, UNDEFINED_IMAGE_ATTRIBUTE},
+    planar::{PlanarRgb, PlanarView},
+    precision::LinearSlice,
+    process_pixel,
+    recovery_curve::{RecoveryCurve, PQ_CURVE_NAME, RECOVERY_CURVE_NAMESPACE},
+    resize::{downscale_box, fit_within, gain_map_size},
+    resolution::Resolution,
+    scopes::{self, HISTOGRAM_HEIGHT, HISTOGRAM_WIDTH, WAVEFORM_HEIGHT},
+    transfer_functions::Transfer,
+    trims::SdrTrims,
+    ultra_hdr_stuff::{make_xmp, GContainerTemplate, HDRGainMapMetadataTemplate},
+    CICP_CHUNK, EXIF_CHUNK, JPEG_QUALITY, MAP_JPEG_QUALITY,
+};
+
+/// Relative to the largest component, negative values smaller than this are not reported as out of gamut
+const GAMUT_TOLERANCE: f32 = 1e-4;
+/// PNG chunk holding ISO 21496-1 Gain Map metadata
+const GAIN_MAP_METADATA_CHUNK: ChunkType = ChunkType(*b"gmAP");
+/// PNG chunk holding the Gain Map, itself a PNG datastream
+const GAIN_MAP_DATA_CHUNK: ChunkType = ChunkType(*b"gdAT");
+/// PNG chunk holding MaxCLL and MaxFALL
+const CONTENT_LIGHT_CHUNK: ChunkType = ChunkType(*b"cLLi");
+/// Size of IDAT chunks PNG rows are streamed to, so no filtered or compressed copy of a whole image is held
+const PNG_STREAM_BUFFER: usize = 1 << 20;
+/// Paint for out of gamut pixels
+const GAMUT_WARNING_COLOR: [u8; 3] = [255, 0, 255];
+
+/// Outputs `--emit` writes, from the same processing pass
+#[derive(ValueEnum, Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum EmitFormat {
+    UltraHdrJpeg,
+    Jpeg,
+    Png,
+    GainMapPng,
+    GainMapJpeg,
+}
+
+impl EmitFormat {
+    /// Appended to the `--emit-to` path, as to input names when outputs are directories
+    pub fn suffix(self) -> &'static str {
+        match self {
+            EmitFormat::UltraHdrJpeg => "_ultra_hdr.jpg",
+            EmitFormat::Jpeg => ".jpg",
+            EmitFormat::Png => ".png",
+            EmitFormat::GainMapPng => "_gain_map.png",
+            EmitFormat::GainMapJpeg => "_gain_map.jpg",
+        }
+    }
+}
+
+/// Result of the single processing pass, shared by every output
+pub struct Planes<'a> {
+    pub width: usize,
+    pub height: usize,
+    /// Gamma-encoded u8 RGB SDR image, one plane per channel
+    pub image: PlanarView<'a>,
+    /// Gamma-encoded u8 recovery values
+    pub gain_map: &'a [u8],
+    /// How many times smaller the Gain Map is than the SDR image
+    pub gain_map_scale: usize,
+    /// Gamma-encoded u16 recovery values, when requested. `gain_map` then holds them dithered
+    pub gain_map_16bit: Option<&'a [u16]>,
+    /// Linear light in output color space, before exposure
+    pub linear_light: LinearSlice<'a>,
+}
+
+impl Planes<'_> {
+    /// Width and height of the Gain Map
+    pub fn gain_map_size(&self) -> (usize, usize) {
+        gain_map_size(self.width, self.height, self.gain_map_scale)
+    }
+}
+
+/// How planes are to be interpreted
+pub struct OutputMetadata<'a> {
+    pub chromaticities: Chromaticities,
+    pub transfer: Transfer,
+    /// Exposure multiplication factor
+    pub factor: f32,
+    pub cicp: Option<Cicp>,
+    /// Describe PNG color with gAMA and cHRM chunks
+    pub legacy_color_chunks: bool,
+    pub icc_profile: &'a [u8],
+    /// EXIF TIFF structure, if any
+    pub exif: Option<&'a [u8]>,
+    /// XMP packet for the SDR image, if any
+    pub xmp: Option<&'a str>,
+    /// Log2 range of the Gain Map
+    pub gain_map_min: f32,
+    pub gain_map_max: f32,
+    pub map_gamma: f32,
+    /// Curve of recovery values, its gamma being `map_gamma` for power curves
+    pub recovery_curve: RecoveryCurve,
+    pub offset_sdr: f32,
+    pub offset_hdr: f32,
+    pub trims: SdrTrims,
+    /// MaxCLL and MaxFALL, if measured
+    pub content_light: Option<ContentLight>,
+    /// Print resolution or pixel aspect ratio, if any
+    pub resolution: Option<Resolution>,
+}
+
+impl OutputMetadata<'_> {
+    fn iso_metadata(&self) -> GainMapMetadata {
+        GainMapMetadata {
+            gain_map_min: self.gain_map_min,
+            gain_map_max: self.gain_map_max,
+            gamma: self.map_gamma,
+            offset_sdr: self.offset_sdr,
+            offset_hdr: self.offset_hdr,
+            hdr_capacity_min: self.gain_map_min,
+            hdr_capacity_max: self.gain_map_max,
+        }
+    }
+}
+
+/// An output file format. New formats implement this and are listed in `Outputs::sinks`
+pub trait OutputSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String>;
+}
+
+// ----- PNG
+
+/// SDR image as 8-bit RGB PNG
+pub struct PngSink {
+    pub path: PathBuf,
+    /// Also carry the Gain Map, so the PNG renders as HDR where supported
+    pub embed_gain_map: bool,
+}
+
+impl OutputSink for PngSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        let mut extra_chunks = Vec::new();
+        if self.embed_gain_map {
+            // Follows the PNG gain map proposal: ISO 21496-1 metadata, then the Gain Map as a PNG datastream
+            let mut gain_map_png = Vec::new();
+            write_gain_map_png(&mut gain_map_png, planes, metadata)
+                .map_err(|e| write_error(&self.path, e))?;
+            extra_chunks.push((GAIN_MAP_METADATA_CHUNK, metadata.iso_metadata().bytes()));
+            extra_chunks.push((GAIN_MAP_DATA_CHUNK, gain_map_png));
+            if let Some(light) = metadata.content_light {
+                extra_chunks.push((CONTENT_LIGHT_CHUNK, light.png_chunk()));
+            }
+        }
+        write_rgb_png(
+            &self.path,
+            planes.image.interleaved_rows(),
+            planes,
+            metadata,
+            &extra_chunks,
+        )
+    }
+}
+
+/// SDR image as PNG, with pixels outside of the output gamut painted magenta. Checked on linear light before any clipping
+pub struct GamutWarningSink(pub PathBuf);
+
+impl OutputSink for GamutWarningSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        let mut out_of_gamut = 0;
+        // Painted row by row while writing
+        let mut pixels = planes.linear_light.iter();
+        let image = planes.image;
+        let rows = (0..planes.height).map(|y| {
+            image
+                .rows(y, y + 1)
+                .pixels()
+                .zip(pixels.by_ref())
+                .flat_map(|(rgb, pixel)| {
+                    if is_out_of_gamut(&pixel) {
+                        out_of_gamut += 1;
+                        GAMUT_WARNING_COLOR
+                    } else {
+                        rgb
+                    }
+                })
+                .collect::<Vec<u8>>()
+        });
+        write_rgb_png(&self.0, rows, planes, metadata, &[])?;
+        info!(
+            pixels = out_of_gamut,
+            percent = 100.0 * out_of_gamut as f32 / planes.linear_light.len().max(1) as f32,
+            "Pixels outside of output gamut"
+        );
+        Ok(())
+    }
+}
+
+/// A component is negative beyond rounding errors of the color space conversion
+pub fn is_out_of_gamut(pixel: &Pixel) -> bool {
+    let max = pixel.r.max(pixel.g).max(pixel.b);
+    let min = pixel.r.min(pixel.g).min(pixel.b);
+    min < -GAMUT_TOLERANCE * max.abs()
+}
+
+/// Write an 8-bit RGB PNG from its rows. Color is described with gAMA and cHRM chunks if wanted, and with a cICP chunk if code points are given. Extra chunks go right before image data
+fn write_rgb_png<R: AsRef<[u8]>>(
+    path: &Path,
+    rows: impl IntoIterator<Item = R>,
+    planes: &Planes,
+    metadata: &OutputMetadata,
+    extra_chunks: &[(ChunkType, Vec<u8>)],
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| write_error(path, e))?;
+    let mut encoder = PNGEncoder::new(
+        BufWriter::new(file),
+        planes.width.try_into().unwrap(),
+        planes.height.try_into().unwrap(),
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_pixel_dims(metadata.resolution.map(|r| r.png_dimensions()));
+    if metadata.legacy_color_chunks {
+        encoder.set_source_gamma(ScaledFloat::new(
+            metadata.transfer.approximate_gamma().recip(),
+        ));
+        if metadata.chromaticities.has_negatives() {
+            warn!(chromaticities = ?metadata.chromaticities, "Some output chromaticities have negative values, PNGs clamps these to 0. Color WILL be affected")
+        }
+        encoder.set_source_chromaticities(to_png_chromaticities(metadata.chromaticities));
+    }
+    let error = |e: png::EncodingError| write_error(path, e);
+    let mut writer = encoder.write_header().map_err(error)?;
+    if let Some(cicp) = metadata.cicp {
+        writer
+            .write_chunk(CICP_CHUNK, &cicp.bytes())
+            .map_err(error)?;
+    }
+    if let Some(exif) = metadata.exif {
+        writer.write_chunk(EXIF_CHUNK, exif).map_err(error)?;
+    }
+    for (chunk_type, data) in extra_chunks {
+        writer.write_chunk(*chunk_type, data).map_err(error)?;
+    }
+    write_png_rows(&mut writer, rows).map_err(error)?;
+    writer.finish().map_err(error)
+}
+
+/// Error of an output file that could not be written
+fn write_error(path: &Path, e: impl std::fmt::Display) -> String {
+    format!("Could not write {}: {}", path.display(), e)
+}
+
+/// Create the file at `path`, `write` streaming its contents through a buffer
+pub fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut dyn Write) -> Result<(), String>,
+) -> Result<(), String> {
+    let mut writer = BufWriter::new(File::create(path).map_err(|e| write_error(path, e))?);
+    write(&mut writer)?;
+    writer.flush().map_err(|e| write_error(path, e))
+}
+
+/// Filter and compress image data one row at a time
+fn write_png_rows<W: Write, R: AsRef<[u8]>>(
+    writer: &mut png::Writer<W>,
+    rows: impl IntoIterator<Item = R>,
+) -> Result<(), png::EncodingError> {
+    let mut stream = writer.stream_writer_with_size(PNG_STREAM_BUFFER)?;
+    for row in rows {
+        stream.write_all(row.as_ref())?;
+    }
+    stream.finish()
+}
+
+/// Gain Map as 8-bit grayscale PNG, for diagnostics
+pub struct GainMapPngSink(pub PathBuf);
+
+impl OutputSink for GainMapPngSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        let file = File::create(&self.0).map_err(|e| write_error(&self.0, e))?;
+        write_gain_map_png(BufWriter::new(file), planes, metadata)
+            .map_err(|e| write_error(&self.0, e))
+    }
+}
+
+fn write_gain_map_png<W: Write>(
+    writer: W,
+    planes: &Planes,
+    metadata: &OutputMetadata,
+) -> Result<(), png::EncodingError> {
+    let (width, height) = planes.gain_map_size();
+    let mut encoder = PNGEncoder::new(
+        writer,
+        width.try_into().unwrap(),
+        height.try_into().unwrap(),
+    );
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(match planes.gain_map_16bit {
+        Some(_) => png::BitDepth::Sixteen,
+        None => png::BitDepth::Eight,
+    });
+    encoder.set_source_gamma(ScaledFloat::new(metadata.map_gamma.recip()));
+    let mut writer = encoder.write_header()?;
+    if let Some(exif) = metadata.exif {
+        writer.write_chunk(EXIF_CHUNK, exif)?;
+    }
+    match planes.gain_map_16bit {
+        Some(recoveries) => {
+            let rows = recoveries.chunks_exact(width).map(|row| {
+                row.iter()
+                    .flat_map(|r| r.to_be_bytes())
+                    .collect::<Vec<u8>>()
+            });
+            write_png_rows(&mut writer, rows)?
+        }
+        None => write_png_rows(&mut writer, planes.gain_map.chunks_exact(width))?,
+    }
+    writer.finish()
+}
+
+// ----- Scopes
+
+/// Histogram of exposed scene luminance, log-scaled, as PNG
+pub struct HistogramSink(pub PathBuf);
+
+impl OutputSink for HistogramSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        let image_data = scopes::histogram(luminances(planes, metadata));
+        write_scope_png(&self.0, &image_data, HISTOGRAM_WIDTH, HISTOGRAM_HEIGHT)
+    }
+}
+
+/// Waveform of exposed scene luminance, log-scaled, as PNG
+pub struct WaveformSink(pub PathBuf);
+
+impl OutputSink for WaveformSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        let luminances: Vec<f32> = luminances(planes, metadata).collect();
+        let image_data = scopes::waveform(&luminances, planes.width);
+        write_scope_png(
+            &self.0,
+            &image_data,
+            scopes::waveform_width(planes.width),
+            WAVEFORM_HEIGHT,
+        )
+    }
+}
+
+/// Luminance of every pixel after exposure, 1.0 being SDR white
+fn luminances<'a>(
+    planes: &'a Planes,
+    metadata: &'a OutputMetadata,
+) -> impl Iterator<Item = f32> + 'a {
+    let coefficients = metadata.chromaticities.luminance_values().unwrap();
+    planes.linear_light.iter().map(move |p| {
+        (p.r * coefficients.red + p.g * coefficients.green + p.b * coefficients.blue)
+            * metadata.factor
+    })
+}
+
+/// Diagnostic render as plain 8-bit RGB PNG, without color metadata
+fn write_scope_png(
+    path: &Path,
+    image_data: &[u8],
+    width: usize,
+    height: usize,
+) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| write_error(path, e))?;
+    let mut encoder = PNGEncoder::new(
+        BufWriter::new(file),
+        width.try_into().unwrap(),
+        height.try_into().unwrap(),
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let error = |e: png::EncodingError| write_error(path, e);
+    let mut writer = encoder.write_header().map_err(error)?;
+    writer.write_image_data(image_data).map_err(error)?;
+    writer.finish().map_err(error)
+}
+
+// ----- JPEG
+
+/// SDR image as JPEG, with ICC profile embedded
+pub struct JpegSink {
+    pub path: PathBuf,
+    pub encoder: EncoderMode,
+    pub progressive: bool,
+    pub backend: JpegBackend,
+}
+
+impl OutputSink for JpegSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        write_file(&self.path, |writer| {
+            jpeg_backend::encode(
+                writer,
+                self.backend,
+                JpegSettings {
+                    mode: self.encoder,
+                    quality: JPEG_QUALITY,
+                    progressive: self.progressive,
+                },
+                planes.image,
+                |encoder| {
+                    if let Some(resolution) = metadata.resolution {
+                        encoder.set_density(resolution.jfif_density());
+                    }
+                    if let Some(exif) = metadata.exif {
+                        encoder.add_app_segment(1, &make_exif(exif))?;
+                    }
+                    if let Some(xmp) = metadata.xmp {
+                        encoder.add_app_segment(1, &make_xmp(xmp.to_string()))?;
+                    }
+                    encoder.add_icc_profile(metadata.icc_profile)
+                },
+            )
+            .map_err(|e| write_error(&self.path, e))
+        })
+    }
+}
+
+/// Gain Map as grayscale JPEG, for diagnostics
+pub struct GainMapJpegSink {
+    pub path: PathBuf,
+    pub encoder: EncoderMode,
+    pub progressive: bool,
+}
+
+impl OutputSink for GainMapJpegSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        let (gain_map_width, gain_map_height) = planes.gain_map_size();
+        write_file(&self.path, |writer| {
+            jpeg_bands::encode(
+                writer,
+                JpegSettings {
+                    mode: self.encoder,
+                    quality: MAP_JPEG_QUALITY,
+                    progressive: self.progressive,
+                },
+                PlanarView::gray(planes.gain_map, gain_map_width, gain_map_height),
+                |encoder| {
+                    if let Some(exif) = metadata.exif {
+                        encoder.add_app_segment(1, &make_exif(exif))?;
+                    }
+                    Ok(())
+                },
+            )
+            .map_err(|e| write_error(&self.path, e))
+        })
+    }
+}
+
+/// SDR image, Gain Map and optional thumbnail in a single Ultra HDR JPEG
+pub struct UltraHdrJpegSink {
+    pub path: PathBuf,
+    /// Longest side of the thumbnail, if one is wanted
+    pub thumbnail_size: Option<usize>,
+    /// Already encoded primary image, used instead of encoding the SDR image
+    pub base_jpeg: Option<BaseJpeg>,
+    pub encoder: EncoderMode,
+    pub backend: JpegBackend,
+    /// Progressive scans for the primary image, and for the Gain Map
+    pub progressive: bool,
+    pub progressive_gain_map: bool,
+}
+
+impl UltraHdrJpegSink {
+    pub fn new(
+        path: &Path,
+        thumbnail_size: Option<usize>,
+        base_jpeg: Option<&Path>,
+        encoder: EncoderMode,
+        backend: JpegBackend,
+        progressive: bool,
+        progressive_gain_map: bool,
+    ) -> Result<UltraHdrJpegSink, String> {
+        Ok(UltraHdrJpegSink {
+            path: path.to_path_buf(),
+            thumbnail_size,
+            base_jpeg: base_jpeg.map(BaseJpeg::read).transpose()?,
+            encoder,
+            backend,
+            progressive,
+            progressive_gain_map,
+        })
+    }
+}
+
+impl OutputSink for UltraHdrJpegSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        let (width, height) = (planes.width, planes.height);
+        let (gain_map_width, gain_map_height) = planes.gain_map_size();
+        if let Some(base) = &self.base_jpeg {
+            if (base.width, base.height) != (width, height) {
+                return Err(format!(
+                    "Base JPEG is {}x{}, output is {}x{}",
+                    base.width, base.height, width, height
+                ));
+            }
+        }
+
+        // Create new file, read access is needed to patch it afterwards
+        let write_file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.path)
+            .map_err(|e| write_error(&self.path, e))?;
+        let mut container = JpegContainerBuilder::new(write_file);
+
+        // Gen directory XMP, reserving space for the largest possible gain map length
+        let directory_xmp = |gain_map_image_len| {
+            let xmp = GContainerTemplate { gain_map_image_len }.render().unwrap();
+            make_xmp(match metadata.xmp {
+                Some(reference) => merge_xmp(xmp, reference),
+                None => xmp,
+            })
+        };
+
+        // Downscale thumbnail from the encoded SDR image, graded or locally tone mapped as it may be, in linear light
+        let thumbnail = self.thumbnail_size.map(|size| {
+            let (thumbnail_width, thumbnail_height) = fit_within(width, height, size);
+            let sdr: Vec<Pixel> = planes
+                .image
+                .pixels()
+                .map(|rgb| {
+                    let [r, g, b] = rgb.map(|v| metadata.transfer.decode(v as f32 / 255.0));
+                    Pixel { r, g, b }
+                })
+                .collect();
+            let thumbnail_data: PlanarRgb =
+                downscale_box(&sdr, width, height, thumbnail_width, thumbnail_height)
+                    .iter()
+                    .map(|p| [p.r, p.g, p.b].map(|v| process_pixel(v, metadata.transfer)))
+                    .collect();
+            (thumbnail_data, thumbnail_width, thumbnail_height)
+        });
+        let image_count = if thumbnail.is_some() { 3 } else { 2 };
+
+        // Encode main image
+        container
+            .add_image(PRIMARY_IMAGE_ATTRIBUTE, |writer| {
+                // Reserve MPF index, filled in once all images are written
+                let mpf_index = mpf::index(&vec![MpEntry::default(); image_count]);
+                if let Some(base) = &self.base_jpeg {
+                    return base.write_with_segments(
+                        writer,
+                        &[(1, &directory_xmp(u64::MAX)), (2, &mpf_index)],
+                    );
+                }
+
+                jpeg_backend::encode(
+                    writer,
+                    self.backend,
+                    JpegSettings {
+                        mode: self.encoder,
+                        quality: JPEG_QUALITY,
+                        progressive: self.progressive,
+                    },
+                    planes.image,
+                    |encoder| {
+                        if let Some(resolution) = metadata.resolution {
+                            encoder.set_density(resolution.jfif_density());
+                        }
+                        if let Some(exif) = metadata.exif {
+                            encoder.add_app_segment(1, &make_exif(exif))?;
+                        }
+                        encoder.add_icc_profile(metadata.icc_profile)?;
+                        encoder.add_app_segment(1, &directory_xmp(u64::MAX))?;
+                        encoder.add_app_segment(2, &mpf_index)
+                    },
+                )
+                .map_err(io::Error::other)
+            })
+            .map_err(|e| write_error(&self.path, e))?;
+
+        // Gen Gain Map XMP data
+        let hdr_xmp = HDRGainMapMetadataTemplate {
+            gain_map_min: metadata.gain_map_min,
+            gain_map_max: metadata.gain_map_max,
+            gamma: metadata.map_gamma,
+            offset_sdr: metadata.offset_sdr,
+            offset_hdr: metadata.offset_hdr,
+            hdr_capacity_min: metadata.gain_map_min,
+            hdr_capacity_max: metadata.gain_map_max,
+            recovery_curve: match metadata.recovery_curve {
+                RecoveryCurve::Power(_) => None,
+                RecoveryCurve::Pq => Some(PQ_CURVE_NAME),
+            },
+            recovery_curve_namespace: RECOVERY_CURVE_NAMESPACE,
+        }
+        .render()
+        .unwrap();
+
+        // Put gain map image next
+        container
+            .add_image(UNDEFINED_IMAGE_ATTRIBUTE, |writer| {
+                jpeg_bands::encode(
+                    writer,
+                    JpegSettings {
+                        mode: self.encoder,
+                        quality: MAP_JPEG_QUALITY,
+                        progressive: self.progressive_gain_map,
+                    },
+                    PlanarView::gray(planes.gain_map, gain_map_width, gain_map_height),
+                    |encoder| encoder.add_app_segment(1, &make_xmp(hdr_xmp.clone())),
+                )
+                .map_err(io::Error::other)
+            })
+            .map_err(|e| write_error(&self.path, e))?;
+
+        // Put thumbnail last, GContainer expects the gain map right after the primary image
+        if let Some((thumbnail_data, thumbnail_width, thumbnail_height)) = thumbnail {
+            container
+                .add_image(
+                    mpf::thumbnail_attribute(thumbnail_width, thumbnail_height),
+                    |writer| {
+                        let mut thumbnail_encoder = JPEGEncoder::new(writer, JPEG_QUALITY);
+                        thumbnail_encoder
+                            .add_icc_profile(metadata.icc_profile)
+                            .map_err(io::Error::other)?;
+                        thumbnail_encoder
+                            .encode_image(thumbnail_data.view(thumbnail_width, thumbnail_height))
+                            .map_err(io::Error::other)
+                    },
+                )
+                .map_err(|e| write_error(&self.path, e))?;
+        }
+
+        // Fill in actual lengths and offsets
+        container
+            .finish(|images| Some(directory_xmp(images[1].length)))
+            .map_err(|e| write_error(&self.path, e))?;
+        Ok(())
+    }
+}
+
+// ----- Web
+
+// https://github.com/MONOGRID/gainmap-js
+
+/// Files of a web export, in the order gainmap-js loads them
+pub const WEB_EXPORT_FILES: [&str; 3] = ["sdr.jpg", "gainmap.jpg", "metadata.json"];
+
+/// Gain Map metadata as gainmap-js reads it, per channel where it allows it
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WebGainMapMetadata {
+    gain_map_min: [f32; 3],
+    gain_map_max: [f32; 3],
+    gamma: [f32; 3],
+    offset_sdr: [f32; 3],
+    offset_hdr: [f32; 3],
+    hdr_capacity_min: f32,
+    hdr_capacity_max: f32,
+}
+
+/// SDR image, Gain Map and their metadata as separate files in a directory, for the gainmap-js loader of three.js
+pub struct WebExportSink {
+    pub directory: PathBuf,
+    pub encoder: EncoderMode,
+    pub backend: JpegBackend,
+    pub progressive: bool,
+    pub progressive_gain_map: bool,
+}
+
+impl OutputSink for WebExportSink {
+    fn write(&self, planes: &Planes, metadata: &OutputMetadata) -> Result<(), String> {
+        fs::create_dir_all(&self.directory)
+            .map_err(|e| format!("Could not create {}: {}", self.directory.display(), e))?;
+        let [sdr, gain_map, metadata_json] = WEB_EXPORT_FILES.map(|f| self.directory.join(f));
+
+        JpegSink {
+            path: sdr,
+            encoder: self.encoder,
+            progressive: self.progressive,
+            backend: self.backend,
+        }
+        .write(planes, metadata)?;
+        GainMapJpegSink {
+            path: gain_map,
+            encoder: self.encoder,
+            progressive: self.progressive_gain_map,
+        }
+        .write(planes, metadata)?;
+
+        let web_metadata = WebGainMapMetadata {
+            gain_map_min: [metadata.gain_map_min; 3],
+            gain_map_max: [metadata.gain_map_max; 3],
+            gamma: [metadata.map_gamma; 3],
+            offset_sdr: [metadata.offset_sdr; 3],
+            offset_hdr: [metadata.offset_hdr; 3],
+            hdr_capacity_min: metadata.gain_map_min,
+            hdr_capacity_max: metadata.gain_map_max,
+        };
+        fs::write(
+            &metadata_json,
+            serde_json::to_string_pretty(&web_metadata).unwrap(),
+        )
+        .map_err(|e| format!("Could not write {}: {}", metadata_json.display(), e))?;
+        Ok(())
+    }
+}
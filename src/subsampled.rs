@@ -0,0 +1,211 @@
+// Reader for scan line images with subsampled channels (mostly luminance / chroma), which the exr crate cannot decode
+
+use std::io::{self, Read};
+
+use exr::{
+    compression::Compression,
+    image::{AnyChannel, AnyChannels, Blocks, Encoding, FlatSamples, Image, Layer},
+    meta::{
+        attribute::{ChannelDescription, SampleType},
+        header::Header,
+        BlockDescription,
+    },
+    prelude::f16,
+};
+
+use crate::decode::ExrImage;
+
+/// Whether the first layer has a channel that the exr crate would refuse
+pub fn is_subsampled(headers: &[Header]) -> bool {
+    headers[0]
+        .channels
+        .list
+        .iter()
+        .any(|c| c.sampling.x() != 1 || c.sampling.y() != 1)
+}
+
+/// Read a single part image, `read` being positioned right after the headers. Subsampled channels keep their reduced resolution
+pub fn read(mut read: impl Read, headers: Vec<Header>) -> Result<ExrImage, String> {
+    let [header] = <[Header; 1]>::try_from(headers)
+        .map_err(|_| "Subsampled channels in a multi-part file".to_string())?;
+    let attributes = header.shared_attributes.clone();
+    if header.blocks != BlockDescription::ScanLines || header.deep {
+        return Err("Subsampled channels in a tiled or deep image".to_string());
+    }
+    let window = header.data_window();
+    let channels = &header.channels.list;
+    let io_error = |e: std::io::Error| e.to_string();
+
+    // Blocks are read in file order, the offset table is not needed
+    io::copy(
+        &mut read.by_ref().take(header.chunk_count as u64 * 8),
+        &mut io::sink(),
+    )
+    .map_err(io_error)?;
+
+    let mut samples: Vec<FlatSamples> = channels
+        .iter()
+        .map(|c| {
+            let count = c.subsampled_resolution(header.layer_size).area();
+            match c.sample_type {
+                SampleType::F16 => FlatSamples::F16(vec![f16::ZERO; count]),
+                SampleType::F32 => FlatSamples::F32(vec![0.0; count]),
+                SampleType::U32 => FlatSamples::U32(vec![0; count]),
+            }
+        })
+        .collect();
+    // Lines skipped by a channel hold no samples of it
+    let line_has =
+        |channel: &ChannelDescription, y: i32| y.rem_euclid(channel.sampling.y() as i32) == 0;
+
+    for _ in 0..header.chunk_count {
+        let mut block_header = [0; 8];
+        read.read_exact(&mut block_header).map_err(io_error)?;
+        let y_coordinate = i32::from_le_bytes(block_header[0..4].try_into().unwrap());
+        let byte_count = u32::from_le_bytes(block_header[4..8].try_into().unwrap());
+        let mut compressed = Vec::new();
+        read.by_ref()
+            .take(byte_count as u64)
+            .read_to_end(&mut compressed)
+            .map_err(io_error)?;
+
+        let lines = y_coordinate
+            ..(y_coordinate + header.compression.scan_lines_per_block() as i32)
+                .min(window.end().y());
+        let size: usize = lines
+            .clone()
+            .flat_map(|y| channels.iter().filter(move |c| line_has(c, y)))
+            .map(|c| window.size.x() / c.sampling.x() * c.sample_type.bytes_per_sample())
+            .sum();
+        let data = decompress(header.compression, compressed, size)?;
+
+        let mut data = data.as_slice();
+        for y in lines {
+            for (channel, samples) in channels.iter().zip(&mut samples) {
+                if !line_has(channel, y) {
+                    continue;
+                }
+                let count = window.size.x() / channel.sampling.x();
+                let start = (y - window.position.y()) as usize / channel.sampling.y() * count;
+                let bytes = count * channel.sample_type.bytes_per_sample();
+                if data.len() < bytes {
+                    return Err("Truncated scan line block".to_string());
+                }
+                let (line, rest) = data.split_at(bytes);
+                data = rest;
+                match samples {
+                    FlatSamples::F16(s) => {
+                        for (sample, b) in s[start..][..count].iter_mut().zip(line.chunks_exact(2))
+                        {
+                            *sample = f16::from_bits(u16::from_le_bytes([b[0], b[1]]))
+                        }
+                    }
+                    FlatSamples::F32(s) => {
+                        for (sample, b) in s[start..][..count].iter_mut().zip(line.chunks_exact(4))
+                        {
+                            *sample = f32::from_le_bytes(b.try_into().unwrap())
+                        }
+                    }
+                    FlatSamples::U32(s) => {
+                        for (sample, b) in s[start..][..count].iter_mut().zip(line.chunks_exact(4))
+                        {
+                            *sample = u32::from_le_bytes(b.try_into().unwrap())
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let list = channels
+        .iter()
+        .zip(samples)
+        .map(|(channel, sample_data)| AnyChannel {
+            name: channel.name.clone(),
+            sample_data,
+            quantize_linearly: channel.quantize_linearly,
+            sampling: channel.sampling,
+        })
+        .collect();
+    Ok(Image {
+        attributes,
+        layer_data: Layer {
+            channel_data: AnyChannels { list },
+            attributes: header.own_attributes,
+            size: header.layer_size,
+            encoding: Encoding {
+                compression: header.compression,
+                blocks: Blocks::ScanLines,
+                line_order: header.line_order,
+            },
+        },
+    })
+}
+
+/// Undo the compression of a block, only the lossless methods that do not depend on channel layout are supported
+fn decompress(
+    compression: Compression,
+    compressed: Vec<u8>,
+    size: usize,
+) -> Result<Vec<u8>, String> {
+    // Blocks that did not get smaller are stored as is
+    if compressed.len() == size {
+        return Ok(compressed);
+    }
+    let mut data = match compression {
+        Compression::Uncompressed => return Ok(compressed),
+        Compression::RLE => {
+            let mut data = Vec::with_capacity(size);
+            let mut remaining = compressed.as_slice();
+            while let [count, rest @ ..] = remaining {
+                let count = *count as i8 as isize;
+                if count < 0 {
+                    let count = (-count) as usize;
+                    if rest.len() < count {
+                        return Err("Truncated RLE data".to_string());
+                    }
+                    let (values, rest) = rest.split_at(count);
+                    data.extend_from_slice(values);
+                    remaining = rest;
+                } else {
+                    let [value, rest @ ..] = rest else {
+                        return Err("Truncated RLE data".to_string());
+                    };
+                    data.resize(data.len() + count as usize + 1, *value);
+                    remaining = rest;
+                }
+            }
+            data
+        }
+        Compression::ZIP1 | Compression::ZIP16 => {
+            let options = zune_inflate::DeflateOptions::default().set_limit(size);
+            zune_inflate::DeflateDecoder::new_with_options(&compressed, options)
+                .decode_zlib()
+                .map_err(|_| "Malformed ZIP data".to_string())?
+        }
+        other => {
+            return Err(format!(
+                "{} compression is not supported with subsampled channels",
+                other
+            ))
+        }
+    };
+    if data.len() != size {
+        return Err("Unexpected decompressed block size".to_string());
+    }
+
+    // Predictor, then interleave the two halves
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+    let (first, second) = data.split_at(size.div_ceil(2));
+    Ok((0..size)
+        .map(|i| {
+            if i % 2 == 0 {
+                first[i / 2]
+            } else {
+                second[i / 2]
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,61 @@
+// Floating-point TIFFs, as written by compositing and photo stitching tools for linear light
+
+use std::{io::Cursor, path::Path};
+
+use tiff::{
+    decoder::{Decoder, DecodingResult, Limits},
+    ColorType,
+};
+
+use crate::{decode::ExrImage, input_format::rgb_image};
+
+/// Read the first image of a TIFF with 32 or 64-bit float samples, gray or RGB. Alpha is dropped, gray is spread to every channel. Integer TIFFs are refused, as they are display-referred
+pub fn read(data: &[u8], path: &Path) -> Result<ExrImage, String> {
+    let error = |e: tiff::TiffError| format!("Could not decode {}: {}", path.display(), e);
+    // Float images of cameras and renders easily exceed the default limits
+    let mut decoder = Decoder::new(Cursor::new(data))
+        .map_err(error)?
+        .with_limits(Limits::unlimited());
+    let (width, height) = decoder.dimensions().map_err(error)?;
+    let (width, height) = (width as usize, height as usize);
+    let components = match decoder.colortype().map_err(error)? {
+        ColorType::Gray(_) => 1,
+        ColorType::GrayA(_) => 2,
+        ColorType::RGB(_) => 3,
+        ColorType::RGBA(_) => 4,
+        other => {
+            return Err(format!(
+                "{} has {:?} pixels, only gray and RGB are supported",
+                path.display(),
+                other
+            ))
+        }
+    };
+    let samples: Vec<f32> = match decoder.read_image().map_err(error)? {
+        DecodingResult::F32(samples) => samples,
+        DecodingResult::F64(samples) => samples.into_iter().map(|v| v as f32).collect(),
+        _ => {
+            return Err(format!(
+                "{} has integer samples, only floating-point TIFFs hold linear light",
+                path.display()
+            ))
+        }
+    };
+
+    let mut channels = [
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+        Vec::with_capacity(width * height),
+    ];
+    for pixel in samples.chunks_exact(components) {
+        let rgb = if components < 3 {
+            [pixel[0]; 3]
+        } else {
+            [pixel[0], pixel[1], pixel[2]]
+        };
+        for (channel, value) in channels.iter_mut().zip(rgb) {
+            channel.push(value);
+        }
+    }
+    Ok(rgb_image(width, height, channels, None))
+}
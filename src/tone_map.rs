@@ -0,0 +1,86 @@
+// Display rendering transforms, compressing HDR highlights into SDR for viewers without Gain Map support
+
+use clap::ValueEnum;
+
+use crate::{bt2446::Bt2446, color_spaces::REC_709, color_stuff::Pixel, Matrix3x3f};
+
+/// Mastering display peak BT.2446 Method A assumes without --peak-nits
+pub const DEFAULT_PEAK_NITS: f32 = 1000.0;
+
+/// Rec. 709 to ACES AP1, with the RRT's saturation adjustment folded in (Stephen Hill's fit)
+const ACES_INPUT: Matrix3x3f = Matrix3x3f::new(
+    0.59719, 0.35458, 0.04823, 0.07600, 0.90834, 0.01566, 0.02840, 0.13383, 0.83777,
+);
+/// ACES AP1 back to Rec. 709, with the ODT's desaturation folded in
+const ACES_OUTPUT: Matrix3x3f = Matrix3x3f::new(
+    1.60475, -0.53108, -0.07367, -0.10208, 1.10813, -0.00605, -0.00327, -0.07276, 1.07602,
+);
+
+/// How HDR linear light is rendered for SDR displays
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum ToneMap {
+    /// Fit of the ACES Reference Rendering Transform and sRGB Output Device Transform: filmic contrast, highlights rolling off and desaturating to white
+    Aces,
+    /// ITU-R BT.2446 Method A, broadcast down-conversion of content mastered up to --peak-nits
+    #[value(name = "bt2446a")]
+    Bt2446A,
+    /// ITU-R BT.2446 Method C, linear up to an inflection point then logarithmic
+    #[value(name = "bt2446c")]
+    Bt2446C,
+}
+
+impl ToneMap {
+    /// Display linear light in 0.0 - 1.0 of Rec. 709 linear light, 1.0 being SDR white. `peak_nits` is the mastering peak of BT.2446 Method A
+    pub fn render(&self, pixel: Pixel, peak_nits: Option<f32>) -> Pixel {
+        let coefficients = REC_709.luminance_values().unwrap();
+        let rendered = match self {
+            ToneMap::Aces => aces(pixel),
+            ToneMap::Bt2446A => DownConversion::Bt2446A
+                .method(peak_nits)
+                .apply(pixel, &coefficients),
+            ToneMap::Bt2446C => Bt2446::C.apply(pixel, &coefficients),
+        };
+        Pixel {
+            r: rendered.r.clamp(0.0, 1.0),
+            g: rendered.g.clamp(0.0, 1.0),
+            b: rendered.b.clamp(0.0, 1.0),
+        }
+    }
+}
+
+fn aces(pixel: Pixel) -> Pixel {
+    let ap1 = pixel.transform(&ACES_INPUT);
+    let curve = |v: f32| {
+        let v = v.max(0.0);
+        (v * (v + 0.0245786) - 0.000090537) / (v * (0.983729 * v + 0.432951) + 0.238081)
+    };
+    Pixel {
+        r: curve(ap1.r),
+        g: curve(ap1.g),
+        b: curve(ap1.b),
+    }
+    .transform(&ACES_OUTPUT)
+}
+
+/// Down-conversions of the SDR base image
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum DownConversion {
+    /// ITU-R BT.2446 Method A, for content mastered up to --peak-nits
+    #[value(name = "bt2446a")]
+    Bt2446A,
+    /// ITU-R BT.2446 Method C
+    #[value(name = "bt2446c")]
+    Bt2446C,
+}
+
+impl DownConversion {
+    /// Method A needs the mastering peak, 1000 nits if unknown
+    pub fn method(self, peak_nits: Option<f32>) -> Bt2446 {
+        match self {
+            DownConversion::Bt2446A => Bt2446::A {
+                peak_nits: peak_nits.unwrap_or(DEFAULT_PEAK_NITS),
+            },
+            DownConversion::Bt2446C => Bt2446::C,
+        }
+    }
+}
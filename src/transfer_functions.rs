@@ -1,13 +1,146 @@
-// https://en.wikipedia.org/wiki/SRGB
-// There is another definition in the ITU document...
-pub fn _srgb_gamma(linear_color: f32) -> f32 {
-    if linear_color <= 0.0031308 {
-        12.92 * linear_color
-    } else {
-        1.055 * linear_color.powf(2.4f32.recip()) - 0.055
+use clap::ValueEnum;
+use num_traits::Float;
+use ultra_hdr_core::transfer::{
+    bt1886_constants, bt1886_eotf, bt1886_inverse_eotf, gamma, hlg_inverse_oetf, pq_eotf,
+    srgb_gamma, srgb_inverse_gamma,
+};
+
+use crate::color_stuff::LuminanceCoefficients;
+
+/// Black level of the display BT.1886 output is encoded for, relative to white: 1000:1 contrast, such as 0.1 nits for 100 nits white
+pub const BT1886_BLACK: f32 = 0.001;
+
+/// Parse `start,strength` of the highlight shoulder applied before encoding SDR outputs
+pub fn parse_knee(text: &str) -> Result<(f32, f32), String> {
+    let (start, strength) = text
+        .split_once(',')
+        .ok_or_else(|| "expected START,STRENGTH".to_string())?;
+    let parse = |n: &str| {
+        n.trim()
+            .parse::<f32>()
+            .map_err(|e| format!("invalid number {:?}: {}", n, e))
+    };
+    let (start, strength) = (parse(start)?, parse(strength)?);
+    if !(0.0..1.0).contains(&start) {
+        return Err("knee start must be at least 0 and below 1".to_string());
     }
+    if !(0.0..=1.0).contains(&strength) {
+        return Err("knee strength must be between 0 and 1".to_string());
+    }
+    Ok((start, strength))
+}
+
+/// Transfer function used to encode display-referred outputs. Discriminants are read by the GPU shader
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum Transfer {
+    /// Pure 2.4 power curve
+    Gamma24,
+    /// Piecewise sRGB curve, with a linear segment near black
+    Srgb,
+    /// Pure 2.2 power curve, what most computer displays actually apply
+    Gamma22,
+    /// Inverse of the BT.1886 EOTF of video displays, lifting black to the display's black level instead of crushing shadows below it
+    Bt1886,
 }
 
-pub fn gamma(linear_color: f32, gamma: f32) -> f32 {
-    linear_color.powf(gamma.recip())
+impl Transfer {
+    /// Encode a linear value in 0.0 - 1.0
+    pub fn encode<F: Float>(&self, linear_color: F) -> F {
+        let value = |v: f32| F::from(v).unwrap();
+        match self {
+            Transfer::Gamma24 => gamma(linear_color, value(2.4)),
+            Transfer::Srgb => srgb_gamma(linear_color),
+            Transfer::Gamma22 => gamma(linear_color, value(2.2)),
+            Transfer::Bt1886 => bt1886_inverse_eotf(linear_color, value(BT1886_BLACK)),
+        }
+    }
+
+    /// Decode an encoded value in 0.0 - 1.0 back to linear
+    pub fn decode(&self, encoded: f32) -> f32 {
+        match self {
+            Transfer::Gamma24 => encoded.powf(2.4),
+            Transfer::Srgb => srgb_inverse_gamma(encoded),
+            Transfer::Gamma22 => encoded.powf(2.2),
+            Transfer::Bt1886 => bt1886_eotf(encoded, BT1886_BLACK),
+        }
+    }
+
+    /// Closest pure power curve, for formats only able to describe those
+    pub fn approximate_gamma(&self) -> f32 {
+        match self {
+            Transfer::Gamma24 | Transfer::Bt1886 => 2.4,
+            Transfer::Srgb | Transfer::Gamma22 => 2.2,
+        }
+    }
+
+    /// ICC parametricCurveType function type and parameters (g, a, b, c, d)
+    pub fn icc_parameters(&self) -> (u16, Vec<f64>) {
+        match self {
+            Transfer::Gamma24 => (0, vec![2.4]),
+            Transfer::Srgb => (
+                3,
+                vec![2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045],
+            ),
+            Transfer::Gamma22 => (0, vec![2.2]),
+            // (aX + b)^g + c, the BT.1886 EOTF rescaled so black is 0.0
+            Transfer::Bt1886 => {
+                let black = BT1886_BLACK as f64;
+                let (a, b) = bt1886_constants(BT1886_BLACK);
+                let scale = (a as f64 / (1.0 - black)).powf(2.4f64.recip());
+                (
+                    2,
+                    vec![2.4, scale, scale * b as f64, -black / (1.0 - black)],
+                )
+            }
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Transfer::Gamma24 => "gamma 2.4",
+            Transfer::Srgb => "sRGB transfer",
+            Transfer::Gamma22 => "gamma 2.2",
+            Transfer::Bt1886 => "BT.1886",
+        }
+    }
+}
+
+/// Transfer function of HDR video signals, decoded when ingesting frames
+#[derive(ValueEnum, Debug, Copy, Clone)]
+pub enum HdrTransfer {
+    /// Perceptual Quantizer (SMPTE ST 2084), absolute luminance
+    Pq,
+    /// Hybrid Log-Gamma, relative to display peak
+    Hlg,
+}
+
+/// Peak luminance of the display HLG is rendered for, the BT.2100 reference
+const HLG_DISPLAY_PEAK_NITS: f32 = 1000.0;
+/// HLG system gamma for a 1000 nit display
+const HLG_SYSTEM_GAMMA: f32 = 1.2;
+
+impl HdrTransfer {
+    /// Transfer from a CICP transfer characteristics code point
+    pub fn from_cicp(transfer_characteristics: u8) -> Option<HdrTransfer> {
+        match transfer_characteristics {
+            16 => Some(HdrTransfer::Pq),
+            18 => Some(HdrTransfer::Hlg),
+            _ => None,
+        }
+    }
+
+    /// Decode a non-linear RGB signal in 0.0 - 1.0 to display light in nits. HLG includes the OOTF, so needs luminance coefficients of the signal primaries
+    pub fn decode_nits(&self, rgb: [f32; 3], coefficients: &LuminanceCoefficients) -> [f32; 3] {
+        match self {
+            HdrTransfer::Pq => rgb.map(pq_eotf),
+            HdrTransfer::Hlg => {
+                let scene = rgb.map(hlg_inverse_oetf);
+                let luminance = scene[0] * coefficients.red
+                    + scene[1] * coefficients.green
+                    + scene[2] * coefficients.blue;
+                let scale = HLG_DISPLAY_PEAK_NITS * luminance.powf(HLG_SYSTEM_GAMMA - 1.0);
+                scene.map(|v| v * scale)
+            }
+        }
+    }
 }
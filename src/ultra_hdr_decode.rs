@@ -0,0 +1,83 @@
+// Ultra HDR JPEGs decoded back to scene-referred EXRs, for grading or re-rendering them where only the JPEG is left
+
+use std::path::PathBuf;
+
+use clap::Args;
+use exr::prelude::{
+    Blocks, Compression, Encoding, Image, Layer, LayerAttributes, LineOrder, SpecificChannels,
+    WritableImage,
+};
+use tracing::info;
+
+use crate::{color_stuff::to_exr_chromaticities, parity::UltraHdrImage, SDR_WHITE_NITS};
+
+/// Decode an Ultra HDR JPEG to a linear EXR, 1.0 being SDR white, with its Gain Map applied at full HDR capacity. Chromaticities and transfer of the primary image are read from its ICC profile
+#[derive(Args)]
+pub struct DecodeArgs {
+    /// Ultra HDR JPEG to decode
+    input: PathBuf,
+    /// Where to write the EXR
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Compression of the EXR: zip or piz, both lossless. dwaa:<level> is not supported, as the EXR writer cannot encode DWAA
+    #[arg(long, value_parser = parse_exr_compression, default_value = "zip")]
+    exr_compression: Compression,
+    /// Luminance of SDR white in nits, written as whiteLuminance
+    #[arg(long, default_value_t = SDR_WHITE_NITS)]
+    white_nits: f32,
+}
+
+/// Parse `zip` or `piz`. DWAA and DWAB are rejected whatever their level, the EXR writer having no lossy codecs
+fn parse_exr_compression(text: &str) -> Result<Compression, String> {
+    let text = text.to_ascii_lowercase();
+    match text.as_str() {
+        "zip" => Ok(Compression::ZIP16),
+        "piz" => Ok(Compression::PIZ),
+        _ if text.starts_with("dwaa") || text.starts_with("dwab") => Err(
+            "DWAA and DWAB compression are not supported by the EXR writer, use zip or piz"
+                .to_string(),
+        ),
+        _ => Err(format!("{:?} is not zip or piz", text)),
+    }
+}
+
+pub fn run(args: &DecodeArgs) -> Result<(), String> {
+    let image = UltraHdrImage::read(&args.input)?;
+    let (width, height) = (image.width, image.height);
+    info!(file = %args.input.display(), width, height, "Reconstructing HDR rendition of Ultra HDR JPEG");
+    let pixels: Vec<(f32, f32, f32)> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let pixel = image.reconstruct(x, y);
+            (pixel.r, pixel.g, pixel.b)
+        })
+        .collect();
+
+    let channels = SpecificChannels::rgb(|position: exr::math::Vec2<usize>| {
+        pixels[position.y() * width + position.x()]
+    });
+    let encoding = Encoding {
+        compression: args.exr_compression,
+        blocks: Blocks::ScanLines,
+        line_order: LineOrder::Increasing,
+    };
+    let mut exr = Image::from_layer(Layer::new(
+        (width, height),
+        LayerAttributes {
+            white_luminance: Some(args.white_nits),
+            ..LayerAttributes::default()
+        },
+        encoding,
+        channels,
+    ));
+    exr.attributes.chromaticities = Some(to_exr_chromaticities(image.chromaticities));
+    exr.write()
+        .to_file(&args.output)
+        .map_err(|e| format!("Could not write {}: {}", args.output.display(), e))?;
+    info!(
+        path = %args.output.display(),
+        compression = ?args.exr_compression,
+        "Wrote decoded EXR"
+    );
+    Ok(())
+}
@@ -0,0 +1,33 @@
+// Ultra HDR JPEGs read back as their HDR rendition, to re-encode them with other settings
+
+use std::path::Path;
+
+use tracing::info;
+
+use crate::{decode::ExrImage, input_format::rgb_image, parity::UltraHdrImage};
+
+/// Read an Ultra HDR JPEG as linear light where 1.0 is SDR white, with its Gain Map applied at full HDR capacity. The primary image is decoded with the transfer and primaries of its ICC profile, sRGB if it has none known
+pub fn read(data: &[u8], path: &Path) -> Result<ExrImage, String> {
+    let image = UltraHdrImage::parse(data, path)?;
+    info!(file = %path.display(), "Reconstructing HDR rendition of Ultra HDR JPEG");
+
+    let mut channels = [
+        Vec::with_capacity(image.width * image.height),
+        Vec::with_capacity(image.width * image.height),
+        Vec::with_capacity(image.width * image.height),
+    ];
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let pixel = image.reconstruct(x, y);
+            for (channel, value) in channels.iter_mut().zip([pixel.r, pixel.g, pixel.b]) {
+                channel.push(value);
+            }
+        }
+    }
+    Ok(rgb_image(
+        image.width,
+        image.height,
+        channels,
+        Some(image.chromaticities),
+    ))
+}
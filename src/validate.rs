@@ -0,0 +1,320 @@
+// Structural checks of Ultra HDR JPEGs, reporting where in the file each problem is
+
+use std::{fs, path::PathBuf};
+
+use clap::Args;
+use tracing::{error, info};
+
+use crate::{
+    mpf, parity,
+    recovery_curve::RecoveryCurve,
+    ultra_hdr_stuff::XMP_NAMESPACE,
+    xmp::{self, GainMapXmp},
+};
+
+const SOI_MARKER: u8 = 0xD8;
+const EOI_MARKER: u8 = 0xD9;
+const SOS_MARKER: u8 = 0xDA;
+const APP1_MARKER: u8 = 0xE1;
+/// Start Of Frame markers, holding image dimensions. C4, C8 and CC are other markers
+const SOF_MARKERS: [u8; 13] = [
+    0xC0, 0xC1, 0xC2, 0xC3, 0xC5, 0xC6, 0xC7, 0xC9, 0xCA, 0xCB, 0xCD, 0xCE, 0xCF,
+];
+
+/// Check the structure of Ultra HDR JPEG files: primary image and Gain Map streams, decoded, GContainer directory, MPF index and Gain Map metadata. Exits with an error if any file fails
+#[derive(Args)]
+pub struct ValidateArgs {
+    /// Ultra HDR JPEG files to check
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
+
+/// A problem found in a file, at a byte offset from its start
+struct Failure {
+    offset: usize,
+    message: String,
+}
+
+impl Failure {
+    fn new(offset: usize, message: impl Into<String>) -> Failure {
+        Failure {
+            offset,
+            message: message.into(),
+        }
+    }
+}
+
+/// A JPEG stream walked marker by marker up to its end, without decoding entropy-coded data
+struct Stream {
+    start: usize,
+    /// Just after the EOI marker
+    end: usize,
+    width: usize,
+    height: usize,
+    /// Offset of the SOF segment
+    frame_offset: usize,
+    /// First XMP packet and the offset of its segment
+    xmp: Option<(String, usize)>,
+}
+
+pub fn run(args: &ValidateArgs) -> Result<(), String> {
+    let mut failed = 0;
+    for path in &args.files {
+        let data =
+            fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let failures = check(&data);
+        if failures.is_empty() {
+            info!(file = %path.display(), "Valid Ultra HDR JPEG");
+            continue;
+        }
+        for failure in failures {
+            error!(file = %path.display(), offset = failure.offset, "{}", failure.message);
+        }
+        failed += 1;
+    }
+
+    if failed > 0 {
+        Err(format!(
+            "{} of {} files failed validation",
+            failed,
+            args.files.len()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Every problem found in an Ultra HDR JPEG file, stopping at those preventing further checks
+fn check(data: &[u8]) -> Vec<Failure> {
+    let mut failures = Vec::new();
+    let primary = match walk(data, 0) {
+        Ok(primary) => primary,
+        Err(failure) => return vec![failure],
+    };
+    failures.extend(decode_stream(data, &primary, "Primary image"));
+
+    // Where the GContainer directory places the Gain Map
+    let Some((packet, xmp_offset)) = &primary.xmp else {
+        failures.push(Failure::new(0, "No XMP in primary image"));
+        return failures;
+    };
+    let items = match xmp::parse(packet).and_then(|p| xmp::container_items(&p)) {
+        Ok(items) => items,
+        Err(e) => {
+            failures.push(Failure::new(*xmp_offset, format!("Primary XMP: {}", e)));
+            return failures;
+        }
+    };
+    let Some(index) = items.iter().position(|i| i.semantic == "GainMap") else {
+        failures.push(Failure::new(
+            *xmp_offset,
+            "No GainMap item in GContainer directory",
+        ));
+        return failures;
+    };
+    // The first item is the primary image, items after it follow its stream
+    if index == 0 {
+        failures.push(Failure::new(
+            *xmp_offset,
+            "GainMap is the first item of the GContainer directory, where the primary image belongs",
+        ));
+        return failures;
+    }
+    let mut gain_map_start = primary.end;
+    for item in &items[1..index] {
+        match item.length {
+            Some(length) => gain_map_start += length as usize,
+            None => {
+                failures.push(Failure::new(
+                    *xmp_offset,
+                    format!("{} item has no Item:Length", item.semantic),
+                ));
+                return failures;
+            }
+        }
+    }
+    let gain_map = match walk(data, gain_map_start) {
+        Ok(gain_map) => gain_map,
+        Err(failure) => {
+            failures.push(failure);
+            return failures;
+        }
+    };
+    failures.extend(decode_stream(data, &gain_map, "Gain Map"));
+    let gain_map_len = gain_map.end - gain_map.start;
+    match items[index].length {
+        Some(length) if length as usize != gain_map_len => failures.push(Failure::new(
+            *xmp_offset,
+            format!(
+                "GainMap Item:Length is {} bytes, the Gain Map stream at offset {} is {} bytes",
+                length, gain_map.start, gain_map_len
+            ),
+        )),
+        None => failures.push(Failure::new(*xmp_offset, "GainMap item has no Item:Length")),
+        _ => {}
+    }
+
+    // The MPF index, if any, must agree with the directory
+    match mpf::images(data) {
+        Ok(Some(images)) => match images.get(1) {
+            Some(image) if *image != (gain_map.start..gain_map.end) => failures.push(Failure::new(
+                image.start,
+                format!(
+                    "MPF locates the second image at {}..{}, the Gain Map stream is at {}..{}",
+                    image.start, image.end, gain_map.start, gain_map.end
+                ),
+            )),
+            Some(_) => {}
+            None => failures.push(Failure::new(0, "MPF index lists no Gain Map image")),
+        },
+        Ok(None) => {}
+        Err(e) => failures.push(Failure::new(0, format!("MPF index: {}", e))),
+    }
+
+    // Gain Map is the primary image scaled down by an integer, dimensions rounded up as libultrahdr does
+    let scaled = |scale: usize| {
+        (
+            primary.width.div_ceil(scale),
+            primary.height.div_ceil(scale),
+        )
+    };
+    let integral = gain_map.width > 0
+        && (primary.width.div_ceil(gain_map.width)..=primary.width)
+            .take_while(|&scale| scaled(scale).0 == gain_map.width)
+            .any(|scale| scaled(scale) == (gain_map.width, gain_map.height));
+    if !integral {
+        failures.push(Failure::new(
+            gain_map.frame_offset,
+            format!(
+                "Gain Map is {}x{}, not an integral fraction of the {}x{} primary image",
+                gain_map.width, gain_map.height, primary.width, primary.height
+            ),
+        ));
+    }
+
+    // Gain Map metadata
+    let Some((packet, xmp_offset)) = &gain_map.xmp else {
+        failures.push(Failure::new(gain_map.start, "No XMP in Gain Map image"));
+        return failures;
+    };
+    match xmp::parse(packet).and_then(|p| GainMapXmp::from_properties(&p)) {
+        Ok(Some(metadata)) => {
+            let gain_map_max = metadata.gain_map_max.into_iter().fold(f32::MIN, f32::max);
+            if metadata.hdr_capacity_max < gain_map_max {
+                failures.push(Failure::new(
+                    *xmp_offset,
+                    format!(
+                        "hdrgm:HDRCapacityMax ({}) is below hdrgm:GainMapMax ({})",
+                        metadata.hdr_capacity_max, gain_map_max
+                    ),
+                ))
+            }
+            // The PQ curve stands in for hdrgm:Gamma, and needs a range of boosts to spread over
+            if let RecoveryCurve::Pq = metadata.recovery_curve {
+                if metadata.gamma != [1.0; 3] {
+                    failures.push(Failure::new(
+                        *xmp_offset,
+                        format!(
+                            "PQ recovery curve with hdrgm:Gamma {:?}, it must be 1",
+                            metadata.gamma
+                        ),
+                    ))
+                }
+                let gain_map_min = metadata.gain_map_min.into_iter().fold(f32::MAX, f32::min);
+                if gain_map_max <= gain_map_min {
+                    failures.push(Failure::new(
+                        *xmp_offset,
+                        "PQ recovery curve with hdrgm:GainMapMax not above hdrgm:GainMapMin",
+                    ))
+                }
+                info!("Gain Map uses the PQ recovery curve, only decoded by exr2ultra-hdr");
+            }
+        }
+        Ok(None) => failures.push(Failure::new(
+            *xmp_offset,
+            "No hdrgm:GainMapMax in Gain Map XMP",
+        )),
+        Err(e) => failures.push(Failure::new(*xmp_offset, format!("Gain Map XMP: {}", e))),
+    }
+    failures
+}
+
+/// Decode the entropy-coded data of a walked stream, which walking skips over. A failure is reported at the start of the stream
+fn decode_stream(data: &[u8], stream: &Stream, name: &str) -> Option<Failure> {
+    parity::decode(&data[stream.start..stream.end])
+        .err()
+        .map(|e| Failure::new(stream.start, format!("{}: {}", name, e)))
+}
+
+/// Walk the JPEG stream starting at `start` through its segments and scans up to EOI
+fn walk(data: &[u8], start: usize) -> Result<Stream, Failure> {
+    if data.get(start..start + 2) != Some(&[0xFF, SOI_MARKER]) {
+        return Err(Failure::new(start, "No JPEG stream starts here"));
+    }
+    let truncated = |offset: usize| Failure::new(offset, "JPEG stream is truncated");
+
+    let mut stream = Stream {
+        start,
+        end: 0,
+        width: 0,
+        height: 0,
+        frame_offset: 0,
+        xmp: None,
+    };
+    let mut position = start + 2;
+    let mut in_scan = false;
+    loop {
+        // Entropy-coded data, where 0xFF is followed by a stuffed 0 or a restart marker
+        if in_scan {
+            loop {
+                match data.get(position..position + 2) {
+                    Some(&[0xFF, 0x00 | 0xD0..=0xD7]) => position += 2,
+                    Some(&[0xFF, _]) => break,
+                    Some(_) => position += 1,
+                    None => return Err(truncated(position)),
+                }
+            }
+            in_scan = false;
+        }
+
+        // Skip fill bytes
+        while data.get(position + 1) == Some(&0xFF) {
+            position += 1;
+        }
+        match data.get(position..position + 2) {
+            Some(&[0xFF, EOI_MARKER]) => {
+                stream.end = position + 2;
+                break;
+            }
+            Some(&[0xFF, _]) => {}
+            Some(_) => return Err(Failure::new(position, "Expected a JPEG marker")),
+            None => return Err(truncated(position)),
+        }
+        let marker = data[position + 1];
+        let length = data
+            .get(position + 2..position + 4)
+            .ok_or_else(|| truncated(position))?;
+        let end = position + 2 + u16::from_be_bytes([length[0], length[1]]) as usize;
+        let payload = data
+            .get(position + 4..end)
+            .ok_or_else(|| truncated(position))?;
+
+        if SOF_MARKERS.contains(&marker) && payload.len() >= 5 {
+            stream.height = u16::from_be_bytes([payload[1], payload[2]]) as usize;
+            stream.width = u16::from_be_bytes([payload[3], payload[4]]) as usize;
+            stream.frame_offset = position;
+        } else if marker == APP1_MARKER && stream.xmp.is_none() {
+            if let Some(packet) = payload.strip_prefix(XMP_NAMESPACE) {
+                stream.xmp = Some((String::from_utf8_lossy(packet).into_owned(), position));
+            }
+        } else if marker == SOS_MARKER {
+            in_scan = true;
+        }
+        position = end;
+    }
+
+    if stream.width == 0 || stream.height == 0 {
+        return Err(Failure::new(start, "JPEG stream has no image dimensions"));
+    }
+    Ok(stream)
+}
@@ -0,0 +1,121 @@
+// https://hajim.rochester.edu/ece/sites/gsharma/ciede2000/ciede2000noteCRNA.pdf
+
+use rcms::{link::link, profile::Intent, IccProfile};
+use tracing::{info, warn};
+
+use crate::{
+    color_stuff::{delta_e_2000, lab, to_cms_xyy, CIEXYZCoords, Chromaticities},
+    Matrix3x1f, Matrix3x3f,
+};
+
+/// Samples per axis of the RGB cube being checked
+const GRID_STEPS: usize = 9;
+/// Differences below this are not noticeable
+const NOTICEABLE_DELTA_E: f32 = 1.0;
+
+/// Outcome of comparing built-in matrix conversion with rcms
+pub struct ColorVerification {
+    /// Largest CIEDE2000 difference found
+    pub max_delta_e: f32,
+    /// Input RGB of the sample with largest difference
+    pub worst_sample: [f32; 3],
+    /// Samples compared
+    pub samples: usize,
+    /// Samples left out because they fall outside of output gamut, where rcms clips
+    pub out_of_gamut: usize,
+}
+
+/// Log how far built-in conversion is from rcms, warning if the difference is noticeable
+pub fn report(input: &Chromaticities, output: &Chromaticities, matrix: &Matrix3x3f) {
+    let Some(verification) = verify_conversion(input, output, matrix) else {
+        warn!("Could not build reference transform to verify colors");
+        return;
+    };
+    if verification.max_delta_e > NOTICEABLE_DELTA_E {
+        warn!(
+            max_delta_e = verification.max_delta_e,
+            worst_sample = ?verification.worst_sample,
+            samples = verification.samples,
+            out_of_gamut = verification.out_of_gamut,
+            "Color conversion differs noticeably from reference CMS"
+        )
+    } else {
+        info!(
+            max_delta_e = verification.max_delta_e,
+            samples = verification.samples,
+            out_of_gamut = verification.out_of_gamut,
+            "Color conversion matches reference CMS"
+        )
+    }
+}
+
+/// Convert a grid of linear RGB values with the given matrix and with a relative colorimetric rcms transform between linear profiles, and measure how far apart results are in output space
+pub fn verify_conversion(
+    input: &Chromaticities,
+    output: &Chromaticities,
+    matrix: &Matrix3x3f,
+) -> Option<ColorVerification> {
+    let transform = link(
+        &[&linear_profile(input)?, &linear_profile(output)?],
+        &[Intent::RelativeColorimetric, Intent::RelativeColorimetric],
+        &[false, false],
+        &[0.0, 0.0],
+    )
+    .ok()?;
+    let to_xyz = output.rgb_to_xyz_matrix()?;
+    let white: CIEXYZCoords = output.white.with_luma(1.0).into();
+
+    let mut verification = ColorVerification {
+        max_delta_e: 0.0,
+        worst_sample: [0.0; 3],
+        samples: 0,
+        out_of_gamut: 0,
+    };
+    let step = |index: usize| index as f32 / (GRID_STEPS - 1) as f32;
+    for r in 0..GRID_STEPS {
+        for g in 0..GRID_STEPS {
+            for b in 0..GRID_STEPS {
+                let sample = [step(r), step(g), step(b)];
+
+                let built_in = matrix * Matrix3x1f::new(sample[0], sample[1], sample[2]);
+                if built_in.iter().any(|v| !(0.0..=1.0).contains(v)) {
+                    verification.out_of_gamut += 1;
+                    continue;
+                }
+
+                let mut reference = [0.0f64; 3];
+                transform.transform(&sample.map(|v| v as f64), &mut reference);
+                let reference = Matrix3x1f::new(
+                    reference[0] as f32,
+                    reference[1] as f32,
+                    reference[2] as f32,
+                );
+
+                let delta_e = delta_e_2000(
+                    lab((to_xyz * built_in).into(), white),
+                    lab((to_xyz * reference).into(), white),
+                );
+                verification.samples += 1;
+                if delta_e > verification.max_delta_e {
+                    verification.max_delta_e = delta_e;
+                    verification.worst_sample = sample;
+                }
+            }
+        }
+    }
+
+    Some(verification)
+}
+
+/// ICC profile without transfer function, matching linear-light values
+fn linear_profile(chromaticities: &Chromaticities) -> Option<IccProfile> {
+    IccProfile::new_rgb(
+        to_cms_xyy(chromaticities.white.with_luma(1.0)),
+        (
+            to_cms_xyy(chromaticities.red.with_luma(1.0)),
+            to_cms_xyy(chromaticities.green.with_luma(1.0)),
+            to_cms_xyy(chromaticities.blue.with_luma(1.0)),
+        ),
+        1.0,
+    )
+}
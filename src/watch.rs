@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    panic::{catch_unwind, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Receiver, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use notify::{recommended_watcher, EventKind, RecursiveMode, Watcher};
+use tracing::{error, info, warn};
+
+use crate::{convert, App};
+
+/// How often pending files are checked for debounce expiry
+const TICK: Duration = Duration::from_millis(100);
+
+/// Convert EXR files as they appear in a directory, until watching stops
+pub fn run(args: &App, directory: &Path) -> Result<(), String> {
+    let (event_sender, events) = channel();
+    // Events stop once the watcher is dropped
+    let _watcher = recommended_watcher(event_sender)
+        .and_then(|mut watcher| {
+            watcher.watch(directory, RecursiveMode::NonRecursive)?;
+            Ok(watcher)
+        })
+        .map_err(|e| format!("Could not watch {}: {}", directory.display(), e))?;
+
+    let debounce = Duration::from_millis(args.watch_debounce);
+    let (job_sender, jobs) = channel::<PathBuf>();
+    let jobs = Mutex::new(jobs);
+
+    info!(directory = %directory.display(), "Watching for new EXR files");
+
+    thread::scope(|scope| {
+        for _ in 0..args.jobs.max(1) {
+            scope.spawn(|| worker(args, &jobs));
+        }
+
+        // Files that changed recently, with time of last change. Renderers write frames progressively so wait for them to settle
+        let mut pending: HashMap<PathBuf, Instant> = HashMap::new();
+        loop {
+            match events.recv_timeout(TICK) {
+                Ok(Ok(event)) => {
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        for path in event.paths.into_iter().filter(|p| is_exr(p)) {
+                            pending.insert(path, Instant::now());
+                        }
+                    }
+                }
+                Ok(Err(e)) => warn!(error = %e, "Watch error"),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            let settled: Vec<PathBuf> = pending
+                .iter()
+                .filter(|(_, last_change)| last_change.elapsed() >= debounce)
+                .map(|(path, _)| path.clone())
+                .collect();
+            for path in settled {
+                pending.remove(&path);
+                job_sender.send(path).unwrap();
+            }
+        }
+
+        drop(job_sender)
+    });
+    Ok(())
+}
+
+/// Convert files one at a time until there are no more jobs. A failing file does not stop the worker
+fn worker(args: &App, jobs: &Mutex<Receiver<PathBuf>>) {
+    loop {
+        let job = jobs.lock().unwrap().recv();
+        let Ok(path) = job else {
+            return;
+        };
+
+        let outputs = args.outputs.in_directories(&path);
+        info!(file = %path.display(), "Converting");
+        match catch_unwind(AssertUnwindSafe(|| convert(args, &path, &outputs, None))) {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => error!(file = %path.display(), error = e, "Failed to convert"),
+            Err(_) => error!(file = %path.display(), "Failed to convert"),
+        }
+    }
+}
+
+fn is_exr(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("exr"))
+}
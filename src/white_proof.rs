@@ -0,0 +1,69 @@
+// https://en.wikipedia.org/wiki/Standard_illuminant#Computation
+// Proof of the SDR rendition as seen by an eye adapted to another white, such as prints under warmer viewing light
+
+use crate::{
+    chromatic_adaptation::Cat,
+    color_stuff::{CIExyCoords, Chromaticities, Pixel},
+    planar::{PlanarRgb, PlanarView},
+    process_pixel,
+    transfer_functions::Transfer,
+};
+
+/// Range of the daylight locus approximation
+const KELVIN_RANGE: std::ops::RangeInclusive<f32> = 4000.0..=25000.0;
+
+/// Viewing white on the daylight locus, given by its correlated color temperature
+#[derive(Debug, Copy, Clone)]
+pub struct SimulatedWhite {
+    pub kelvin: f32,
+}
+
+/// Parse a correlated color temperature such as 5000K or 5000
+pub fn parse_simulated_white(text: &str) -> Result<SimulatedWhite, String> {
+    let number = text.trim().trim_end_matches(['K', 'k']);
+    let kelvin: f32 = number
+        .parse()
+        .map_err(|e| format!("{:?} is not a temperature: {}", text, e))?;
+    if !KELVIN_RANGE.contains(&kelvin) {
+        return Err(format!(
+            "{}K is outside of the daylight locus, {}K to {}K",
+            kelvin,
+            KELVIN_RANGE.start(),
+            KELVIN_RANGE.end()
+        ));
+    }
+    Ok(SimulatedWhite { kelvin })
+}
+
+impl SimulatedWhite {
+    pub fn white(&self) -> CIExyCoords {
+        CIExyCoords::from_black_body(self.kelvin)
+    }
+
+    /// Suffix of proof file names, such as _5000K
+    pub fn suffix(&self) -> String {
+        format!("_{}K", self.kelvin)
+    }
+
+    /// SDR image `image`, encoded with `transfer` in `chromaticities`, with its colors adapted from the display white to this one through `cat`. Colors leaving the gamut are clipped
+    pub fn proof(
+        &self,
+        image: PlanarView,
+        chromaticities: &Chromaticities,
+        transfer: Transfer,
+        cat: Cat,
+    ) -> PlanarRgb {
+        let matrix = chromaticities.xyz_to_rgb_matrix().unwrap()
+            * cat.adaptation_matrix(chromaticities.white, self.white())
+            * chromaticities.rgb_to_xyz_matrix().unwrap();
+        image
+            .pixels()
+            .map(|rgb| {
+                let [r, g, b] = rgb.map(|v| transfer.decode(v as f32 / 255.0));
+                let adapted = Pixel { r, g, b }.transform(&matrix);
+                [adapted.r, adapted.g, adapted.b]
+                    .map(|v| process_pixel(v.clamp(0.0, 1.0), transfer))
+            })
+            .collect()
+    }
+}
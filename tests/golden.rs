@@ -0,0 +1,1927 @@
+//! Golden-output tests: synthetic EXRs are converted with `--deterministic`, then output hashes and gain map metadata are compared with `tests/golden/*.txt`.
+//!
+//! After an intended output change, regenerate expected files with `UPDATE_GOLDEN=1 cargo test`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use exr::{
+    meta::attribute::Preview,
+    prelude::{
+        read_first_rgba_layer_from_file, write_rgb_file, Encoding, Image, ImageAttributes,
+        IntegerBounds, Layer, LayerAttributes, SpecificChannels, Vec2, WritableImage,
+    },
+};
+use tiff::encoder::{colortype, TiffEncoder};
+
+const WIDTH: usize = 96;
+const HEIGHT: usize = 64;
+
+// ----- Generators
+
+/// Horizontal ramp from black to 4.0, with a vertical hue change
+fn gradient(x: usize, y: usize) -> (f32, f32, f32) {
+    let v = x as f32 / (WIDTH - 1) as f32 * 4.0;
+    let t = y as f32 / (HEIGHT - 1) as f32;
+    (v * t, v * 0.5, v * (1.0 - t))
+}
+
+// https://en.wikipedia.org/wiki/ColorChecker (linear sRGB, approximate)
+const COLOR_CHECKER: [(f32, f32, f32); 24] = [
+    (0.171, 0.084, 0.057),
+    (0.545, 0.300, 0.223),
+    (0.120, 0.194, 0.336),
+    (0.104, 0.150, 0.054),
+    (0.231, 0.218, 0.437),
+    (0.132, 0.518, 0.409),
+    (0.680, 0.197, 0.025),
+    (0.071, 0.106, 0.392),
+    (0.535, 0.084, 0.121),
+    (0.108, 0.043, 0.141),
+    (0.342, 0.502, 0.045),
+    (0.752, 0.364, 0.021),
+    (0.031, 0.048, 0.283),
+    (0.062, 0.293, 0.063),
+    (0.439, 0.031, 0.039),
+    (0.807, 0.584, 0.010),
+    (0.497, 0.081, 0.298),
+    (0.000, 0.237, 0.376),
+    (0.880, 0.880, 0.870),
+    (0.585, 0.589, 0.585),
+    (0.358, 0.361, 0.358),
+    (0.192, 0.192, 0.192),
+    (0.088, 0.089, 0.089),
+    (0.031, 0.032, 0.032),
+];
+
+/// 6x4 grid of ColorChecker patches
+fn color_checker(x: usize, y: usize) -> (f32, f32, f32) {
+    let column = x * 6 / WIDTH;
+    let row = y * 4 / HEIGHT;
+    COLOR_CHECKER[row * 6 + column]
+}
+
+/// Neutral ramp covering 1e-4 to 1e4, 16 stops beyond SDR white
+fn extreme_dynamic_range(x: usize, _y: usize) -> (f32, f32, f32) {
+    let v = 10.0f32.powf(x as f32 / (WIDTH - 1) as f32 * 8.0 - 4.0);
+    (v, v, v)
+}
+
+// ----- Harness
+
+/// Soft horizontal ramp up to 2.0, sprinkled with isolated pixels at 1000 like path tracing fireflies
+fn fireflies(x: usize, y: usize) -> (f32, f32, f32) {
+    if (x * 7 + y * 13).is_multiple_of(97) {
+        return (1000.0, 1000.0, 1000.0);
+    }
+    let v = 0.5 + x as f32 / (WIDTH - 1) as f32 * 1.5;
+    (v, v, v)
+}
+
+fn case_directory(name: &str) -> PathBuf {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR"))
+        .join("golden")
+        .join(name);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// 64-bit FNV-1a, enough to notice any byte changing
+fn fnv1a64(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Pull `hdrgm:Name="value"` attributes out of the gain map XMP
+fn gain_map_metadata(jpeg: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(jpeg);
+    [
+        "GainMapMin",
+        "GainMapMax",
+        "Gamma",
+        "OffsetSDR",
+        "OffsetHDR",
+        "HDRCapacityMin",
+        "HDRCapacityMax",
+    ]
+    .iter()
+    .filter_map(|name| {
+        let key = format!("hdrgm:{}=\"", name);
+        let start = text.find(&key)? + key.len();
+        let end = start + text[start..].find('"')?;
+        Some(format!("hdrgm:{}={}", name, &text[start..end]))
+    })
+    .collect()
+}
+
+/// Convert a synthetic image and compare the summary of its outputs with the golden file
+fn check(name: &str, generator: fn(usize, usize) -> (f32, f32, f32), extra_args: &[&str]) {
+    check_sized(name, (WIDTH, HEIGHT), generator, extra_args)
+}
+
+/// Same as `check`, for an image of another size. Generators must stay defined over it
+fn check_sized(
+    name: &str,
+    (width, height): (usize, usize),
+    generator: fn(usize, usize) -> (f32, f32, f32),
+    extra_args: &[&str],
+) {
+    let directory = case_directory(name);
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, width, height, generator).unwrap();
+
+    let png = directory.join("output.png");
+    let ultra_hdr_jpg = directory.join("output.jpg");
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args(["--deterministic", "--log-level", "error"])
+        .arg("--png")
+        .arg(&png)
+        .arg("--ultra-hdr-jpg")
+        .arg(&ultra_hdr_jpg)
+        .args(extra_args)
+        .status()
+        .unwrap();
+    assert!(status.success(), "conversion of {} failed", name);
+
+    let png = fs::read(png).unwrap();
+    let ultra_hdr_jpg = fs::read(ultra_hdr_jpg).unwrap();
+    let mut summary = vec![
+        format!("png fnv1a64={:016x}", fnv1a64(&png)),
+        format!("ultra_hdr_jpg fnv1a64={:016x}", fnv1a64(&ultra_hdr_jpg)),
+    ];
+    summary.extend(gain_map_metadata(&ultra_hdr_jpg));
+    let summary = summary.join("\n") + "\n";
+
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.txt", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &summary).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&golden).unwrap_or_else(|_| {
+        panic!(
+            "missing {}, run with UPDATE_GOLDEN=1 to create it",
+            golden.display()
+        )
+    });
+    assert_eq!(summary, expected, "{} output changed", name);
+}
+
+#[test]
+fn golden_gradient() {
+    check("gradient", gradient, &[])
+}
+
+#[test]
+fn golden_gradient_display_p3() {
+    check("gradient_display_p3", gradient, &["-o", "display-p3"])
+}
+
+#[test]
+fn golden_gradient_full_precision() {
+    check("gradient_full_precision", gradient, &["--precision", "f32"])
+}
+
+#[test]
+fn golden_gradient_double_precision() {
+    check(
+        "gradient_double_precision",
+        gradient,
+        &["--precision", "f64", "--gain-map-16bit"],
+    )
+}
+
+#[test]
+fn golden_color_checker() {
+    check("color_checker", color_checker, &["--exposure", "1"])
+}
+
+#[test]
+fn golden_extreme_dynamic_range() {
+    check("extreme_dynamic_range", extreme_dynamic_range, &[])
+}
+
+#[test]
+fn golden_extreme_dynamic_range_pq_curve() {
+    check(
+        "extreme_dynamic_range_pq_curve",
+        extreme_dynamic_range,
+        &["--map-curve", "pq", "--gain-map-16bit"],
+    )
+}
+
+#[test]
+fn golden_extreme_dynamic_range_bt2446a() {
+    check(
+        "extreme_dynamic_range_bt2446a",
+        extreme_dynamic_range,
+        &["--sdr-down-conversion", "bt2446a", "--peak-nits", "4000"],
+    )
+}
+
+#[test]
+fn golden_gradient_bt2446c() {
+    check(
+        "gradient_bt2446c",
+        gradient,
+        &["--sdr-down-conversion", "bt2446c"],
+    )
+}
+
+#[test]
+fn golden_color_checker_max_rgb() {
+    check(
+        "color_checker_max_rgb",
+        color_checker,
+        &["--exposure", "1", "--gain-basis", "max-rgb"],
+    )
+}
+
+#[test]
+fn golden_odd_size_gain_map_scale() {
+    check_sized(
+        "odd_size_gain_map_scale",
+        (WIDTH - 1, HEIGHT - 1),
+        gradient,
+        &["--gain-map-scale", "2"],
+    )
+}
+
+/// Width and height from the first SOF0 segment of a JPEG
+fn jpeg_size(jpeg: &[u8]) -> (usize, usize) {
+    let sof = jpeg
+        .windows(2)
+        .position(|w| w == [0xFF, 0xC0])
+        .expect("no SOF0 segment");
+    let read = |at: usize| u16::from_be_bytes([jpeg[sof + at], jpeg[sof + at + 1]]) as usize;
+    (read(7), read(5))
+}
+
+#[test]
+fn odd_sized_gain_maps_round_up_and_validate() {
+    let directory = case_directory("odd_size");
+    let exr = directory.join("input.exr");
+    let (width, height) = (WIDTH - 1, HEIGHT - 1);
+    write_rgb_file(&exr, width, height, gradient).unwrap();
+
+    // 3 is rounded up to 4, to line up with 4:2:0 chroma
+    for (scale, expected) in [(1, 1), (2, 2), (3, 4), (8, 8)] {
+        let ultra_hdr_jpg = directory.join(format!("scale_{}.jpg", scale));
+        let gain_map_jpg = directory.join(format!("scale_{}_gain_map.jpg", scale));
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .args(["--gain-map-scale", &scale.to_string()])
+            .arg("--ultra-hdr-jpg")
+            .arg(&ultra_hdr_jpg)
+            .arg("--gain-map-jpeg")
+            .arg(&gain_map_jpg)
+            .status()
+            .unwrap();
+        assert!(status.success(), "conversion with scale {} failed", scale);
+
+        assert_eq!(
+            jpeg_size(&fs::read(&gain_map_jpg).unwrap()),
+            (width.div_ceil(expected), height.div_ceil(expected)),
+            "Gain Map size with scale {}",
+            scale
+        );
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "validate"])
+            .arg(&ultra_hdr_jpg)
+            .status()
+            .unwrap();
+        assert!(status.success(), "validation with scale {} failed", scale);
+    }
+}
+
+#[test]
+fn anamorphic_pixels_are_squared_or_recorded() {
+    let directory = case_directory("anamorphic");
+    let exr = directory.join("input.exr");
+    let mut image = Image::from_channels(
+        (WIDTH, HEIGHT),
+        SpecificChannels::rgb(|position: Vec2<usize>| gradient(position.x(), position.y())),
+    );
+    image.attributes.pixel_aspect = 2.0;
+    image.write().to_file(&exr).unwrap();
+
+    // XResolution tag, little endian, of type rational
+    let x_resolution_entry = [0x1A, 0x01, 0x05, 0x00];
+    for (keep, expected_width) in [(false, WIDTH * 2), (true, WIDTH)] {
+        let output = directory.join(format!("keep_{}.jpg", keep));
+        let mut command = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"));
+        command
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .arg("--ultra-hdr-jpg")
+            .arg(&output);
+        if keep {
+            command.arg("--keep-pixel-aspect");
+        }
+        assert!(command.status().unwrap().success());
+
+        let jpeg = fs::read(&output).unwrap();
+        assert_eq!(jpeg_size(&jpeg), (expected_width, HEIGHT));
+        assert_eq!(
+            jpeg.windows(4).any(|w| w == x_resolution_entry),
+            keep,
+            "pixel aspect in EXIF with keep {}",
+            keep
+        );
+    }
+}
+
+#[test]
+fn dpi_is_written_to_jfif_png_and_exif() {
+    let directory = case_directory("dpi");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let (jpg, png) = (directory.join("output.jpg"), directory.join("output.png"));
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args(["--deterministic", "--log-level", "error", "--dpi", "300"])
+        .arg("--ultra-hdr-jpg")
+        .arg(&jpg)
+        .arg("--png")
+        .arg(&png)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // JFIF APP0 right after SOI: units 1 (dots per inch), then horizontal and vertical densities
+    let jpeg = fs::read(&jpg).unwrap();
+    assert_eq!(&jpeg[6..11], b"JFIF\0");
+    assert_eq!(&jpeg[13..18], &[1, 1, 44, 1, 44]);
+    // EXIF XResolution of 300 inches, as 3000000 / 10000
+    let x_resolution = [0x1A, 0x01, 0x05, 0x00, 1, 0, 0, 0];
+    assert!(jpeg.windows(8).any(|w| w == x_resolution));
+
+    // pHYs in pixels per meter, unit 1 (meter)
+    let png = fs::read(&png).unwrap();
+    let phys = png
+        .windows(4)
+        .position(|w| w == b"pHYs")
+        .expect("no pHYs chunk");
+    let per_meter = (300.0f32 / 0.0254).round() as u32;
+    let mut expected = per_meter.to_be_bytes().repeat(2);
+    expected.push(1);
+    assert_eq!(&png[phys + 4..phys + 13], &expected[..]);
+}
+
+#[test]
+fn graded_sdr_defines_the_base_image() {
+    let directory = case_directory("graded_sdr");
+    let (hdr, sdr) = (directory.join("hdr.exr"), directory.join("sdr.exr"));
+    write_rgb_file(&hdr, WIDTH, HEIGHT, gradient).unwrap();
+    // Flat mid gray, 191 once encoded with the default 2.4 gamma
+    write_rgb_file(&sdr, WIDTH, HEIGHT, |_, _| (0.5, 0.5, 0.5)).unwrap();
+    let (jpg, png) = (directory.join("output.jpg"), directory.join("output.png"));
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&hdr)
+        .args(["--deterministic", "--log-level", "error", "--sdr-exr"])
+        .arg(&sdr)
+        .args(["--thumbnail-size", "16", "--ultra-hdr-jpg"])
+        .arg(&jpg)
+        .arg("--png")
+        .arg(&png)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut reader = png::Decoder::new(fs::File::open(&png).unwrap())
+        .read_info()
+        .unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    assert!(data.iter().all(|&v| v == 191));
+
+    // The thumbnail, last image of the file, is downscaled from the grade too
+    let file = fs::read(&jpg).unwrap();
+    let thumbnail = file
+        .windows(3)
+        .rposition(|window| window == [0xFF, 0xD8, 0xFF])
+        .unwrap();
+    let mut decoder = jpeg_decoder::Decoder::new(&file[thumbnail..]);
+    let pixels = decoder.decode().unwrap();
+    assert_eq!(decoder.info().unwrap().width, 16);
+    assert!(pixels.iter().all(|&v| v.abs_diff(191) <= 2), "{:?}", pixels);
+
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(["--log-level", "error", "validate"])
+        .arg(&jpg)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn firefly_clamp_keeps_fireflies_out_of_gain_map_range() {
+    let directory = case_directory("fireflies");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, fireflies).unwrap();
+
+    let gain_map_max = |extra_args: &[&str]| {
+        let output = directory.join("output.jpg");
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .args(extra_args)
+            .arg("--ultra-hdr-jpg")
+            .arg(&output)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        gain_map_metadata(&fs::read(&output).unwrap())
+            .iter()
+            .find_map(|m| m.strip_prefix("hdrgm:GainMapMax="))
+            .unwrap()
+            .parse::<f32>()
+            .unwrap()
+    };
+
+    // Fireflies are almost 10 stops above SDR white, the ramp only 1
+    assert!(gain_map_max(&[]) > 8.0);
+    assert!(gain_map_max(&["--firefly-clamp", "2"]) < 2.0);
+}
+
+#[test]
+fn deterministic_runs_are_identical() {
+    let directory = case_directory("repeat");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+
+    let outputs: Vec<Vec<u8>> = (0..2)
+        .map(|run| {
+            let output = directory.join(format!("output_{}.jpg", run));
+            let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+                .arg(&exr)
+                .args(["--deterministic", "--log-level", "error"])
+                .arg("--ultra-hdr-jpg")
+                .arg(&output)
+                .status()
+                .unwrap();
+            assert!(status.success());
+            fs::read(output).unwrap()
+        })
+        .collect();
+
+    assert_eq!(outputs[0], outputs[1]);
+}
+
+#[test]
+fn generated_patterns_convert_and_validate() {
+    let directory = case_directory("generate");
+    for pattern in ["gradient", "sweep", "zone-plate", "chart", "checkerboard"] {
+        let exr = directory.join(format!("{}.exr", pattern));
+        let ultra_hdr_jpg = directory.join(format!("{}.jpg", pattern));
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "generate", pattern])
+            .arg(&exr)
+            .args(["--size", &format!("{}x{}", WIDTH, HEIGHT)])
+            .status()
+            .unwrap();
+        assert!(status.success(), "generating {} failed", pattern);
+
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .arg("--ultra-hdr-jpg")
+            .arg(&ultra_hdr_jpg)
+            .status()
+            .unwrap();
+        assert!(status.success(), "converting {} failed", pattern);
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "validate"])
+            .arg(&ultra_hdr_jpg)
+            .status()
+            .unwrap();
+        assert!(status.success(), "validating {} failed", pattern);
+    }
+
+    // Middle of the chart's 7th column is SDR white, 203 nits
+    let image = read_first_rgba_layer_from_file(
+        directory.join("chart.exr"),
+        |resolution, _| vec![(0.0, 0.0, 0.0); resolution.width() * resolution.height()],
+        |pixels, position, (r, g, b, _): (f32, f32, f32, f32)| {
+            pixels[position.y() * WIDTH + position.x()] = (r, g, b)
+        },
+    )
+    .unwrap();
+    let layer = &image.layer_data;
+    assert_eq!(layer.attributes.white_luminance, Some(203.0));
+    let column_width = WIDTH / 12;
+    let (r, g, b) =
+        layer.channel_data.pixels[HEIGHT / 2 * WIDTH + column_width * 6 + column_width / 2];
+    assert_eq!((r, g, b), (1.0, 1.0, 1.0));
+}
+
+#[test]
+fn parts_are_listed_and_selected() {
+    let directory = case_directory("parts");
+    let exr = directory.join("parts.exr");
+    // Flat gray parts, 0.5 being 191 once encoded with the default 2.4 gamma
+    let part = |name: &str, value: f32| {
+        Layer::new(
+            (WIDTH, HEIGHT),
+            LayerAttributes::named(name),
+            Encoding::FAST_LOSSLESS,
+            SpecificChannels::rgb(move |_: Vec2<usize>| (value, value, value)),
+        )
+    };
+    Image::from_layers(
+        ImageAttributes::new(IntegerBounds::from_dimensions((WIDTH, HEIGHT))),
+        vec![part("dark", 0.25), part("gray", 0.5)],
+    )
+    .write()
+    .to_file(&exr)
+    .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg("analyze")
+        .arg(&exr)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let listing = String::from_utf8(output.stdout).unwrap();
+    assert!(listing.contains("part 0 \"dark\""));
+    assert!(listing.contains("part 1 \"gray\""));
+    assert!(listing.contains("layer (none): B (f32), G (f32), R (f32)"));
+
+    let convert = |part: Option<&str>| {
+        let png = directory.join(format!("{}.png", part.unwrap_or("default")));
+        let mut command = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"));
+        command
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error", "--png"])
+            .arg(&png);
+        if let Some(part) = part {
+            command.args(["--part", part]);
+        }
+        if !command.status().unwrap().success() {
+            return None;
+        }
+        let mut reader = png::Decoder::new(fs::File::open(&png).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        Some(data)
+    };
+    let gray = vec![191; WIDTH * HEIGHT * 3];
+    assert_ne!(convert(None), Some(gray.clone()));
+    assert_eq!(convert(Some("1")), Some(gray.clone()));
+    assert_eq!(convert(Some("gray")), Some(gray));
+    assert_eq!(convert(Some("2")), None);
+    assert_eq!(convert(Some("missing")), None);
+}
+
+#[test]
+fn environment_variables_stand_for_flags() {
+    let directory = case_directory("environment");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let (png, overridden) = (directory.join("output.png"), directory.join("other.png"));
+    let _ = fs::remove_file(&png);
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .env("EXR2UHDR_PNG", &png)
+        .env("EXR2UHDR_DETERMINISTIC", "1")
+        .env("EXR2UHDR_LOG_LEVEL", "warn")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(png.exists());
+    // Piped logs are never colored
+    assert!(!output.stderr.is_empty() && !output.stderr.contains(&0x1B));
+
+    // The command line takes precedence
+    let _ = fs::remove_file(&overridden);
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .env("EXR2UHDR_PNG", &png)
+        .env("EXR2UHDR_DETERMINISTIC", "off")
+        .args(["--log-level", "error", "--png"])
+        .arg(&overridden)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(overridden.exists());
+}
+
+#[test]
+fn emitted_formats_share_one_pass() {
+    let directory = case_directory("emit");
+    for file in [
+        "shot.png",
+        "shot.jpg",
+        "shot_gain_map.png",
+        "shot_ultra_hdr.jpg",
+    ] {
+        let _ = fs::remove_file(directory.join(file));
+    }
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args(["--deterministic", "--log-level", "error"])
+        .args(["--emit", "ultra-hdr-jpeg,png,gain-map-png", "--emit-to"])
+        .arg(directory.join("shot"))
+        .arg("--png")
+        .arg(directory.join("own_flag.png"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // Outputs of their own flags take precedence
+    assert!(!directory.join("shot.png").exists());
+    assert!(directory.join("own_flag.png").exists());
+    assert!(directory.join("shot_gain_map.png").exists());
+    assert!(!directory.join("shot.jpg").exists());
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(["--log-level", "error", "validate"])
+        .arg(directory.join("shot_ultra_hdr.jpg"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn sdr_preview_is_tone_mapped_srgb() {
+    let directory = case_directory("sdr_preview");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, extreme_dynamic_range).unwrap();
+    let (preview, clipped) = (directory.join("preview.jpg"), directory.join("clipped.jpg"));
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args(["--deterministic", "--log-level", "error", "--sdr-preview"])
+        .arg(&preview)
+        .arg("--jpg")
+        .arg(&clipped)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let (preview, clipped) = (fs::read(preview).unwrap(), fs::read(clipped).unwrap());
+    assert_eq!(jpeg_size(&preview), (WIDTH, HEIGHT));
+    // ICC profile description, in UTF-16
+    let srgb = b"\0s\0R\0G\0B";
+    assert!(preview.windows(srgb.len()).any(|w| w == srgb));
+    assert_ne!(preview, clipped);
+}
+
+#[test]
+fn libultrahdr_comparison_reports_differences() {
+    let directory = case_directory("compare_libultrahdr");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let reference = directory.join("reference.jpg");
+
+    let convert = |output: &str, extra_args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "info"])
+            .args(extra_args)
+            .arg("--ultra-hdr-jpg")
+            .arg(directory.join(output))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stderr).unwrap()
+    };
+    // Stands in for a libultrahdr file
+    convert("reference.jpg", &[]);
+
+    let reference = reference.to_str().unwrap();
+    let same = convert("same.jpg", &["--compare-libultrahdr", reference]);
+    assert!(same.contains("Gain Map metadata matches libultrahdr's"));
+    assert!(same.contains("Reconstructed HDR matches libultrahdr's"));
+
+    let different = convert(
+        "different.jpg",
+        &["--compare-libultrahdr", reference, "--gain-map-scale", "2"],
+    );
+    assert!(different.contains("Gain Map size differs"));
+    assert!(!different.contains("Gain Map metadata matches"));
+}
+
+#[test]
+fn edge_aware_gain_map_keeps_step_edges_sharp() {
+    const SIZE: usize = 64;
+    const EDGE: usize = 45;
+    let directory = case_directory("step_edge");
+    // Dark surface with no boost next to a light 2 stops above SDR white
+    fn step(position: usize) -> (f32, f32, f32) {
+        if position < EDGE {
+            (0.05, 0.05, 0.05)
+        } else {
+            (4.0, 4.0, 4.0)
+        }
+    }
+    let gain_map = |name: &str, generator: fn(usize, usize) -> (f32, f32, f32), mode: &str| {
+        let exr = directory.join(format!("{}.exr", name));
+        write_rgb_file(&exr, SIZE, SIZE, generator).unwrap();
+        let png = directory.join(format!("{}_{}.png", name, mode));
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .args(["--gain-map-scale", "4", "--gain-map-downscale", mode])
+            .arg("--gain-map-png")
+            .arg(&png)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let mut reader = png::Decoder::new(fs::File::open(&png).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        data
+    };
+    let map_size = SIZE / 4;
+    // The block of columns 44 - 47 straddles the edge, one column dark and three light
+    let straddling = EDGE / 4;
+
+    let vertical = gain_map("vertical", |x, _| step(x), "box");
+    let (dark, light) = (vertical[0], vertical[map_size - 1]);
+    assert!(dark < vertical[straddling] && vertical[straddling] < light);
+
+    let vertical = gain_map("vertical", |x, _| step(x), "edge-aware");
+    for row in vertical.chunks_exact(map_size) {
+        assert_eq!(row[straddling - 1], dark);
+        assert_eq!(row[straddling], light);
+    }
+    // The same edge turned a quarter gives the same Gain Map turned a quarter
+    let horizontal = gain_map("horizontal", |_, y| step(y), "edge-aware");
+    for y in 0..map_size {
+        for x in 0..map_size {
+            assert_eq!(horizontal[y * map_size + x], vertical[x * map_size + y]);
+        }
+    }
+}
+
+#[test]
+fn batches_stop_or_continue_after_a_corrupt_file() {
+    let directory = case_directory("batch_errors");
+    let inputs = ["a.exr", "corrupt.exr", "c.exr"].map(|name| directory.join(name));
+    write_rgb_file(&inputs[0], WIDTH, HEIGHT, gradient).unwrap();
+    write_rgb_file(&inputs[2], WIDTH, HEIGHT, gradient).unwrap();
+    // A truncated copy
+    let exr = fs::read(&inputs[0]).unwrap();
+    fs::write(&inputs[1], &exr[..exr.len() / 4]).unwrap();
+
+    let convert = |extra_args: &[&str]| {
+        let outputs = directory.join(format!("outputs{}", extra_args.len()));
+        let _ = fs::remove_dir_all(&outputs);
+        fs::create_dir_all(&outputs).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(&inputs)
+            .args(["--log-level", "error"])
+            .args(extra_args)
+            .arg("--ultra-hdr-jpg")
+            .arg(&outputs)
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let summary = String::from_utf8(output.stdout).unwrap();
+        (summary, outputs.join("c_ultra_hdr.jpg").exists())
+    };
+
+    let (summary, converted_last) = convert(&[]);
+    assert!(summary.contains("failed"));
+    assert!(summary.contains("skipped"));
+    assert!(!converted_last);
+
+    let (summary, converted_last) = convert(&["--continue-on-error"]);
+    assert_eq!(summary.matches("converted").count(), 2);
+    assert!(!summary.contains("skipped"));
+    assert!(converted_last);
+
+    let (summary, converted_last) = convert(&["--continue-on-error", "--jobs", "2"]);
+    assert_eq!(summary.matches("converted").count(), 2);
+    assert_eq!(summary.matches("failed").count(), 1);
+    assert!(converted_last);
+}
+
+#[test]
+fn parallel_batches_match_sequential_ones() {
+    let directory = case_directory("parallel_batch");
+    let inputs: Vec<PathBuf> = (0..4)
+        .map(|index| directory.join(format!("frame{}.exr", index)))
+        .collect();
+    write_rgb_file(&inputs[0], WIDTH, HEIGHT, gradient).unwrap();
+    write_rgb_file(&inputs[1], WIDTH, HEIGHT, color_checker).unwrap();
+    write_rgb_file(&inputs[2], WIDTH, HEIGHT, fireflies).unwrap();
+    write_rgb_file(&inputs[3], WIDTH, HEIGHT, extreme_dynamic_range).unwrap();
+
+    let convert = |jobs: &str| {
+        let outputs = directory.join(format!("jobs{}", jobs));
+        fs::create_dir_all(&outputs).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(&inputs)
+            .args(["--deterministic", "--log-level", "error", "--sequence"])
+            .args(["--jobs", jobs, "--ultra-hdr-jpg"])
+            .arg(&outputs)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        // Summary lists frames in order whatever finished first
+        let summary = String::from_utf8(output.stdout).unwrap();
+        let order: Vec<usize> = (0..inputs.len())
+            .map(|index| summary.find(&format!("frame{}.exr", index)).unwrap())
+            .collect();
+        assert!(order.windows(2).all(|w| w[0] < w[1]), "{}", summary);
+        (0..inputs.len())
+            .map(|index| fs::read(outputs.join(format!("frame{}_ultra_hdr.jpg", index))).unwrap())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(convert("1"), convert("3"));
+
+    // Statistics that cannot be written fail the run
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(&inputs)
+        .args(["--log-level", "error", "--sequence", "--range-to"])
+        .arg(directory.join("missing").join("range.json"))
+        .arg("--ultra-hdr-jpg")
+        .arg(directory.join("jobs1"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Could not write"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn fast_previews_use_the_preview_attribute_or_decimate() {
+    let directory = case_directory("fast_preview");
+    let fast_preview = |exr: &Path| {
+        let png = exr.with_extension("png");
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(exr)
+            .args(["--log-level", "error", "--fast-preview", "--png"])
+            .arg(&png)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let mut reader = png::Decoder::new(fs::File::open(&png).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        let info = reader.info();
+        (info.width, info.height, data)
+    };
+
+    // A red preview attribute over gray pixels
+    let with_preview = directory.join("with_preview.exr");
+    let attributes = LayerAttributes {
+        preview: Some(Preview {
+            size: Vec2(4, 2),
+            pixel_data: [127, 0, 0, 127]
+                .repeat(8)
+                .iter()
+                .map(|&v| v as i8)
+                .collect(),
+        }),
+        ..LayerAttributes::default()
+    };
+    Image::from_layer(Layer::new(
+        (WIDTH, HEIGHT),
+        attributes,
+        Encoding::FAST_LOSSLESS,
+        SpecificChannels::rgb(|_: Vec2<usize>| (0.5f32, 0.5, 0.5)),
+    ))
+    .write()
+    .to_file(&with_preview)
+    .unwrap();
+    let (width, height, data) = fast_preview(&with_preview);
+    assert_eq!((width, height), (4, 2));
+    assert_eq!(data, [127, 0, 0].repeat(8));
+
+    // Without one, every third row and column of a 600x400 image fits in 256 pixels
+    let without_preview = directory.join("without_preview.exr");
+    write_rgb_file(&without_preview, 600, 400, |_, _| (0.5, 0.5, 0.5)).unwrap();
+    let (width, height, data) = fast_preview(&without_preview);
+    assert_eq!((width, height), (200, 134));
+    // Mid gray in sRGB, rows left undecoded would be black
+    assert!(data.iter().all(|&v| v == 188));
+}
+
+#[test]
+fn anchors_set_exposure() {
+    let directory = case_directory("anchor");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let png = |name: &str, args: &[&str]| {
+        let png = directory.join(format!("{}.png", name));
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .args(args)
+            .arg("--png")
+            .arg(&png)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        fs::read(png).unwrap()
+    };
+
+    // 0.36 shown as middle gray is one stop down, 4.0 shown as white two
+    assert_eq!(
+        png("gray18", &["--anchor", "gray18=0.36"]),
+        png("minus_one", &["--exposure", "-1"])
+    );
+    assert_eq!(
+        png("white", &["--anchor", "white=4"]),
+        png("minus_two", &["--exposure", "-2"])
+    );
+    assert_ne!(
+        png("minus_one", &["--exposure", "-1"]),
+        png("minus_two", &["--exposure", "-2"])
+    );
+}
+
+#[test]
+fn log_files_get_json_lines_naming_the_file() {
+    let directory = case_directory("log_file");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let log = directory.join("run.log");
+    let _ = fs::remove_file(&log);
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args(["--deterministic", "--log-level", "warn", "--log-file"])
+        .arg(&log)
+        .arg("--png")
+        .arg(directory.join("output.png"))
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty(), "logs leaked to stdout");
+
+    let log = fs::read_to_string(log).unwrap();
+    let warning = log
+        .lines()
+        .find(|line| line.contains("No chromaticities"))
+        .expect("warning missing from the log file");
+    assert!(warning.starts_with('{') && warning.contains(r#""level":"WARN""#));
+    assert!(warning.contains(&exr.display().to_string()));
+}
+
+#[test]
+fn input_formats_are_told_from_their_first_bytes() {
+    let directory = case_directory("input_formats");
+    let pixels: Vec<(f32, f32, f32)> = (0..HEIGHT)
+        .flat_map(|y| (0..WIDTH).map(move |x| gradient(x, y)))
+        .collect();
+    write_rgb_file(directory.join("input.exr"), WIDTH, HEIGHT, gradient).unwrap();
+
+    // Little-endian PFM, bottom row first
+    let mut pfm = format!("PF\n{} {}\n-1.0\n", WIDTH, HEIGHT).into_bytes();
+    for row in pixels.chunks_exact(WIDTH).rev() {
+        for (r, g, b) in row {
+            for v in [r, g, b] {
+                pfm.extend(v.to_le_bytes());
+            }
+        }
+    }
+    fs::write(directory.join("input.pfm"), &pfm).unwrap();
+    fs::write(directory.join("pfm_named.exr"), &pfm).unwrap();
+
+    let flat: Vec<f32> = pixels.iter().flat_map(|&(r, g, b)| [r, g, b]).collect();
+    let mut tiff = fs::File::create(directory.join("input.tif")).unwrap();
+    TiffEncoder::new(&mut tiff)
+        .unwrap()
+        .write_image::<colortype::RGB32Float>(WIDTH as u32, HEIGHT as u32, &flat)
+        .unwrap();
+
+    // Flat RGBE pixels
+    let mut hdr = format!(
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        HEIGHT, WIDTH
+    )
+    .into_bytes();
+    for &(r, g, b) in &pixels {
+        let max = r.max(g).max(b);
+        if max < 1e-32 {
+            hdr.extend([0; 4]);
+        } else {
+            let exponent = max.log2().floor() as i32 + 1;
+            let scale = 256.0 / (exponent as f32).exp2();
+            hdr.extend([r, g, b].map(|v| (v * scale) as u8));
+            hdr.push((exponent + 128) as u8);
+        }
+    }
+    fs::write(directory.join("input.hdr"), hdr).unwrap();
+
+    let convert = |input: &str, args: &[&str]| {
+        let png = directory.join(format!("{}.png", input));
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(directory.join(input))
+            .args(["--deterministic", "--log-level", "error"])
+            .args(args)
+            .arg("--png")
+            .arg(&png)
+            .status()
+            .unwrap();
+        status.success().then(|| fs::read(png).unwrap())
+    };
+
+    let reference = convert("input.exr", &[]).unwrap();
+    assert_eq!(convert("input.pfm", &[]).unwrap(), reference);
+    assert_eq!(convert("input.tif", &[]).unwrap(), reference);
+    // The extension does not matter, only --input-format does
+    assert_eq!(convert("pfm_named.exr", &[]).unwrap(), reference);
+    assert!(convert("input.pfm", &["--input-format", "exr"]).is_none());
+    // 8-bit mantissas are lossy
+    assert!(convert("input.hdr", &[]).is_some());
+
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(directory.join("input.exr"))
+        .args(["--deterministic", "--log-level", "error", "--ultra-hdr-jpg"])
+        .arg(directory.join("input.jpg"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(convert("input.jpg", &[]).is_some());
+}
+
+#[test]
+fn edits_keep_ultra_hdr_jpegs_valid() {
+    let directory = case_directory("edit");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let csv = directory.join("metadata.csv");
+    fs::write(
+        &csv,
+        "file,artist,latitude,longitude\ninput.exr,Someone,48.5,2.25\n",
+    )
+    .unwrap();
+    let original = directory.join("original.jpg");
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args([
+            "--deterministic",
+            "--log-level",
+            "error",
+            "--thumbnail-size",
+            "32",
+        ])
+        .arg("--metadata-csv")
+        .arg(&csv)
+        .arg("--ultra-hdr-jpg")
+        .arg(&original)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let edit = |name: &str, args: &[&str]| {
+        let output = directory.join(name);
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "edit"])
+            .arg(&original)
+            .arg("--output")
+            .arg(&output)
+            .args(args)
+            .status()
+            .unwrap();
+        if !status.success() {
+            return None;
+        }
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "validate"])
+            .arg(&output)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} is not valid", name);
+        Some(fs::read(output).unwrap())
+    };
+    // Degrees of the GPS latitude, as an EXIF rational
+    let latitude = [48u32.to_le_bytes(), 1u32.to_le_bytes()].concat();
+    let contains = |data: &[u8], part: &[u8]| data.windows(part.len()).any(|w| w == part);
+
+    assert!(contains(&fs::read(&original).unwrap(), &latitude));
+    let stripped = edit("stripped.jpg", &["--strip-gps"]).unwrap();
+    assert!(!contains(&stripped, &latitude));
+    assert!(contains(&stripped, b"Someone"));
+
+    let small = edit("small.jpg", &["--max-size", "48", "--quality", "80"]).unwrap();
+    let mut decoder = jpeg_decoder::Decoder::new(&small[..]);
+    decoder.read_info().unwrap();
+    let info = decoder.info().unwrap();
+    assert_eq!((info.width, info.height), (48, 32));
+
+    // Raising the capacity only changes metadata, lowering it below GainMapMax clips the Gain Map too
+    let raised = edit("raised.jpg", &["--hdr-capacity-max", "8"]).unwrap();
+    assert!(contains(&raised, br#"hdrgm:HDRCapacityMax="8""#));
+    let lowered = edit("lowered.jpg", &["--hdr-capacity-max", "1"]).unwrap();
+    assert!(contains(&lowered, br#"hdrgm:GainMapMax="1""#));
+    assert!(edit("invalid.jpg", &["--hdr-capacity-max", "0"]).is_none());
+}
+
+/// Dim room lit at 0.05 with a window on its right, stripes outside at 8 and 16, 3 and 4 stops above SDR white
+fn interior_with_window(x: usize, y: usize) -> (f32, f32, f32) {
+    if x < WIDTH / 2 {
+        return (0.05, 0.05, 0.05);
+    }
+    let v = if (y / 4).is_multiple_of(2) { 8.0 } else { 16.0 };
+    (v, v, v)
+}
+
+#[test]
+fn local_tone_mapping_keeps_window_detail() {
+    let directory = case_directory("local_tone_map");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, interior_with_window).unwrap();
+
+    let render = |tonemap: &str| {
+        let (jpg, png) = (
+            directory.join(format!("{}.jpg", tonemap)),
+            directory.join(format!("{}.png", tonemap)),
+        );
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args([
+                "--deterministic",
+                "--log-level",
+                "error",
+                "--tonemap",
+                tonemap,
+            ])
+            .arg("--ultra-hdr-jpg")
+            .arg(&jpg)
+            .arg("--png")
+            .arg(&png)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "validate"])
+            .arg(&jpg)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut reader = png::Decoder::new(fs::File::open(&png).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        // Red of a dark stripe and a bright one inside the window, and of the room
+        let red = |x: usize, y: usize| data[(y * WIDTH + x) * 3];
+        (
+            red(WIDTH * 3 / 4, HEIGHT / 2),
+            red(WIDTH * 3 / 4, HEIGHT / 2 + 4),
+            red(WIDTH / 8, HEIGHT / 2),
+        )
+    };
+
+    let (dark, bright, room) = render("global");
+    assert_eq!((dark, bright), (255, 255));
+    let (local_dark, local_bright, local_room) = render("local");
+    assert!(local_bright < 255, "{}", local_bright);
+    assert!(
+        local_dark + 10 < local_bright,
+        "{} {}",
+        local_dark,
+        local_bright
+    );
+    assert!(room.abs_diff(local_room) <= 2, "{} {}", room, local_room);
+}
+
+/// Highlights ramping from 1 to 16 across the image, all clipped in SDR so the Gain Map alone carries the ramp
+fn clipped_highlight_ramp(x: usize, _y: usize) -> (f32, f32, f32) {
+    let v = (x as f32 / (WIDTH - 1) as f32 * 4.0).exp2();
+    (v, v, v)
+}
+
+#[test]
+fn banding_analysis_dithers_coarse_gain_maps() {
+    let directory = case_directory("banding");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, clipped_highlight_ramp).unwrap();
+
+    let gain_map = |extra_args: &[&str]| {
+        let (jpg, png) = (
+            directory.join(format!("output{}.jpg", extra_args.len())),
+            directory.join(format!("gain_map{}.png", extra_args.len())),
+        );
+        let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "warn"])
+            .args(extra_args)
+            .arg("--ultra-hdr-jpg")
+            .arg(&jpg)
+            .arg("--gain-map-png")
+            .arg(&png)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "validate"])
+            .arg(&jpg)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut reader = png::Decoder::new(fs::File::open(&png).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        (String::from_utf8_lossy(&output.stderr).into_owned(), data)
+    };
+    // Columns of the Gain Map are constant, unless dithered
+    let columns_vary =
+        |data: &[u8]| (0..WIDTH).any(|x| (0..HEIGHT).any(|y| data[y * WIDTH + x] != data[x]));
+
+    let (log, plain) = gain_map(&[]);
+    assert!(!log.contains("banding"), "{}", log);
+    assert!(!columns_vary(&plain));
+
+    let (log, dithered) = gain_map(&["--analyze-banding"]);
+    assert!(log.contains("may show as banding"), "{}", log);
+    assert!(columns_vary(&dithered));
+    // Noise averages out over columns
+    for x in 0..WIDTH {
+        let mean = (0..HEIGHT)
+            .map(|y| dithered[y * WIDTH + x] as f32)
+            .sum::<f32>()
+            / HEIGHT as f32;
+        assert!(
+            (mean - plain[x] as f32).abs() <= 1.0,
+            "{} {}",
+            mean,
+            plain[x]
+        );
+    }
+
+    let (log, _) = gain_map(&["--analyze-banding", "--banding-threshold", "1000"]);
+    assert!(!log.contains("banding"), "{}", log);
+}
+
+#[test]
+fn cached_conversions_are_restored_instead_of_converted() {
+    let directory = case_directory("cache");
+    let _ = fs::remove_dir_all(directory.join("cache"));
+    let inputs = ["a.exr", "b.exr"].map(|name| directory.join(name));
+    write_rgb_file(&inputs[0], WIDTH, HEIGHT, gradient).unwrap();
+    write_rgb_file(&inputs[1], WIDTH, HEIGHT, color_checker).unwrap();
+    let outputs = directory.join("outputs");
+    fs::create_dir_all(&outputs).unwrap();
+
+    let convert = |extra_args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(&inputs)
+            .args(["--deterministic", "--log-level", "error", "--cache-dir"])
+            .arg(directory.join("cache"))
+            .args(extra_args)
+            .arg("--ultra-hdr-jpg")
+            .arg(&outputs)
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let summary = String::from_utf8(output.stdout).unwrap();
+        (
+            summary.matches("converted").count(),
+            summary.matches("cached").count(),
+        )
+    };
+
+    assert_eq!(convert(&[]), (2, 0));
+    let first = fs::read(outputs.join("b_ultra_hdr.jpg")).unwrap();
+    assert_eq!(convert(&[]), (0, 2));
+
+    // Only the changed input is converted, a deleted output comes back from the cache
+    write_rgb_file(&inputs[0], WIDTH, HEIGHT, fireflies).unwrap();
+    fs::remove_file(outputs.join("b_ultra_hdr.jpg")).unwrap();
+    assert_eq!(convert(&[]), (1, 1));
+    assert_eq!(fs::read(outputs.join("b_ultra_hdr.jpg")).unwrap(), first);
+
+    // Other settings are other conversions
+    assert_eq!(convert(&["--exposure", "-1"]), (2, 0));
+    assert_eq!(convert(&["--exposure", "-1"]), (0, 2));
+    assert_eq!(convert(&["--force"]), (2, 0));
+}
+
+/// Bright right half, with red misregistered 3 pixels to the right
+fn misregistered_red(x: usize, _y: usize) -> (f32, f32, f32) {
+    let level = |edge: usize| if x >= edge { 0.8 } else { 0.1 };
+    (level(WIDTH / 2 + 3), level(WIDTH / 2), level(WIDTH / 2))
+}
+
+/// Bright band around the center, with red 5 % wider as through a lens with lateral chromatic aberration
+fn lateral_ca(x: usize, _y: usize) -> (f32, f32, f32) {
+    let distance = (x as f32 - (WIDTH - 1) as f32 / 2.0).abs();
+    let level = |half_width: f32| if distance < half_width { 0.8 } else { 0.1 };
+    (level(21.0), level(20.0), level(20.0))
+}
+
+#[test]
+fn pixel_filters_realign_channels() {
+    let directory = case_directory("pixel_filter");
+    // Largest difference of red and green along a row
+    let fringe = |name: &str, generator: fn(usize, usize) -> (f32, f32, f32), filter: &[&str]| {
+        let (exr, jpg, png) = (
+            directory.join(format!("{}.exr", name)),
+            directory.join(format!("{}.jpg", name)),
+            directory.join(format!("{}.png", name)),
+        );
+        write_rgb_file(&exr, WIDTH, HEIGHT, generator).unwrap();
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .args(filter)
+            .arg("--ultra-hdr-jpg")
+            .arg(&jpg)
+            .arg("--png")
+            .arg(&png)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "validate"])
+            .arg(&jpg)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut reader = png::Decoder::new(fs::File::open(&png).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        let row = HEIGHT / 2 * WIDTH * 3;
+        (0..WIDTH)
+            .map(|x| data[row + x * 3].abs_diff(data[row + x * 3 + 1]))
+            .max()
+            .unwrap()
+    };
+
+    assert!(fringe("misregistered", misregistered_red, &[]) > 100);
+    let aligned = fringe("aligned", misregistered_red, &["--align-channel", "r:-3,0"]);
+    assert!(aligned <= 2, "{}", aligned);
+
+    assert!(fringe("aberrated", lateral_ca, &[]) > 100);
+    let corrected = fringe(
+        "corrected",
+        lateral_ca,
+        &["--ca-correction", &format!("{},1", 20.0 / 21.0)],
+    );
+    assert!(corrected <= 2, "{}", corrected);
+}
+
+#[test]
+fn white_simulation_writes_warmer_proofs() {
+    let directory = case_directory("simulate_white");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, color_checker).unwrap();
+    let (png, jpg) = (directory.join("sdr.png"), directory.join("sdr.jpg"));
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args([
+            "--deterministic",
+            "--log-level",
+            "error",
+            "--simulate-white",
+            "4000K",
+        ])
+        .arg("--png")
+        .arg(&png)
+        .arg("--jpg")
+        .arg(&jpg)
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(directory.join("sdr_4000K.jpg").is_file());
+
+    // Red and blue of a neutral patch of the bottom row
+    let neutral = |path: &Path| {
+        let mut reader = png::Decoder::new(fs::File::open(path).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        let index = (HEIGHT * 7 / 8 * WIDTH + WIDTH * 5 / 12) * 3;
+        (data[index], data[index + 2])
+    };
+    let (red, blue) = neutral(&png);
+    assert!(red.abs_diff(blue) <= 2, "{} {}", red, blue);
+    let (red, blue) = neutral(&directory.join("sdr_4000K.png"));
+    assert!(red > blue + 20, "{} {}", red, blue);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args(["--log-level", "error", "--simulate-white", "1000K"])
+        .arg("--png")
+        .arg(&png)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
+#[test]
+fn unwritable_outputs_are_reported() {
+    let directory = case_directory("unwritable_outputs");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let missing = directory.join("missing");
+    for (output, name) in [
+        ("--ultra-hdr-jpg", "u.jpg"),
+        ("--jpg", "s.jpg"),
+        ("--png", "s.png"),
+        ("--gain-map-png", "g.png"),
+        ("--histogram", "h.png"),
+    ] {
+        let result = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error", output])
+            .arg(missing.join(name))
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(!result.status.success(), "{}", output);
+        assert!(stderr.contains("Could not write"), "{}: {}", output, stderr);
+        assert!(!stderr.contains("panicked"), "{}: {}", output, stderr);
+    }
+}
+
+#[test]
+fn missing_jpeg_backends_are_reported() {
+    let directory = case_directory("missing_backend");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    for output in ["--ultra-hdr-jpg", "--jpg"] {
+        let result = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .args(["--jpeg-backend", "mozjpeg", output])
+            .arg(directory.join("output.jpg"))
+            // No cjpeg to be found
+            .env("PATH", "")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        assert!(!result.status.success(), "{}", output);
+        assert!(
+            stderr.contains("Could not run cjpeg"),
+            "{}: {}",
+            output,
+            stderr
+        );
+        assert!(!stderr.contains("panicked"), "{}: {}", output, stderr);
+    }
+}
+
+#[test]
+fn validation_reports_gain_map_listed_first() {
+    let directory = case_directory("validate_item_order");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let jpg = directory.join("output.jpg");
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args(["--deterministic", "--log-level", "error", "--ultra-hdr-jpg"])
+        .arg(&jpg)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // Both semantics are 7 bytes long, segment lengths stay right
+    let mut data = fs::read(&jpg).unwrap();
+    let swaps = [
+        (
+            b"Item:Semantic=\"Primary\"".as_slice(),
+            b"Item:Semantic=\"GainMap\"".as_slice(),
+        ),
+        (b"Item:Semantic=\"GainMap\"", b"Item:Semantic=\"Primary\""),
+    ];
+    let positions: Vec<(usize, &[u8])> = swaps
+        .iter()
+        .map(|(from, to)| {
+            let position = data
+                .windows(from.len())
+                .position(|window| window == *from)
+                .unwrap();
+            (position, *to)
+        })
+        .collect();
+    for (position, to) in positions {
+        data[position..position + to.len()].copy_from_slice(to);
+    }
+    let swapped = directory.join("swapped.jpg");
+    fs::write(&swapped, data).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(["--log-level", "error", "validate"])
+        .arg(&swapped)
+        .output()
+        .unwrap();
+    let report = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!output.status.success());
+    assert!(report.contains("first item"), "{}", report);
+    assert!(!report.contains("panicked"), "{}", report);
+}
+
+#[test]
+fn validation_decodes_gain_map_stream() {
+    let directory = case_directory("validate_decode");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, gradient).unwrap();
+    let jpg = directory.join("output.jpg");
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args(["--deterministic", "--log-level", "error", "--ultra-hdr-jpg"])
+        .arg(&jpg)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // Point the Gain Map component at an undefined quantization table, its markers stay intact
+    let mut data = fs::read(&jpg).unwrap();
+    let gain_map_start = data
+        .windows(4)
+        .skip(2)
+        .position(|window| window == [0xFF, 0xD8, 0xFF, 0xE0] || window == [0xFF, 0xD8, 0xFF, 0xE1])
+        .unwrap()
+        + 2;
+    let frame = gain_map_start
+        + data[gain_map_start..]
+            .windows(2)
+            .position(|window| window == [0xFF, 0xC0])
+            .unwrap();
+    data[frame + 12] = 3;
+    let corrupted = directory.join("corrupted.jpg");
+    fs::write(&corrupted, data).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(["--log-level", "error", "validate"])
+        .arg(&corrupted)
+        .output()
+        .unwrap();
+    let report = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!output.status.success());
+    assert!(report.contains("Gain Map: Could not decode"), "{}", report);
+    assert!(
+        report.contains(&format!("offset={}", gain_map_start)),
+        "{}",
+        report
+    );
+}
+
+#[test]
+fn oversized_headers_are_rejected_before_reading() {
+    let directory = case_directory("oversized_headers");
+    // Headers asking for far more pixels than follow them
+    let files = [
+        (
+            "huge.hdr",
+            b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 99999 +X 99999\n".as_slice(),
+        ),
+        ("overflow.pfm", b"PF\n18446744073709551615 2\n-1.0\nxxxx"),
+    ];
+    for (name, data) in files {
+        let input = directory.join(name);
+        fs::write(&input, data).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&input)
+            .args(["--log-level", "error", "--png"])
+            .arg(directory.join("output.png"))
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "{}", name);
+        assert!(stderr.contains(name), "{}: {}", name, stderr);
+        assert!(!stderr.contains("panicked"), "{}: {}", name, stderr);
+    }
+}
+
+#[test]
+fn watching_a_missing_directory_is_reported() {
+    let directory = case_directory("watch_missing");
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(["--log-level", "error", "--watch"])
+        .arg(directory.join("missing"))
+        .arg("--png")
+        .arg(&directory)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("Could not watch"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn local_tone_mapping_reaches_brackets_and_sizes() {
+    let directory = case_directory("local_tone_map_variants");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, interior_with_window).unwrap();
+    let (png, jpg) = (directory.join("local.png"), directory.join("local.jpg"));
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&exr)
+        .args([
+            "--deterministic",
+            "--log-level",
+            "error",
+            "--tonemap",
+            "local",
+        ])
+        .args(["--bracket", "0", "--sizes", "48"])
+        .arg("--png")
+        .arg(&png)
+        .arg("--ultra-hdr-jpg")
+        .arg(&jpg)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let read_png = |path: &Path| {
+        let mut reader = png::Decoder::new(fs::File::open(path).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        data
+    };
+    let bracket = read_png(&directory.join("local_ev+0.png"));
+    assert!(bracket == read_png(&png), "--bracket 0 differs from --png");
+
+    // The window keeps its detail in the downscaled base image too, rather than clipping
+    let small = fs::read(directory.join("local_48.jpg")).unwrap();
+    let mut decoder = jpeg_decoder::Decoder::new(&small[..]);
+    let pixels = decoder.decode().unwrap();
+    let window_red: Vec<u8> = (0..32)
+        .flat_map(|y| (30..44).map(move |x| (y * 48 + x) * 3))
+        .map(|index| pixels[index])
+        .collect();
+    let brightest = *window_red.iter().max().unwrap();
+    assert!(brightest < 250, "{:?}", window_red);
+}
+
+#[test]
+fn graded_sdr_reaches_sizes_brackets_and_manifest() {
+    let directory = case_directory("graded_sdr_variants");
+    let (hdr, sdr) = (directory.join("hdr.exr"), directory.join("sdr.exr"));
+    write_rgb_file(&hdr, WIDTH, HEIGHT, gradient).unwrap();
+    write_rgb_file(&sdr, WIDTH, HEIGHT, |_, _| (0.5, 0.5, 0.5)).unwrap();
+    let (jpg, png) = (directory.join("graded.jpg"), directory.join("graded.png"));
+    let manifest = directory.join("manifest.json");
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&hdr)
+        .args(["--deterministic", "--log-level", "error", "--sdr-exr"])
+        .arg(&sdr)
+        .args(["--bracket", "1", "--sizes", "48", "--manifest"])
+        .arg(&manifest)
+        .arg("--ultra-hdr-jpg")
+        .arg(&jpg)
+        .arg("--png")
+        .arg(&png)
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // One stop over the mid gray grade reaches SDR white
+    let mut reader = png::Decoder::new(fs::File::open(directory.join("graded_ev+1.png")).unwrap())
+        .read_info()
+        .unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    assert!(data.iter().all(|&v| v == 255));
+
+    // The downscaled base image is the grade rather than the tone mapped gradient
+    let small = fs::read(directory.join("graded_48.jpg")).unwrap();
+    let pixels = jpeg_decoder::Decoder::new(&small[..]).decode().unwrap();
+    assert!(pixels.iter().all(|&v| v.abs_diff(191) <= 2), "{:?}", pixels);
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest).unwrap()).unwrap();
+    let references = manifest["references"].as_array().unwrap();
+    assert!(
+        references
+            .iter()
+            .any(|r| r["path"].as_str() == Some(sdr.to_str().unwrap())),
+        "{:?}",
+        references
+    );
+}
+
+#[test]
+fn ultra_hdr_decodes_back_to_exr() {
+    let directory = case_directory("decode");
+    let exr = directory.join("input.exr");
+    // Neutral, as saturated colors clipped in the base image are not recovered by a single channel Gain Map
+    let ramp = |x: usize, _: usize| {
+        let v = x as f32 / (WIDTH - 1) as f32 * 4.0;
+        (v, v, v)
+    };
+    write_rgb_file(&exr, WIDTH, HEIGHT, ramp).unwrap();
+
+    // Default gamma 2.4 in Rec. 709, and the sRGB curve in Display P3, both read from the ICC profile
+    for (name, display, red) in [
+        ("default", None, (0.64, 0.33)),
+        ("display_p3", Some("display-p3"), (0.68, 0.32)),
+    ] {
+        let jpg = directory.join(format!("{}.jpg", name));
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(&exr)
+            .args(["--deterministic", "--log-level", "error"])
+            .args(
+                display
+                    .map(|display| ["--output", display])
+                    .iter()
+                    .flatten(),
+            )
+            .arg("--ultra-hdr-jpg")
+            .arg(&jpg)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let decoded = directory.join(format!("{}.exr", name));
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .args(["--log-level", "error", "decode"])
+            .arg(&jpg)
+            .arg("-o")
+            .arg(&decoded)
+            .args(["--exr-compression", "piz", "--white-nits", "100"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let image = read_first_rgba_layer_from_file(
+            &decoded,
+            |resolution, _| vec![(0.0, 0.0, 0.0); resolution.width() * resolution.height()],
+            |pixels, position, (r, g, b, _): (f32, f32, f32, f32)| {
+                pixels[position.y() * WIDTH + position.x()] = (r, g, b)
+            },
+        )
+        .unwrap();
+        let layer = &image.layer_data;
+        assert_eq!(layer.attributes.white_luminance, Some(100.0));
+        assert_eq!(
+            layer.encoding.compression,
+            exr::compression::Compression::PIZ
+        );
+        let chromaticities = image.attributes.chromaticities.unwrap();
+        assert_eq!(
+            (chromaticities.red.0, chromaticities.red.1),
+            red,
+            "{}",
+            name
+        );
+        for (index, decoded) in layer.channel_data.pixels.iter().enumerate() {
+            let original = ramp(index % WIDTH, index / WIDTH);
+            for (decoded, original) in [
+                (decoded.0, original.0),
+                (decoded.1, original.1),
+                (decoded.2, original.2),
+            ] {
+                assert!(
+                    (decoded - original).abs() <= 0.005 + original * 0.04,
+                    "{} pixel {}: {} decoded as {}",
+                    name,
+                    index,
+                    original,
+                    decoded
+                );
+            }
+        }
+    }
+
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(["--log-level", "error", "decode"])
+        .arg(directory.join("default.jpg"))
+        .arg("-o")
+        .arg(directory.join("dwaa.exr"))
+        .args(["--exr-compression", "dwaa:45"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("DWAA and DWAB compression are not supported"));
+}
+
+#[test]
+fn ultra_hdr_input_keeps_levels_of_default_outputs() {
+    let directory = case_directory("ultra_hdr_round_trip");
+    let exr = directory.join("input.exr");
+    write_rgb_file(&exr, WIDTH, HEIGHT, color_checker).unwrap();
+    let convert = |input: &Path, name: &str| {
+        let png = directory.join(format!("{}.png", name));
+        let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+            .arg(input)
+            .args(["--deterministic", "--log-level", "error"])
+            .arg("--ultra-hdr-jpg")
+            .arg(directory.join(format!("{}.jpg", name)))
+            .arg("--png")
+            .arg(&png)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let mut reader = png::Decoder::new(fs::File::open(&png).unwrap())
+            .read_info()
+            .unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut data).unwrap();
+        data
+    };
+    // The gamma 2.4 base image read back with its own curve, rather than as sRGB
+    let original = convert(&exr, "original");
+    let round_trip = convert(&directory.join("original.jpg"), "round_trip");
+    let worst = original
+        .iter()
+        .zip(&round_trip)
+        .map(|(a, b)| a.abs_diff(*b))
+        .max()
+        .unwrap();
+    assert!(worst <= 6, "levels differ by up to {}", worst);
+}
+
+#[test]
+fn sequence_measuring_pass_only_gathers_statistics() {
+    let directory = case_directory("sequence_measuring");
+    let inputs: Vec<PathBuf> = (0..2)
+        .map(|index| directory.join(format!("frame{}.exr", index)))
+        .collect();
+    write_rgb_file(&inputs[0], WIDTH, HEIGHT, gradient).unwrap();
+    write_rgb_file(&inputs[1], WIDTH, HEIGHT, color_checker).unwrap();
+    let outputs = directory.join("outputs");
+    fs::create_dir_all(&outputs).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(&inputs)
+        .args(["--deterministic", "--log-level", "error", "--sequence"])
+        .args(["--probe", "1,1", "--png"])
+        .arg(&outputs)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // Probes are printed once per frame, by the conversion itself
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.matches("probe 1,1").count(), 2, "{}", stdout);
+}
+
+#[test]
+fn clipping_qc_checks_the_written_base_image() {
+    let directory = case_directory("qc_base_image");
+    let inputs: Vec<PathBuf> = (0..2)
+        .map(|index| directory.join(format!("frame{}.exr", index)))
+        .collect();
+    for input in &inputs {
+        write_rgb_file(input, WIDTH, HEIGHT, gradient).unwrap();
+    }
+
+    // Sequences measure their range before writing anything, then fail after writing every frame
+    let outputs = directory.join("outputs");
+    fs::create_dir_all(&outputs).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .args(&inputs)
+        .args(["--deterministic", "--log-level", "error", "--sequence"])
+        .args([
+            "--continue-on-error",
+            "--fail-on-clipping",
+            "0",
+            "--ultra-hdr-jpg",
+        ])
+        .arg(&outputs)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    for index in 0..inputs.len() {
+        assert!(outputs
+            .join(format!("frame{}_ultra_hdr.jpg", index))
+            .is_file());
+    }
+
+    // A grade within SDR range clips nowhere, unlike the built-in curve on the same pixels
+    let sdr = directory.join("sdr.exr");
+    write_rgb_file(&sdr, WIDTH, HEIGHT, |_, _| (0.5, 0.5, 0.5)).unwrap();
+    let status = Command::new(env!("CARGO_BIN_EXE_exr2ultra-hdr"))
+        .arg(&inputs[0])
+        .args(["--deterministic", "--log-level", "error", "--sdr-exr"])
+        .arg(&sdr)
+        .args(["--fail-on-clipping", "0", "--ultra-hdr-jpg"])
+        .arg(directory.join("graded.jpg"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
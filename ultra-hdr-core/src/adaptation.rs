@@ -0,0 +1,39 @@
+// http://www.brucelindbloom.com/index.html?Eqn_ChromAdapt.html
+// https://en.wikipedia.org/wiki/CIECAM02#CAT02
+
+use crate::{color::CIExyCoords, Matrix3x3d, Matrix3x3f};
+
+// ----- Cone response matrices, from XYZ
+
+pub const BRADFORD: Matrix3x3d = Matrix3x3d::new(
+    0.8951, 0.2664, -0.1614, -0.7502, 1.7135, 0.0367, 0.0389, -0.0685, 1.0296,
+);
+pub const CAT02: Matrix3x3d = Matrix3x3d::new(
+    0.7328, 0.4296, -0.1624, -0.7036, 1.6975, 0.0061, 0.0030, 0.0136, 0.9834,
+);
+pub const VON_KRIES: Matrix3x3d = Matrix3x3d::new(
+    0.40024, 0.70760, -0.08081, -0.22630, 1.16532, 0.04570, 0.0, 0.0, 0.91822,
+);
+/// Scaling XYZ directly, the crudest adaptation
+pub const XYZ_SCALING: Matrix3x3d = Matrix3x3d::new(1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
+
+/// Matrix adapting XYZ colors seen under source white to destination white, scaling them in the cone response domain given by `cone`. None if `cone` is singular
+pub fn adaptation_matrix(
+    cone: &Matrix3x3d,
+    source: CIExyCoords,
+    destination: CIExyCoords,
+) -> Option<Matrix3x3f> {
+    Some(adaptation_matrix_f64(cone, source, destination)?.cast())
+}
+
+/// Same as `adaptation_matrix`, keeping f64 precision
+pub fn adaptation_matrix_f64(
+    cone: &Matrix3x3d,
+    source: CIExyCoords,
+    destination: CIExyCoords,
+) -> Option<Matrix3x3d> {
+    let source_cone = cone * source.to_xyz_f64();
+    let destination_cone = cone * destination.to_xyz_f64();
+    let scale = Matrix3x3d::from_diagonal(&destination_cone.component_div(&source_cone));
+    Some(cone.try_inverse()? * scale * cone)
+}
@@ -0,0 +1,60 @@
+// https://developer.apple.com/documentation/appkit/applying-apple-hdr-effect-to-your-photos
+// https://exiftool.org/TagNames/Apple.html
+
+use alloc::{vec, vec::Vec};
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+use crate::exif::{ExifValue, EXIF_IFD_TAG, EXIF_VERSION, EXIF_VERSION_TAG, MAKER_NOTE_TAG};
+
+/// Starts Apple maker notes, followed by a big-endian IFD whose offsets are from the start of the maker note
+const MAKER_NOTE_HEADER: &[u8] = b"Apple iOS\0\0\x01MM";
+const HDR_HEADROOM_TAG: u16 = 0x0021;
+const HDR_GAIN_TAG: u16 = 0x0030;
+const TYPE_SRATIONAL: u16 = 10;
+/// Denominator of both values
+const PRECISION: i32 = 100000;
+/// Highest headroom Photos can read from maker notes, in stops
+const MAX_HEADROOM_LOG2: f32 = 3.0;
+
+/// Exif IFD entry holding an Apple maker note, so Photos on iOS and macOS shows the HDR rendition up to this headroom (log2). Values are picked by inverting the mapping from tags 33 and 48 to headroom given by Apple
+pub fn headroom_entry(headroom_log2: f32) -> (u16, ExifValue) {
+    let stops = headroom_log2.clamp(0.0, MAX_HEADROOM_LOG2);
+    // Headroom is piecewise linear in HDRGain, on one of two curves picked by HDRHeadroom being below 1
+    let (headroom, gain) = if stops >= 2.3 {
+        (1.0, (3.0 - stops) / 70.0)
+    } else if stops > 1.8 {
+        (1.0, (2.303 - stops) / 0.303)
+    } else if stops >= 1.6 {
+        (0.0, (1.8 - stops) / 20.0)
+    } else {
+        (0.0, (1.601 - stops) / 0.101)
+    };
+
+    let entries = [(HDR_HEADROOM_TAG, headroom), (HDR_GAIN_TAG, gain)];
+    let values_start = MAKER_NOTE_HEADER.len() + 2 + entries.len() * 12 + 4;
+    let mut data = MAKER_NOTE_HEADER.to_vec();
+    data.extend((entries.len() as u16).to_be_bytes());
+    let mut values = Vec::new();
+    for (tag, value) in entries {
+        data.extend(tag.to_be_bytes());
+        data.extend(TYPE_SRATIONAL.to_be_bytes());
+        data.extend(1u32.to_be_bytes());
+        data.extend(((values_start + values.len()) as u32).to_be_bytes());
+        values.extend(((value * PRECISION as f32).round() as i32).to_be_bytes());
+        values.extend(PRECISION.to_be_bytes());
+    }
+    data.extend(0u32.to_be_bytes()); // Offset to next IFD, none
+    data.extend(values);
+
+    (
+        EXIF_IFD_TAG,
+        ExifValue::Ifd(vec![
+            (
+                EXIF_VERSION_TAG,
+                ExifValue::Undefined(EXIF_VERSION.to_vec()),
+            ),
+            (MAKER_NOTE_TAG, ExifValue::Undefined(data)),
+        ]),
+    )
+}
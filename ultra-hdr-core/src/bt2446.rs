@@ -0,0 +1,137 @@
+// https://www.itu.int/rec/R-REC-BT.2446
+// HDR to SDR down-conversions of ITU-R BT.2446, for SDR renditions matching broadcast practice
+
+use num_traits::Float;
+
+use crate::color::{LuminanceCoefficients, Rgb};
+
+/// HDR reference white of BT.2408, what exposed linear light of 1.0 stands for
+pub const HDR_REFERENCE_WHITE_NITS: f32 = 203.0;
+/// Peak of the SDR display both methods target
+const SDR_PEAK_NITS: f32 = 100.0;
+
+/// Method C: SDR luminance of the inflection point, and the curve's constants
+const C_SDR_INFLECTION_NITS: f32 = 58.5;
+const C_K1: f32 = 0.83802;
+const C_K3: f32 = 0.74204;
+/// Method C: crosstalk between components before tone mapping, desaturating highlights. A moderate amount within the 0 - 0.33 range of the Recommendation
+const C_CROSSTALK: f32 = 0.05;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Bt2446 {
+    /// Method A: luma tone curve in the gamma-encoded domain, then chroma scaled to match, for content mastered on a display of `peak_nits`
+    A { peak_nits: f32 },
+    /// Method C: linear luminance kept up to an inflection point, then compressed logarithmically, with crosstalk desaturating highlights
+    C,
+}
+
+impl Bt2446 {
+    /// SDR display linear light of an exposed linear pixel, 1.0 being SDR peak white. Values above 1.0 are left to clipping
+    pub fn apply<F: Float>(
+        &self,
+        pixel: Rgb<F>,
+        coefficients: &LuminanceCoefficients<F>,
+    ) -> Rgb<F> {
+        match *self {
+            Bt2446::A { peak_nits } => method_a(pixel, coefficients, peak_nits),
+            Bt2446::C => method_c(pixel, coefficients),
+        }
+    }
+}
+
+fn method_a<F: Float>(
+    pixel: Rgb<F>,
+    coefficients: &LuminanceCoefficients<F>,
+    peak_nits: f32,
+) -> Rgb<F> {
+    let value = |v: f32| F::from(v).unwrap();
+    let (kr, kg, kb) = (coefficients.red, coefficients.green, coefficients.blue);
+    let gamma = value(2.4);
+
+    // Gamma-encoded HDR signal, 1.0 at the mastering peak
+    let scale = value(HDR_REFERENCE_WHITE_NITS / peak_nits);
+    let encode = |v: F| (v * scale).max(F::zero()).powf(gamma.recip());
+    let (r, g, b) = (encode(pixel.r), encode(pixel.g), encode(pixel.b));
+    let y = kr * r + kg * g + kb * b;
+    if y <= F::zero() {
+        return Rgb {
+            r: F::zero(),
+            g: F::zero(),
+            b: F::zero(),
+        };
+    }
+
+    // Luma tone mapping: perceptual linearization, knee, back to SDR signal
+    let rho = |nits: f32| F::one() + value(32.0) * value(nits / 10000.0).powf(gamma.recip());
+    let rho_hdr = rho(peak_nits);
+    let y_p = (F::one() + (rho_hdr - F::one()) * y).ln() / rho_hdr.ln();
+    let y_c = if y_p <= value(0.7399) {
+        value(1.077) * y_p
+    } else if y_p < value(0.9909) {
+        value(-1.1510) * y_p * y_p + value(2.7811) * y_p - value(0.6302)
+    } else {
+        value(0.5) * y_p + value(0.5)
+    };
+    let rho_sdr = rho(SDR_PEAK_NITS);
+    let y_sdr = (rho_sdr.powf(y_c) - F::one()) / (rho_sdr - F::one());
+
+    // Chroma scaled by the luma ratio, luma lowered for saturated reds
+    let (cb_scale, cr_scale) = (value(2.0) * (F::one() - kb), value(2.0) * (F::one() - kr));
+    let ratio = y_sdr / (value(1.1) * y);
+    let cb = ratio * (b - y) / cb_scale;
+    let cr = ratio * (r - y) / cr_scale;
+    let y_tmo = y_sdr - (value(0.1) * cr).max(F::zero());
+
+    let r = y_tmo + cr_scale * cr;
+    let b = y_tmo + cb_scale * cb;
+    let g = (y_tmo - kr * r - kb * b) / kg;
+    let decode = |v: F| v.max(F::zero()).powf(gamma);
+    Rgb {
+        r: decode(r),
+        g: decode(g),
+        b: decode(b),
+    }
+}
+
+fn method_c<F: Float>(pixel: Rgb<F>, coefficients: &LuminanceCoefficients<F>) -> Rgb<F> {
+    let value = |v: f32| F::from(v).unwrap();
+    let alpha = value(C_CROSSTALK);
+
+    // Symmetric crosstalk matrix, (1 - 3a) I + a J, inverted as (v - a sum(v)) / (1 - 3a)
+    let sum = pixel.r + pixel.g + pixel.b;
+    let crosstalk = |v: F| (F::one() - value(3.0) * alpha) * v + alpha * sum;
+    let mixed = Rgb {
+        r: crosstalk(pixel.r),
+        g: crosstalk(pixel.g),
+        b: crosstalk(pixel.b),
+    };
+
+    let y = coefficients.luminance(&mixed);
+    if y <= F::zero() {
+        return Rgb {
+            r: F::zero(),
+            g: F::zero(),
+            b: F::zero(),
+        };
+    }
+    let (k1, k3) = (value(C_K1), value(C_K3));
+    let y_ip = value(C_SDR_INFLECTION_NITS) / k1;
+    let k2 = k1 * (F::one() - k3) * y_ip;
+    let k4 = k1 * y_ip - k2 * (F::one() - k3).ln();
+    let y_hdr = y * value(HDR_REFERENCE_WHITE_NITS);
+    let y_sdr = if y_hdr < y_ip {
+        k1 * y_hdr
+    } else {
+        k2 * (y_hdr / y_ip - k3).ln() + k4
+    };
+
+    // Chromaticity is kept by scaling every component
+    let ratio = y_sdr / value(SDR_PEAK_NITS) / y;
+    let mapped_sum = sum * ratio;
+    let uncross = |v: F| (v * ratio - alpha * mapped_sum) / (F::one() - value(3.0) * alpha);
+    Rgb {
+        r: uncross(mixed.r),
+        g: uncross(mixed.g),
+        b: uncross(mixed.b),
+    }
+}
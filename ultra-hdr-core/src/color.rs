@@ -0,0 +1,315 @@
+// https://github.com/MONOGRID/gainmap-js
+// https://helpx.adobe.com/content/dam/help/en/camera-raw/using/gain-map/jcr_content/root/content/flex/items/position/position-par/table/row-io13dug-column-4a63daf/download_section/download-1/Gain_Map_1_0d14.pdf
+// https://developer.android.com/media/platform/hdr-image-format
+// https://openexr.com/en/latest/TechnicalIntroduction.html#
+// https://stackoverflow.com/questions/45605506/how-are-cie-xyy-luminance-values-for-color-primaries-determined
+
+// http://www.brucelindbloom.com/index.html?Eqn_XYZ_to_xyY.html
+
+use nalgebra::{SMatrix, Scalar};
+use num_traits::Float;
+
+use crate::{Matrix3x1d, Matrix3x1f, Matrix3x3d, Matrix3x3f};
+
+// ----- Pixel
+
+/// Linear-light pixel, with components of any float type
+#[derive(Default, Copy, Clone, Debug)]
+pub struct Rgb<F> {
+    pub r: F,
+    pub g: F,
+    pub b: F,
+}
+
+/// Linear-light pixel
+pub type Pixel = Rgb<f32>;
+
+impl<F: Float> Rgb<F> {
+    /// Same pixel with components of another float type
+    pub fn cast<G: Float>(self) -> Rgb<G> {
+        Rgb {
+            r: G::from(self.r).unwrap(),
+            g: G::from(self.g).unwrap(),
+            b: G::from(self.b).unwrap(),
+        }
+    }
+
+    /// Multiply by a 3x3 matrix, such as a color space conversion
+    pub fn transform(self, matrix: &SMatrix<F, 3, 3>) -> Rgb<F>
+    where
+        F: Scalar,
+    {
+        let row =
+            |i: usize| matrix[(i, 0)] * self.r + matrix[(i, 1)] * self.g + matrix[(i, 2)] * self.b;
+        Rgb {
+            r: row(0),
+            g: row(1),
+            b: row(2),
+        }
+    }
+}
+
+impl From<Matrix3x1f> for Pixel {
+    fn from(value: Matrix3x1f) -> Self {
+        Self {
+            r: value[(0, 0)],
+            g: value[(1, 0)],
+            b: value[(2, 0)],
+        }
+    }
+}
+
+impl From<Pixel> for Matrix3x1f {
+    fn from(value: Pixel) -> Self {
+        Self::new(value.r, value.g, value.b)
+    }
+}
+
+// ----- CIE xy coords
+
+/// xy CIE 1391 coordinates
+#[derive(Copy, Clone, Debug)]
+pub struct CIExyCoords {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl CIExyCoords {
+    /// Effectively add luma and turn these coordinates into xyY
+    pub fn with_luma(self, luma: f32) -> CIExyYCoords {
+        CIExyYCoords { coords: self, luma }
+    }
+
+    // https://en.wikipedia.org/wiki/Standard_illuminant
+    pub fn from_black_body(temperature: f32) -> CIExyCoords {
+        // Not handling below 4000K and above 25000K properly but oh well
+        let x = if temperature <= 7000.0 {
+            0.244063
+                + 0.09911 * 10.0f32.powi(3) * temperature.recip()
+                + 2.9678 * 10.0f32.powi(6) * temperature.powi(2).recip()
+                - 4.6070 * 10.0f32.powi(9) * temperature.powi(3).recip()
+        } else {
+            0.237040
+                + 0.24748 * 10.0f32.powi(3) * temperature.recip()
+                + 1.9018 * 10.0f32.powi(6) * temperature.powi(2).recip()
+                - 2.0064 * 10.0f32.powi(9) * temperature.powi(3).recip()
+        };
+        let y = -3.000 * x.powi(2) + 2.870 * x - 0.275;
+
+        CIExyCoords { x, y }
+    }
+
+    pub fn has_negatives(&self) -> bool {
+        self.x.is_sign_negative() | self.y.is_sign_negative()
+    }
+
+    /// XYZ coordinates of this color with a luma of 1, in f64
+    pub fn to_xyz_f64(self) -> Matrix3x1d {
+        let (x, y) = (self.x as f64, self.y as f64);
+        Matrix3x1d::new(x / y, 1.0, (1.0 - x - y) / y)
+    }
+}
+
+// ----- CIE XYZ coords
+
+#[derive(Copy, Clone, Debug)]
+pub struct CIEXYZCoords {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl CIEXYZCoords {
+    // http://www.brucelindbloom.com/index.html?Eqn_XYZ_to_xyY.html
+    /// Takes in XYZ coordinates and returns xyY
+    pub fn to_xyy(self, illuminant: CIExyCoords) -> CIExyYCoords {
+        // Handle pure black
+        if (self.x < f32::EPSILON) & (self.y < f32::EPSILON) & (self.z < f32::EPSILON) {
+            // If pure black, return white point with zero luma
+            return illuminant.with_luma(0.0);
+        }
+
+        CIExyYCoords {
+            coords: CIExyCoords {
+                x: self.x / (self.x + self.y + self.z),
+                y: self.y / (self.x + self.y + self.z),
+            },
+            luma: self.y,
+        }
+    }
+}
+
+impl From<Matrix3x1f> for CIEXYZCoords {
+    fn from(value: Matrix3x1f) -> Self {
+        Self {
+            x: value[(0, 0)],
+            y: value[(1, 0)],
+            z: value[(2, 0)],
+        }
+    }
+}
+
+impl From<CIEXYZCoords> for Matrix3x1f {
+    fn from(value: CIEXYZCoords) -> Self {
+        Self::new(value.x, value.y, value.z)
+    }
+}
+
+impl From<CIExyYCoords> for CIEXYZCoords {
+    fn from(value: CIExyYCoords) -> Self {
+        // Black
+        if value.luma < f32::EPSILON {
+            return Self {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            };
+        }
+
+        Self {
+            x: (value.coords.x * value.luma) / value.coords.y,
+            y: value.luma,
+            z: ((1.0 - value.coords.x - value.coords.y) * value.luma) / value.coords.y,
+        }
+    }
+}
+
+// ----- CIE xyY coords
+
+/// CIE xyY coordinates, x and y refer to the color, Y is luma
+#[derive(Copy, Clone, Debug)]
+pub struct CIExyYCoords {
+    pub coords: CIExyCoords,
+    pub luma: f32,
+}
+
+// ----- Chromaticities
+
+/// Use to define a color space
+#[derive(Copy, Clone, Debug)]
+pub struct Chromaticities {
+    pub red: CIExyCoords,
+    pub green: CIExyCoords,
+    pub blue: CIExyCoords,
+    pub white: CIExyCoords,
+}
+
+impl Chromaticities {
+    // http://www.brucelindbloom.com/index.html?Eqn_RGB_XYZ_Matrix.html
+    /// Use this matrix to go from RGB values to CIE XYZ values. This matrix goes first in multiplication order. Derived in f64, as primaries such as ACES AP0's (negative blue y) make the f32 inversion lose precision
+    pub fn rgb_to_xyz_matrix(&self) -> Option<Matrix3x3f> {
+        Some(self.rgb_to_xyz_matrix_f64()?.cast())
+    }
+
+    /// Same as `rgb_to_xyz_matrix`, keeping f64 precision
+    pub fn rgb_to_xyz_matrix_f64(&self) -> Option<Matrix3x3d> {
+        let red = self.red.to_xyz_f64();
+        let green = self.green.to_xyz_f64();
+        let blue = self.blue.to_xyz_f64();
+        let primaries = Matrix3x3d::from_columns(&[red, green, blue]);
+
+        let s_coefficients = primaries.try_inverse()? * self.white.to_xyz_f64();
+        Some(primaries * Matrix3x3d::from_diagonal(&s_coefficients))
+    }
+
+    pub fn xyz_to_rgb_matrix(&self) -> Option<Matrix3x3f> {
+        Some(self.xyz_to_rgb_matrix_f64()?.cast())
+    }
+
+    /// Same as `xyz_to_rgb_matrix`, keeping f64 precision
+    pub fn xyz_to_rgb_matrix_f64(&self) -> Option<Matrix3x3d> {
+        self.rgb_to_xyz_matrix_f64()?.try_inverse()
+    }
+
+    /// Matrix for going from this color space to another one. If destination space is smaller than this one, be careful of output. This matrix comes first in multiplication
+    pub fn rgb_space_conversion_matrix(&self, destination: &Chromaticities) -> Option<Matrix3x3f> {
+        Some(self.rgb_space_conversion_matrix_f64(destination)?.cast())
+    }
+
+    /// Same as `rgb_space_conversion_matrix`, keeping f64 precision
+    pub fn rgb_space_conversion_matrix_f64(
+        &self,
+        destination: &Chromaticities,
+    ) -> Option<Matrix3x3d> {
+        Some(destination.xyz_to_rgb_matrix_f64()? * self.rgb_to_xyz_matrix_f64()?)
+    }
+
+    /// Does this color space contain this color ?
+    pub fn contains_color(&self, color: CIExyCoords) -> bool {
+        // https://stackoverflow.com/a/2049593
+        fn sign(p1: CIExyCoords, p2: CIExyCoords, p3: CIExyCoords) -> f32 {
+            (p1.x - p3.x) * (p2.y - p3.y) - (p2.x - p3.x) * (p1.y - p3.y)
+        }
+
+        let d1 = sign(color, self.red, self.green);
+        let d2 = sign(color, self.green, self.blue);
+        let d3 = sign(color, self.blue, self.red);
+
+        let has_neg = (d1 < 0.0) | (d2 < 0.0) | (d3 < 0.0);
+        let has_pos = (d1 > 0.0) | (d2 > 0.0) | (d3 > 0.0);
+
+        !(has_neg & has_pos)
+    }
+
+    /// Does this color space cover another color space completely ? Does not take into account white point
+    pub fn contains_space(&self, other: &Chromaticities) -> bool {
+        self.contains_color(other.red)
+            & self.contains_color(other.green)
+            & self.contains_color(other.blue)
+    }
+
+    /// Use to calculate the luminance of a pixel
+    pub fn luminance_values(&self) -> Option<LuminanceCoefficients> {
+        let coefficients = self.luminance_values_f64()?;
+        Some(LuminanceCoefficients {
+            red: coefficients.red as f32,
+            green: coefficients.green as f32,
+            blue: coefficients.blue as f32,
+        })
+    }
+
+    /// Same as `luminance_values`, keeping f64 precision
+    pub fn luminance_values_f64(&self) -> Option<LuminanceCoefficients<f64>> {
+        let mat = self.rgb_to_xyz_matrix_f64()?;
+
+        Some(LuminanceCoefficients {
+            red: mat[(1, 0)],
+            green: mat[(1, 1)],
+            blue: mat[(1, 2)],
+        })
+    }
+
+    /// True if any component is negative
+    pub fn has_negatives(&self) -> bool {
+        self.red.has_negatives()
+            | self.green.has_negatives()
+            | self.blue.has_negatives()
+            | self.white.has_negatives()
+    }
+}
+
+// ----- Luminance coefficients
+
+/// Use to calculate the luminance of an RGB pixel
+#[derive(Debug, Copy, Clone)]
+pub struct LuminanceCoefficients<F = f32> {
+    pub red: F,
+    pub green: F,
+    pub blue: F,
+}
+
+impl<F: Float> LuminanceCoefficients<F> {
+    /// Luminance of a linear pixel
+    pub fn luminance(&self, pixel: &Rgb<F>) -> F {
+        pixel.r * self.red + pixel.g * self.green + pixel.b * self.blue
+    }
+
+    /// Same coefficients as another float type
+    pub fn cast<G: Float>(&self) -> LuminanceCoefficients<G> {
+        LuminanceCoefficients {
+            red: G::from(self.red).unwrap(),
+            green: G::from(self.green).unwrap(),
+            blue: G::from(self.blue).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,319 @@
+// https://www.cipa.jp/std/documents/e/DC-X008-Translation-2019-E.pdf
+
+use alloc::{string::String, vec, vec::Vec};
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+/// Header starting every EXIF APP1 segment in JPEG files
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const LITTLE_ENDIAN_MARKER: &[u8] = &[0x49, 0x49, 0x2A, 0];
+
+pub const IMAGE_DESCRIPTION_TAG: u16 = 0x010E;
+pub const ORIENTATION_TAG: u16 = 0x0112;
+pub const X_RESOLUTION_TAG: u16 = 0x011A;
+pub const Y_RESOLUTION_TAG: u16 = 0x011B;
+pub const RESOLUTION_UNIT_TAG: u16 = 0x0128;
+pub const ARTIST_TAG: u16 = 0x013B;
+pub const COPYRIGHT_TAG: u16 = 0x8298;
+/// Pointer to the Exif IFD
+pub const EXIF_IFD_TAG: u16 = 0x8769;
+/// Pointer to the GPS IFD
+pub const GPS_INFO_TAG: u16 = 0x8825;
+/// Required in the Exif IFD
+pub const EXIF_VERSION_TAG: u16 = 0x9000;
+/// Manufacturer data, in the Exif IFD
+pub const MAKER_NOTE_TAG: u16 = 0x927C;
+/// Version of the specification EXIF is written after
+pub const EXIF_VERSION: &[u8] = b"0232";
+/// Resolutions only giving the pixel aspect ratio
+pub const RESOLUTION_UNIT_NONE: u16 = 1;
+pub const RESOLUTION_UNIT_INCH: u16 = 2;
+/// Orientation value of pixels stored upright
+pub const NORMAL_ORIENTATION: u16 = 1;
+
+const GPS_VERSION_ID_TAG: u16 = 0x0000;
+const GPS_LATITUDE_REF_TAG: u16 = 0x0001;
+const GPS_LATITUDE_TAG: u16 = 0x0002;
+const GPS_LONGITUDE_REF_TAG: u16 = 0x0003;
+const GPS_LONGITUDE_TAG: u16 = 0x0004;
+const GPS_ALTITUDE_REF_TAG: u16 = 0x0005;
+const GPS_ALTITUDE_TAG: u16 = 0x0006;
+/// Denominator of seconds of arc and meters of altitude
+const GPS_PRECISION: u32 = 10000;
+/// Denominator of resolutions
+const RESOLUTION_PRECISION: u32 = 10000;
+
+const TYPE_BYTE: u16 = 1;
+const TYPE_ASCII: u16 = 2;
+const TYPE_SHORT: u16 = 3;
+const TYPE_LONG: u16 = 4;
+const TYPE_RATIONAL: u16 = 5;
+const TYPE_UNDEFINED: u16 = 7;
+
+/// Value of a single IFD entry
+#[derive(Clone, Debug)]
+pub enum ExifValue {
+    Byte(Vec<u8>),
+    Ascii(String),
+    /// Bytes with a meaning of their own, such as maker notes
+    Undefined(Vec<u8>),
+    Short(u16),
+    /// Numerators and denominators
+    Rational(Vec<(u32, u32)>),
+    /// Entries of a sub-IFD, such as the GPS one, pointed to by this entry
+    Ifd(Vec<(u16, ExifValue)>),
+}
+
+impl ExifValue {
+    fn field_type(&self) -> u16 {
+        match self {
+            ExifValue::Byte(_) => TYPE_BYTE,
+            ExifValue::Ascii(_) => TYPE_ASCII,
+            ExifValue::Undefined(_) => TYPE_UNDEFINED,
+            ExifValue::Short(_) => TYPE_SHORT,
+            ExifValue::Rational(_) => TYPE_RATIONAL,
+            ExifValue::Ifd(_) => TYPE_LONG,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match self {
+            ExifValue::Byte(bytes) | ExifValue::Undefined(bytes) => bytes.len() as u32,
+            // NUL terminated
+            ExifValue::Ascii(text) => text.len() as u32 + 1,
+            ExifValue::Short(_) | ExifValue::Ifd(_) => 1,
+            ExifValue::Rational(values) => values.len() as u32,
+        }
+    }
+
+    /// Bytes of values stored in the entry or at its offset, not for sub-IFDs
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            ExifValue::Byte(bytes) | ExifValue::Undefined(bytes) => bytes.clone(),
+            ExifValue::Ascii(text) => text.bytes().chain([0]).collect(),
+            ExifValue::Short(v) => v.to_le_bytes().to_vec(),
+            ExifValue::Rational(values) => values
+                .iter()
+                .flat_map(|(n, d)| n.to_le_bytes().into_iter().chain(d.to_le_bytes()))
+                .collect(),
+            ExifValue::Ifd(_) => Vec::new(),
+        }
+    }
+}
+
+/// Build TIFF-structured EXIF data with a single IFD0 containing these entries, as found in PNG eXIf chunks
+pub fn make_tiff(entries: &[(u16, ExifValue)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend(LITTLE_ENDIAN_MARKER);
+    data.extend(8u32.to_le_bytes()); // Offset to IFD0
+    write_ifd(&mut data, entries);
+    data
+}
+
+/// Append an IFD, then values too large for their entry and sub-IFDs. Offsets are from the start of `data`, the TIFF header
+fn write_ifd(data: &mut Vec<u8>, entries: &[(u16, ExifValue)]) {
+    let mut entries = entries.to_vec();
+    // Entries must be sorted by tag
+    entries.sort_by_key(|(tag, _)| *tag);
+
+    data.extend((entries.len() as u16).to_le_bytes());
+    let mut deferred = Vec::new();
+    for (tag, value) in &entries {
+        data.extend(tag.to_le_bytes());
+        data.extend(value.field_type().to_le_bytes());
+        data.extend(value.count().to_le_bytes());
+        let bytes = value.bytes();
+        if matches!(value, ExifValue::Ifd(_)) || bytes.len() > 4 {
+            deferred.push((data.len(), value));
+            data.extend([0; 4]);
+        } else {
+            // Value is left-justified in the 4 bytes
+            data.extend(&bytes);
+            data.extend(vec![0; 4 - bytes.len()]);
+        }
+    }
+    data.extend(0u32.to_le_bytes()); // Offset to next IFD, none
+
+    for (field, value) in deferred {
+        // Offsets are word aligned
+        if data.len() % 2 == 1 {
+            data.push(0)
+        }
+        let offset = data.len() as u32;
+        data[field..field + 4].copy_from_slice(&offset.to_le_bytes());
+        match value {
+            ExifValue::Ifd(entries) => write_ifd(data, entries),
+            value => data.extend(value.bytes()),
+        }
+    }
+}
+
+/// IFD0 entries of horizontal and vertical resolutions in pixels per `unit`, one of the `RESOLUTION_UNIT_` values
+pub fn resolution_entries(x: f32, y: f32, unit: u16) -> Vec<(u16, ExifValue)> {
+    let rational = |resolution: f32| {
+        let numerator = (resolution * RESOLUTION_PRECISION as f32).round() as u32;
+        ExifValue::Rational(vec![(numerator.max(1), RESOLUTION_PRECISION)])
+    };
+    vec![
+        (X_RESOLUTION_TAG, rational(x)),
+        (Y_RESOLUTION_TAG, rational(y)),
+        (RESOLUTION_UNIT_TAG, ExifValue::Short(unit)),
+    ]
+}
+
+/// GPS IFD entry for a position in degrees (north and east positive) and an altitude in meters above sea level
+pub fn gps_entry(latitude: f64, longitude: f64, altitude: Option<f64>) -> (u16, ExifValue) {
+    let reference = |value: f64, positive: char, negative: char| {
+        ExifValue::Ascii(String::from(if value < 0.0 { negative } else { positive }))
+    };
+    // Degrees, minutes and seconds
+    let angle = |value: f64| {
+        let seconds = (value.abs() * 3600.0 * GPS_PRECISION as f64).round() as u64;
+        let whole_seconds = seconds / GPS_PRECISION as u64;
+        ExifValue::Rational(vec![
+            ((whole_seconds / 3600) as u32, 1),
+            ((whole_seconds / 60 % 60) as u32, 1),
+            (
+                (seconds % (60 * GPS_PRECISION as u64)) as u32,
+                GPS_PRECISION,
+            ),
+        ])
+    };
+    let mut entries = vec![
+        (GPS_VERSION_ID_TAG, ExifValue::Byte(vec![2, 3, 0, 0])),
+        (GPS_LATITUDE_REF_TAG, reference(latitude, 'N', 'S')),
+        (GPS_LATITUDE_TAG, angle(latitude)),
+        (GPS_LONGITUDE_REF_TAG, reference(longitude, 'E', 'W')),
+        (GPS_LONGITUDE_TAG, angle(longitude)),
+    ];
+    if let Some(altitude) = altitude {
+        entries.push((
+            GPS_ALTITUDE_REF_TAG,
+            ExifValue::Byte(vec![(altitude < 0.0) as u8]),
+        ));
+        entries.push((
+            GPS_ALTITUDE_TAG,
+            ExifValue::Rational(vec![(
+                (altitude.abs() * GPS_PRECISION as f64).round() as u32,
+                GPS_PRECISION,
+            )]),
+        ));
+    }
+    (GPS_INFO_TAG, ExifValue::Ifd(entries))
+}
+
+/// Build the payload of an EXIF APP1 segment from TIFF-structured data
+pub fn make_exif(tiff: &[u8]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend(EXIF_HEADER);
+    data.extend(tiff);
+    data
+}
+
+/// TIFF-structured data of an EXIF APP1 segment payload
+pub fn tiff_from_exif(payload: &[u8]) -> Option<&[u8]> {
+    payload.strip_prefix(EXIF_HEADER)
+}
+
+/// Overwrite the orientation of existing TIFF-structured EXIF data, and drop the IFD1 thumbnail which would show the original image. None if malformed, false if IFD0 has no orientation entry
+pub fn retarget(tiff: &mut [u8], orientation: u16) -> Option<bool> {
+    let little_endian = tiff.starts_with(&LITTLE_ENDIAN_MARKER[..2]);
+    let u16_at = |tiff: &[u8], at: usize| -> Option<u16> {
+        let bytes = tiff.get(at..at + 2)?.try_into().unwrap();
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let ifd0 = tiff.get(4..8)?.try_into().unwrap();
+    let ifd0 = if little_endian {
+        u32::from_le_bytes(ifd0)
+    } else {
+        u32::from_be_bytes(ifd0)
+    } as usize;
+
+    let count = u16_at(tiff, ifd0)? as usize;
+    let mut found = false;
+    for entry in (0..count).map(|i| ifd0 + 2 + i * 12) {
+        if u16_at(tiff, entry)? == ORIENTATION_TAG && u16_at(tiff, entry + 2)? == TYPE_SHORT {
+            let value = if little_endian {
+                orientation.to_le_bytes()
+            } else {
+                orientation.to_be_bytes()
+            };
+            tiff.get_mut(entry + 8..entry + 10)?.copy_from_slice(&value);
+            found = true;
+        }
+    }
+    let next_ifd = ifd0 + 2 + count * 12;
+    tiff.get_mut(next_ifd..next_ifd + 4)?.fill(0);
+    Some(found)
+}
+
+/// Remove the GPS IFD of TIFF-structured EXIF data in place: its entry leaves IFD0, and the IFD and its values are zeroed so no location is left in the bytes. None if malformed, false if there is no GPS IFD
+pub fn strip_gps(tiff: &mut [u8]) -> Option<bool> {
+    let little_endian = tiff.starts_with(&LITTLE_ENDIAN_MARKER[..2]);
+    let u16_at = |tiff: &[u8], at: usize| -> Option<u16> {
+        let bytes = tiff.get(at..at + 2)?.try_into().unwrap();
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let u32_at = |tiff: &[u8], at: usize| -> Option<usize> {
+        let bytes = tiff.get(at..at + 4)?.try_into().unwrap();
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        } as usize)
+    };
+
+    let ifd0 = u32_at(tiff, 4)?;
+    let count = u16_at(tiff, ifd0)? as usize;
+    let ifd0_end = ifd0 + 2 + count * 12 + 4;
+    tiff.get(..ifd0_end)?;
+    let Some(entry) = (0..count)
+        .map(|i| ifd0 + 2 + i * 12)
+        .find(|&entry| u16_at(tiff, entry) == Some(GPS_INFO_TAG))
+    else {
+        return Some(false);
+    };
+
+    // Values too large for their entry, then the GPS IFD itself
+    let gps = u32_at(tiff, entry + 8)?;
+    let gps_count = u16_at(tiff, gps)? as usize;
+    let gps_end = gps + 2 + gps_count * 12 + 4;
+    tiff.get(..gps_end)?;
+    for field in (0..gps_count).map(|i| gps + 2 + i * 12) {
+        let size = match u16_at(tiff, field + 2)? {
+            // Bytes, ASCII, signed bytes, undefined
+            1 | 2 | 6 | 7 => 1,
+            // Shorts
+            3 | 8 => 2,
+            // Longs, floats
+            4 | 9 | 11 => 4,
+            // Rationals, doubles
+            5 | 10 | 12 => 8,
+            _ => return None,
+        } * u32_at(tiff, field + 4)?;
+        if size > 4 {
+            let offset = u32_at(tiff, field + 8)?;
+            tiff.get_mut(offset..offset + size)?.fill(0);
+        }
+    }
+    tiff[gps..gps_end].fill(0);
+
+    // Following entries and the next IFD offset move over the GPS entry
+    tiff.copy_within(entry + 12..ifd0_end, entry);
+    tiff[ifd0_end - 12..ifd0_end].fill(0);
+    let count = count as u16 - 1;
+    tiff[ifd0..ifd0 + 2].copy_from_slice(&if little_endian {
+        count.to_le_bytes()
+    } else {
+        count.to_be_bytes()
+    });
+    Some(true)
+}
@@ -0,0 +1,69 @@
+use num_traits::{clamp, Float};
+
+use crate::{
+    color::{LuminanceCoefficients, Rgb},
+    trims::SdrTrims,
+};
+
+/// What gains are the ratio of, between HDR and SDR pixels
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum GainBasis {
+    /// Relative luminance
+    #[default]
+    Luminance,
+    /// Largest component, so saturated lights get the boost of their brightest channel
+    MaxRgb,
+    /// Mean of components, weighing primaries equally
+    MeanRgb,
+}
+
+impl GainBasis {
+    /// Value of a pixel on this basis
+    pub fn value<F: Float>(&self, pixel: &Rgb<F>, coefficients: &LuminanceCoefficients<F>) -> F {
+        match self {
+            GainBasis::Luminance => coefficients.luminance(pixel),
+            GainBasis::MaxRgb => pixel.r.max(pixel.g).max(pixel.b),
+            GainBasis::MeanRgb => (pixel.r + pixel.g + pixel.b) / F::from(3.0).unwrap(),
+        }
+    }
+}
+
+/// Exposed and trimmed SDR rendition of a linear pixel, before clipping
+pub fn sdr_pixel<F: Float>(
+    pixel: &Rgb<F>,
+    factor: F,
+    trims: &SdrTrims,
+    coefficients: &LuminanceCoefficients<F>,
+) -> Rgb<F> {
+    let exposed = Rgb {
+        r: pixel.r * factor,
+        g: pixel.g * factor,
+        b: pixel.b * factor,
+    };
+    trims.apply(exposed, coefficients)
+}
+
+/// Compute gain value for this pixel, used to build gain map for Ultra HDR JPEG
+pub fn calculate_gain<F: Float>(
+    pixel: &Rgb<F>,
+    sdr_pixel: &Rgb<F>,
+    basis: GainBasis,
+    coefficients: &LuminanceCoefficients<F>,
+    offset_hdr: F,
+    offset_sdr: F,
+) -> F {
+    // Out-of-gamut conversions can still give negative values, which have no meaningful gain
+    let hdr_value = basis.value(pixel, coefficients).max(F::zero());
+
+    let clip = |v: F| clamp(v, F::zero(), F::one());
+    let sdr_value = basis.value(
+        &Rgb {
+            r: clip(sdr_pixel.r),
+            g: clip(sdr_pixel.g),
+            b: clip(sdr_pixel.b),
+        },
+        coefficients,
+    );
+
+    (hdr_value + offset_hdr) / (sdr_value + offset_sdr)
+}
@@ -0,0 +1,67 @@
+/// Histogram covers log2 gains in this range, values outside land in the first or last bin
+use alloc::{vec, vec::Vec};
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+const HISTOGRAM_MIN_LOG2: f32 = -16.0;
+const HISTOGRAM_MAX_LOG2: f32 = 16.0;
+/// 1/128 of a stop per bin
+const HISTOGRAM_BINS: usize = 4096;
+
+/// Gain statistics accumulated while pixels are processed, so gains never need to be scanned again. Partial statistics of separate chunks can be merged
+#[derive(Clone, Debug)]
+pub struct GainStats {
+    pub min: f32,
+    pub max: f32,
+    count: u64,
+    histogram: Vec<u64>,
+}
+
+impl Default for GainStats {
+    fn default() -> Self {
+        GainStats {
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            count: 0,
+            histogram: vec![0; HISTOGRAM_BINS],
+        }
+    }
+}
+
+impl GainStats {
+    pub fn add(&mut self, gain: f32) {
+        self.min = self.min.min(gain);
+        self.max = self.max.max(gain);
+        self.count += 1;
+        let position =
+            (gain.log2() - HISTOGRAM_MIN_LOG2) / (HISTOGRAM_MAX_LOG2 - HISTOGRAM_MIN_LOG2);
+        let bin = (position * HISTOGRAM_BINS as f32) as isize;
+        self.histogram[bin.clamp(0, HISTOGRAM_BINS as isize - 1) as usize] += 1;
+    }
+
+    pub fn merge(&mut self, other: &GainStats) {
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.count += other.count;
+        for (bin, other_bin) in self.histogram.iter_mut().zip(&other.histogram) {
+            *bin += other_bin
+        }
+    }
+
+    /// Approximate log2 gain below which this percentage (0 - 100) of pixels fall, never beyond actual min and max
+    pub fn percentile_log2(&self, percentile: f32) -> f32 {
+        let target =
+            (percentile.clamp(0.0, 100.0) as f64 / 100.0 * self.count as f64).ceil() as u64;
+        let mut cumulative = 0;
+        let bin = self
+            .histogram
+            .iter()
+            .position(|count| {
+                cumulative += count;
+                cumulative >= target.max(1)
+            })
+            .unwrap_or(HISTOGRAM_BINS - 1);
+        let upper_edge = HISTOGRAM_MIN_LOG2
+            + (bin + 1) as f32 / HISTOGRAM_BINS as f32 * (HISTOGRAM_MAX_LOG2 - HISTOGRAM_MIN_LOG2);
+        upper_edge.clamp(self.min.log2(), self.max.log2())
+    }
+}
@@ -0,0 +1,43 @@
+// ISO 21496-1 gain map metadata, binary form as serialized by libultrahdr
+
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+/// All fractions share this denominator, exact for power-of-two offsets
+const DENOMINATOR: u32 = 1 << 20;
+/// Fractions are stored with a common denominator
+const COMMON_DENOMINATOR_FLAG: u8 = 1 << 3;
+
+/// Single channel gain map metadata, applied to the SDR base image. Values are log2 like in the XMP metadata
+pub struct GainMapMetadata {
+    pub gain_map_min: f32,
+    pub gain_map_max: f32,
+    pub gamma: f32,
+    pub offset_sdr: f32,
+    pub offset_hdr: f32,
+    pub hdr_capacity_min: f32,
+    pub hdr_capacity_max: f32,
+}
+
+impl GainMapMetadata {
+    pub fn bytes(&self) -> Vec<u8> {
+        let signed = |v: f32| ((v * DENOMINATOR as f32).round() as i32).to_be_bytes();
+        let unsigned = |v: f32| ((v * DENOMINATOR as f32).round().max(0.0) as u32).to_be_bytes();
+
+        let mut data = Vec::with_capacity(37);
+        // Minimum version, writer version
+        data.extend(0u16.to_be_bytes());
+        data.extend(0u16.to_be_bytes());
+        data.push(COMMON_DENOMINATOR_FLAG);
+        data.extend(DENOMINATOR.to_be_bytes());
+        data.extend(unsigned(self.hdr_capacity_min));
+        data.extend(unsigned(self.hdr_capacity_max));
+        data.extend(signed(self.gain_map_min));
+        data.extend(signed(self.gain_map_max));
+        data.extend(unsigned(self.gamma));
+        data.extend(signed(self.offset_sdr));
+        data.extend(signed(self.offset_hdr));
+        data
+    }
+}
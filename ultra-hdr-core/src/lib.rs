@@ -0,0 +1,29 @@
+//! Pure computations behind exr2ultra-hdr: color math, transfer functions, Gain Map computation, and serialization of container metadata (MPF, EXIF, Apple maker notes, ISO 21496-1, XMP). No file or image format dependencies, and `no_std` (with `alloc`) when the default `std` feature is disabled, floating point functions then coming from libm
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod adaptation;
+pub mod apple;
+pub mod bt2446;
+pub mod color;
+pub mod exif;
+pub mod gain;
+pub mod gain_stats;
+pub mod iso21496;
+pub mod mpf;
+pub mod perceptual;
+pub mod recovery_curve;
+pub mod transfer;
+pub mod trims;
+pub mod xmp;
+
+use nalgebra::SMatrix;
+
+// ----- Matrix type definitions
+
+pub type Matrix3x1f = SMatrix<f32, 3, 1>;
+pub type Matrix3x3f = SMatrix<f32, 3, 3>;
+pub type Matrix3x1d = SMatrix<f64, 3, 1>;
+pub type Matrix3x3d = SMatrix<f64, 3, 3>;
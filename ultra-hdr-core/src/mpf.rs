@@ -0,0 +1,214 @@
+// https://www.cipa.jp/std/documents/e/DC-X007-KEY_E.pdf
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::ops::Range;
+
+/// MP Entry attribute of the primary image (Baseline MP Primary Image, JPEG)
+pub const PRIMARY_IMAGE_ATTRIBUTE: u32 = 0x030000;
+/// MP Entry attribute of any other image (Undefined type, JPEG)
+pub const UNDEFINED_IMAGE_ATTRIBUTE: u32 = 0x000000;
+/// MP Entry attribute of a Large Thumbnail, VGA equivalent class
+pub const VGA_THUMBNAIL_ATTRIBUTE: u32 = 0x010001;
+/// MP Entry attribute of a Large Thumbnail, Full-HD equivalent class
+pub const FULL_HD_THUMBNAIL_ATTRIBUTE: u32 = 0x010002;
+
+const MPF_MAGIC: &[u8] = b"MPF\0";
+const LITTLE_ENDIAN_MARKER: &[u8] = &[0x49, 0x49, 0x2A, 0];
+
+const VERSION_TAG: u16 = 0xB000;
+const NUMBER_OF_IMAGES_TAG: u16 = 0xB001;
+const MP_ENTRY_TAG: u16 = 0xB002;
+
+const TYPE_LONG: u16 = 4;
+const TYPE_UNDEFINED: u16 = 7;
+
+/// Size of a single MP Entry
+const MP_ENTRY_LEN: usize = 16;
+/// Offset of the MP Entries relative to the endian marker: endian marker + IFD offset + count + 3 IFD entries + next IFD offset
+const MP_ENTRIES_OFFSET: usize = 4 + 4 + 2 + 3 * 12 + 4;
+
+/// Offset of the endian marker relative to the start of the APP2 payload, all MP Entry offsets are relative to it
+pub const OFFSET_BASE: usize = MPF_MAGIC.len();
+
+/// One image in the MP Index IFD
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MpEntry {
+    pub attribute: u32,
+    /// Size of the image, from SOI to EOI
+    pub size: u32,
+    /// Offset of the image relative to the endian marker of the MPF segment, zero for first image
+    pub offset: u32,
+    pub dependent_image_1: u16,
+    pub dependent_image_2: u16,
+}
+
+/// Pick the Large Thumbnail class matching these dimensions
+pub fn thumbnail_attribute(width: usize, height: usize) -> u32 {
+    if width.max(height) <= 640 {
+        VGA_THUMBNAIL_ATTRIBUTE
+    } else {
+        FULL_HD_THUMBNAIL_ATTRIBUTE
+    }
+}
+
+/// Length of an APP2 MPF segment payload (magic included) describing this many images
+pub fn index_len(image_count: usize) -> usize {
+    MPF_MAGIC.len() + MP_ENTRIES_OFFSET + MP_ENTRY_LEN * image_count
+}
+
+/// Build the APP2 MPF payload (magic included) containing the MP Index IFD for these images
+pub fn index(entries: &[MpEntry]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(index_len(entries.len()));
+    data.extend(MPF_MAGIC);
+    data.extend(LITTLE_ENDIAN_MARKER);
+    data.extend(8u32.to_le_bytes()); // Offset to first IFD
+
+    // ---- Index IFD
+    data.extend(3u16.to_le_bytes()); // Count
+                                     // -- Version
+    data.extend(VERSION_TAG.to_le_bytes());
+    data.extend(TYPE_UNDEFINED.to_le_bytes());
+    data.extend(4u32.to_le_bytes());
+    data.extend(b"0100");
+    // -- Number of images
+    data.extend(NUMBER_OF_IMAGES_TAG.to_le_bytes());
+    data.extend(TYPE_LONG.to_le_bytes());
+    data.extend(1u32.to_le_bytes());
+    data.extend((entries.len() as u32).to_le_bytes());
+    // -- MP Entry
+    data.extend(MP_ENTRY_TAG.to_le_bytes());
+    data.extend(TYPE_UNDEFINED.to_le_bytes());
+    data.extend(((MP_ENTRY_LEN * entries.len()) as u32).to_le_bytes());
+    data.extend((MP_ENTRIES_OFFSET as u32).to_le_bytes());
+    data.extend(0u32.to_le_bytes()); // Offset to next IFD, none
+
+    // ---- MP Entries
+    for entry in entries {
+        data.extend(entry.attribute.to_le_bytes());
+        data.extend(entry.size.to_le_bytes());
+        data.extend(entry.offset.to_le_bytes());
+        data.extend(entry.dependent_image_1.to_le_bytes());
+        data.extend(entry.dependent_image_2.to_le_bytes());
+    }
+
+    data
+}
+
+/// Is this APP2 payload an MPF segment ?
+pub fn is_index(payload: &[u8]) -> bool {
+    payload.starts_with(MPF_MAGIC)
+}
+
+/// Read the MP Index IFD of an APP2 MPF payload (magic included), in either byte order. Empty for segments without MP Entry, as found in images other than the first
+pub fn parse(payload: &[u8]) -> Result<Vec<MpEntry>, String> {
+    let invalid = || "Malformed MPF segment".to_string();
+    let tiff = payload.strip_prefix(MPF_MAGIC).ok_or_else(invalid)?;
+    let big_endian = match tiff.get(..4) {
+        Some([0x49, 0x49, 0x2A, 0]) => false,
+        Some([0x4D, 0x4D, 0, 0x2A]) => true,
+        _ => return Err(invalid()),
+    };
+    let u16_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 2)?.try_into().unwrap();
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().unwrap();
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let ifd = u32_at(4).ok_or_else(invalid)? as usize;
+    let count = u16_at(ifd).ok_or_else(invalid)? as usize;
+    let mut image_count = None;
+    let mut mp_entries = None;
+    for field in (0..count).map(|i| ifd + 2 + i * 12) {
+        match u16_at(field).ok_or_else(invalid)? {
+            NUMBER_OF_IMAGES_TAG => image_count = u32_at(field + 8),
+            MP_ENTRY_TAG => {
+                mp_entries = Some((u32_at(field + 4), u32_at(field + 8)));
+            }
+            _ => {}
+        }
+    }
+    // Images other than the first only have attributes
+    let Some(mp_entries) = mp_entries else {
+        return Ok(Vec::new());
+    };
+    let (Some(length), Some(offset)) = mp_entries else {
+        return Err(invalid());
+    };
+    let (length, offset) = (length as usize, offset as usize);
+    if length % MP_ENTRY_LEN != 0
+        || image_count.is_some_and(|n| n as usize != length / MP_ENTRY_LEN)
+    {
+        return Err("MP Entry does not match the number of images".to_string());
+    }
+
+    (offset..offset + length)
+        .step_by(MP_ENTRY_LEN)
+        .map(|entry| {
+            Some(MpEntry {
+                attribute: u32_at(entry)?,
+                size: u32_at(entry + 4)?,
+                offset: u32_at(entry + 8)?,
+                dependent_image_1: u16_at(entry + 12)?,
+                dependent_image_2: u16_at(entry + 14)?,
+            })
+        })
+        .collect::<Option<_>>()
+        .ok_or_else(invalid)
+}
+
+/// Find the MPF segment of a whole file and locate every image it lists, as byte ranges of `jpeg`. None when there is no MPF segment, empty when it has no index
+pub fn images(jpeg: &[u8]) -> Result<Option<Vec<Range<usize>>>, String> {
+    let mut position = 2;
+    let entries = loop {
+        let (Some(&[0xFF, marker]), Some(length)) = (
+            jpeg.get(position..position + 2),
+            jpeg.get(position + 2..position + 4),
+        ) else {
+            return Ok(None);
+        };
+        // Image data begins, no more segments
+        if marker == 0xDA {
+            return Ok(None);
+        }
+        let end = position + 2 + u16::from_be_bytes([length[0], length[1]]) as usize;
+        let payload = jpeg.get(position + 4..end).unwrap_or_default();
+        if marker == 0xE2 && is_index(payload) {
+            break parse(payload)?;
+        }
+        position = end;
+    };
+
+    let base = position + 4 + OFFSET_BASE;
+    entries
+        .iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let start = if index == 0 {
+                0
+            } else {
+                base + entry.offset as usize
+            };
+            let range = start..start + entry.size as usize;
+            if range.end > jpeg.len() {
+                return Err(format!("MPF image {} extends past end of file", index));
+            }
+            Ok(range)
+        })
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
@@ -0,0 +1,277 @@
+// http://www.brucelindbloom.com/index.html?Eqn_XYZ_to_Lab.html
+// http://www.brucelindbloom.com/index.html?Eqn_Lab_to_XYZ.html
+// https://hajim.rochester.edu/ece/sites/gsharma/ciede2000/ciede2000noteCRNA.pdf
+// https://doi.org/10.1002/col.22131 (CAM16 and CAM16-UCS, Li et al. 2017)
+
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+use crate::{color::CIEXYZCoords, Matrix3x1d, Matrix3x3d};
+
+const EPSILON: f32 = 216.0 / 24389.0;
+const KAPPA: f32 = 24389.0 / 27.0;
+
+// ----- CIE Lab
+
+/// CIE Lab of an XYZ color, relative to the XYZ of the reference white
+pub fn lab(color: CIEXYZCoords, white: CIEXYZCoords) -> [f32; 3] {
+    let f = |t: f32| {
+        if t > EPSILON {
+            t.cbrt()
+        } else {
+            (KAPPA * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (
+        f(color.x / white.x),
+        f(color.y / white.y),
+        f(color.z / white.z),
+    );
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// XYZ of a CIE Lab color, scaled like the XYZ of the reference white
+pub fn lab_to_xyz([l, a, b]: [f32; 3], white: CIEXYZCoords) -> CIEXYZCoords {
+    let fy = (l + 16.0) / 116.0;
+    let (fx, fz) = (fy + a / 500.0, fy - b / 200.0);
+    let f_inverse = |f: f32| {
+        if f.powi(3) > EPSILON {
+            f.powi(3)
+        } else {
+            (116.0 * f - 16.0) / KAPPA
+        }
+    };
+    let y = if l > KAPPA * EPSILON {
+        fy.powi(3)
+    } else {
+        l / KAPPA
+    };
+    CIEXYZCoords {
+        x: f_inverse(fx) * white.x,
+        y: y * white.y,
+        z: f_inverse(fz) * white.z,
+    }
+}
+
+/// CIEDE2000 color difference, closer to perceived differences than CIE76
+pub fn delta_e_2000(lab1: [f32; 3], lab2: [f32; 3]) -> f32 {
+    let [l1, a1, b1] = lab1.map(f64::from);
+    let [l2, a2, b2] = lab2.map(f64::from);
+    let pow7 = |v: f64| v.powi(7);
+    let c_bar = (a1.hypot(b1) + a2.hypot(b2)) / 2.0;
+    let g = 0.5 * (1.0 - (pow7(c_bar) / (pow7(c_bar) + pow7(25.0))).sqrt());
+    let (a1, a2) = ((1.0 + g) * a1, (1.0 + g) * a2);
+    let (c1, c2) = (a1.hypot(b1), a2.hypot(b2));
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            let degrees = b.atan2(a).to_degrees();
+            if degrees < 0.0 {
+                degrees + 360.0
+            } else {
+                degrees
+            }
+        }
+    };
+    let (h1, h2) = (hue(b1, a1), hue(b2, a2));
+
+    let delta_l = l2 - l1;
+    let delta_c = c2 - c1;
+    let delta_h = if c1 * c2 == 0.0 {
+        0.0
+    } else {
+        match h2 - h1 {
+            d if d > 180.0 => d - 360.0,
+            d if d < -180.0 => d + 360.0,
+            d => d,
+        }
+    };
+    let delta_h = 2.0 * (c1 * c2).sqrt() * (delta_h.to_radians() / 2.0).sin();
+
+    let l_bar = (l1 + l2) / 2.0;
+    let c_bar = (c1 + c2) / 2.0;
+    let h_bar = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+    let cos = |degrees: f64| degrees.to_radians().cos();
+    let t =
+        1.0 - 0.17 * cos(h_bar - 30.0) + 0.24 * cos(2.0 * h_bar) + 0.32 * cos(3.0 * h_bar + 6.0)
+            - 0.20 * cos(4.0 * h_bar - 63.0);
+    let delta_theta = 30.0 * (-((h_bar - 275.0) / 25.0).powi(2)).exp();
+    let r_c = 2.0 * (pow7(c_bar) / (pow7(c_bar) + pow7(25.0))).sqrt();
+    let s_l = 1.0 + 0.015 * (l_bar - 50.0).powi(2) / (20.0 + (l_bar - 50.0).powi(2)).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar;
+    let s_h = 1.0 + 0.015 * c_bar * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let (l, c, h) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l * l + c * c + h * h + r_t * c * h).sqrt() as f32
+}
+
+// ----- CAM16
+
+/// XYZ to the cone responses CAM16 adapts
+pub const M16: Matrix3x3d = Matrix3x3d::new(
+    0.401288, 0.650173, -0.051461, -0.250268, 1.204414, 0.045854, -0.002079, 0.048952, 0.953127,
+);
+
+/// Luminance of the surroundings relative to the white of the scene
+#[derive(Copy, Clone, Debug)]
+pub enum Surround {
+    /// Surface colors, prints viewed in a lit room
+    Average,
+    /// Displays in a dim room
+    Dim,
+    /// Projection in a dark room
+    Dark,
+}
+
+impl Surround {
+    /// F, c and Nc factors
+    fn factors(self) -> (f64, f64, f64) {
+        match self {
+            Surround::Average => (1.0, 0.69, 1.0),
+            Surround::Dim => (0.9, 0.59, 0.9),
+            Surround::Dark => (0.8, 0.525, 0.8),
+        }
+    }
+}
+
+/// Everything CAM16 derives from the viewing conditions, computed once for many colors
+#[derive(Copy, Clone, Debug)]
+pub struct ViewingConditions {
+    /// Degree of adaptation applied per cone response
+    d_rgb: Matrix3x1d,
+    f_l: f64,
+    n: f64,
+    z: f64,
+    n_bb: f64,
+    c: f64,
+    n_c: f64,
+    /// Achromatic response of the white
+    a_w: f64,
+}
+
+/// Appearance correlates of a color under some viewing conditions
+#[derive(Copy, Clone, Debug)]
+pub struct Cam16 {
+    /// Lightness J
+    pub lightness: f64,
+    /// Chroma C
+    pub chroma: f64,
+    /// Hue angle h in degrees
+    pub hue: f64,
+    /// Colorfulness M
+    pub colorfulness: f64,
+}
+
+impl ViewingConditions {
+    /// Conditions of a scene with the given white, as XYZ where Y is usually 100, adapting field luminance in cd/m² (often 20% of the white luminance), and background luminance in the scale of Y of white (often 20)
+    pub fn new(
+        white: Matrix3x1d,
+        adapting_luminance: f64,
+        background_luminance: f64,
+        surround: Surround,
+    ) -> ViewingConditions {
+        let (f, c, n_c) = surround.factors();
+        let rgb_w = M16 * white;
+        let d =
+            (f * (1.0 - (1.0 / 3.6) * ((-adapting_luminance - 42.0) / 92.0).exp())).clamp(0.0, 1.0);
+        let d_rgb = rgb_w.map(|v| d * white.y / v + 1.0 - d);
+
+        let k = 1.0 / (5.0 * adapting_luminance + 1.0);
+        let k4 = k.powi(4);
+        let f_l = 0.2 * k4 * (5.0 * adapting_luminance)
+            + 0.1 * (1.0 - k4).powi(2) * (5.0 * adapting_luminance).cbrt();
+        let n = background_luminance / white.y;
+        let z = 1.48 + n.sqrt();
+        let n_bb = 0.725 * n.powf(-0.2);
+
+        let mut conditions = ViewingConditions {
+            d_rgb,
+            f_l,
+            n,
+            z,
+            n_bb,
+            c,
+            n_c,
+            a_w: 0.0,
+        };
+        let rgb_aw = conditions.adapt(rgb_w);
+        conditions.a_w = conditions.achromatic(rgb_aw);
+        conditions
+    }
+
+    /// Adapted and compressed cone responses
+    fn adapt(&self, rgb: Matrix3x1d) -> Matrix3x1d {
+        rgb.component_mul(&self.d_rgb).map(|v| {
+            let x = (self.f_l * v.abs() / 100.0).powf(0.42);
+            v.signum() * 400.0 * x / (x + 27.13) + 0.1
+        })
+    }
+
+    fn achromatic(&self, rgb_a: Matrix3x1d) -> f64 {
+        (2.0 * rgb_a.x + rgb_a.y + rgb_a.z / 20.0 - 0.305) * self.n_bb
+    }
+
+    /// Appearance correlates of an XYZ color, in the scale of the XYZ of white
+    pub fn cam16(&self, xyz: Matrix3x1d) -> Cam16 {
+        let rgb_a = self.adapt(M16 * xyz);
+        let a = rgb_a.x - 12.0 * rgb_a.y / 11.0 + rgb_a.z / 11.0;
+        let b = (rgb_a.x + rgb_a.y - 2.0 * rgb_a.z) / 9.0;
+        let hue = b.atan2(a);
+        let e_t = 0.25 * ((hue + 2.0).cos() + 3.8);
+
+        let lightness = 100.0
+            * (self.achromatic(rgb_a) / self.a_w)
+                .max(0.0)
+                .powf(self.c * self.z);
+        let t = (50000.0 / 13.0 * self.n_c * self.n_bb * e_t * a.hypot(b))
+            / (rgb_a.x + rgb_a.y + 21.0 / 20.0 * rgb_a.z);
+        let chroma = t.max(0.0).powf(0.9)
+            * (lightness / 100.0).sqrt()
+            * (1.64 - 0.29f64.powf(self.n)).powf(0.73);
+        let degrees = hue.to_degrees();
+        Cam16 {
+            lightness,
+            chroma,
+            hue: if degrees < 0.0 {
+                degrees + 360.0
+            } else {
+                degrees
+            },
+            colorfulness: chroma * self.f_l.powf(0.25),
+        }
+    }
+
+    /// CAM16-UCS J', a' and b' of an XYZ color, in the scale of the XYZ of white
+    pub fn cam16_ucs(&self, xyz: Matrix3x1d) -> [f64; 3] {
+        self.cam16(xyz).ucs()
+    }
+}
+
+impl Cam16 {
+    /// CAM16-UCS J', a' and b', where euclidean distances match perceived differences
+    pub fn ucs(&self) -> [f64; 3] {
+        let j = 1.7 * self.lightness / (1.0 + 0.007 * self.lightness);
+        let m = (1.0 + 0.0228 * self.colorfulness).ln() / 0.0228;
+        let hue = self.hue.to_radians();
+        [j, m * hue.cos(), m * hue.sin()]
+    }
+}
+
+/// Color difference of two CAM16-UCS colors
+pub fn delta_e_cam16_ucs(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| (a - b).powi(2))
+        .sum::<f64>()
+        .sqrt()
+}
@@ -0,0 +1,61 @@
+// Curves from recovery values (where a log2 boost lies between the Gain Map minimum and maximum, 0.0 - 1.0) to encoded Gain Map values
+
+use num_traits::Float;
+
+use crate::transfer::{pq_eotf, pq_inverse_eotf};
+
+/// Namespace of exr2ultra-hdr's own Gain Map properties, ignored by other decoders
+pub const RECOVERY_CURVE_NAMESPACE: &str = "https://github.com/MarimeGui/exr2ultra-hdr/ns/1.0/";
+/// Value of the RecoveryCurve property for `RecoveryCurve::Pq`
+pub const PQ_CURVE_NAME: &str = "PQ";
+
+/// Luminance the largest boost is mapped to, PQ's peak
+const PQ_PEAK_NITS: f64 = 10000.0;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RecoveryCurve {
+    /// Power curve of hdrgm:Gamma, the only one of the specification
+    Power(f32),
+    /// PQ over the boosts of the Gain Map, the largest one mapped to 10000 nits: steps are even in perceived HDR luminance rather than in stops, so Gain Maps spanning many stops band less. Only exr2ultra-hdr decodes it, hdrgm:Gamma is then 1
+    Pq,
+}
+
+impl RecoveryCurve {
+    /// Encoded value of a recovery, for a Gain Map spanning `stops` from minimum to maximum
+    pub fn encode<F: Float>(&self, recovery: F, stops: F) -> F {
+        match *self {
+            RecoveryCurve::Power(gamma) => recovery.powf(F::from(gamma).unwrap()),
+            // In f64, as signals of a few stops are all close to the peak
+            RecoveryCurve::Pq => {
+                let (recovery, stops) = (recovery.to_f64().unwrap(), stops.to_f64().unwrap());
+                if stops <= 0.0 {
+                    return F::from(recovery).unwrap();
+                }
+                let black = pq_black(stops);
+                let signal = pq_inverse_eotf(PQ_PEAK_NITS * ((recovery - 1.0) * stops).exp2());
+                F::from((signal - black) / (1.0 - black)).unwrap()
+            }
+        }
+    }
+
+    /// Recovery of an encoded value, inverse of `encode`
+    pub fn decode(&self, encoded: f32, stops: f32) -> f32 {
+        match *self {
+            RecoveryCurve::Power(gamma) => encoded.powf(gamma.recip()),
+            RecoveryCurve::Pq => {
+                if stops <= 0.0 {
+                    return encoded;
+                }
+                let (encoded, stops) = (encoded as f64, stops as f64);
+                let black = pq_black(stops);
+                let nits = pq_eotf(black + encoded * (1.0 - black));
+                (1.0 + (nits / PQ_PEAK_NITS).log2() / stops).max(0.0) as f32
+            }
+        }
+    }
+}
+
+/// PQ signal of the smallest boost, `stops` below the peak
+fn pq_black(stops: f64) -> f64 {
+    pq_inverse_eotf(PQ_PEAK_NITS * (-stops).exp2())
+}
@@ -0,0 +1,89 @@
+use num_traits::Float;
+
+// https://www.itu.int/rec/R-REC-BT.2100
+const M1: f64 = 2610.0 / 16384.0;
+const M2: f64 = 2523.0 / 4096.0 * 128.0;
+const C1: f64 = 3424.0 / 4096.0;
+const C2: f64 = 2413.0 / 4096.0 * 32.0;
+const C3: f64 = 2392.0 / 4096.0 * 32.0;
+const PQ_PEAK: f64 = 10000.0;
+
+/// PQ non-linear signal to display luminance in nits
+pub fn pq_eotf<F: Float>(signal: F) -> F {
+    let [m1, m2, c1, c2, c3] = [M1, M2, C1, C2, C3].map(constant::<F>);
+    let e = signal.max(F::zero()).min(F::one()).powf(m2.recip());
+    constant::<F>(PQ_PEAK) * ((e - c1).max(F::zero()) / (c2 - c3 * e)).powf(m1.recip())
+}
+
+/// Display luminance in nits to PQ non-linear signal
+pub fn pq_inverse_eotf<F: Float>(nits: F) -> F {
+    let [m1, m2, c1, c2, c3] = [M1, M2, C1, C2, C3].map(constant::<F>);
+    let y = (nits / constant(PQ_PEAK))
+        .max(F::zero())
+        .min(F::one())
+        .powf(m1);
+    ((c1 + c2 * y) / (F::one() + c3 * y)).powf(m2)
+}
+
+/// HLG non-linear signal to normalized scene light in 0.0 - 1.0
+pub fn hlg_inverse_oetf(signal: f32) -> f32 {
+    const A: f32 = 0.17883277;
+    const B: f32 = 1.0 - 4.0 * A;
+    const C: f32 = 0.559_910_7;
+
+    let signal = signal.clamp(0.0, 1.0);
+    if signal <= 0.5 {
+        signal * signal / 3.0
+    } else {
+        (((signal - C) / A).exp() + B) / 12.0
+    }
+}
+
+/// Float constant of any float type
+fn constant<F: Float>(value: f64) -> F {
+    F::from(value).unwrap()
+}
+
+// https://en.wikipedia.org/wiki/SRGB
+// There is another definition in the ITU document...
+pub fn srgb_gamma<F: Float>(linear_color: F) -> F {
+    if linear_color <= constant(0.0031308) {
+        constant::<F>(12.92) * linear_color
+    } else {
+        constant::<F>(1.055) * linear_color.powf(constant::<F>(2.4).recip()) - constant(0.055)
+    }
+}
+
+pub fn srgb_inverse_gamma(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// https://www.itu.int/rec/R-REC-BT.1886
+const BT1886_GAMMA: f64 = 2.4;
+
+/// Gain and black lift of the BT.1886 EOTF, for a display with white at 1.0 and this black level
+pub fn bt1886_constants<F: Float>(black: F) -> (F, F) {
+    let gamma = constant::<F>(BT1886_GAMMA);
+    let range = F::one() - black.powf(gamma.recip());
+    (range.powf(gamma), black.powf(gamma.recip()) / range)
+}
+
+/// Relative display light to BT.1886 signal, for a display with this black level relative to white. 0.0 lands on display black rather than being crushed below it
+pub fn bt1886_inverse_eotf<F: Float>(linear_color: F, black: F) -> F {
+    let (a, b) = bt1886_constants(black);
+    ((black + (F::one() - black) * linear_color) / a).powf(constant::<F>(BT1886_GAMMA).recip()) - b
+}
+
+/// BT.1886 signal to relative display light, inverse of `bt1886_inverse_eotf`
+pub fn bt1886_eotf(signal: f32, black: f32) -> f32 {
+    let (a, b) = bt1886_constants(black);
+    (a * (signal + b).max(0.0).powf(BT1886_GAMMA as f32) - black) / (1.0 - black)
+}
+
+pub fn gamma<F: Float>(linear_color: F, gamma: F) -> F {
+    linear_color.powf(gamma.recip())
+}
@@ -0,0 +1,91 @@
+use num_traits::Float;
+
+use crate::{
+    bt2446::Bt2446,
+    color::{LuminanceCoefficients, Rgb},
+};
+
+/// Creative adjustments of the SDR rendition only. The Gain Map makes up for them, so the HDR rendition still matches scene data
+#[derive(Debug, Copy, Clone)]
+pub struct SdrTrims {
+    /// BT.2446 down-conversion replacing clipping, applied before other trims
+    pub down_conversion: Option<Bt2446>,
+    /// 0 is grayscale, 1 unchanged
+    pub saturation: f32,
+    /// Slope around the pivot in log2 luminance, 1 unchanged
+    pub contrast: f32,
+    /// Exposed linear luminance left in place by contrast
+    pub pivot: f32,
+    /// Exposed linear component value above which the shoulder rolls highlights off
+    pub knee_start: f32,
+    /// How much the shoulder compresses highlights, from 0 (hard clip at 1) to 1 (components approach 1 without reaching it)
+    pub knee_strength: f32,
+}
+
+impl SdrTrims {
+    pub fn is_identity(&self) -> bool {
+        self.down_conversion.is_none()
+            && self.saturation == 1.0
+            && self.contrast == 1.0
+            && self.knee_strength == 0.0
+    }
+
+    /// Apply to an exposed linear pixel. Down-conversion comes first, then contrast scales the whole pixel to keep hue, saturation moves components towards luminance without changing it, then the shoulder rolls off each component
+    pub fn apply<F: Float>(
+        &self,
+        pixel: Rgb<F>,
+        coefficients: &LuminanceCoefficients<F>,
+    ) -> Rgb<F> {
+        if self.is_identity() {
+            return pixel;
+        }
+        let value = |v: f32| F::from(v).unwrap();
+        let pixel = match self.down_conversion {
+            Some(method) => method.apply(pixel, coefficients),
+            None => pixel,
+        };
+
+        let pixel = if self.saturation == 1.0 && self.contrast == 1.0 {
+            pixel
+        } else {
+            let (contrast, pivot) = (value(self.contrast), value(self.pivot));
+            let y = coefficients.luminance(&pixel);
+            let scale = if y > F::zero() && self.contrast != 1.0 {
+                pivot * (y / pivot).powf(contrast) / y
+            } else {
+                F::one()
+            };
+            let y = y * scale;
+
+            let saturate = |v: F| y + (v * scale - y) * value(self.saturation);
+            Rgb {
+                r: saturate(pixel.r),
+                g: saturate(pixel.g),
+                b: saturate(pixel.b),
+            }
+        };
+
+        if self.knee_strength == 0.0 {
+            return pixel;
+        }
+        Rgb {
+            r: self.shoulder(pixel.r),
+            g: self.shoulder(pixel.g),
+            b: self.shoulder(pixel.b),
+        }
+    }
+
+    /// Exponential roll-off above the knee start, with the same slope as the input there so the curve stays smooth. Tends to start + (1 - start) / strength
+    fn shoulder<F: Float>(&self, value: F) -> F {
+        let (start, strength) = (
+            F::from(self.knee_start).unwrap(),
+            F::from(self.knee_strength).unwrap(),
+        );
+        if value <= start {
+            return value;
+        }
+        let range = F::one() - start;
+        let x = (value - start) / range;
+        start + range * (F::one() - (-strength * x).exp()) / strength
+    }
+}
@@ -0,0 +1,351 @@
+// https://developer.android.com/media/platform/hdr-image-format
+// https://helpx.adobe.com/camera-raw/using/gain-map.html
+
+use alloc::{
+    collections::BTreeMap,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::recovery_curve::{RecoveryCurve, PQ_CURVE_NAME, RECOVERY_CURVE_NAMESPACE};
+
+pub const RDF_NAMESPACE: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+pub const GAIN_MAP_NAMESPACE: &str = "http://ns.adobe.com/hdr-gain-map/1.0/";
+pub const CONTAINER_NAMESPACE: &str = "http://ns.google.com/photos/1.0/container/";
+pub const ITEM_NAMESPACE: &str = "http://ns.google.com/photos/1.0/container/item/";
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+const XMLNS: &str = "xmlns";
+
+/// Qualified names and unescaped values
+type Attributes<'a> = Vec<(&'a str, String)>;
+
+/// An element being parsed
+struct OpenElement {
+    /// Namespace of every prefix in scope
+    bindings: BTreeMap<String, String>,
+    namespace: String,
+    name: String,
+    has_children: bool,
+}
+
+/// A simple value of an XMP packet, set as an attribute or as element text
+#[derive(Debug, Clone)]
+pub struct Property {
+    pub namespace: String,
+    pub name: String,
+    pub value: String,
+}
+
+/// Every simple property of an XMP packet, in document order. Elements in the RDF namespace are structure only: the items of an rdf:Seq are reported as values of the property holding it
+pub fn parse(xmp: &str) -> Result<Vec<Property>, String> {
+    let mut properties = Vec::new();
+    let mut stack: Vec<OpenElement> = Vec::new();
+    let mut text = String::new();
+    let mut rest = xmp;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let skip_to = |rest: &str, end: &str| {
+            rest.find(end)
+                .map(|p| p + end.len())
+                .ok_or_else(|| format!("Unterminated {}", &rest[..rest.len().min(4)]))
+        };
+        if rest.starts_with("<?") {
+            rest = &rest[skip_to(rest, "?>")?..];
+            continue;
+        }
+        if rest.starts_with("<!--") {
+            rest = &rest[skip_to(rest, "-->")?..];
+            continue;
+        }
+        if rest.starts_with("<!") {
+            rest = &rest[skip_to(rest, ">")?..];
+            continue;
+        }
+
+        if let Some(end_tag) = rest.strip_prefix("</") {
+            let end = end_tag.find('>').ok_or("Unterminated end tag")?;
+            rest = &end_tag[end + 1..];
+            let element = stack.pop().ok_or("Unbalanced end tag")?;
+            if !element.has_children && !text.trim().is_empty() {
+                // Structural RDF elements give their value to the enclosing property
+                let owner = if element.namespace == RDF_NAMESPACE {
+                    stack
+                        .iter()
+                        .rev()
+                        .find(|e| e.namespace != RDF_NAMESPACE)
+                        .map(|e| (e.namespace.clone(), e.name.clone()))
+                } else {
+                    Some((element.namespace, element.name))
+                };
+                if let Some((namespace, name)) = owner {
+                    properties.push(Property {
+                        namespace,
+                        name,
+                        value: unescape(text.trim())?,
+                    })
+                }
+            }
+            text.clear();
+            continue;
+        }
+
+        // Start tag
+        text.clear();
+        let (tag, self_closing, after) = start_tag(&rest[1..])?;
+        rest = after;
+        let (element, attributes) = split_tag(tag)?;
+
+        // The xml prefix is bound by definition
+        let mut bindings = stack.last().map_or_else(
+            || BTreeMap::from([("xml".to_string(), XML_NAMESPACE.to_string())]),
+            |e| e.bindings.clone(),
+        );
+        for (name, value) in &attributes {
+            if *name == XMLNS {
+                bindings.insert(String::new(), value.clone());
+            } else if let Some(prefix) = name.strip_prefix("xmlns:") {
+                bindings.insert(prefix.to_string(), value.clone());
+            }
+        }
+        let resolve = |qualified: &str| {
+            let (prefix, name) = qualified.split_once(':').unwrap_or(("", qualified));
+            bindings
+                .get(prefix)
+                .map(|namespace| (namespace.clone(), name.to_string()))
+                .ok_or_else(|| format!("Undeclared namespace prefix in {}", qualified))
+        };
+
+        let (namespace, name) = resolve(element)?;
+        for (attribute, value) in &attributes {
+            // Unprefixed attributes have no namespace
+            if *attribute == XMLNS || attribute.starts_with("xmlns:") || !attribute.contains(':') {
+                continue;
+            }
+            let (namespace, name) = resolve(attribute)?;
+            if namespace != RDF_NAMESPACE {
+                properties.push(Property {
+                    namespace,
+                    name,
+                    value: value.clone(),
+                })
+            }
+        }
+        if let Some(parent) = stack.last_mut() {
+            parent.has_children = true;
+        }
+        if !self_closing {
+            stack.push(OpenElement {
+                bindings,
+                namespace,
+                name,
+                has_children: false,
+            });
+        }
+    }
+    if !stack.is_empty() {
+        return Err("Unclosed element".to_string());
+    }
+    Ok(properties)
+}
+
+/// Values of a property
+pub fn values<'a>(
+    properties: &'a [Property],
+    namespace: &'a str,
+    name: &'a str,
+) -> impl Iterator<Item = &'a str> {
+    properties
+        .iter()
+        .filter(move |p| p.namespace == namespace && p.name == name)
+        .map(|p| p.value.as_str())
+}
+
+/// Gain map parameters, in log2 space like the XMP. Single channel values are repeated
+#[derive(Debug, Clone)]
+pub struct GainMapXmp {
+    pub gain_map_min: [f32; 3],
+    pub gain_map_max: [f32; 3],
+    pub gamma: [f32; 3],
+    pub offset_sdr: [f32; 3],
+    pub offset_hdr: [f32; 3],
+    pub hdr_capacity_min: f32,
+    pub hdr_capacity_max: f32,
+    pub base_rendition_is_hdr: bool,
+    /// Curve of recovery values, from hdrgm:Gamma unless exr2ultra-hdr's RecoveryCurve property says otherwise
+    pub recovery_curve: RecoveryCurve,
+}
+
+impl GainMapXmp {
+    /// Read hdrgm properties, None if there is no gain map metadata (the primary image only has hdrgm:Version). Missing optional values take their default
+    pub fn from_properties(properties: &[Property]) -> Result<Option<GainMapXmp>, String> {
+        if values(properties, GAIN_MAP_NAMESPACE, "GainMapMax")
+            .next()
+            .is_none()
+        {
+            return Ok(None);
+        }
+        let channels = |name: &str, default: Option<f32>| -> Result<[f32; 3], String> {
+            let parsed = values(properties, GAIN_MAP_NAMESPACE, name)
+                .map(|v| {
+                    v.parse::<f32>()
+                        .map_err(|_| format!("hdrgm:{} is not a number: {}", name, v))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            match (parsed.as_slice(), default) {
+                (&[value], _) => Ok([value; 3]),
+                (&[r, g, b], _) => Ok([r, g, b]),
+                (&[], Some(default)) => Ok([default; 3]),
+                (&[], None) => Err(format!("Missing hdrgm:{}", name)),
+                _ => Err(format!("hdrgm:{} must have 1 or 3 values", name)),
+            }
+        };
+
+        let gain_map_max = channels("GainMapMax", None)?;
+        let gamma = channels("Gamma", Some(1.0))?;
+        let recovery_curve =
+            match values(properties, RECOVERY_CURVE_NAMESPACE, "RecoveryCurve").next() {
+                None => RecoveryCurve::Power(gamma[0]),
+                Some(PQ_CURVE_NAME) => RecoveryCurve::Pq,
+                Some(other) => return Err(format!("Unknown recovery curve: {}", other)),
+            };
+        Ok(Some(GainMapXmp {
+            gain_map_min: channels("GainMapMin", Some(0.0))?,
+            gain_map_max,
+            gamma,
+            offset_sdr: channels("OffsetSDR", Some(1.0 / 64.0))?,
+            offset_hdr: channels("OffsetHDR", Some(1.0 / 64.0))?,
+            hdr_capacity_min: channels("HDRCapacityMin", Some(0.0))?[0],
+            hdr_capacity_max: channels("HDRCapacityMax", Some(gain_map_max[0]))?[0],
+            base_rendition_is_hdr: values(properties, GAIN_MAP_NAMESPACE, "BaseRenditionIsHDR")
+                .next()
+                .is_some_and(|v| v.eq_ignore_ascii_case("true")),
+            recovery_curve,
+        }))
+    }
+}
+
+/// One image of a GContainer directory
+#[derive(Debug, Clone)]
+pub struct ContainerItem {
+    pub semantic: String,
+    pub mime: String,
+    /// Size in bytes, absent for the primary image
+    pub length: Option<u64>,
+}
+
+/// Images listed in the GContainer directory, in file order
+pub fn container_items(properties: &[Property]) -> Result<Vec<ContainerItem>, String> {
+    let mut items: Vec<ContainerItem> = Vec::new();
+    for property in properties.iter().filter(|p| p.namespace == ITEM_NAMESPACE) {
+        // Every item starts with its mandatory semantic
+        if property.name == "Semantic" {
+            items.push(ContainerItem {
+                semantic: property.value.clone(),
+                mime: String::new(),
+                length: None,
+            });
+            continue;
+        }
+        let item = items
+            .last_mut()
+            .ok_or_else(|| format!("Item:{} before Item:Semantic", property.name))?;
+        match property.name.as_str() {
+            "Mime" => item.mime = property.value.clone(),
+            "Length" => {
+                item.length = Some(
+                    property
+                        .value
+                        .parse()
+                        .map_err(|_| format!("Item:Length is not a size: {}", property.value))?,
+                )
+            }
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+/// Split a start tag after its `<`, returning its contents, whether it closes itself, and what follows
+fn start_tag(rest: &str) -> Result<(&str, bool, &str), String> {
+    let mut quote = None;
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '>') => {
+                let tag = &rest[..i];
+                return Ok(match tag.strip_suffix('/') {
+                    Some(tag) => (tag, true, &rest[i + 1..]),
+                    None => (tag, false, &rest[i + 1..]),
+                });
+            }
+            _ => {}
+        }
+    }
+    Err("Unterminated start tag".to_string())
+}
+
+/// Element name and attributes of a start tag
+fn split_tag(tag: &str) -> Result<(&str, Attributes<'_>), String> {
+    let tag = tag.trim();
+    let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+    let (element, mut rest) = tag.split_at(name_end);
+    if element.is_empty() {
+        return Err("Empty tag".to_string());
+    }
+    let mut attributes = Vec::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Ok((element, attributes));
+        }
+        let (name, value) = rest.split_once('=').ok_or("Attribute without value")?;
+        let value = value.trim_start();
+        let quote = value
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\''))
+            .ok_or("Unquoted attribute value")?;
+        let end = value[1..]
+            .find(quote)
+            .ok_or("Unterminated attribute value")?
+            + 1;
+        attributes.push((name.trim(), unescape(&value[1..end])?));
+        rest = &value[end + 1..];
+    }
+}
+
+/// Replace predefined entities and character references
+fn unescape(text: &str) -> Result<String, String> {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        let end = rest[start..].find(';').ok_or("Unterminated entity")? + start;
+        let entity = &rest[start + 1..end];
+        let character = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity
+                    .strip_prefix('#')
+                    .and_then(|decimal| decimal.parse().ok())
+                    .and_then(char::from_u32),
+            },
+        };
+        output.push(character.ok_or_else(|| format!("Unknown entity &{};", entity))?);
+        rest = &rest[end + 1..];
+    }
+    output.push_str(rest);
+    Ok(output)
+}
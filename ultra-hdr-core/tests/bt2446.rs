@@ -0,0 +1,91 @@
+//! BT.2446 down-conversions keep grays neutral, never reverse the order of luminances, and land the curves' anchor points where the Recommendation puts them.
+
+use ultra_hdr_core::{
+    bt2446::{Bt2446, HDR_REFERENCE_WHITE_NITS},
+    color::{CIExyCoords, Chromaticities, Rgb},
+};
+
+const METHODS: [Bt2446; 3] = [
+    Bt2446::A { peak_nits: 1000.0 },
+    Bt2446::A { peak_nits: 4000.0 },
+    Bt2446::C,
+];
+
+/// Rec. 2020, the color space BT.2446 is written for
+const REC_2020: Chromaticities = Chromaticities {
+    red: CIExyCoords { x: 0.708, y: 0.292 },
+    green: CIExyCoords { x: 0.170, y: 0.797 },
+    blue: CIExyCoords { x: 0.131, y: 0.046 },
+    white: CIExyCoords {
+        x: 0.3127,
+        y: 0.3290,
+    },
+};
+
+fn gray(value: f32) -> Rgb<f32> {
+    Rgb {
+        r: value,
+        g: value,
+        b: value,
+    }
+}
+
+/// Exposed linear values from deep shadows to 10000 nits
+fn levels() -> impl Iterator<Item = f32> {
+    (0..=400).map(|step| (step as f32 / 20.0 - 14.0).exp2() * 10000.0 / HDR_REFERENCE_WHITE_NITS)
+}
+
+#[test]
+fn grays_stay_neutral_and_ordered() {
+    let coefficients = REC_2020.luminance_values().unwrap();
+    for method in METHODS {
+        let mut previous = 0.0;
+        for level in levels() {
+            let sdr = method.apply(gray(level), &coefficients);
+            assert!(
+                (sdr.r - sdr.g).abs() < 1e-4 && (sdr.b - sdr.g).abs() < 1e-4,
+                "{:?} tinted gray {}: {:?}",
+                method,
+                level,
+                sdr
+            );
+            assert!(
+                sdr.g >= previous,
+                "{:?} reversed order at {}",
+                method,
+                level
+            );
+            previous = sdr.g;
+        }
+    }
+}
+
+#[test]
+fn method_a_maps_the_mastering_peak_to_sdr_peak() {
+    let coefficients = REC_2020.luminance_values().unwrap();
+    for peak_nits in [1000.0, 4000.0] {
+        let peak = gray(peak_nits / HDR_REFERENCE_WHITE_NITS);
+        let sdr = Bt2446::A { peak_nits }.apply(peak, &coefficients);
+        assert!(
+            (sdr.g - 1.0).abs() < 1e-3,
+            "{} nits gave {:?}",
+            peak_nits,
+            sdr
+        );
+    }
+}
+
+#[test]
+fn method_c_is_linear_below_the_inflection_point() {
+    let coefficients = REC_2020.luminance_values().unwrap();
+    // 50 nits, below the 69.8 nits inflection point, scaled by k1 = 0.83802 into 100 nits SDR
+    let level = 50.0 / HDR_REFERENCE_WHITE_NITS;
+    let sdr = Bt2446::C.apply(gray(level), &coefficients);
+    assert!((sdr.g - 50.0 * 0.83802 / 100.0).abs() < 1e-5, "{:?}", sdr);
+
+    // Continuous through the inflection point
+    let inflection = 58.5 / 0.83802 / HDR_REFERENCE_WHITE_NITS;
+    let below = Bt2446::C.apply(gray(inflection * 0.99999), &coefficients);
+    let above = Bt2446::C.apply(gray(inflection * 1.00001), &coefficients);
+    assert!((above.g - below.g).abs() < 1e-4, "{:?} {:?}", below, above);
+}
@@ -0,0 +1,161 @@
+//! Round-trip properties of the public matrix API: converting to another color space or white and back is the identity, on standard spaces and on generated primaries.
+
+use ultra_hdr_core::{
+    adaptation::{adaptation_matrix_f64, BRADFORD, CAT02, VON_KRIES, XYZ_SCALING},
+    color::{CIExyCoords, Chromaticities},
+    Matrix3x1d, Matrix3x3d, Matrix3x3f,
+};
+
+const fn xy(x: f32, y: f32) -> CIExyCoords {
+    CIExyCoords { x, y }
+}
+
+const D65: CIExyCoords = xy(0.3127, 0.3290);
+const D50: CIExyCoords = xy(0.34567, 0.35850);
+const ACES_WHITE: CIExyCoords = xy(0.32168, 0.33767);
+
+const REC_709: Chromaticities = Chromaticities {
+    red: xy(0.64, 0.33),
+    green: xy(0.30, 0.60),
+    blue: xy(0.15, 0.06),
+    white: D65,
+};
+const REC_2020: Chromaticities = Chromaticities {
+    red: xy(0.708, 0.292),
+    green: xy(0.170, 0.797),
+    blue: xy(0.131, 0.046),
+    white: D65,
+};
+const DISPLAY_P3: Chromaticities = Chromaticities {
+    red: xy(0.680, 0.320),
+    green: xy(0.265, 0.690),
+    blue: xy(0.150, 0.060),
+    white: D65,
+};
+/// Blue has a negative y, making its XYZ huge and the primaries matrix close to singular
+const ACES_AP0: Chromaticities = Chromaticities {
+    red: xy(0.7347, 0.2653),
+    green: xy(0.0, 1.0),
+    blue: xy(0.0001, -0.0770),
+    white: ACES_WHITE,
+};
+const ACES_AP1: Chromaticities = Chromaticities {
+    red: xy(0.713, 0.293),
+    green: xy(0.165, 0.830),
+    blue: xy(0.128, 0.044),
+    white: ACES_WHITE,
+};
+
+const SPACES: [Chromaticities; 5] = [REC_709, REC_2020, DISPLAY_P3, ACES_AP0, ACES_AP1];
+
+/// Largest absolute difference with the identity matrix
+fn distance_to_identity(matrix: &Matrix3x3d) -> f64 {
+    (matrix - Matrix3x3d::identity()).abs().max()
+}
+
+/// Pseudo-random primaries and whites around Rec. 709's, the same on every run so failures reproduce
+fn generated_spaces(count: usize) -> Vec<Chromaticities> {
+    let mut state: u64 = 0x2545f4914f6cdd1d;
+    let mut jitter = move |c: CIExyCoords| {
+        let mut next = || {
+            state = state
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((state >> 40) as f32 / (1u64 << 24) as f32 - 0.5) * 0.1
+        };
+        xy(c.x + next(), c.y + next())
+    };
+    (0..count)
+        .map(|_| Chromaticities {
+            red: jitter(REC_709.red),
+            green: jitter(REC_709.green),
+            blue: jitter(REC_709.blue),
+            white: jitter(D65),
+        })
+        .collect()
+}
+
+fn all_spaces() -> Vec<Chromaticities> {
+    SPACES.into_iter().chain(generated_spaces(50)).collect()
+}
+
+#[test]
+fn rgb_to_xyz_inverts() {
+    for space in all_spaces() {
+        let to_xyz = space.rgb_to_xyz_matrix_f64().unwrap();
+        let from_xyz = space.xyz_to_rgb_matrix_f64().unwrap();
+        assert!(
+            distance_to_identity(&(from_xyz * to_xyz)) < 1e-12,
+            "{:?}",
+            space
+        );
+    }
+}
+
+#[test]
+fn white_maps_to_white_point() {
+    for space in all_spaces() {
+        let white = space.rgb_to_xyz_matrix_f64().unwrap() * Matrix3x1d::repeat(1.0);
+        assert!(
+            (white - space.white.to_xyz_f64()).abs().max() < 1e-12,
+            "{:?}",
+            space
+        );
+        // Luminance of white is 1
+        let coefficients = space.luminance_values().unwrap();
+        let sum = coefficients.red + coefficients.green + coefficients.blue;
+        assert!((sum - 1.0).abs() < 1e-5, "{:?}", space);
+    }
+}
+
+#[test]
+fn space_conversion_round_trips() {
+    let spaces = all_spaces();
+    for source in &spaces {
+        for destination in &spaces {
+            let there = source.rgb_space_conversion_matrix_f64(destination).unwrap();
+            let back = destination.rgb_space_conversion_matrix_f64(source).unwrap();
+            assert!(
+                distance_to_identity(&(back * there)) < 1e-10,
+                "{:?} -> {:?}",
+                source,
+                destination
+            );
+        }
+    }
+}
+
+#[test]
+fn f32_matrices_are_rounded_f64_ones() {
+    for source in &SPACES {
+        for destination in &SPACES {
+            let single: Matrix3x3f = source.rgb_space_conversion_matrix(destination).unwrap();
+            let double = source.rgb_space_conversion_matrix_f64(destination).unwrap();
+            let error = (single.cast::<f64>() - double).abs().max();
+            assert!(
+                error <= double.abs().max() * f32::EPSILON as f64,
+                "{:?} -> {:?}",
+                source,
+                destination
+            );
+        }
+    }
+}
+
+#[test]
+fn adaptation_round_trips() {
+    let whites = [D65, D50, ACES_WHITE, xy(0.28, 0.29), xy(0.45, 0.41)];
+    for cone in [BRADFORD, CAT02, VON_KRIES, XYZ_SCALING] {
+        for &source in &whites {
+            for &destination in &whites {
+                let there = adaptation_matrix_f64(&cone, source, destination).unwrap();
+                let back = adaptation_matrix_f64(&cone, destination, source).unwrap();
+                assert!(distance_to_identity(&(back * there)) < 1e-12);
+
+                // Source white becomes destination white
+                let adapted = there * source.to_xyz_f64();
+                assert!((adapted - destination.to_xyz_f64()).abs().max() < 1e-12);
+            }
+        }
+    }
+}
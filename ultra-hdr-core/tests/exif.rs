@@ -0,0 +1,46 @@
+//! Stripping GPS from EXIF leaves no trace of the location in the bytes and keeps every other entry readable.
+
+use ultra_hdr_core::exif::{gps_entry, make_tiff, strip_gps, ExifValue, ARTIST_TAG, GPS_INFO_TAG};
+
+/// Seconds of arc of the latitude below, 48°30'31", as stored
+const LATITUDE_SECONDS: u32 = 31 * 10000;
+
+fn tiff() -> Vec<u8> {
+    make_tiff(&[
+        (ARTIST_TAG, ExifValue::Ascii("Someone".to_string())),
+        gps_entry(48.5 + 31.0 / 3600.0, 2.25, Some(35.0)),
+    ])
+}
+
+/// Tags of IFD0, little-endian as written by `make_tiff`
+fn ifd0_tags(tiff: &[u8]) -> Vec<u16> {
+    let ifd0 = u32::from_le_bytes(tiff[4..8].try_into().unwrap()) as usize;
+    let count = u16::from_le_bytes(tiff[ifd0..ifd0 + 2].try_into().unwrap()) as usize;
+    (0..count)
+        .map(|i| ifd0 + 2 + i * 12)
+        .map(|entry| u16::from_le_bytes(tiff[entry..entry + 2].try_into().unwrap()))
+        .collect()
+}
+
+#[test]
+fn gps_is_removed_and_zeroed() {
+    let mut tiff = tiff();
+    let size = tiff.len();
+    assert_eq!(ifd0_tags(&tiff), [ARTIST_TAG, GPS_INFO_TAG]);
+    assert!(tiff.windows(4).any(|w| w == LATITUDE_SECONDS.to_le_bytes()));
+
+    assert_eq!(strip_gps(&mut tiff), Some(true));
+    assert_eq!(tiff.len(), size);
+    assert_eq!(ifd0_tags(&tiff), [ARTIST_TAG]);
+    assert!(!tiff.windows(4).any(|w| w == LATITUDE_SECONDS.to_le_bytes()));
+    assert!(tiff.windows(7).any(|w| w == b"Someone"));
+
+    assert_eq!(strip_gps(&mut tiff), Some(false));
+}
+
+#[test]
+fn malformed_exif_is_reported() {
+    let mut tiff = tiff();
+    tiff.truncate(20);
+    assert_eq!(strip_gps(&mut tiff), None);
+}
@@ -0,0 +1,70 @@
+//! Every gain basis agrees on grays, and max RGB gives saturated lights the boost of their brightest channel where luminance falls short of it.
+
+use ultra_hdr_core::{
+    color::{LuminanceCoefficients, Rgb},
+    gain::{calculate_gain, GainBasis},
+};
+
+const BASES: [GainBasis; 3] = [GainBasis::Luminance, GainBasis::MaxRgb, GainBasis::MeanRgb];
+
+/// Rec. 709 luminance coefficients
+const REC_709: LuminanceCoefficients = LuminanceCoefficients {
+    red: 0.2126,
+    green: 0.7152,
+    blue: 0.0722,
+};
+
+/// Default Gain Map offsets
+const OFFSET: f32 = 1.0 / 64.0;
+
+fn gain(basis: GainBasis, hdr: Rgb<f32>) -> f32 {
+    let sdr = Rgb {
+        r: hdr.r.min(1.0),
+        g: hdr.g.min(1.0),
+        b: hdr.b.min(1.0),
+    };
+    calculate_gain(&hdr, &sdr, basis, &REC_709, OFFSET, OFFSET)
+}
+
+#[test]
+fn grays_get_the_same_gain() {
+    for level in [0.0, 0.25, 1.0, 2.0, 16.0] {
+        let gray = Rgb {
+            r: level,
+            g: level,
+            b: level,
+        };
+        let reference = gain(GainBasis::Luminance, gray);
+        for basis in BASES {
+            let gain = gain(basis, gray);
+            assert!(
+                (gain - reference).abs() < 1e-5,
+                "{:?} of gray {}: {} instead of {}",
+                basis,
+                level,
+                gain,
+                reference
+            );
+        }
+    }
+}
+
+#[test]
+fn max_rgb_boosts_saturated_lights_fully() {
+    // A blue light 4 times brighter than SDR white, clipped to 1 in SDR
+    let light = Rgb {
+        r: 0.0,
+        g: 0.0,
+        b: 4.0,
+    };
+    let luminance = gain(GainBasis::Luminance, light);
+    let max_rgb = gain(GainBasis::MaxRgb, light);
+    assert!(
+        max_rgb > luminance,
+        "max RGB gain {} is below luminance gain {}",
+        max_rgb,
+        luminance
+    );
+    // Offsets keep it just short of the full ratio
+    assert!((max_rgb - 4.0).abs() < 0.05, "max RGB gain is {}", max_rgb);
+}
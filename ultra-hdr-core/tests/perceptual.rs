@@ -0,0 +1,92 @@
+//! Lab, CIEDE2000 and CAM16 against published reference values: Sharma's CIEDE2000 test data and the worked CAM16 example of colour-science.
+
+use ultra_hdr_core::{
+    color::CIEXYZCoords,
+    perceptual::{delta_e_2000, delta_e_cam16_ucs, lab, lab_to_xyz, Surround, ViewingConditions},
+    Matrix3x1d,
+};
+
+const D65: CIEXYZCoords = CIEXYZCoords {
+    x: 0.95047,
+    y: 1.0,
+    z: 1.08883,
+};
+
+#[test]
+fn lab_round_trips() {
+    let white = lab(D65, D65);
+    assert!((white[0] - 100.0).abs() < 1e-4);
+    assert!(white[1].abs() < 1e-4 && white[2].abs() < 1e-4);
+
+    // Dark colors go through the linear segment
+    for color in [
+        CIEXYZCoords {
+            x: 0.2,
+            y: 0.1,
+            z: 0.7,
+        },
+        CIEXYZCoords {
+            x: 0.002,
+            y: 0.003,
+            z: 0.001,
+        },
+    ] {
+        let back = lab_to_xyz(lab(color, D65), D65);
+        for (a, b) in [(color.x, back.x), (color.y, back.y), (color.z, back.z)] {
+            assert!((a - b).abs() < 1e-5, "{:?} came back as {:?}", color, back);
+        }
+    }
+}
+
+#[test]
+fn delta_e_2000_matches_sharma_data() {
+    // Pairs 1, 7, 17 and 25 of "The CIEDE2000 Color-Difference Formula: Implementation Notes"
+    for (lab1, lab2, expected) in [
+        ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+        ([50.0, 0.0, 0.0], [50.0, -1.0, 2.0], 2.3669),
+        ([50.0, 2.5, 0.0], [73.0, 25.0, -18.0], 27.1492),
+        (
+            [60.2574, -34.0099, 36.2677],
+            [60.4626, -34.1751, 39.4387],
+            1.2644,
+        ),
+    ] {
+        let delta_e = delta_e_2000(lab1, lab2);
+        assert!(
+            (delta_e - expected).abs() < 1e-4,
+            "{:?} and {:?}: {} instead of {}",
+            lab1,
+            lab2,
+            delta_e,
+            expected
+        );
+        assert!((delta_e_2000(lab2, lab1) - expected).abs() < 1e-4);
+    }
+}
+
+#[test]
+fn cam16_matches_reference_example() {
+    let conditions = ViewingConditions::new(
+        Matrix3x1d::new(95.05, 100.0, 108.88),
+        318.31,
+        20.0,
+        Surround::Average,
+    );
+    let color = conditions.cam16(Matrix3x1d::new(19.01, 20.0, 21.78));
+    assert!((color.lightness - 41.73120791).abs() < 1e-4, "{:?}", color);
+    assert!((color.chroma - 0.10335574).abs() < 1e-4, "{:?}", color);
+    assert!((color.hue - 217.06795977).abs() < 1e-2, "{:?}", color);
+    assert!(
+        (color.colorfulness - 0.10743677).abs() < 1e-4,
+        "{:?}",
+        color
+    );
+
+    let white = conditions.cam16(Matrix3x1d::new(95.05, 100.0, 108.88));
+    assert!((white.lightness - 100.0).abs() < 1e-6);
+
+    let gray = conditions.cam16_ucs(Matrix3x1d::new(19.01, 20.0, 21.78));
+    let lighter = conditions.cam16_ucs(Matrix3x1d::new(21.0, 22.1, 24.0));
+    assert_eq!(delta_e_cam16_ucs(gray, gray), 0.0);
+    assert!(delta_e_cam16_ucs(gray, lighter) > 1.0);
+}
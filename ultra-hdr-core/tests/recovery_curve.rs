@@ -0,0 +1,66 @@
+//! Recovery curves decode what they encode, keep both ends of the Gain Map range in place and never reverse the order of boosts.
+
+use ultra_hdr_core::recovery_curve::RecoveryCurve;
+
+const CURVES: [RecoveryCurve; 4] = [
+    RecoveryCurve::Power(1.0),
+    RecoveryCurve::Power(0.5),
+    RecoveryCurve::Power(2.2),
+    RecoveryCurve::Pq,
+];
+const SPANS: [f32; 5] = [0.5, 1.0, 4.0, 12.0, 20.0];
+const STEPS: usize = 1000;
+
+fn recoveries() -> impl Iterator<Item = f32> {
+    (0..=STEPS).map(|step| step as f32 / STEPS as f32)
+}
+
+#[test]
+fn decode_inverts_encode() {
+    for curve in CURVES {
+        for stops in SPANS {
+            for recovery in recoveries() {
+                let encoded: f32 = curve.encode(recovery, stops);
+                let decoded = curve.decode(encoded, stops);
+                // Error in stops, the unit a viewer's boost is in
+                assert!(
+                    (decoded - recovery).abs() * stops < 1e-5,
+                    "{:?} over {} stops: {} came back as {}",
+                    curve,
+                    stops,
+                    recovery,
+                    decoded
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn ends_stay_in_place() {
+    for curve in CURVES {
+        for stops in SPANS {
+            assert!(curve.encode(0.0f32, stops).abs() < 1e-5, "{:?}", curve);
+            assert!(
+                (curve.encode(1.0f32, stops) - 1.0).abs() < 1e-5,
+                "{:?}",
+                curve
+            );
+        }
+    }
+}
+
+#[test]
+fn encoding_is_monotonic() {
+    for curve in CURVES {
+        for stops in SPANS {
+            let encoded: Vec<f32> = recoveries().map(|r| curve.encode(r, stops)).collect();
+            assert!(
+                encoded.windows(2).all(|w| w[0] < w[1]),
+                "{:?} over {} stops",
+                curve,
+                stops
+            );
+        }
+    }
+}